@@ -188,7 +188,7 @@ fn protocol() {
             size1 += client_hello.declassify().len();
 
             let (server_hello, server_finished, server) =
-                Server::accept(ciphersuite, db.clone(), &client_hello, &mut rng).unwrap();
+                Server::accept(ciphersuite, db.clone(), &client_hello, None, &mut rng).unwrap();
             size2 += server_hello.declassify().len();
             size2 += server_finished.declassify().len();
 
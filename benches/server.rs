@@ -126,7 +126,7 @@ fn protocol() {
 
             let start_time = Instant::now();
             let (server_hello, server_finished, server) =
-                Server::accept(ciphersuite, db.clone(), &client_hello, &mut rng).unwrap();
+                Server::accept(ciphersuite, db.clone(), &client_hello, None, &mut rng).unwrap();
             let end_time = Instant::now();
             handshake_time += end_time.duration_since(start_time);
 
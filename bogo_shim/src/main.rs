@@ -26,6 +26,7 @@ struct Options {
     hostname: String,
     expect_fallback_scsv: bool,
     expect_extended_master_secret: bool,
+    enable_grease: bool,
 }
 
 #[derive(Debug, Default)]
@@ -49,7 +50,6 @@ const UNHANDLED_ARGUMENTS: &[&str] = &[
     "-ech-config-list",
     "-enable-early-data",
     "-enable-ech-grease",
-    "-enable-grease",
     "-enable-ocsp-stapling",
     "-enforce-rsa-key-usage",
     "-expect-certificate-types",
@@ -169,6 +169,9 @@ fn main() {
                 options.expect_fallback_scsv = true;
                 skip_currently(&arg);
             }
+            "-enable-grease" => {
+                options.enable_grease = true;
+            }
             "-expect-extended-master-secret" => {
                 options.expect_extended_master_secret = true;
                 skip_currently(&arg);
@@ -218,6 +221,9 @@ fn main() {
                 stream,
             )
             .unwrap();
+            if options.enable_grease {
+                client.enable_grease();
+            }
             let _r = client.start(&mut rand::thread_rng());
         }
         Role::Server => {
@@ -5,10 +5,13 @@ use bertie::{
     stream::BertieStream,
     tls13crypto::SignatureScheme,
     tls13crypto::{
+        SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_P256, SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_X25519,
+        SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_P256, SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_X25519,
         SHA256_Aes128Gcm_EcdsaSecp256r1Sha256_P256, SHA256_Aes128Gcm_EcdsaSecp256r1Sha256_X25519,
         SHA256_Aes128Gcm_RsaPssRsaSha256_P256, SHA256_Aes128Gcm_RsaPssRsaSha256_X25519,
         SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_P256,
         SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519,
+        SHA256_Chacha20Poly1305_MlDsa65_X25519MlKem768,
         SHA256_Chacha20Poly1305_RsaPssRsaSha256_P256,
         SHA256_Chacha20Poly1305_RsaPssRsaSha256_X25519, SHA384_Aes256Gcm_EcdsaSecp256r1Sha256_P256,
         SHA384_Aes256Gcm_EcdsaSecp256r1Sha256_X25519, SHA384_Aes256Gcm_RsaPssRsaSha256_P256,
@@ -80,8 +83,80 @@ fn test_sha384_aes256_gcm_rsa_pss_rsa_sha256_x25519() {
         self_test_algorithm(SHA384_Aes256Gcm_RsaPssRsaSha256_X25519);
     }
 }
+#[test]
+fn test_sha256_aes128_ccm_ecdsa_secp256r1_sha256_x25519() {
+    self_test_algorithm(SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_X25519);
+}
+#[test]
+fn test_sha256_aes128_ccm_ecdsa_secp256r1_sha256_p256() {
+    self_test_algorithm(SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_P256);
+}
+#[test]
+fn test_sha256_aes128_ccm8_ecdsa_secp256r1_sha256_x25519() {
+    self_test_algorithm(SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_X25519);
+}
+#[test]
+fn test_sha256_aes128_ccm8_ecdsa_secp256r1_sha256_p256() {
+    self_test_algorithm(SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_P256);
+}
+
+#[test]
+fn test_sha256_chacha20_poly1305_ml_dsa65_x25519_ml_kem768() {
+    self_test_algorithm(SHA256_Chacha20Poly1305_MlDsa65_X25519MlKem768);
+}
+
+#[test]
+fn test_client_accepts_server_signature_scheme() {
+    // The client prefers ECDSA but must accept the RSA-PSS signature the server sends.
+    self_test_algorithms(
+        SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519,
+        SHA256_Chacha20Poly1305_RsaPssRsaSha256_X25519,
+    );
+}
+
+#[test]
+fn test_certificate_chain() {
+    // The server sends the leaf and the intermediate certificate.
+    self_test_with_files(
+        SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519,
+        SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519,
+        "../tests/assets/chain_cert.pem",
+        "../tests/assets/chain_key.pem",
+    );
+}
 
 fn self_test_algorithm(ciphersuite: bertie::tls13crypto::Algorithms) {
+    self_test_algorithms(ciphersuite, ciphersuite)
+}
+
+fn self_test_algorithms(
+    client_ciphersuite: bertie::tls13crypto::Algorithms,
+    ciphersuite: bertie::tls13crypto::Algorithms,
+) {
+    let (cert_file, key_file) = match ciphersuite.signature() {
+        SignatureScheme::EcdsaSecp256r1Sha256 => (
+            "../tests/assets/p256_cert.der",
+            "../tests/assets/p256_key.der",
+        ),
+        SignatureScheme::RsaPssRsaSha256 => (
+            "../tests/assets/rsa_cert.der",
+            "../tests/assets/rsa_key.der",
+        ),
+        SignatureScheme::MlDsa65 => (
+            "../tests/assets/mldsa65_cert.der",
+            "../tests/assets/mldsa65_key.der",
+        ),
+        _ => unreachable!("Unknown ciphersuite {:?}", ciphersuite),
+    };
+    self_test_with_files(client_ciphersuite, ciphersuite, cert_file, key_file)
+}
+
+fn self_test_with_files(
+    client_ciphersuite: bertie::tls13crypto::Algorithms,
+    ciphersuite: bertie::tls13crypto::Algorithms,
+    cert_file: &'static str,
+    key_file: &'static str,
+) {
     let _ = tracing_subscriber::fmt::try_init();
 
     let (tx, rx) = std::sync::mpsc::channel();
@@ -100,17 +175,6 @@ fn self_test_algorithm(ciphersuite: bertie::tls13crypto::Algorithms) {
 
         // tls13server(stream, "127.0.0.1", Some(algorithms)).unwrap();
 
-        let (cert_file, key_file) = match ciphersuite.signature() {
-            SignatureScheme::EcdsaSecp256r1Sha256 => (
-                "../tests/assets/p256_cert.der",
-                "../tests/assets/p256_key.der",
-            ),
-            SignatureScheme::RsaPssRsaSha256 => (
-                "../tests/assets/rsa_cert.der",
-                "../tests/assets/rsa_key.der",
-            ),
-            _ => unreachable!("Unknown ciphersuite {:?}", ciphersuite),
-        };
         let mut server =
             BertieStream::server("127.0.0.1", port, stream, ciphersuite, cert_file, key_file)
                 .unwrap();
@@ -123,7 +187,7 @@ fn self_test_algorithm(ciphersuite: bertie::tls13crypto::Algorithms) {
     // Client thread.
     let port = rx.recv().unwrap();
 
-    let mut client = BertieStream::client("127.0.0.1", port, ciphersuite, &mut thread_rng())
+    let mut client = BertieStream::client("127.0.0.1", port, client_ciphersuite, &mut thread_rng())
         .expect("Error connecting to server");
     eprintln!("Client connected to 127.0.0.1:{}.", port);
 
@@ -35,6 +35,11 @@ struct Cli {
     ///   * SHA384_Aes256Gcm_EcdsaSecp256r1Sha256_X25519
     ///   * SHA384_Aes256Gcm_RsaPssRsaSha256_P256
     ///   * SHA384_Aes256Gcm_RsaPssRsaSha256_X25519
+    ///   * SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_X25519
+    ///   * SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_P256
+    ///   * SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_X25519
+    ///   * SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_P256
+    ///   * SHA256_Chacha20Poly1305_MlDsa65_X25519MlKem768
     ///
     /// The default value is SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519.
     #[clap(verbatim_doc_comment)]
@@ -126,7 +126,7 @@ fn test_full_round_trip() {
         }
         Ok((client_hello, client_state)) => {
             println!("Client0 Complete");
-            match Server::accept(ciphersuite, db, &client_hello, &mut server_rng) {
+            match Server::accept(ciphersuite, db, &client_hello, None, &mut server_rng) {
                 Err(x) => {
                     println!("ServerInit Error {}", x);
                     b = false;
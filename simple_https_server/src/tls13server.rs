@@ -20,14 +20,25 @@ struct Cli {
     #[arg(short, long)]
     port: Option<u16>,
 
-    /// The file with the server key in DER
+    /// The file with the server key in DER or PEM
     #[arg(long)]
     key: String,
 
-    /// The file with the server certificate in DER
+    /// The file with the server certificate in DER or PEM.
+    /// A PEM file may hold the full chain, starting with the server certificate.
     #[arg(long)]
     cert: String,
 
+    /// An optional file with a DER encoded OCSP response for the server
+    /// certificate, which is stapled for clients that ask for it.
+    #[arg(long)]
+    ocsp: Option<String>,
+
+    /// An optional file with a serialized SignedCertificateTimestampList for
+    /// the server certificate, which is stapled for clients that ask for it.
+    #[arg(long)]
+    sct_list: Option<String>,
+
     /// Algorithms to attempt to accept from a client.
     /// Can be one of the following strings:
     ///   * SHA256_Chacha20Poly1305_RsaPssRsaSha256_X25519
@@ -42,6 +53,11 @@ struct Cli {
     ///   * SHA384_Aes256Gcm_EcdsaSecp256r1Sha256_X25519
     ///   * SHA384_Aes256Gcm_RsaPssRsaSha256_P256
     ///   * SHA384_Aes256Gcm_RsaPssRsaSha256_X25519
+    ///   * SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_X25519
+    ///   * SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_P256
+    ///   * SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_X25519
+    ///   * SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_P256
+    ///   * SHA256_Chacha20Poly1305_MlDsa65_X25519MlKem768
     ///
     /// The default value is SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519.
     #[clap(verbatim_doc_comment)]
@@ -75,6 +91,12 @@ pub fn main() -> anyhow::Result<()> {
         println!("New connection established!");
         match BertieStream::server(&host, port, stream, ciphersuite, &cli.cert, &cli.key) {
             Ok(mut server) => {
+                if let Some(ocsp) = &cli.ocsp {
+                    server.staple_ocsp_response(ocsp).unwrap();
+                }
+                if let Some(sct_list) = &cli.sct_list {
+                    server.staple_sct_list(sct_list).unwrap();
+                }
                 server.connect(&mut thread_rng()).unwrap();
                 server
                     .write(b"Hello, this is the Bertie TLS 1.3 server.\n")
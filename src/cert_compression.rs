@@ -0,0 +1,213 @@
+//! # Certificate Compression
+//!
+//! A server may compress its Certificate message with an algorithm the client
+//! offers in the `compress_certificate` extension (RFC 8879).
+//!
+//! Zlib and Brotli are available with the `zlib` and `brotli` features.
+//! Clients offer, and servers use, all available algorithms.
+//!
+//! Clients refuse to decompress certificates larger than
+//! [`MAX_UNCOMPRESSED_LEN`], so a small message can't expand into an
+//! arbitrary amount of memory.
+
+#[cfg(any(feature = "zlib", feature = "brotli"))]
+use std::io::{Read, Write};
+
+use crate::tls13utils::{Bytes, TLSError, BAD_CERTIFICATE, UNSUPPORTED_ALGORITHM};
+
+/// The largest uncompressed Certificate message a client accepts.
+pub const MAX_UNCOMPRESSED_LEN: usize = 1 << 16;
+
+/// A certificate compression algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertificateCompressionAlgorithm {
+    /// Zlib (RFC 1950), with the `zlib` feature.
+    Zlib,
+    /// Brotli (RFC 7932), with the `brotli` feature.
+    Brotli,
+}
+
+impl CertificateCompressionAlgorithm {
+    /// The code point of the algorithm.
+    pub fn code(self) -> u16 {
+        match self {
+            CertificateCompressionAlgorithm::Zlib => 1,
+            CertificateCompressionAlgorithm::Brotli => 2,
+        }
+    }
+
+    /// Get the available algorithm with the `code` point.
+    ///
+    /// Returns [`None`] if the algorithm is unknown or not available.
+    pub(crate) fn from_code(code: u16) -> Option<Self> {
+        supported_algorithms()
+            .into_iter()
+            .find(|algorithm| algorithm.code() == code)
+    }
+}
+
+/// The algorithms available with the enabled features, in order of
+/// preference.
+pub fn supported_algorithms() -> Vec<CertificateCompressionAlgorithm> {
+    #[allow(unused_mut)]
+    let mut algorithms = Vec::new();
+    #[cfg(feature = "brotli")]
+    algorithms.push(CertificateCompressionAlgorithm::Brotli);
+    #[cfg(feature = "zlib")]
+    algorithms.push(CertificateCompressionAlgorithm::Zlib);
+    algorithms
+}
+
+/// Compress the `data` with the `algorithm`.
+///
+/// Returns an [`UNSUPPORTED_ALGORITHM`] error if the algorithm isn't available.
+#[allow(clippy::match_single_binding)] // Without the `zlib` and `brotli` features.
+pub(crate) fn compress(
+    algorithm: CertificateCompressionAlgorithm,
+    data: &Bytes,
+) -> Result<Bytes, TLSError> {
+    let compressed: Vec<u8> = match algorithm {
+        #[cfg(feature = "zlib")]
+        CertificateCompressionAlgorithm::Zlib => {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder
+                .write_all(&data.declassify())
+                .and_then(|()| encoder.finish())
+                .map_err(|_| UNSUPPORTED_ALGORITHM)
+        }
+        #[cfg(feature = "brotli")]
+        CertificateCompressionAlgorithm::Brotli => {
+            // The compression runs on every handshake, so a medium quality,
+            // and a window that just covers the largest message.
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 17);
+            encoder
+                .write_all(&data.declassify())
+                .map(|()| encoder.into_inner())
+                .map_err(|_| UNSUPPORTED_ALGORITHM)
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(UNSUPPORTED_ALGORITHM),
+    }?;
+    Ok(compressed.into())
+}
+
+/// Read exactly `len` bytes from the `decoder`, and nothing more.
+#[cfg(any(feature = "zlib", feature = "brotli"))]
+fn read_exactly(decoder: impl Read, len: usize) -> Result<Vec<u8>, TLSError> {
+    let mut data = Vec::with_capacity(len);
+    decoder
+        .take(len as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|_| BAD_CERTIFICATE)?;
+    if data.len() == len {
+        Ok(data)
+    } else {
+        Err(BAD_CERTIFICATE)
+    }
+}
+
+/// Decompress the `compressed` data with the `algorithm`.
+///
+/// The decompressed data must be `uncompressed_len` bytes long, at most
+/// [`MAX_UNCOMPRESSED_LEN`]. Decompression stops as soon as the data gets
+/// longer.
+///
+/// Returns a [`BAD_CERTIFICATE`] error if the data can't be decompressed, and
+/// an [`UNSUPPORTED_ALGORITHM`] error if the algorithm isn't available.
+#[allow(clippy::match_single_binding)] // Without the `zlib` and `brotli` features.
+pub(crate) fn decompress(
+    algorithm: CertificateCompressionAlgorithm,
+    compressed: &Bytes,
+    uncompressed_len: usize,
+) -> Result<Bytes, TLSError> {
+    if uncompressed_len == 0 || uncompressed_len > MAX_UNCOMPRESSED_LEN {
+        return Err(BAD_CERTIFICATE);
+    }
+    let data: Vec<u8> = match algorithm {
+        #[cfg(feature = "zlib")]
+        CertificateCompressionAlgorithm::Zlib => read_exactly(
+            flate2::read::ZlibDecoder::new(compressed.declassify().as_slice()),
+            uncompressed_len,
+        ),
+        #[cfg(feature = "brotli")]
+        CertificateCompressionAlgorithm::Brotli => read_exactly(
+            brotli::Decompressor::new(compressed.declassify().as_slice(), 4096),
+            uncompressed_len,
+        ),
+        #[allow(unreachable_patterns)]
+        _ => Err(UNSUPPORTED_ALGORITHM),
+    }?;
+    Ok(data.into())
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = Bytes::from(std::fs::read("tests/assets/chain_cert.pem").unwrap());
+        for algorithm in supported_algorithms() {
+            let compressed = compress(algorithm, &data).unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(
+                decompress(algorithm, &compressed, data.len()),
+                Ok(data.clone())
+            );
+
+            // The length must match.
+            assert_eq!(
+                decompress(algorithm, &compressed, data.len() - 1),
+                Err(BAD_CERTIFICATE)
+            );
+            assert_eq!(
+                decompress(algorithm, &compressed, data.len() + 1),
+                Err(BAD_CERTIFICATE)
+            );
+            let truncated = compressed.slice(0, compressed.len() / 2);
+            assert_eq!(
+                decompress(algorithm, &truncated, data.len()),
+                Err(BAD_CERTIFICATE)
+            );
+        }
+    }
+
+    #[test]
+    fn unavailable_algorithms() {
+        let data = Bytes::from(std::fs::read("tests/assets/chain_cert.pem").unwrap());
+        for algorithm in [
+            CertificateCompressionAlgorithm::Zlib,
+            CertificateCompressionAlgorithm::Brotli,
+        ] {
+            if !supported_algorithms().contains(&algorithm) {
+                assert_eq!(
+                    CertificateCompressionAlgorithm::from_code(algorithm.code()),
+                    None
+                );
+                assert_eq!(compress(algorithm, &data), Err(UNSUPPORTED_ALGORITHM));
+                assert_eq!(
+                    decompress(algorithm, &data, data.len()),
+                    Err(UNSUPPORTED_ALGORITHM)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decompression_bomb() {
+        // A few hundred bytes that expand to 256 KiB.
+        let bomb = Bytes::from(vec![0u8; MAX_UNCOMPRESSED_LEN * 4]);
+        for algorithm in supported_algorithms() {
+            let compressed = compress(algorithm, &bomb).unwrap();
+            assert_eq!(
+                decompress(algorithm, &compressed, bomb.len()),
+                Err(BAD_CERTIFICATE)
+            );
+            assert_eq!(
+                decompress(algorithm, &compressed, MAX_UNCOMPRESSED_LEN),
+                Err(BAD_CERTIFICATE)
+            );
+        }
+    }
+}
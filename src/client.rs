@@ -0,0 +1,229 @@
+//! # TLS 1.3 Client Configuration
+//!
+//! A [`ClientConfig`] holds the options of a client connection. It starts
+//! with the ciphersuite and the server name, and the `with_*` methods add
+//! options. [`Client::connect_with_config`](crate::Client::connect_with_config)
+//! starts the handshake.
+//!
+//! A client can authenticate with a certificate when the server asks for one
+//! after the handshake (post-handshake authentication, RFC 8446 Section 4.6.2).
+//! The [`ClientCredentials`] hold the
+//! * certificate chain
+//! * signer for the certificate's private key
+//!
+//! A client with credentials offers the `post_handshake_auth` extension, see
+//! [`ClientConfig::with_client_credentials`].
+
+use std::sync::Arc;
+
+use crate::{
+    ech::{parse_ech_config_list, EchOffer},
+    psk::PreSharedKey,
+    signer::{InMemoryKey, Signer, SigningKey},
+    tls13cert::{certificate_names, verification_key_from_spki},
+    tls13crypto::{Algorithms, LibcruxProvider, SignatureKey},
+    tls13utils::{unix_time, Bytes, TLSError, UNSUPPORTED_ALGORITHM},
+};
+
+/// The configuration of a client connection.
+///
+/// The client uses the [`CryptoProvider`](crate::tls13crypto::CryptoProvider)
+/// `Crypto` for all cryptographic operations. It defaults to the
+/// [`LibcruxProvider`].
+pub struct ClientConfig<Crypto = LibcruxProvider> {
+    pub(crate) crypto: Crypto,
+    pub(crate) ciphersuite: Algorithms,
+    pub(crate) server_name: Bytes,
+    pub(crate) psks: Vec<PreSharedKey>,
+    pub(crate) server_key: Option<Bytes>,
+    pub(crate) ech: Option<EchOffer>,
+    pub(crate) grease: bool,
+    pub(crate) credentials: Option<ClientCredentials>,
+    pub(crate) certificate_authorities: Vec<Bytes>,
+    /// The current time, in seconds since the Unix epoch.
+    pub(crate) clock: fn() -> u64,
+}
+
+impl ClientConfig {
+    /// Create a new configuration for connecting to the `server_name` with the
+    /// `ciphersuite`.
+    ///
+    /// Note that Bertie clients only support a single ciphersuite at a time
+    /// and do not perform ciphersuite negotiation.
+    pub fn new(ciphersuite: Algorithms, server_name: &Bytes) -> Self {
+        Self {
+            crypto: LibcruxProvider,
+            ciphersuite,
+            server_name: server_name.clone(),
+            psks: Vec::new(),
+            server_key: None,
+            ech: None,
+            grease: false,
+            credentials: None,
+            certificate_authorities: Vec::new(),
+            clock: unix_time,
+        }
+    }
+}
+
+impl<Crypto> ClientConfig<Crypto> {
+    /// Use the `crypto` provider for all cryptographic operations.
+    pub fn with_provider<Other>(self, crypto: Other) -> ClientConfig<Other> {
+        ClientConfig {
+            crypto,
+            ciphersuite: self.ciphersuite,
+            server_name: self.server_name,
+            psks: self.psks,
+            server_key: self.server_key,
+            ech: self.ech,
+            grease: self.grease,
+            credentials: self.credentials,
+            certificate_authorities: self.certificate_authorities,
+            clock: self.clock,
+        }
+    }
+
+    /// Offer the pre-shared key `psk`, e.g. an external PSK.
+    ///
+    /// The ciphersuite must be in PSK mode, with the hash algorithm of the
+    /// `psk`. Without a key exchange (see
+    /// [`Algorithms::with_psk_key_exchange_mode`]), the connection isn't
+    /// forward secret.
+    ///
+    /// Each call offers one more PSK. The server picks one of them, and the
+    /// connection continues with that. Early data is always sent with the
+    /// first PSK.
+    pub fn with_psk(mut self, psk: PreSharedKey) -> Self {
+        self.psks.push(psk);
+        self
+    }
+
+    /// Encrypt the ClientHello (ECH) with the first config of the
+    /// `ech_config_list`.
+    ///
+    /// The encrypted ClientHello with the server name is sent inside a
+    /// ClientHello for the public name of the config. ECH can't be used with
+    /// PSKs.
+    ///
+    /// If the server rejects ECH, the client authenticates the server for the
+    /// public name and aborts the handshake with an `ech_required` alert, see
+    /// [`Client::read_handshake`](crate::Client::read_handshake).
+    /// [`Client::ech_status`](crate::Client::ech_status) returns the retry
+    /// configs the server sent, which can be used for a new connection.
+    ///
+    /// Returns a [`TLSError`] if the `ech_config_list` is malformed or has no
+    /// supported config.
+    pub fn with_ech(mut self, ech_config_list: &Bytes) -> Result<Self, TLSError> {
+        let config = parse_ech_config_list(ech_config_list)?
+            .into_iter()
+            .next()
+            .ok_or(UNSUPPORTED_ALGORITHM)?;
+        self.ech = Some(EchOffer::Config(config));
+        Ok(self)
+    }
+
+    /// Send a GREASE ECH extension.
+    ///
+    /// This looks like an ECH offer to the network, but the handshake is a
+    /// regular one with the server name.
+    pub fn with_grease_ech(mut self) -> Self {
+        self.ech = Some(EchOffer::Grease);
+        self
+    }
+
+    /// Send GREASE values (RFC 8701).
+    ///
+    /// The client inserts reserved values into the cipher suites, groups,
+    /// signature algorithms, key shares, versions and extensions it offers,
+    /// to check that the server ignores unknown values.
+    pub fn with_grease(mut self) -> Self {
+        self.grease = true;
+        self
+    }
+
+    /// Authenticate with the `credentials` when the server asks for a
+    /// certificate after the handshake.
+    ///
+    /// The client offers post-handshake authentication. When the server asks
+    /// for a certificate, [`Client::read`](crate::Client::read) takes the
+    /// request, and
+    /// [`Client::certificate_response`](crate::Client::certificate_response)
+    /// answers it.
+    pub fn with_client_credentials(mut self, credentials: ClientCredentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Use the `clock`, which returns the current time in seconds since the
+    /// Unix epoch, instead of the system clock.
+    ///
+    /// The client checks the validity period of the server's delegated
+    /// credential at this time.
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// List the `trust_anchors` in the `certificate_authorities` extension.
+    ///
+    /// The `trust_anchors` are DER encoded CA certificates, whose subject
+    /// names are sent to the server. A server with several certificate chains
+    /// for the server name then prefers one that ends at one of them.
+    /// The server certificate is not checked against the `trust_anchors`.
+    ///
+    /// Returns a [`TLSError`] if one of the `trust_anchors` is malformed.
+    pub fn with_certificate_authorities(
+        mut self,
+        trust_anchors: &[Bytes],
+    ) -> Result<Self, TLSError> {
+        for cert in trust_anchors {
+            let (_, subject) = certificate_names(cert)?;
+            self.certificate_authorities.push(subject);
+        }
+        Ok(self)
+    }
+
+    /// Authenticate the server with the raw public key `server_key`
+    /// (RFC 7250) instead of a certificate.
+    ///
+    /// The `server_key` is the DER encoded SubjectPublicKeyInfo the server
+    /// must present. Any other key, or a certificate, fails the handshake.
+    ///
+    /// Returns a [`TLSError`] if the `server_key` is malformed.
+    pub fn with_server_key(mut self, server_key: Bytes) -> Result<Self, TLSError> {
+        verification_key_from_spki(&server_key)?;
+        self.server_key = Some(server_key);
+        Ok(self)
+    }
+}
+
+/// The certificate chain and signer of a client.
+#[derive(Debug, Clone)]
+pub struct ClientCredentials {
+    pub(crate) cert_chain: Vec<Bytes>,
+    pub(crate) key: SigningKey,
+}
+
+impl ClientCredentials {
+    /// Create new credentials with the raw private key `sk` and the
+    /// certificate chain `cert_chain`.
+    ///
+    /// The chain starts with the end-entity certificate, which `sk` belongs to,
+    /// followed by the intermediate certificates.
+    pub fn new(cert_chain: Vec<Bytes>, sk: SignatureKey) -> Self {
+        let leaf = cert_chain.first().cloned().unwrap_or_default();
+        Self {
+            cert_chain,
+            key: SigningKey::InMemory(InMemoryKey::new(leaf, sk)),
+        }
+    }
+
+    /// Create new credentials that sign with the `signer` for the
+    /// certificate chain `cert_chain`.
+    pub fn with_signer(cert_chain: Vec<Bytes>, signer: Arc<dyn Signer>) -> Self {
+        Self {
+            cert_chain,
+            key: SigningKey::External(signer),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! # Certificate Transparency
+//!
+//! A server may staple signed certificate timestamps (SCTs, RFC 6962) to its
+//! certificate. Each SCT is a log's promise that it publishes the certificate.
+//!
+//! The client asks for SCTs in every handshake and makes them available on the
+//! [`Client`](crate::Client). It may check them against the logs it trusts with
+//! a [`CtPolicy`].
+
+#[cfg(not(feature = "secret_integers"))]
+use crate::tls13utils::Declassify;
+use crate::{
+    tls13cert::{cert_public_key, verification_key_from_spki},
+    tls13crypto::{CryptoProvider, HashAlgorithm, SignatureScheme},
+    tls13formats::parse_ecdsa_signature,
+    tls13utils::{
+        check, encode_length_u16, encode_length_u24, length_u16_encoded_slice, Bytes, TLSError,
+        CT_POLICY_FAILED, U8, UNSUPPORTED_ALGORITHM,
+    },
+};
+
+/// The only SCT version, v1.
+const SCT_VERSION_V1: u8 = 0;
+
+/// The length of a log id, a SHA-256 hash.
+const LOG_ID_LEN: usize = 32;
+
+/// The TLS 1.2 `SignatureAndHashAlgorithm` for ECDSA with SHA-256.
+const ECDSA_SHA256: u16 = 0x0403;
+
+/// A signed certificate timestamp.
+///
+/// ```TLS
+/// struct {
+///     Version sct_version;
+///     LogID id;
+///     uint64 timestamp;
+///     CtExtensions extensions;
+///     digitally-signed struct {
+///         ...
+///     };
+/// } SignedCertificateTimestamp;
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedCertificateTimestamp {
+    /// The SHA-256 hash of the log's public key.
+    pub log_id: Bytes,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The extensions, which are opaque.
+    pub extensions: Bytes,
+    /// The TLS 1.2 `SignatureAndHashAlgorithm` of the signature.
+    pub signature_algorithm: u16,
+    /// The signature of the log.
+    pub signature: Bytes,
+}
+
+/// Parse a single v1 SCT, filling all of `sct`.
+fn parse_sct(sct: &[U8]) -> Result<SignedCertificateTimestamp, TLSError> {
+    let mut next = 1 + LOG_ID_LEN + 8;
+    check(sct.len() >= next && sct[0].declassify() == SCT_VERSION_V1)?;
+    let log_id = sct[1..1 + LOG_ID_LEN].into();
+    let timestamp = sct[1 + LOG_ID_LEN..next]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 8) | b.declassify() as u64);
+
+    let extensions_len = length_u16_encoded_slice(&sct[next..sct.len()])?;
+    let extensions = sct[next + 2..next + 2 + extensions_len].into();
+    next = next + 2 + extensions_len;
+
+    check(sct.len() - next >= 2)?;
+    let signature_algorithm =
+        ((sct[next].declassify() as u16) << 8) | sct[next + 1].declassify() as u16;
+    let signature_len = length_u16_encoded_slice(&sct[next + 2..sct.len()])?;
+    check(next + 4 + signature_len == sct.len())?;
+    let signature = sct[next + 4..sct.len()].into();
+
+    Ok(SignedCertificateTimestamp {
+        log_id,
+        timestamp,
+        extensions,
+        signature_algorithm,
+        signature,
+    })
+}
+
+/// Parse a serialized `SignedCertificateTimestampList`.
+///
+/// SCTs of unknown versions are skipped.
+pub(crate) fn parse_sct_list(
+    sct_list: &Bytes,
+) -> Result<Vec<SignedCertificateTimestamp>, TLSError> {
+    let sct_list = sct_list.as_raw();
+    let len = length_u16_encoded_slice(sct_list)?;
+    check(len > 0 && len + 2 == sct_list.len())?;
+    let mut scts = Vec::new();
+    let mut next = 2;
+    while next < sct_list.len() {
+        let sct_len = length_u16_encoded_slice(&sct_list[next..sct_list.len()])?;
+        check(sct_len > 0)?;
+        let sct = &sct_list[next + 2..next + 2 + sct_len];
+        if sct[0].declassify() == SCT_VERSION_V1 {
+            scts.push(parse_sct(sct)?);
+        }
+        next = next + 2 + sct_len;
+    }
+    Ok(scts)
+}
+
+/// A Certificate Transparency log.
+#[derive(Clone, Debug)]
+pub struct CtLog {
+    key: Bytes,
+}
+
+impl CtLog {
+    /// Create a log with its DER encoded SubjectPublicKeyInfo `key`.
+    ///
+    /// Only logs with ECDSA P-256 keys are supported.
+    /// Returns a [`TLSError`] if the key can't be used.
+    pub fn new(key: Bytes) -> Result<Self, TLSError> {
+        let (scheme, _) = verification_key_from_spki(&key)?;
+        if scheme == SignatureScheme::EcdsaSecp256r1Sha256 {
+            Ok(Self { key })
+        } else {
+            Err(UNSUPPORTED_ALGORITHM)
+        }
+    }
+}
+
+/// A policy for the SCTs of server certificates.
+///
+/// A certificate complies if it has valid SCTs from at least `min_logs`
+/// different logs of the policy.
+#[derive(Clone, Debug)]
+pub struct CtPolicy {
+    logs: Vec<CtLog>,
+    min_logs: usize,
+}
+
+impl CtPolicy {
+    /// Create a policy that requires SCTs from `min_logs` of the `logs`.
+    pub fn new(logs: Vec<CtLog>, min_logs: usize) -> Self {
+        Self { logs, min_logs }
+    }
+}
+
+/// Verify the signature of the `log` in the `sct` for the certificate `cert`.
+fn verify_sct(
+    log: &CtLog,
+    sct: &SignedCertificateTimestamp,
+    cert: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<(), TLSError> {
+    check(sct.signature_algorithm == ECDSA_SHA256)?;
+
+    // The version, the signature type certificate_timestamp, the timestamp,
+    // and the entry type x509_entry, followed by the entry and the extensions.
+    let signed = Bytes::from(&[SCT_VERSION_V1, 0])
+        .concat(Bytes::from(&sct.timestamp.to_be_bytes()))
+        .concat(Bytes::from(&[0, 0]))
+        .concat(encode_length_u24(cert)?)
+        .concat(encode_length_u16(sct.extensions.clone())?);
+
+    let spki = verification_key_from_spki(&log.key)?;
+    let key = cert_public_key(&log.key, &spki)?;
+    let signature = parse_ecdsa_signature(sct.signature.clone())?;
+    crypto.verify(
+        &SignatureScheme::EcdsaSecp256r1Sha256,
+        &key,
+        &signed,
+        &signature,
+    )
+}
+
+/// Check the `scts` of the end-entity certificate `cert` against the `policy`
+/// at the time `now`, in seconds since the Unix epoch.
+///
+/// SCTs of unknown logs, from the future, or with an invalid signature don't
+/// count.
+///
+/// Returns `Ok(())` if the certificate complies with the policy, and a
+/// [`CT_POLICY_FAILED`] error otherwise.
+pub(crate) fn verify_scts(
+    policy: &CtPolicy,
+    scts: &[SignedCertificateTimestamp],
+    cert: &Bytes,
+    now: u64,
+    crypto: &impl CryptoProvider,
+) -> Result<(), TLSError> {
+    let mut valid_logs: Vec<Bytes> = Vec::new();
+    for log in &policy.logs {
+        let log_id = crypto.hash(&HashAlgorithm::SHA256, &log.key)?;
+        let valid = scts.iter().any(|sct| {
+            sct.log_id == log_id
+                && sct.timestamp / 1000 <= now
+                && verify_sct(log, sct, cert, crypto).is_ok()
+        });
+        if valid && !valid_logs.contains(&log_id) {
+            valid_logs.push(log_id);
+        }
+    }
+    if valid_logs.len() >= policy.min_logs {
+        Ok(())
+    } else {
+        Err(CT_POLICY_FAILED)
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn sct_list() {
+        let list = Bytes::from(std::fs::read("tests/assets/chain_scts.bin").unwrap());
+        let scts = parse_sct_list(&list).unwrap();
+        assert_eq!(scts.len(), 2);
+        assert_eq!(scts[1].timestamp, 1792396800002);
+        assert_eq!(scts[1].extensions, Bytes::new());
+
+        // SCTs of unknown versions are skipped, empty lists are rejected.
+        let unknown = Bytes::from(&[0, 3, 0, 1, 1]);
+        assert_eq!(parse_sct_list(&unknown), Ok(Vec::new()));
+        assert!(parse_sct_list(&Bytes::from(&[0, 0])).is_err());
+        assert!(parse_sct_list(&list.slice(0, list.len() - 1)).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! # Delegated Credentials
+//!
+//! With a delegated credential (DC, RFC 9345), the holder of a certificate key
+//! authorizes another, short-lived key to sign the CertificateVerify message
+//! on its behalf. The certificate key can stay in a central place, while edge
+//! servers only hold credentials that expire within days.
+//!
+//! The credential is signed with [`DelegatedCredential::delegate`] by the
+//! holder of the certificate key. A server uses it with its private key in a
+//! [`ServerEntry`](crate::server::ServerEntry), see
+//! [`ServerEntry::with_delegated_credential`](crate::server::ServerEntry::with_delegated_credential).
+//!
+//! The client accepts delegated credentials in every handshake. The
+//! certificate must allow the delegation with the DelegationUsage extension.
+//! The credential is checked during the handshake, including its validity
+//! period at the client's clock, see
+//! [`ClientConfig::with_clock`](crate::client::ClientConfig::with_clock).
+//! [`Client::verify_delegated_credential`](crate::Client::verify_delegated_credential)
+//! checks it again at another time.
+
+use std::sync::Arc;
+
+use rand::{CryptoRng, RngCore};
+
+#[cfg(not(feature = "secret_integers"))]
+use crate::tls13utils::Declassify;
+use crate::{
+    signer::{InMemoryKey, Signer, SigningKey},
+    tls13cert::verification_key_from_spki,
+    tls13crypto::{SignatureKey, SignatureScheme},
+    tls13formats::ecdsa_signature,
+    tls13utils::{
+        check, encode_length_u16, encode_length_u24, length_u16_encoded_slice, length_u24_encoded,
+        Bytes, TLSError, U8,
+    },
+};
+
+/// The context string of the signature over a delegated credential, after 64
+/// spaces.
+const DELEGATED_CREDENTIAL_CONTEXT: &[u8; 34] = b"TLS, server delegated credentials\0";
+
+/// A delegated credential with the signer for its key.
+#[derive(Debug, Clone)]
+pub struct DelegatedCredential {
+    /// The serialized `DelegatedCredential`.
+    pub(crate) credential: Bytes,
+    pub(crate) key: SigningKey,
+}
+
+impl DelegatedCredential {
+    /// Create a delegated credential for the end-entity certificate `cert`,
+    /// signed with the `cert_signer` for the certificate key.
+    ///
+    /// The credential delegates to the key with the DER encoded
+    /// SubjectPublicKeyInfo `spki`, and is valid for `valid_time` seconds
+    /// from the notBefore time of the certificate. Clients reject credentials
+    /// that are valid for more than 7 days from the time they check them.
+    ///
+    /// Returns the serialized `DelegatedCredential`, to be used with
+    /// [`DelegatedCredential::new`].
+    pub fn delegate(
+        cert: &Bytes,
+        cert_signer: &dyn Signer,
+        spki: &Bytes,
+        valid_time: u32,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError> {
+        let (dc_cert_verify_algorithm, _) = verification_key_from_spki(spki)?;
+        let credential = Bytes::from(&valid_time.to_be_bytes())
+            .concat(dc_cert_verify_algorithm.code_point()?)
+            .concat(encode_length_u24(spki)?);
+        let algorithm = cert_signer.scheme()?;
+        let signed = signature_input(cert, &credential, &algorithm)?;
+        let signature = cert_signer.sign(&signed, rng)?;
+        let signature = match algorithm {
+            SignatureScheme::EcdsaSecp256r1Sha256 => ecdsa_signature(&signature)?,
+            _ => signature,
+        };
+        Ok(credential
+            .concat(algorithm.code_point()?)
+            .concat(encode_length_u16(signature)?))
+    }
+
+    /// Use the serialized `credential` with the raw private key `sk` of the
+    /// delegated key.
+    ///
+    /// Returns a [`TLSError`] if the credential is malformed.
+    pub fn new(credential: Bytes, sk: SignatureKey) -> Result<Self, TLSError> {
+        let spki = parse_delegated_credential(&credential)?.spki;
+        Ok(Self {
+            credential,
+            key: SigningKey::InMemory(InMemoryKey::for_raw_public_key(spki, sk)),
+        })
+    }
+
+    /// Use the serialized `credential` with the `signer` for the delegated
+    /// key.
+    ///
+    /// Returns a [`TLSError`] if the credential is malformed.
+    pub fn with_signer(credential: Bytes, signer: Arc<dyn Signer>) -> Result<Self, TLSError> {
+        parse_delegated_credential(&credential)?;
+        Ok(Self {
+            credential,
+            key: SigningKey::External(signer),
+        })
+    }
+}
+
+/// A parsed delegated credential.
+///
+/// ```TLS
+/// struct {
+///     uint32 valid_time;
+///     SignatureScheme dc_cert_verify_algorithm;
+///     opaque ASN1_subjectPublicKeyInfo<1..2^24-1>;
+/// } Credential;
+///
+/// struct {
+///     Credential cred;
+///     SignatureScheme algorithm;
+///     opaque signature<0..2^16-1>;
+/// } DelegatedCredential;
+/// ```
+pub(crate) struct DelegatedCredentialData {
+    /// Seconds from the notBefore time of the certificate.
+    pub(crate) valid_time: u32,
+    /// The scheme the server signs the CertificateVerify message with.
+    pub(crate) dc_cert_verify_algorithm: SignatureScheme,
+    /// The DER encoded SubjectPublicKeyInfo of the delegated key.
+    pub(crate) spki: Bytes,
+    /// The serialized `Credential`, which is signed.
+    pub(crate) credential: Bytes,
+    /// The scheme of the certificate key.
+    pub(crate) algorithm: SignatureScheme,
+    /// The signature, DER encoded for ECDSA.
+    pub(crate) signature: Bytes,
+}
+
+/// Parse a serialized `DelegatedCredential`, filling all of `dc`.
+pub(crate) fn parse_delegated_credential(dc: &Bytes) -> Result<DelegatedCredentialData, TLSError> {
+    let dc = dc.as_raw();
+    check(dc.len() >= 9)?;
+    let valid_time = dc[0..4]
+        .iter()
+        .fold(0u32, |acc, b| (acc << 8) | b.declassify() as u32);
+    let dc_cert_verify_algorithm = SignatureScheme::from_code_point(&dc[4..6])?;
+    let spki_len = length_u24_encoded(&dc[6..dc.len()])?;
+    check(spki_len > 0)?;
+    let next = 9 + spki_len;
+    check(dc.len() - next >= 2)?;
+    let algorithm = SignatureScheme::from_code_point(&dc[next..next + 2])?;
+    let signature_len = length_u16_encoded_slice(&dc[next + 2..dc.len()])?;
+    check(next + 4 + signature_len == dc.len())?;
+    Ok(DelegatedCredentialData {
+        valid_time,
+        dc_cert_verify_algorithm,
+        spki: dc[9..next].into(),
+        credential: dc[0..next].into(),
+        algorithm,
+        signature: dc[next + 4..dc.len()].into(),
+    })
+}
+
+/// The input of the signature over the serialized `credential` for the
+/// end-entity certificate `cert`, with the `algorithm` of the certificate key.
+pub(crate) fn signature_input(
+    cert: &Bytes,
+    credential: &Bytes,
+    algorithm: &SignatureScheme,
+) -> Result<Bytes, TLSError> {
+    Ok(Bytes::from(vec![U8(0x20); 64])
+        .concat(Bytes::from(DELEGATED_CREDENTIAL_CONTEXT))
+        .concat(cert.clone())
+        .concat(credential.clone())
+        .concat(algorithm.code_point()?))
+}
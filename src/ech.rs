@@ -0,0 +1,762 @@
+//! # Encrypted Client Hello
+//!
+//! With Encrypted Client Hello (ECH, draft-ietf-tls-esni), the client encrypts
+//! its ClientHello, the ClientHelloInner, to a key the server publishes in an
+//! `ECHConfigList`, e.g. in DNS. On-path observers only see the
+//! ClientHelloOuter, which names the server's public name instead of the real
+//! server name.
+//!
+//! The server decrypts the ClientHelloInner with one of its [`EchKeyPair`]s
+//! and signals in the ServerHello that it accepted ECH. If it can't decrypt
+//! it, the handshake continues with the ClientHelloOuter, and the server sends
+//! its current configs as retry configs. The client then completes the
+//! handshake, but refuses to use the connection.
+//!
+//! Clients without an ECH config may send a GREASE ECH extension, so that
+//! their connections look like those with ECH.
+//!
+//! Only HPKE (RFC 9180) with DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, and
+//! AES-128-GCM or ChaCha20Poly1305 is supported. The `ech_outer_extensions`
+//! extension isn't supported, and ECH can't be used in PSK mode.
+
+use rand::{CryptoRng, RngCore};
+
+#[cfg(not(feature = "secret_integers"))]
+use crate::tls13utils::Declassify;
+use crate::{
+    tls13crypto::{
+        AeadAlgorithm, AeadKey, CryptoProvider, HashAlgorithm, KemScheme, LibcruxProvider,
+    },
+    tls13utils::{
+        bytes, bytes1, bytes2, check, encode_length_u16, encode_length_u8,
+        length_u16_encoded_slice, length_u8_encoded, Bytes, TLSError, DECODE_ERROR, U8,
+        UNSUPPORTED_ALGORITHM,
+    },
+};
+
+/// The code point of the `encrypted_client_hello` extension.
+pub(crate) const ECH_EXTENSION: u16 = 0xfe0d;
+
+/// The version of the supported `ECHConfig`s.
+const ECH_VERSION: u16 = 0xfe0d;
+
+/// The `ECHClientHelloType` of the ClientHelloOuter.
+const ECH_OUTER: u8 = 0;
+
+/// The `ECHClientHelloType` of the ClientHelloInner.
+const ECH_INNER: u8 = 1;
+
+/// The HPKE KEM DHKEM(X25519, HKDF-SHA256).
+const KEM_X25519_SHA256: u16 = 0x0020;
+
+/// The HPKE KDF HKDF-SHA256.
+const KDF_HKDF_SHA256: u16 = 0x0001;
+
+/// The HPKE AEAD AES-128-GCM.
+const AEAD_AES_128_GCM: u16 = 0x0001;
+
+/// The HPKE AEAD ChaCha20Poly1305.
+const AEAD_CHACHA20_POLY1305: u16 = 0x0003;
+
+/// The length of an encapsulated X25519 key.
+const ENC_LEN: usize = 32;
+
+/// The length of the AEAD tag of all supported AEADs.
+const TAG_LEN: usize = 16;
+
+/// Encode `value` as two big endian bytes.
+fn u16_bytes(value: u16) -> Bytes {
+    bytes2((value >> 8) as u8, value as u8)
+}
+
+/// Read the big endian [`u16`] at the start of `bytes`.
+fn read_u16(bytes: &[U8]) -> Result<u16, TLSError> {
+    check(bytes.len() >= 2)?;
+    Ok(((bytes[0].declassify() as u16) << 8) | bytes[1].declassify() as u16)
+}
+
+/// Get the AEAD algorithm of the HPKE AEAD `aead_id`, if it's supported.
+fn hpke_aead(aead_id: u16) -> Option<AeadAlgorithm> {
+    match aead_id {
+        AEAD_AES_128_GCM => Some(AeadAlgorithm::Aes128Gcm),
+        AEAD_CHACHA20_POLY1305 => Some(AeadAlgorithm::Chacha20Poly1305),
+        _ => None,
+    }
+}
+
+/* HPKE: See RFC 9180 */
+
+/// HPKE `LabeledExtract` with HKDF-SHA256.
+fn labeled_extract(
+    suite_id: &Bytes,
+    salt: &Bytes,
+    label: &[u8],
+    ikm: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<Bytes, TLSError> {
+    let labeled_ikm = bytes(b"HPKE-v1")
+        .concat(suite_id.clone())
+        .concat(bytes(label))
+        .concat(ikm.clone());
+    crypto.hkdf_extract(&HashAlgorithm::SHA256, &labeled_ikm, salt)
+}
+
+/// HPKE `LabeledExpand` with HKDF-SHA256.
+fn labeled_expand(
+    suite_id: &Bytes,
+    prk: &Bytes,
+    label: &[u8],
+    info: &Bytes,
+    len: usize,
+    crypto: &impl CryptoProvider,
+) -> Result<Bytes, TLSError> {
+    let labeled_info = u16_bytes(len as u16)
+        .concat(bytes(b"HPKE-v1"))
+        .concat(suite_id.clone())
+        .concat(bytes(label))
+        .concat(info.clone());
+    crypto.hkdf_expand(&HashAlgorithm::SHA256, prk, &labeled_info, len)
+}
+
+/// Derive the DHKEM shared secret from the X25519 output `dh`, the
+/// encapsulated key `enc` and the recipient's public key `pk_r`.
+fn dhkem_shared_secret(
+    dh: &Bytes,
+    enc: &Bytes,
+    pk_r: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<Bytes, TLSError> {
+    let suite_id = bytes(b"KEM").concat(u16_bytes(KEM_X25519_SHA256));
+    let eae_prk = labeled_extract(&suite_id, &Bytes::new(), b"eae_prk", dh, crypto)?;
+    let kem_context = enc.clone().concat(pk_r.clone());
+    labeled_expand(
+        &suite_id,
+        &eae_prk,
+        b"shared_secret",
+        &kem_context,
+        32,
+        crypto,
+    )
+}
+
+/// The HPKE key schedule in base mode.
+///
+/// Returns the AEAD key and the base nonce.
+fn key_schedule(
+    aead_id: u16,
+    shared_secret: &Bytes,
+    info: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<(AeadKey, Bytes), TLSError> {
+    let aead = hpke_aead(aead_id).ok_or(UNSUPPORTED_ALGORITHM)?;
+    let suite_id = bytes(b"HPKE")
+        .concat(u16_bytes(KEM_X25519_SHA256))
+        .concat(u16_bytes(KDF_HKDF_SHA256))
+        .concat(u16_bytes(aead_id));
+    let empty = Bytes::new();
+    let psk_id_hash = labeled_extract(&suite_id, &empty, b"psk_id_hash", &empty, crypto)?;
+    let info_hash = labeled_extract(&suite_id, &empty, b"info_hash", info, crypto)?;
+    let context = bytes1(0).concat(psk_id_hash).concat(info_hash);
+    let secret = labeled_extract(&suite_id, shared_secret, b"secret", &empty, crypto)?;
+    let key = labeled_expand(&suite_id, &secret, b"key", &context, aead.key_len(), crypto)?;
+    let base_nonce = labeled_expand(
+        &suite_id,
+        &secret,
+        b"base_nonce",
+        &context,
+        aead.iv_len(),
+        crypto,
+    )?;
+    Ok((AeadKey::new(key, aead), base_nonce))
+}
+
+/// Set up an HPKE sender context for the public key `pk_r`.
+///
+/// Returns the encapsulated key, the AEAD key and the nonce of the first
+/// message.
+fn setup_base_sender(
+    pk_r: &Bytes,
+    aead_id: u16,
+    info: &Bytes,
+    crypto: &impl CryptoProvider,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(Bytes, AeadKey, Bytes), TLSError> {
+    let (dh, enc) = crypto.kem_encap(KemScheme::X25519, pk_r, rng)?;
+    let shared_secret = dhkem_shared_secret(&dh, &enc, pk_r, crypto)?;
+    let (key, nonce) = key_schedule(aead_id, &shared_secret, info, crypto)?;
+    Ok((enc, key, nonce))
+}
+
+/// Decrypt the first message `ct` of an HPKE sender with the encapsulated
+/// key `enc`, for the key pair `sk_r` and `pk_r`.
+#[allow(clippy::too_many_arguments)]
+fn open_base(
+    sk_r: &Bytes,
+    pk_r: &Bytes,
+    aead_id: u16,
+    enc: &Bytes,
+    info: &Bytes,
+    aad: &Bytes,
+    ct: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<Bytes, TLSError> {
+    check(enc.len() == ENC_LEN)?;
+    let dh = crypto.kem_decap(KemScheme::X25519, enc, sk_r)?;
+    let shared_secret = dhkem_shared_secret(&dh, enc, pk_r, crypto)?;
+    let (key, nonce) = key_schedule(aead_id, &shared_secret, info, crypto)?;
+    crypto.aead_decrypt(&key, &nonce, ct, aad)
+}
+
+/* ECH Configurations */
+
+/// An ECH configuration of a server.
+///
+/// ```TLS
+/// struct {
+///     uint16 version;
+///     uint16 length;
+///     struct {
+///         HpkeKeyConfig key_config;
+///         uint8 maximum_name_length;
+///         opaque public_name<1..255>;
+///         ECHConfigExtension extensions<0..2^16-1>;
+///     } contents;
+/// } ECHConfig;
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct EchConfig {
+    config_id: u8,
+    public_key: Bytes,
+    aead_ids: Vec<u16>,
+    maximum_name_length: u8,
+    public_name: Bytes,
+    /// The serialized `ECHConfig`, which the HPKE info is computed from.
+    encoded: Bytes,
+}
+
+impl EchConfig {
+    /// Get the id of the config.
+    pub fn config_id(&self) -> u8 {
+        self.config_id
+    }
+
+    /// Get the public name, which the client sends in the ClientHelloOuter.
+    pub fn public_name(&self) -> &Bytes {
+        &self.public_name
+    }
+
+    /// Get the serialized `ECHConfig`.
+    pub fn encoded(&self) -> &Bytes {
+        &self.encoded
+    }
+
+    /// The HPKE info for this config.
+    fn info(&self) -> Bytes {
+        bytes(b"tls ech")
+            .concat(bytes1(0))
+            .concat(self.encoded.clone())
+    }
+}
+
+/// Parse the `contents` of a version 0xfe0d `ECHConfig`.
+///
+/// Returns [`None`] if the config can't be used, e.g. because the KEM isn't
+/// supported or it has a mandatory extension.
+fn parse_ech_config_contents(
+    contents: &[U8],
+    encoded: Bytes,
+) -> Result<Option<EchConfig>, TLSError> {
+    check(contents.len() >= 3)?;
+    let config_id = contents[0].declassify();
+    let kem_id = read_u16(&contents[1..])?;
+    let mut next = 3;
+    let public_key_len = length_u16_encoded_slice(&contents[next..])?;
+    let public_key: Bytes = contents[next + 2..next + 2 + public_key_len].into();
+    next = next + 2 + public_key_len;
+    let suites_len = length_u16_encoded_slice(&contents[next..])?;
+    check(suites_len > 0 && suites_len % 4 == 0)?;
+    let aead_ids = contents[next + 2..next + 2 + suites_len]
+        .chunks(4)
+        .filter(|suite| read_u16(suite) == Ok(KDF_HKDF_SHA256))
+        .filter_map(|suite| read_u16(&suite[2..]).ok())
+        .filter(|aead_id| hpke_aead(*aead_id).is_some())
+        .collect::<Vec<u16>>();
+    next = next + 2 + suites_len;
+    check(contents.len() > next)?;
+    let maximum_name_length = contents[next].declassify();
+    next += 1;
+    let public_name_len = length_u8_encoded(&contents[next..])?;
+    check(public_name_len > 0)?;
+    let public_name: Bytes = contents[next + 1..next + 1 + public_name_len].into();
+    next = next + 1 + public_name_len;
+    let extensions_len = length_u16_encoded_slice(&contents[next..])?;
+    check(next + 2 + extensions_len == contents.len())?;
+    let mut extensions = &contents[next + 2..];
+    let mut mandatory_extension = false;
+    while !extensions.is_empty() {
+        let extension_type = read_u16(extensions)?;
+        let len = length_u16_encoded_slice(&extensions[2..])?;
+        mandatory_extension = mandatory_extension || extension_type & 0x8000 != 0;
+        extensions = &extensions[4 + len..];
+    }
+
+    if kem_id != KEM_X25519_SHA256
+        || public_key.len() != ENC_LEN
+        || aead_ids.is_empty()
+        || mandatory_extension
+    {
+        return Ok(None);
+    }
+    Ok(Some(EchConfig {
+        config_id,
+        public_key,
+        aead_ids,
+        maximum_name_length,
+        public_name,
+        encoded,
+    }))
+}
+
+/// Parse an `ECHConfigList`.
+///
+/// Returns the configs Bertie can use, in the order of the list. Configs of
+/// other versions, with other algorithms, or with mandatory extensions are
+/// skipped.
+pub fn parse_ech_config_list(ech_config_list: &Bytes) -> Result<Vec<EchConfig>, TLSError> {
+    let list = ech_config_list.as_raw();
+    let len = length_u16_encoded_slice(list)?;
+    check(len > 0 && len + 2 == list.len())?;
+    let mut configs = Vec::new();
+    let mut next = 2;
+    while next < list.len() {
+        let version = read_u16(&list[next..])?;
+        let contents_len = length_u16_encoded_slice(&list[next + 2..])?;
+        let end = next + 4 + contents_len;
+        if version == ECH_VERSION {
+            let config = parse_ech_config_contents(&list[next + 4..end], list[next..end].into())?;
+            configs.extend(config);
+        }
+        next = end;
+    }
+    Ok(configs)
+}
+
+/// The ECH key pair of a server, with its [`EchConfig`].
+#[derive(Clone)]
+pub struct EchKeyPair {
+    config: EchConfig,
+    secret_key: Bytes,
+}
+
+impl std::fmt::Debug for EchKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the secret key.
+        f.debug_struct("EchKeyPair")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EchKeyPair {
+    /// Create an ECH key pair from the X25519 `secret_key` and `public_key`.
+    ///
+    /// The config has the `config_id` and the `public_name`, and offers all
+    /// supported HPKE cipher suites.
+    ///
+    /// Returns a [`TLSError`] if the keys or the name have the wrong length.
+    pub fn new(
+        config_id: u8,
+        public_name: &Bytes,
+        secret_key: Bytes,
+        public_key: Bytes,
+    ) -> Result<Self, TLSError> {
+        check(secret_key.len() == 32 && public_key.len() == ENC_LEN && public_name.len() > 0)?;
+        let cipher_suites = [AEAD_AES_128_GCM, AEAD_CHACHA20_POLY1305].iter().fold(
+            Bytes::new(),
+            |suites, aead_id| {
+                suites
+                    .concat(u16_bytes(KDF_HKDF_SHA256))
+                    .concat(u16_bytes(*aead_id))
+            },
+        );
+        let contents = bytes1(config_id)
+            .concat(u16_bytes(KEM_X25519_SHA256))
+            .concat(encode_length_u16(public_key.clone())?)
+            .concat(encode_length_u16(cipher_suites)?)
+            .concat(bytes1(0))
+            .concat(encode_length_u8(public_name.as_raw())?)
+            .concat(encode_length_u16(Bytes::new())?);
+        let encoded = u16_bytes(ECH_VERSION).concat(encode_length_u16(contents)?);
+        let config = parse_ech_config_contents(&encoded.as_raw()[4..], encoded.clone())?
+            .ok_or(UNSUPPORTED_ALGORITHM)?;
+        Ok(Self { config, secret_key })
+    }
+
+    /// Generate a new ECH key pair.
+    ///
+    /// See [`EchKeyPair::new`].
+    pub fn generate(
+        config_id: u8,
+        public_name: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Self, TLSError> {
+        let (secret_key, public_key) = LibcruxProvider.kem_keygen(KemScheme::X25519, rng)?;
+        Self::new(config_id, public_name, secret_key, public_key)
+    }
+
+    /// Get the [`EchConfig`] of this key pair.
+    pub fn config(&self) -> &EchConfig {
+        &self.config
+    }
+}
+
+/// Serialize the configs of the `keys` as an `ECHConfigList`.
+pub(crate) fn ech_config_list(keys: &[EchKeyPair]) -> Result<Bytes, TLSError> {
+    encode_length_u16(keys.iter().fold(Bytes::new(), |list, key| {
+        list.concat(key.config.encoded.clone())
+    }))
+}
+
+/* The encrypted_client_hello extension */
+
+/// The `encrypted_client_hello` extension of a ClientHelloInner.
+pub(crate) fn ech_inner_extension() -> Result<Bytes, TLSError> {
+    Ok(u16_bytes(ECH_EXTENSION).concat(encode_length_u16(bytes1(ECH_INNER))?))
+}
+
+/// The `encrypted_client_hello` extension of a ClientHelloOuter.
+///
+/// ```TLS
+/// struct {
+///     ECHClientHelloType type;
+///     HpkeSymmetricCipherSuite cipher_suite;
+///     uint8 config_id;
+///     opaque enc<0..2^16-1>;
+///     opaque payload<1..2^16-1>;
+/// } ECHClientHello;
+/// ```
+fn ech_outer_extension(
+    aead_id: u16,
+    config_id: u8,
+    enc: &Bytes,
+    payload: Bytes,
+) -> Result<Bytes, TLSError> {
+    let ech = bytes1(ECH_OUTER)
+        .concat(u16_bytes(KDF_HKDF_SHA256))
+        .concat(u16_bytes(aead_id))
+        .concat(bytes1(config_id))
+        .concat(encode_length_u16(enc.clone())?)
+        .concat(encode_length_u16(payload)?);
+    Ok(u16_bytes(ECH_EXTENSION).concat(encode_length_u16(ech)?))
+}
+
+/// The fields of the `encrypted_client_hello` extension of a
+/// ClientHelloOuter.
+struct EchOuter {
+    aead_id: u16,
+    config_id: u8,
+    enc: Bytes,
+    payload: Bytes,
+}
+
+/// Parse the content of the `encrypted_client_hello` extension.
+///
+/// Returns [`None`] for the extension of a ClientHelloInner.
+fn parse_ech_extension(ech: &[U8]) -> Result<Option<EchOuter>, TLSError> {
+    check(!ech.is_empty())?;
+    match ech[0].declassify() {
+        ECH_INNER => {
+            check(ech.len() == 1)?;
+            Ok(None)
+        }
+        ECH_OUTER => {
+            check(ech.len() >= 6)?;
+            let kdf_id = read_u16(&ech[1..])?;
+            let aead_id = read_u16(&ech[3..])?;
+            let config_id = ech[5].declassify();
+            let enc_len = length_u16_encoded_slice(&ech[6..])?;
+            let enc = ech[8..8 + enc_len].into();
+            let payload_len = length_u16_encoded_slice(&ech[8 + enc_len..])?;
+            check(payload_len > 0 && 10 + enc_len + payload_len == ech.len())?;
+            let payload = ech[10 + enc_len..].into();
+            check(kdf_id == KDF_HKDF_SHA256).map_err(|_| UNSUPPORTED_ALGORITHM)?;
+            Ok(Some(EchOuter {
+                aead_id,
+                config_id,
+                enc,
+                payload,
+            }))
+        }
+        _ => Err(DECODE_ERROR),
+    }
+}
+
+/// Check whether the `ech` extension is the one of a ClientHelloInner.
+pub(crate) fn is_ech_inner(ech: &Bytes) -> bool {
+    matches!(parse_ech_extension(ech.as_raw()), Ok(None))
+}
+
+/// Find the offset of the `encrypted_client_hello` extension payload in the
+/// ClientHelloOuter `body`, i.e. the message without its header.
+///
+/// Returns the offset and the length of the payload.
+fn find_payload(body: &Bytes) -> Result<(usize, usize), TLSError> {
+    let body = body.as_raw();
+    // Skip the legacy version and the random.
+    let mut next = 34;
+    check(body.len() > next)?;
+    next = next + 1 + length_u8_encoded(&body[next..])?;
+    next = next + 2 + length_u16_encoded_slice(&body[next..])?;
+    check(body.len() > next)?;
+    next = next + 1 + length_u8_encoded(&body[next..])?;
+    let extensions_len = length_u16_encoded_slice(&body[next..])?;
+    check(next + 2 + extensions_len == body.len())?;
+    next += 2;
+    while next < body.len() {
+        let extension_type = read_u16(&body[next..])?;
+        let len = length_u16_encoded_slice(&body[next + 2..])?;
+        if extension_type == ECH_EXTENSION {
+            let ech = &body[next + 4..next + 4 + len];
+            let outer = parse_ech_extension(ech)?.ok_or(DECODE_ERROR)?;
+            let payload_len = outer.payload.len();
+            return Ok((next + 4 + len - payload_len, payload_len));
+        }
+        next = next + 4 + len;
+    }
+    Err(DECODE_ERROR)
+}
+
+/// Get the ClientHello `body`, without its header, with the payload of the
+/// `encrypted_client_hello` extension set to zeros.
+fn client_hello_outer_aad(body: &Bytes) -> Result<Bytes, TLSError> {
+    let (offset, len) = find_payload(body)?;
+    Ok(body.update_slice(offset, &Bytes::zeroes(len), 0, len))
+}
+
+/// Encode the ClientHelloInner `body` without the legacy session id, and pad
+/// it to hide the length of the `server_name`.
+fn encode_client_hello_inner(
+    body: &Bytes,
+    server_name: &Bytes,
+    maximum_name_length: u8,
+) -> Result<Bytes, TLSError> {
+    check(body.len() > 34)?;
+    let session_id_len = length_u8_encoded(&body.as_raw()[34..])?;
+    let encoded = body
+        .slice_range(0..34)
+        .concat(bytes1(0))
+        .concat(body.slice_range(35 + session_id_len..body.len()));
+    let maximum_name_length = maximum_name_length as usize;
+    let padding = if server_name.len() > 0 {
+        maximum_name_length.saturating_sub(server_name.len())
+    } else {
+        9 + maximum_name_length
+    };
+    let len = encoded.len() + padding;
+    let padding = padding + 31 - ((len - 1) % 32);
+    Ok(encoded.concat(Bytes::zeroes(padding)))
+}
+
+/// Decode the `encoded` ClientHelloInner, with the legacy `session_id` of the
+/// ClientHelloOuter.
+///
+/// Returns the body of the ClientHelloInner.
+fn decode_client_hello_inner(encoded: &Bytes, session_id: &Bytes) -> Result<Bytes, TLSError> {
+    let raw = encoded.as_raw();
+    // The legacy session id must be empty.
+    check(raw.len() > 35 && raw[34].declassify() == 0)?;
+    let mut next = 35;
+    next = next + 2 + length_u16_encoded_slice(&raw[next..])?;
+    check(raw.len() > next)?;
+    next = next + 1 + length_u8_encoded(&raw[next..])?;
+    next = next + 2 + length_u16_encoded_slice(&raw[next..])?;
+    // Everything after the message is padding.
+    check(raw[next..].iter().all(|b| b.declassify() == 0))?;
+    Ok(encoded
+        .slice_range(0..34)
+        .concat(encode_length_u8(session_id.as_raw())?)
+        .concat(encoded.slice_range(35..next)))
+}
+
+/// The `encrypted_client_hello` extension of a ClientHelloOuter, with a
+/// payload of zeros.
+///
+/// Returns the extension, the encapsulated key, and the AEAD key and nonce to
+/// encrypt the payload with.
+pub(crate) fn ech_outer_placeholder(
+    config: &EchConfig,
+    inner_body: &Bytes,
+    server_name: &Bytes,
+    crypto: &impl CryptoProvider,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(Bytes, Bytes, AeadKey, Bytes), TLSError> {
+    let aead_id = config.aead_ids[0];
+    let (enc, key, nonce) =
+        setup_base_sender(&config.public_key, aead_id, &config.info(), crypto, rng)?;
+    let encoded = encode_client_hello_inner(inner_body, server_name, config.maximum_name_length)?;
+    let payload = Bytes::zeroes(encoded.len() + TAG_LEN);
+    let extension = ech_outer_extension(aead_id, config.config_id, &enc, payload)?;
+    Ok((extension, encoded, key, nonce))
+}
+
+/// Encrypt the `encoded` ClientHelloInner into the payload of the
+/// ClientHelloOuter `outer_body`, which has a placeholder payload.
+///
+/// Returns the body of the ClientHelloOuter.
+pub(crate) fn seal_client_hello_inner(
+    outer_body: &Bytes,
+    encoded: &Bytes,
+    key: &AeadKey,
+    nonce: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<Bytes, TLSError> {
+    let (offset, len) = find_payload(outer_body)?;
+    let payload = crypto.aead_encrypt(key, nonce, encoded, outer_body)?;
+    check(payload.len() == len)?;
+    Ok(outer_body.update_slice(offset, &payload, 0, len))
+}
+
+/// A GREASE `encrypted_client_hello` extension, for a ClientHello with a body
+/// of `body_len` bytes.
+pub(crate) fn grease_ech_extension(
+    body_len: usize,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<Bytes, TLSError> {
+    let mut random = [0u8; 1 + ENC_LEN];
+    rng.fill_bytes(&mut random);
+    // Look like an encrypted ClientHelloInner of about the same size.
+    let payload_len = (body_len / 32 + 1) * 32 + TAG_LEN;
+    let mut payload = vec![0u8; payload_len];
+    rng.fill_bytes(&mut payload);
+    ech_outer_extension(
+        AEAD_AES_128_GCM,
+        random[0],
+        &Bytes::from(&random[1..]),
+        payload.into(),
+    )
+}
+
+/// Decrypt the ClientHelloInner in the `ech` extension of the ClientHelloOuter
+/// `outer_body` with one of the `keys`.
+///
+/// Returns the body of the ClientHelloInner, or [`None`] if none of the keys
+/// can decrypt it, e.g. because the client sent a GREASE extension.
+/// Returns a [`TLSError`] if the extension or the ClientHelloInner is
+/// malformed.
+pub(crate) fn open_client_hello_inner(
+    keys: &[EchKeyPair],
+    outer_body: &Bytes,
+    ech: &Bytes,
+    session_id: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<Option<Bytes>, TLSError> {
+    let outer = parse_ech_extension(ech.as_raw())?.ok_or(DECODE_ERROR)?;
+    let aad = client_hello_outer_aad(outer_body)?;
+    let encoded = keys
+        .iter()
+        .filter(|key| key.config.config_id == outer.config_id)
+        .find_map(|key| {
+            open_base(
+                &key.secret_key,
+                &key.config.public_key,
+                outer.aead_id,
+                &outer.enc,
+                &key.config.info(),
+                &aad,
+                &outer.payload,
+                crypto,
+            )
+            .ok()
+        });
+    match encoded {
+        Some(encoded) => Ok(Some(decode_client_hello_inner(&encoded, session_id)?)),
+        None => Ok(None),
+    }
+}
+
+/// The outcome of ECH on the client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EchStatus {
+    /// The client didn't offer ECH.
+    NotOffered,
+    /// The client sent a GREASE ECH extension.
+    Grease,
+    /// The client offered ECH, but the server hasn't answered yet.
+    Offered,
+    /// The server accepted ECH.
+    Accepted,
+    /// The server rejected ECH, and sent the `retry_configs`, an
+    /// `ECHConfigList`, if any.
+    Rejected { retry_configs: Option<Bytes> },
+}
+
+/// What a client offers in the `encrypted_client_hello` extension.
+pub(crate) enum EchOffer {
+    /// Encrypt the ClientHello to this configuration.
+    Config(EchConfig),
+    /// Send a GREASE extension.
+    Grease,
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn hpke_round_trip() {
+        let crypto = LibcruxProvider;
+        let rng = &mut rand::rngs::OsRng;
+        let (sk_r, pk_r) = crypto.kem_keygen(KemScheme::X25519, rng).unwrap();
+        let info = bytes(b"info");
+        let aad = bytes(b"aad");
+        let plaintext = bytes(b"ClientHelloInner");
+        for aead_id in [AEAD_AES_128_GCM, AEAD_CHACHA20_POLY1305] {
+            let (enc, key, nonce) = setup_base_sender(&pk_r, aead_id, &info, &crypto, rng).unwrap();
+            let ct = crypto.aead_encrypt(&key, &nonce, &plaintext, &aad).unwrap();
+            let opened = open_base(&sk_r, &pk_r, aead_id, &enc, &info, &aad, &ct, &crypto);
+            assert_eq!(opened, Ok(plaintext.clone()));
+
+            // The info and the additional data are authenticated.
+            let other = bytes(b"other");
+            assert!(open_base(&sk_r, &pk_r, aead_id, &enc, &other, &aad, &ct, &crypto).is_err());
+            assert!(open_base(&sk_r, &pk_r, aead_id, &enc, &info, &other, &ct, &crypto).is_err());
+        }
+    }
+
+    #[test]
+    fn config_list() {
+        let public_name = bytes(b"public.example.com");
+        let key = EchKeyPair::generate(7, &public_name, &mut rand::rngs::OsRng).unwrap();
+        let list = ech_config_list(&[key.clone()]).unwrap();
+        assert_eq!(parse_ech_config_list(&list), Ok(vec![key.config().clone()]));
+        assert_eq!(key.config().config_id(), 7);
+        assert_eq!(key.config().public_name(), &public_name);
+
+        // Unknown versions are skipped, truncated lists are rejected.
+        let unknown = encode_length_u16(
+            u16_bytes(0xfe0c).concat(encode_length_u16(bytes(b"config")).unwrap()),
+        )
+        .unwrap();
+        assert_eq!(parse_ech_config_list(&unknown), Ok(Vec::new()));
+        assert!(parse_ech_config_list(&list.slice(0, list.len() - 1)).is_err());
+    }
+
+    #[test]
+    fn client_hello_inner_encoding() {
+        // The legacy version, the random, a session id, a cipher suite, the
+        // compression methods and no extensions.
+        let session_id = Bytes::from([1u8; 32]);
+        let body = Bytes::zeroes(34)
+            .concat(encode_length_u8(session_id.as_raw()).unwrap())
+            .concat(bytes(&[0, 2, 0x13, 0x01, 1, 0, 0, 0]));
+        let encoded = encode_client_hello_inner(&body, &bytes(b"example.com"), 32).unwrap();
+        assert_eq!(encoded.len() % 32, 0);
+        assert_eq!(decode_client_hello_inner(&encoded, &session_id), Ok(body));
+
+        // The padding must be zeros.
+        let mut bad_padding = encoded.clone();
+        let last = bad_padding.len() - 1;
+        bad_padding[last] = U8(1);
+        assert!(decode_client_hello_inner(&bad_padding, &session_id).is_err());
+    }
+}
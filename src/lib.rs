@@ -31,13 +31,22 @@ mod tls13formats;
 mod tls13handshake;
 mod tls13record;
 
+pub mod cert_compression;
+pub mod client;
+pub mod ct;
+pub mod delegated_credential;
+pub mod ech;
+pub mod psk;
+pub mod quic;
 pub mod server;
+pub mod signer;
 pub mod tls13api;
 pub mod tls13cert;
 pub mod tls13crypto;
 pub mod tls13utils;
 
-pub use tls13api::{Client, Server};
+pub use tls13api::{Client, Server, ServerConfig};
+pub use tls13formats::{peek_client_hello, ClientHelloInfo};
 // Debug exports only
 pub use tls13formats::{handshake_data::get_hs_type, AlertDescription, AlertLevel, ContentType};
 
@@ -52,6 +61,8 @@ mod test_tls13traces_internal;
 
 // === Public API that is NOT in hacspec
 
+#[cfg(feature = "api")]
+pub mod keys;
 #[cfg(feature = "api")]
 pub mod stream;
 
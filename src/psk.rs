@@ -0,0 +1,76 @@
+//! # Pre-Shared Keys
+//!
+//! A pre-shared key (PSK) is either a resumption PSK, established in an
+//! earlier connection and identified by its session ticket, or an external
+//! PSK, provisioned out of band and identified by a name both peers agree on
+//! (RFC 8446, Section 4.2.11).
+//!
+//! Every PSK is bound to the hash algorithm of the cipher suites it is used
+//! with. The binders of external PSKs are computed with the `ext binder`
+//! label, so that an external PSK can never be confused with a resumption
+//! PSK.
+
+use crate::{
+    tls13crypto::{HashAlgorithm, Psk},
+    tls13utils::Bytes,
+};
+
+/// A pre-shared key with its identity.
+#[derive(Clone)]
+pub struct PreSharedKey {
+    pub(crate) identity: Bytes,
+    pub(crate) key: Psk,
+    pub(crate) hash: HashAlgorithm,
+    pub(crate) external: bool,
+}
+
+impl std::fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the key.
+        f.debug_struct("PreSharedKey")
+            .field("identity", &self.identity)
+            .field("hash", &self.hash)
+            .field("external", &self.external)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PreSharedKey {
+    /// Create an external PSK with the `identity` and the `key`, for cipher
+    /// suites with the `hash` algorithm.
+    pub fn external(identity: Bytes, key: Bytes, hash: HashAlgorithm) -> Self {
+        Self {
+            identity,
+            key,
+            hash,
+            external: true,
+        }
+    }
+
+    /// Create a resumption PSK with the `key` for the session `ticket`, for
+    /// cipher suites with the `hash` algorithm.
+    pub fn resumption(ticket: Bytes, key: Bytes, hash: HashAlgorithm) -> Self {
+        Self {
+            identity: ticket,
+            key,
+            hash,
+            external: false,
+        }
+    }
+
+    /// Get the identity, i.e. the name of an external PSK or the session
+    /// ticket of a resumption PSK.
+    pub fn identity(&self) -> &Bytes {
+        &self.identity
+    }
+
+    /// Get the [`HashAlgorithm`] this PSK is used with.
+    pub fn hash(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    /// Returns `true` for an external PSK and `false` for a resumption PSK.
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+}
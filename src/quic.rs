@@ -0,0 +1,642 @@
+//! # TLS 1.3 over QUIC
+//!
+//! QUIC (RFC 9001) carries the TLS handshake messages in its own CRYPTO
+//! frames instead of TLS records, and protects its packets with keys derived
+//! from the TLS traffic secrets. The [`QuicClient`] and [`QuicServer`] take
+//! and return raw handshake messages, tagged with the [`EncryptionLevel`] of
+//! the packets they are carried in, and hand out the traffic secrets of each
+//! level as soon as they are available.
+//!
+//! TLS over QUIC differs from TLS over TCP in a few ways, which are enforced
+//! here:
+//! * The peers exchange their transport parameters, which Bertie doesn't
+//!   interpret, in the `quic_transport_parameters` extension.
+//! * An ALPN protocol must be negotiated.
+//! * The legacy session id is empty, and there are no ChangeCipherSpec
+//!   messages, as there is no middlebox compatibility mode.
+//! * There is no EndOfEarlyData message, and no KeyUpdate message, as QUIC
+//!   updates its keys itself.
+//!
+//! The server doesn't send HelloRetryRequests, QUIC servers validate client
+//! addresses with Retry packets instead, and ECH isn't supported.
+
+use rand::{CryptoRng, RngCore};
+
+#[cfg(not(feature = "secret_integers"))]
+use crate::tls13utils::Declassify;
+use crate::{
+    client::ClientConfig,
+    server::ServerDB,
+    tls13crypto::{Algorithms, CryptoProvider, LibcruxProvider},
+    tls13formats::{
+        handshake_data::{HandshakeData, HandshakeType},
+        is_hello_retry_request, parse_client_hello_info, parse_quic_extensions,
+    },
+    tls13handshake::*,
+    tls13utils::{
+        check, Bytes, TLSError, ILLEGAL_PARAMETER, INCORRECT_STATE, MISSING_EXTENSION,
+        NO_APPLICATION_PROTOCOL, PAYLOAD_TOO_LONG, UNEXPECTED_MESSAGE, UNSUPPORTED,
+    },
+};
+
+/// The encryption level of QUIC packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionLevel {
+    /// Initial packets, protected with keys derived from the connection id.
+    Initial,
+    /// 0-RTT packets, protected with the client early traffic secret.
+    ZeroRtt,
+    /// Handshake packets, protected with the handshake traffic secrets.
+    Handshake,
+    /// 1-RTT packets, protected with the application traffic secrets.
+    OneRtt,
+}
+
+/// The QUIC parameters of one side of a connection.
+#[derive(Clone, Debug)]
+pub struct QuicParameters {
+    pub(crate) alpn_protocols: Vec<Bytes>,
+    pub(crate) transport_parameters: Bytes,
+}
+
+impl QuicParameters {
+    /// Create the QUIC parameters with the `alpn_protocols`, in order of
+    /// preference, and the encoded `transport_parameters` (RFC 9000, Section
+    /// 18.2).
+    ///
+    /// Returns a [`NO_APPLICATION_PROTOCOL`] error if there are no protocols,
+    /// and an [`ILLEGAL_PARAMETER`] error if a protocol name is empty or longer
+    /// than 255 bytes.
+    pub fn new(alpn_protocols: Vec<Bytes>, transport_parameters: Bytes) -> Result<Self, TLSError> {
+        check(!alpn_protocols.is_empty()).map_err(|_| NO_APPLICATION_PROTOCOL)?;
+        check(
+            alpn_protocols
+                .iter()
+                .all(|protocol| !protocol.is_empty() && protocol.len() < 256),
+        )
+        .map_err(|_| ILLEGAL_PARAMETER)?;
+        Ok(Self {
+            alpn_protocols,
+            transport_parameters,
+        })
+    }
+}
+
+/// The largest handshake message accepted, 64 KiB. This leaves room for long
+/// certificate chains, while a peer can't make the client or server buffer
+/// arbitrarily much data for a single message.
+const MAX_HANDSHAKE_MESSAGE_LEN: usize = 1 << 16;
+
+/// The most CRYPTO data buffered at an encryption level, enough for a flight
+/// of the server with the largest Certificate message.
+const MAX_CRYPTO_BUFFER_LEN: usize = 2 * MAX_HANDSHAKE_MESSAGE_LEN;
+
+/// The traffic secrets of an encryption level.
+pub struct QuicSecrets {
+    /// The encryption level the secrets protect.
+    pub level: EncryptionLevel,
+    /// The traffic secret of the client.
+    pub client: Bytes,
+    /// The traffic secret of the server, or [`None`] for 0-RTT, where only the
+    /// client sends.
+    pub server: Option<Bytes>,
+}
+
+impl QuicSecrets {
+    /// The client and server traffic `secrets` of the `level`.
+    fn new(level: EncryptionLevel, secrets: TrafficSecrets) -> Self {
+        Self {
+            level,
+            client: secrets.client,
+            server: Some(secrets.server),
+        }
+    }
+
+    /// The client early traffic `secret`, for 0-RTT.
+    fn early(secret: Bytes) -> Self {
+        Self {
+            level: EncryptionLevel::ZeroRtt,
+            client: secret,
+            server: None,
+        }
+    }
+}
+
+/// What the QUIC stack has to do after a handshake step.
+#[derive(Default)]
+pub struct QuicOutput {
+    /// The handshake messages to send, with the encryption level of the
+    /// CRYPTO frames to send them in.
+    pub handshake: Vec<(EncryptionLevel, Bytes)>,
+    /// The traffic secrets that became available, in the order they did.
+    pub secrets: Vec<QuicSecrets>,
+}
+
+/// Add the `handshake_bytes` of CRYPTO frames to the bytes `buf` of the same
+/// encryption level that weren't processed yet.
+///
+/// Fails with a [`PAYLOAD_TOO_LONG`] error if more than
+/// [`MAX_CRYPTO_BUFFER_LEN`] bytes would be buffered.
+fn buffer_crypto_data(buf: Bytes, handshake_bytes: &Bytes) -> Result<Bytes, TLSError> {
+    let buf = buf.concat(handshake_bytes.clone());
+    check(buf.len() <= MAX_CRYPTO_BUFFER_LEN).map_err(|_| PAYLOAD_TOO_LONG)?;
+    Ok(buf)
+}
+
+/// Split the first handshake message off the `buf`.
+///
+/// Returns the message and the rest of the `buf`, or [`None`] if the message
+/// isn't complete yet. A message longer than [`MAX_HANDSHAKE_MESSAGE_LEN`] is
+/// a [`PAYLOAD_TOO_LONG`] error as soon as its header is read.
+fn next_message(buf: &Bytes) -> Result<Option<(HandshakeData, Bytes)>, TLSError> {
+    if buf.len() < 4 {
+        Ok(None)
+    } else {
+        let len = ((buf[1].declassify() as usize) << 16)
+            | ((buf[2].declassify() as usize) << 8)
+            | buf[3].declassify() as usize;
+        check(len <= MAX_HANDSHAKE_MESSAGE_LEN).map_err(|_| PAYLOAD_TOO_LONG)?;
+        if buf.len() - 4 < len {
+            Ok(None)
+        } else {
+            Ok(Some((
+                HandshakeData::from(buf.slice_range(0..4 + len)),
+                buf.slice_range(4 + len..buf.len()),
+            )))
+        }
+    }
+}
+
+/// Split the messages up to and including the next Finished message off the
+/// `buf`.
+///
+/// Returns the messages and the rest of the `buf`, or [`None`] if the
+/// Finished message isn't complete yet. An EndOfEarlyData message is an
+/// [`UNEXPECTED_MESSAGE`] error, as QUIC has none.
+fn next_flight(buf: &Bytes) -> Result<Option<(HandshakeData, Bytes)>, TLSError> {
+    let mut flight = HandshakeData::from(Bytes::new());
+    let mut rest = buf.clone();
+    loop {
+        match next_message(&rest)? {
+            None => return Ok(None),
+            Some((message, next)) => {
+                let HandshakeData(message_bytes) = &message;
+                let message_type = message_bytes[0].declassify();
+                check(message_type != HandshakeType::EndOfEarlyData as u8)
+                    .map_err(|_| UNEXPECTED_MESSAGE)?;
+                flight = flight.concat(&message);
+                rest = next;
+                if message_type == HandshakeType::Finished as u8 {
+                    return Ok(Some((flight, rest)));
+                }
+            }
+        }
+    }
+}
+
+/// The QUIC client state.
+///
+/// The client uses the [`CryptoProvider`] `Crypto` for all cryptographic
+/// operations. It defaults to the [`LibcruxProvider`].
+pub enum QuicClient<Crypto = LibcruxProvider> {
+    /// Waiting for the server hello, with the Initial bytes received so far,
+    /// and the clock of the [`ClientConfig`].
+    Client0(
+        ClientPostClientHello,
+        QuicParameters,
+        Bytes,
+        fn() -> u64,
+        Crypto,
+    ),
+
+    /// Waiting for the server's Finished message, with the Handshake bytes
+    /// received so far, and the clock of the [`ClientConfig`].
+    ClientH(
+        ClientPostServerHello,
+        QuicParameters,
+        Bytes,
+        fn() -> u64,
+        Crypto,
+    ),
+
+    /// The handshake is complete, with the negotiated ALPN protocol, the
+    /// transport parameters of the server, and the 1-RTT bytes received so
+    /// far.
+    Client1(ClientPostClientFinished, Bytes, Bytes, Bytes, Crypto),
+}
+
+impl<Crypto: CryptoProvider> QuicClient<Crypto> {
+    /// Start a TLS handshake over QUIC as client.
+    ///
+    /// This function takes the
+    /// * `config` for the options of the client
+    /// * `quic` for the ALPN protocols and transport parameters of the client
+    /// * `rng` for the randomness required in the handshake
+    ///
+    /// ECH and post-handshake authentication can't be used over QUIC, and a
+    /// `config` with ECH or client credentials fails with an [`UNSUPPORTED`]
+    /// error.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns a tuple with the first element the
+    /// client hello at the [`EncryptionLevel::Initial`], and the 0-RTT secret
+    /// if the ciphersuite allows early data, and the [`QuicClient`] state as
+    /// the second element.
+    /// If an error occurs, it returns a [`TLSError`].
+    pub fn connect(
+        config: ClientConfig<Crypto>,
+        quic: QuicParameters,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(QuicOutput, Self), TLSError> {
+        check(config.ech.is_none() && config.credentials.is_none()).map_err(|_| UNSUPPORTED)?;
+        let (client_hello, _cipher0, early_secret, state) = client_init(
+            config.ciphersuite,
+            &config.server_name,
+            config.psks,
+            config.server_key,
+            None,
+            config.grease,
+            None,
+            config.certificate_authorities,
+            Some(quic.clone()),
+            &config.crypto,
+            rng,
+        )?;
+        Ok((
+            QuicOutput {
+                handshake: vec![(EncryptionLevel::Initial, client_hello.to_bytes())],
+                secrets: early_secret.into_iter().map(QuicSecrets::early).collect(),
+            },
+            QuicClient::Client0(state, quic, Bytes::new(), config.clock, config.crypto),
+        ))
+    }
+
+    /// Read the `handshake_bytes` of CRYPTO frames at the encryption `level`.
+    ///
+    /// The bytes may hold partial messages, which are kept until the rest
+    /// arrives.
+    /// At most 128 KiB are kept at a level, and a message may be at most
+    /// 64 KiB long, or this fails with a [`PAYLOAD_TOO_LONG`] error.
+    /// After a HelloRetryRequest, the second client hello is sent at the
+    /// [`EncryptionLevel::Initial`]. After the server hello, the handshake
+    /// secrets are available, and after the server's Finished message, the
+    /// client Finished message is sent at the [`EncryptionLevel::Handshake`]
+    /// and the 1-RTT secrets are available.
+    /// Session tickets after the handshake are ignored.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns a tuple with the first element
+    /// the [`QuicOutput`], and the new [`QuicClient`] state as the second
+    /// element.
+    /// If an error occurs, it returns a [`TLSError`]: an
+    /// [`UNEXPECTED_MESSAGE`] error for messages at the wrong level or that
+    /// QUIC doesn't allow, a [`NO_APPLICATION_PROTOCOL`] error if the server
+    /// didn't select one of the client's ALPN protocols, and a
+    /// [`MISSING_EXTENSION`] error if it didn't send transport parameters.
+    pub fn read_handshake(
+        self,
+        level: EncryptionLevel,
+        handshake_bytes: &Bytes,
+    ) -> Result<(QuicOutput, Self), TLSError> {
+        match (self, level) {
+            (QuicClient::Client0(state, quic, buf, clock, crypto), EncryptionLevel::Initial) => {
+                let buf = buffer_crypto_data(buf, handshake_bytes)?;
+                match next_message(&buf)? {
+                    None => Ok((
+                        QuicOutput::default(),
+                        QuicClient::Client0(state, quic, buf, clock, crypto),
+                    )),
+                    Some((server_hello, rest)) => {
+                        check(rest.is_empty()).map_err(|_| UNEXPECTED_MESSAGE)?;
+                        if is_hello_retry_request(&server_hello) {
+                            let (client_hello, state) =
+                                client_retry(&server_hello, state, &crypto)?;
+                            Ok((
+                                QuicOutput {
+                                    handshake: vec![(
+                                        EncryptionLevel::Initial,
+                                        client_hello.to_bytes(),
+                                    )],
+                                    secrets: Vec::new(),
+                                },
+                                QuicClient::Client0(state, quic, Bytes::new(), clock, crypto),
+                            ))
+                        } else {
+                            let (_cipher_hs, secrets, state) =
+                                client_set_params(&server_hello, state, &crypto)?;
+                            Ok((
+                                QuicOutput {
+                                    handshake: Vec::new(),
+                                    secrets: vec![QuicSecrets::new(
+                                        EncryptionLevel::Handshake,
+                                        secrets,
+                                    )],
+                                },
+                                QuicClient::ClientH(state, quic, Bytes::new(), clock, crypto),
+                            ))
+                        }
+                    }
+                }
+            }
+            (QuicClient::ClientH(state, quic, buf, clock, crypto), EncryptionLevel::Handshake) => {
+                let buf = buffer_crypto_data(buf, handshake_bytes)?;
+                match next_flight(&buf)? {
+                    None => Ok((
+                        QuicOutput::default(),
+                        QuicClient::ClientH(state, quic, buf, clock, crypto),
+                    )),
+                    Some((flight, rest)) => {
+                        check(rest.is_empty()).map_err(|_| UNEXPECTED_MESSAGE)?;
+                        let (client_finished, _cipher1, secrets, state) =
+                            client_finish(&flight, state, clock(), &crypto)?;
+                        let (encrypted_extensions, _) = flight.next_handshake_message()?;
+                        let (protocol, transport_parameters) =
+                            parse_quic_extensions(&encrypted_extensions)?;
+                        let protocol = protocol
+                            .filter(|protocol| quic.alpn_protocols.contains(protocol))
+                            .ok_or(NO_APPLICATION_PROTOCOL)?;
+                        let transport_parameters = transport_parameters.ok_or(MISSING_EXTENSION)?;
+                        Ok((
+                            QuicOutput {
+                                handshake: vec![(
+                                    EncryptionLevel::Handshake,
+                                    client_finished.to_bytes(),
+                                )],
+                                secrets: vec![QuicSecrets::new(EncryptionLevel::OneRtt, secrets)],
+                            },
+                            QuicClient::Client1(
+                                state,
+                                protocol,
+                                transport_parameters,
+                                Bytes::new(),
+                                crypto,
+                            ),
+                        ))
+                    }
+                }
+            }
+            (
+                QuicClient::Client1(state, protocol, transport_parameters, buf, crypto),
+                EncryptionLevel::OneRtt,
+            ) => {
+                let mut buf = buffer_crypto_data(buf, handshake_bytes)?;
+                while let Some((message, rest)) = next_message(&buf)? {
+                    let HandshakeData(message_bytes) = message;
+                    check(message_bytes[0].declassify() == HandshakeType::NewSessionTicket as u8)
+                        .map_err(|_| UNEXPECTED_MESSAGE)?;
+                    buf = rest;
+                }
+                Ok((
+                    QuicOutput::default(),
+                    QuicClient::Client1(state, protocol, transport_parameters, buf, crypto),
+                ))
+            }
+            _ => Err(UNEXPECTED_MESSAGE),
+        }
+    }
+
+    /// Check whether the handshake is complete.
+    pub fn is_handshake_complete(&self) -> bool {
+        matches!(self, QuicClient::Client1(..))
+    }
+
+    /// Get the ALPN protocol the server selected.
+    ///
+    /// Returns an [`INCORRECT_STATE`] error before the handshake is complete.
+    pub fn alpn_protocol(&self) -> Result<Bytes, TLSError> {
+        match self {
+            QuicClient::Client1(_, protocol, _, _, _) => Ok(protocol.clone()),
+            _ => Err(INCORRECT_STATE),
+        }
+    }
+
+    /// Get the encoded transport parameters of the server.
+    ///
+    /// Returns an [`INCORRECT_STATE`] error before the handshake is complete.
+    pub fn transport_parameters(&self) -> Result<Bytes, TLSError> {
+        match self {
+            QuicClient::Client1(_, _, transport_parameters, _, _) => {
+                Ok(transport_parameters.clone())
+            }
+            _ => Err(INCORRECT_STATE),
+        }
+    }
+
+    /// Get the DER encoded certificate chain of the server, starting with the
+    /// end-entity certificate.
+    ///
+    /// The chain is empty before the handshake is complete and in PSK mode.
+    pub fn server_certificates(&self) -> Vec<Bytes> {
+        match self {
+            QuicClient::Client1(state, _, _, _, _) => certificates_post_client_finished(state)
+                .iter()
+                .map(|entry| entry.cert_data.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The QUIC server state.
+///
+/// The server uses the [`CryptoProvider`] `Crypto` for all cryptographic
+/// operations. It defaults to the [`LibcruxProvider`].
+pub enum QuicServer<Crypto = LibcruxProvider> {
+    /// Waiting for the client's Finished message, with the negotiated ALPN
+    /// protocol, the transport parameters of the client, and the Handshake
+    /// bytes received so far.
+    ServerH(ServerPostServerFinished, Bytes, Bytes, Bytes, Crypto),
+
+    /// The handshake is complete, with the negotiated ALPN protocol and the
+    /// transport parameters of the client.
+    Server1(ServerPostClientFinished, Bytes, Bytes, Crypto),
+}
+
+impl QuicServer {
+    /// Start a new TLS handshake over QUIC as server.
+    ///
+    /// This function takes the
+    /// * `ciphersuite` to use for this server
+    /// * `db` for the server database containing certificates and keys
+    /// * `client_hello` for the client hello message from the Initial CRYPTO
+    ///   frames
+    /// * `quic` for the ALPN protocols, in order of preference, and the
+    ///   transport parameters of the server
+    /// * `rng` for the randomness required in the handshake
+    ///
+    /// The server selects its most preferred ALPN protocol the client offers.
+    /// The `db` must not hold ECH keys.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns a tuple with the first element
+    /// the server hello at the [`EncryptionLevel::Initial`], the rest of the
+    /// server's messages at the [`EncryptionLevel::Handshake`], and the
+    /// secrets of all levels, and the new [`QuicServer`] state as the second
+    /// element.
+    /// If an error occurs, it returns a [`TLSError`]: a
+    /// [`NO_APPLICATION_PROTOCOL`] error if the client offers none of the
+    /// server's ALPN protocols, and a [`MISSING_EXTENSION`] error if it didn't
+    /// send transport parameters.
+    pub fn accept(
+        ciphersuite: Algorithms,
+        db: ServerDB,
+        client_hello: &Bytes,
+        quic: QuicParameters,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(QuicOutput, Self), TLSError> {
+        Self::accept_with_provider(LibcruxProvider, ciphersuite, db, client_hello, quic, rng)
+    }
+}
+
+impl<Crypto: CryptoProvider> QuicServer<Crypto> {
+    /// Start a new TLS handshake over QUIC as server, using the `crypto`
+    /// provider.
+    ///
+    /// See [`QuicServer::accept`] for the other arguments and the return
+    /// value.
+    pub fn accept_with_provider(
+        crypto: Crypto,
+        ciphersuite: Algorithms,
+        db: ServerDB,
+        client_hello: &Bytes,
+        quic: QuicParameters,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(QuicOutput, Self), TLSError> {
+        check(db.ech_keys.is_empty()).map_err(|_| UNSUPPORTED)?;
+        let ch = HandshakeData::from(client_hello.clone());
+        let info = parse_client_hello_info(&ch)?;
+        check(info.legacy_session_id.is_empty()).map_err(|_| ILLEGAL_PARAMETER)?;
+        let client_transport_parameters =
+            info.quic_transport_parameters.ok_or(MISSING_EXTENSION)?;
+        let protocol = quic
+            .alpn_protocols
+            .iter()
+            .find(|protocol| info.alpn_protocols.contains(protocol))
+            .ok_or(NO_APPLICATION_PROTOCOL)?
+            .clone();
+        let selected = QuicParameters {
+            alpn_protocols: vec![protocol.clone()],
+            transport_parameters: quic.transport_parameters,
+        };
+        let (
+            server_hello,
+            flight,
+            _cipher0,
+            _cipher_hs,
+            _cipher1,
+            (early_secret, handshake_secrets, application_secrets),
+            state,
+        ) = server_init(
+            ciphersuite,
+            &ch,
+            db,
+            &None,
+            None,
+            Some(selected),
+            &crypto,
+            rng,
+        )?;
+        let mut secrets: Vec<QuicSecrets> =
+            early_secret.into_iter().map(QuicSecrets::early).collect();
+        secrets.push(QuicSecrets::new(
+            EncryptionLevel::Handshake,
+            handshake_secrets,
+        ));
+        secrets.push(QuicSecrets::new(
+            EncryptionLevel::OneRtt,
+            application_secrets,
+        ));
+        Ok((
+            QuicOutput {
+                handshake: vec![
+                    (EncryptionLevel::Initial, server_hello.to_bytes()),
+                    (EncryptionLevel::Handshake, flight.to_bytes()),
+                ],
+                secrets,
+            },
+            QuicServer::ServerH(
+                state,
+                protocol,
+                client_transport_parameters,
+                Bytes::new(),
+                crypto,
+            ),
+        ))
+    }
+
+    /// Read the `handshake_bytes` of CRYPTO frames at the encryption `level`.
+    ///
+    /// The bytes may hold partial messages, which are kept until the rest
+    /// arrives. The handshake is complete after the client's Finished message.
+    /// At most 128 KiB are kept at a level, and a message may be at most
+    /// 64 KiB long, or this fails with a [`PAYLOAD_TOO_LONG`] error.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns a tuple with the first element
+    /// the [`QuicOutput`], which is empty, and the new [`QuicServer`] state as
+    /// the second element.
+    /// If an error occurs, it returns a [`TLSError`], e.g. an
+    /// [`UNEXPECTED_MESSAGE`] error for messages at the wrong level or that
+    /// QUIC doesn't allow, like EndOfEarlyData.
+    pub fn read_handshake(
+        self,
+        level: EncryptionLevel,
+        handshake_bytes: &Bytes,
+    ) -> Result<(QuicOutput, Self), TLSError> {
+        match (self, level) {
+            (
+                QuicServer::ServerH(state, protocol, transport_parameters, buf, crypto),
+                EncryptionLevel::Handshake,
+            ) => {
+                let buf = buffer_crypto_data(buf, handshake_bytes)?;
+                match next_flight(&buf)? {
+                    None => Ok((
+                        QuicOutput::default(),
+                        QuicServer::ServerH(state, protocol, transport_parameters, buf, crypto),
+                    )),
+                    Some((client_finished, rest)) => {
+                        check(rest.is_empty()).map_err(|_| UNEXPECTED_MESSAGE)?;
+                        let state = server_finish(&client_finished, state, &crypto)?;
+                        Ok((
+                            QuicOutput::default(),
+                            QuicServer::Server1(state, protocol, transport_parameters, crypto),
+                        ))
+                    }
+                }
+            }
+            _ => Err(UNEXPECTED_MESSAGE),
+        }
+    }
+
+    /// Check whether the handshake is complete.
+    pub fn is_handshake_complete(&self) -> bool {
+        matches!(self, QuicServer::Server1(..))
+    }
+
+    /// Get the negotiated ALPN protocol.
+    pub fn alpn_protocol(&self) -> Bytes {
+        match self {
+            QuicServer::ServerH(_, protocol, _, _, _) | QuicServer::Server1(_, protocol, _, _) => {
+                protocol.clone()
+            }
+        }
+    }
+
+    /// Get the encoded transport parameters of the client.
+    pub fn transport_parameters(&self) -> Bytes {
+        match self {
+            QuicServer::ServerH(_, _, transport_parameters, _, _)
+            | QuicServer::Server1(_, _, transport_parameters, _) => transport_parameters.clone(),
+        }
+    }
+
+    /// Get the server name the client asked for, if any.
+    pub fn server_name(&self) -> Option<Bytes> {
+        match self {
+            QuicServer::ServerH(state, _, _, _, _) => {
+                server_name_post_server_finished(state).cloned()
+            }
+            QuicServer::Server1(state, _, _, _) => server_name_post_client_finished(state).cloned(),
+        }
+    }
+}
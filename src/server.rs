@@ -1,82 +1,753 @@
 //! # TLS 1.3 Server
 //!
-//! This module implements a simple TLS 1.3 server database to handle the server
+//! This module implements a simple TLS 1.3 server database.
+//! The database holds one [`ServerEntry`] per virtual host, each with the
 //! * name
-//! * certificate
-//! * private signature key
-//! * optional PSKs
+//! * certificate chain, or a raw public key (RFC 7250)
+//! * signer for the certificate's private key
+//! * optional PSKs, with their identities
+//! * optional OCSP response and signed certificate timestamps, stapled to the
+//!   certificate for clients that ask for them
+//! * optional delegated credential (RFC 9345), whose key signs instead of the
+//!   certificate key
+//!
+//! The entry for a connection is chosen by the server name indication (SNI)
+//! of the client.
+//! Several entries for the same name, e.g. with an RSA and an ECDSA certificate,
+//! are told apart by the signature algorithms the client offers, and an entry
+//! with a raw public key is only used for clients that ask for one in a
+//! `server_certificate_type` extension.
+//! The `client_certificate_type` extension is ignored, since clients only
+//! authenticate with X.509 certificates after the handshake.
+//! An entry with a delegated credential is only used for clients that accept
+//! delegated credentials for its signature scheme.
+//! Among the remaining entries, the server prefers one whose chain ends at one
+//! of the trust anchors in the client's `certificate_authorities` extension.
+//! In PSK mode, the entry must hold one of the PSKs the client offers.
+//!
+//! The database also holds the server's ECH keys, see [`crate::ech`], the
+//! key for the cookies of HelloRetryRequests, and the trust anchors for
+//! client certificates after the handshake.
+//! With Encrypted Client Hello, the entry is chosen by the server name in the
+//! ClientHelloInner. If ECH is rejected, it is chosen by the public name in
+//! the ClientHelloOuter instead, so there should be an entry for it.
+
+use std::sync::Arc;
 
 use crate::{
+    cert_compression::CertificateCompressionAlgorithm,
+    delegated_credential::DelegatedCredential,
+    ech::{ech_config_list, EchKeyPair},
+    psk::PreSharedKey,
+    quic::QuicParameters,
+    signer::{InMemoryKey, Signer, SigningKey},
+    tls13cert::{certificate_names, chain_matches_authorities},
     tls13crypto::{Algorithms, Psk, SignatureKey},
-    tls13utils::{check_eq, eq, parse_failed, Bytes, TLSError, PSK_MODE_MISMATCH},
+    tls13formats::{CERTIFICATE_TYPE_RAW_PUBLIC_KEY, CERTIFICATE_TYPE_X509},
+    tls13utils::{
+        check_mem, unix_time, Bytes, TLSError, DECRYPT_ERROR, PSK_MODE_MISMATCH, UNRECOGNIZED_NAME,
+        UNSUPPORTED_ALGORITHM,
+    },
 };
 
-/// The Server Database
-#[derive(Debug, Clone, Default)]
-pub struct ServerDB {
+/// A virtual host in the [`ServerDB`].
+#[derive(Debug, Clone)]
+pub struct ServerEntry {
     pub(crate) server_name: Bytes,
-    pub(crate) cert: Bytes,
-    pub(crate) sk: SignatureKey,
+    pub(crate) cert_chain: Vec<Bytes>,
+    pub(crate) key: SigningKey,
     pub(crate) psk_opt: Option<(Bytes, Psk)>,
+    pub(crate) psks: Vec<PreSharedKey>,
+    pub(crate) ocsp_response: Option<Bytes>,
+    pub(crate) sct_list: Option<Bytes>,
+    /// Whether the `cert_chain` is a single raw public key.
+    pub(crate) raw_public_key: bool,
+    /// The serialized `DelegatedCredential` the `signer` signs for, if any.
+    pub(crate) delegated_credential: Option<Bytes>,
 }
 
-impl ServerDB {
-    /// Create a new server database.
+impl ServerEntry {
+    /// Create a new entry for the `server_name` with the raw private key `sk`
+    /// for the certificate `cert`.
     ///
-    /// Note that this only holds one value at a time right now. #51
+    /// The `server_name` is either an exact host name, e.g. `www.example.com`,
+    /// or a wildcard name such as `*.example.com`, which matches exactly one
+    /// additional label.
     pub fn new(
         server_name: Bytes,
         cert: Bytes,
         sk: SignatureKey,
         psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self::with_chain(server_name, vec![cert], sk, psk_opt)
+    }
+
+    /// Create a new entry with the raw private key `sk` and the certificate
+    /// chain `cert_chain`.
+    ///
+    /// The chain starts with the end-entity certificate, which `sk` belongs to,
+    /// followed by the intermediate certificates.
+    pub fn with_chain(
+        server_name: Bytes,
+        cert_chain: Vec<Bytes>,
+        sk: SignatureKey,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        let leaf = cert_chain.first().cloned().unwrap_or_default();
+        let key = SigningKey::InMemory(InMemoryKey::new(leaf, sk));
+        Self::with_key(server_name, cert_chain, key, psk_opt)
+    }
+
+    /// Create a new entry that signs with the `signer` for the certificate
+    /// chain `cert_chain`.
+    ///
+    /// The private key doesn't have to be known to the server, e.g. when it is
+    /// held by an HSM.
+    pub fn with_signer(
+        server_name: Bytes,
+        cert_chain: Vec<Bytes>,
+        signer: Arc<dyn Signer>,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self::with_key(
+            server_name,
+            cert_chain,
+            SigningKey::External(signer),
+            psk_opt,
+        )
+    }
+
+    /// Create a new entry that signs with the `key` for the certificate chain
+    /// `cert_chain`.
+    fn with_key(
+        server_name: Bytes,
+        cert_chain: Vec<Bytes>,
+        key: SigningKey,
+        psk_opt: Option<(Bytes, Psk)>,
     ) -> Self {
         Self {
             server_name,
-            cert,
+            cert_chain,
+            key,
+            psk_opt,
+            psks: Vec::new(),
+            ocsp_response: None,
+            sct_list: None,
+            raw_public_key: false,
+            delegated_credential: None,
+        }
+    }
+
+    /// Create a new entry with the raw private key `sk` for the DER encoded
+    /// SubjectPublicKeyInfo `spki`, which is sent instead of a certificate.
+    ///
+    /// The entry is only used for clients that accept raw public keys
+    /// (RFC 7250). Those clients have to know the key in advance.
+    /// Raw public keys only authenticate the server: clients authenticate
+    /// after the handshake with X.509 certificates, so the
+    /// `client_certificate_type` extension is never negotiated.
+    pub fn with_raw_public_key(
+        server_name: Bytes,
+        spki: Bytes,
+        sk: SignatureKey,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        let key = SigningKey::InMemory(InMemoryKey::for_raw_public_key(spki.clone(), sk));
+        Self {
+            raw_public_key: true,
+            ..Self::with_key(server_name, vec![spki], key, psk_opt)
+        }
+    }
+
+    /// Create a new entry that signs with the `signer` for the raw public key
+    /// `spki`.
+    ///
+    /// See [`ServerEntry::with_raw_public_key`].
+    pub fn with_raw_public_key_signer(
+        server_name: Bytes,
+        spki: Bytes,
+        signer: Arc<dyn Signer>,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self {
+            raw_public_key: true,
+            ..Self::with_signer(server_name, vec![spki], signer, psk_opt)
+        }
+    }
+
+    /// Create a new entry that signs with the key of the delegated credential
+    /// `dc` for the certificate chain `cert_chain`.
+    ///
+    /// The certificate key isn't needed. The entry is only used for clients
+    /// that accept the delegated credential, see
+    /// [`crate::delegated_credential`].
+    pub fn with_delegated_credential(
+        server_name: Bytes,
+        cert_chain: Vec<Bytes>,
+        dc: DelegatedCredential,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self {
+            delegated_credential: Some(dc.credential),
+            ..Self::with_key(server_name, cert_chain, dc.key, psk_opt)
+        }
+    }
+
+    /// Accept the `psk` in PSK mode, e.g. an external PSK.
+    ///
+    /// The `psk_opt` of the constructors is a resumption PSK with its ticket.
+    pub fn with_psk(mut self, psk: PreSharedKey) -> Self {
+        self.psks.push(psk);
+        self
+    }
+
+    /// Staple the DER encoded OCSP `response` for the end-entity certificate.
+    ///
+    /// The response is sent to clients that send a `status_request`. It is
+    /// not checked by the server, and has to be refreshed by the operator
+    /// before it expires.
+    pub fn with_ocsp_response(mut self, response: Bytes) -> Self {
+        self.ocsp_response = Some(response);
+        self
+    }
+
+    /// Staple the DER encoded OCSP response in the file at `path`.
+    ///
+    /// See [`ServerEntry::with_ocsp_response`].
+    #[cfg(feature = "api")]
+    pub fn with_ocsp_response_file(
+        self,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<Self> {
+        let response = std::fs::read(path)?;
+        Ok(self.with_ocsp_response(response.into()))
+    }
+
+    /// Staple the signed certificate timestamps for the end-entity
+    /// certificate.
+    ///
+    /// The `sct_list` is a serialized `SignedCertificateTimestampList`, see
+    /// RFC 6962, Section 3.3. It is sent to clients that send a
+    /// `signed_certificate_timestamp` extension.
+    pub fn with_sct_list(mut self, sct_list: Bytes) -> Self {
+        self.sct_list = Some(sct_list);
+        self
+    }
+
+    /// Staple the serialized `SignedCertificateTimestampList` in the file at
+    /// `path`.
+    ///
+    /// See [`ServerEntry::with_sct_list`].
+    #[cfg(feature = "api")]
+    pub fn with_sct_list_file(self, path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let sct_list = std::fs::read(path)?;
+        Ok(self.with_sct_list(sct_list.into()))
+    }
+}
+
+/// The Server Database
+///
+/// A server name is looked up as follows.
+/// 1. An entry with exactly the requested name, ignoring ASCII case.
+/// 2. A wildcard entry matching the requested name.
+/// 3. The default entry, if one is set.
+///
+/// Clients that don't send a server name get the default entry, or the entries
+/// with the name of the first entry if there's no default.
+/// All other connections are rejected with an `unrecognized_name` alert.
+///
+/// Among the entries for a name, the server uses the first one that signs with
+/// the negotiated signature scheme, if the client offers it, and has a
+/// certificate type the client accepts. If the client lists its trust anchors,
+/// the first such entry whose chain ends at one of them is preferred.
+/// In PSK mode, it uses the first PSK the client offers that it has, and
+/// aborts the handshake if its binder is invalid.
+#[derive(Debug, Clone)]
+pub struct ServerDB {
+    pub(crate) entries: Vec<ServerEntry>,
+    pub(crate) default_entry: Option<ServerEntry>,
+    pub(crate) ech_keys: Vec<EchKeyPair>,
+    pub(crate) cookie_key: Option<Bytes>,
+    pub(crate) require_cookie: bool,
+    /// The current time, in seconds since the Unix epoch.
+    pub(crate) clock: fn() -> u64,
+    /// The trust anchors for client certificates.
+    pub(crate) client_trust_anchors: Vec<Bytes>,
+    /// The distinguished names of the trust anchors for client certificates.
+    pub(crate) client_certificate_authorities: Vec<Bytes>,
+}
+
+impl ServerDB {
+    /// Create a new server database for a single host with the raw private
+    /// key `sk` for the certificate `cert`.
+    ///
+    /// See [`ServerEntry::new`].
+    pub fn new(
+        server_name: Bytes,
+        cert: Bytes,
+        sk: SignatureKey,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self::from_entries(vec![ServerEntry::new(server_name, cert, sk, psk_opt)])
+    }
+
+    /// Create a new server database for a single host with the raw private
+    /// key `sk` and the certificate chain `cert_chain`.
+    ///
+    /// See [`ServerEntry::with_chain`].
+    pub fn with_chain(
+        server_name: Bytes,
+        cert_chain: Vec<Bytes>,
+        sk: SignatureKey,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self::from_entries(vec![ServerEntry::with_chain(
+            server_name,
+            cert_chain,
+            sk,
+            psk_opt,
+        )])
+    }
+
+    /// Create a new server database for a single host that signs with the
+    /// `signer` for the certificate chain `cert_chain`.
+    ///
+    /// See [`ServerEntry::with_signer`].
+    pub fn with_signer(
+        server_name: Bytes,
+        cert_chain: Vec<Bytes>,
+        signer: Arc<dyn Signer>,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self::from_entries(vec![ServerEntry::with_signer(
+            server_name,
+            cert_chain,
+            signer,
+            psk_opt,
+        )])
+    }
+
+    /// Create a new server database for a single host with the raw private
+    /// key `sk` for the raw public key `spki`.
+    ///
+    /// See [`ServerEntry::with_raw_public_key`].
+    pub fn with_raw_public_key(
+        server_name: Bytes,
+        spki: Bytes,
+        sk: SignatureKey,
+        psk_opt: Option<(Bytes, Psk)>,
+    ) -> Self {
+        Self::from_entries(vec![ServerEntry::with_raw_public_key(
+            server_name,
+            spki,
             sk,
             psk_opt,
+        )])
+    }
+
+    /// Create a new server database with the virtual hosts in `entries` and
+    /// no default entry.
+    pub fn from_entries(entries: Vec<ServerEntry>) -> Self {
+        Self {
+            entries,
+            default_entry: None,
+            ech_keys: Vec::new(),
+            cookie_key: None,
+            require_cookie: false,
+            clock: unix_time,
+            client_trust_anchors: Vec::new(),
+            client_certificate_authorities: Vec::new(),
+        }
+    }
+
+    /// Add the virtual host `entry`.
+    pub fn add_entry(&mut self, entry: ServerEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Use the `entry` for server names that don't match any other entry.
+    pub fn set_default_entry(&mut self, entry: ServerEntry) {
+        self.default_entry = Some(entry);
+    }
+
+    /// Add the ECH `key`.
+    ///
+    /// Clients may encrypt their ClientHello with the config of any of the
+    /// keys. The configs of all keys are sent as retry configs to clients
+    /// whose ClientHello can't be decrypted.
+    pub fn add_ech_key(&mut self, key: EchKeyPair) {
+        self.ech_keys.push(key);
+    }
+
+    /// Set the `key` that authenticates the cookies of HelloRetryRequests.
+    ///
+    /// The server keeps no state between a HelloRetryRequest and the
+    /// client's second ClientHello. It sends the hash of the first ClientHello
+    /// to the client in a cookie, authenticated with this key, and continues
+    /// the handshake from the cookie the client sends back.
+    /// The key should be at least 32 random bytes. Servers that share it
+    /// accept each other's cookies.
+    ///
+    /// A cookie also holds the time it was issued, and is rejected if it is
+    /// more than a minute old, see [`ServerDB::set_clock`]. It is bound to
+    /// the client's address too, if the server passes it to
+    /// [`Server::hello_retry_request`](crate::Server::hello_retry_request).
+    ///
+    /// Without a cookie key, the server never sends a HelloRetryRequest.
+    pub fn set_cookie_key(&mut self, key: Bytes) {
+        self.cookie_key = Some(key);
+    }
+
+    /// Ask every client to retry with a cookie before the server does any
+    /// expensive work, e.g. while it is under a denial of service attack.
+    ///
+    /// Otherwise, the server only sends a HelloRetryRequest to clients that
+    /// didn't send a key share for its group. This needs a cookie key, see
+    /// [`ServerDB::set_cookie_key`].
+    pub fn set_require_cookie(&mut self, require_cookie: bool) {
+        self.require_cookie = require_cookie;
+    }
+
+    /// Use the `clock`, which returns the current time in seconds since the
+    /// Unix epoch, instead of the system clock.
+    pub fn set_clock(&mut self, clock: fn() -> u64) {
+        self.clock = clock;
+    }
+
+    /// Ask for client certificates that chain to one of the DER encoded CA
+    /// certificates `trust_anchors` after the handshake.
+    ///
+    /// The subject names of the `trust_anchors` are sent in the
+    /// `certificate_authorities` extension of CertificateRequests, and
+    /// clients whose certificates don't chain to any of them send none.
+    /// A chain the client does send must end at one of the `trust_anchors`,
+    /// with valid signatures and validity periods, see
+    /// [`Server::client_certificates`](crate::Server::client_certificates).
+    /// Without trust anchors, no client certificate is accepted.
+    ///
+    /// Returns a [`TLSError`] if a certificate is malformed.
+    pub fn set_client_certificate_authorities(
+        &mut self,
+        trust_anchors: &[Bytes],
+    ) -> Result<(), TLSError> {
+        let mut names = Vec::new();
+        for cert in trust_anchors {
+            let (_, subject) = certificate_names(cert)?;
+            names.push(subject);
+        }
+        self.client_trust_anchors = trust_anchors.to_vec();
+        self.client_certificate_authorities = names;
+        Ok(())
+    }
+
+    /// Get the `ECHConfigList` with the configs of all ECH keys, to publish
+    /// to clients.
+    ///
+    /// Returns [`None`] if there are no ECH keys.
+    pub fn ech_config_list(&self) -> Result<Option<Bytes>, TLSError> {
+        if self.ech_keys.is_empty() {
+            Ok(None)
+        } else {
+            ech_config_list(&self.ech_keys).map(Some)
+        }
+    }
+
+    /// Find the entries for the server name `sni`.
+    ///
+    /// Returns an empty list if the name isn't recognized.
+    pub(crate) fn lookup(&self, sni: &[u8]) -> Vec<&ServerEntry> {
+        let name = match (sni.is_empty(), &self.default_entry, self.entries.first()) {
+            (false, _, _) => sni.to_vec(),
+            (true, Some(default), _) => return vec![default],
+            (true, None, Some(first)) => first.server_name.declassify(),
+            (true, None, None) => return vec![],
+        };
+        let exact: Vec<&ServerEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.server_name.declassify().eq_ignore_ascii_case(&name))
+            .collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+        let wildcard: Vec<&ServerEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| wildcard_matches(&entry.server_name.declassify(), &name))
+            .collect();
+        if !wildcard.is_empty() {
+            return wildcard;
+        }
+        self.default_entry.iter().collect()
+    }
+}
+
+/// Check whether the wildcard `pattern`, e.g. `*.example.com`, matches the
+/// host `name`.
+///
+/// The wildcard only matches a single, non-empty label.
+fn wildcard_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (
+        pattern.strip_prefix(b"*"),
+        name.iter().position(|c| *c == b'.'),
+    ) {
+        (Some(suffix), Some(dot)) => dot > 0 && name[dot..].eq_ignore_ascii_case(suffix),
+        _ => false,
+    }
+}
+
+/// What the server checks client certificates against after the handshake.
+#[derive(Debug, Clone)]
+pub(crate) struct ClientCertificateTrust {
+    /// The DER encoded trust anchors.
+    pub(crate) trust_anchors: Vec<Bytes>,
+    /// The distinguished names of the trust anchors, which are sent in
+    /// CertificateRequests.
+    pub(crate) certificate_authorities: Vec<Bytes>,
+    /// The current time, in seconds since the Unix epoch.
+    pub(crate) clock: fn() -> u64,
+}
+
+impl ServerDB {
+    /// What client certificates are checked against.
+    pub(crate) fn client_certificate_trust(&self) -> ClientCertificateTrust {
+        ClientCertificateTrust {
+            trust_anchors: self.client_trust_anchors.clone(),
+            certificate_authorities: self.client_certificate_authorities.clone(),
+            clock: self.clock,
         }
     }
 }
 
 /// Global server information.
 pub(crate) struct ServerInfo {
-    pub(crate) cert: Bytes,
-    pub(crate) sk: SignatureKey,
-    pub(crate) psk_opt: Option<Psk>,
+    /// The server name sent by the client, if any.
+    pub(crate) server_name: Option<Bytes>,
+    pub(crate) cert_chain: Vec<Bytes>,
+    pub(crate) key: SigningKey,
+    /// The PSK the client offered, in PSK mode.
+    pub(crate) psk: Option<PreSharedKey>,
+    /// The index of the `psk` among the client's offers.
+    pub(crate) psk_index: usize,
+    /// The OCSP response to staple to the certificate.
+    pub(crate) ocsp_response: Option<Bytes>,
+    /// The signed certificate timestamps to staple to the certificate.
+    pub(crate) sct_list: Option<Bytes>,
+    /// The algorithm to compress the Certificate message with.
+    pub(crate) certificate_compression: Option<CertificateCompressionAlgorithm>,
+    /// The certificate type to confirm in a `server_certificate_type`
+    /// extension, if the client sent one.
+    pub(crate) server_certificate_type: Option<u8>,
+    /// The ECH configs to send if ECH was rejected.
+    pub(crate) ech_retry_configs: Option<Bytes>,
+    /// What client certificates are checked against, if the client offered
+    /// post-handshake authentication.
+    pub(crate) post_handshake_auth: Option<ClientCertificateTrust>,
+    /// The delegated credential to attach to the certificate.
+    pub(crate) delegated_credential: Option<Bytes>,
+    /// The ALPN protocol the server selected, outside of QUIC.
+    pub(crate) alpn_protocol: Option<Bytes>,
+    /// The negotiated ALPN protocol and the transport parameters of the
+    /// server, in QUIC.
+    pub(crate) quic: Option<QuicParameters>,
+}
+
+impl ServerEntry {
+    /// The certificate type of this entry.
+    fn certificate_type(&self) -> u8 {
+        if self.raw_public_key {
+            CERTIFICATE_TYPE_RAW_PUBLIC_KEY
+        } else {
+            CERTIFICATE_TYPE_X509
+        }
+    }
+
+    /// Check whether the client accepts the certificate type of this entry.
+    ///
+    /// Clients that don't send a `server_certificate_type` extension only
+    /// accept X.509 certificates.
+    fn accepts_certificate_type(&self, server_certificate_types: &[u8]) -> bool {
+        if server_certificate_types.is_empty() {
+            !self.raw_public_key
+        } else {
+            server_certificate_types.contains(&self.certificate_type())
+        }
+    }
+
+    /// Find the PSK with the `identity` for the hash algorithm of the
+    /// `ciphersuite`.
+    fn find_psk(&self, ciphersuite: &Algorithms, identity: &Bytes) -> Option<PreSharedKey> {
+        let hash = ciphersuite.hash();
+        self.psk_opt
+            .iter()
+            .map(|(ticket, key)| PreSharedKey::resumption(ticket.clone(), key.clone(), hash))
+            .chain(self.psks.iter().cloned())
+            .find(|psk| psk.hash == hash && psk.identity == *identity)
+    }
+}
+
+/// What the client offers in its ClientHello for the server's authentication.
+pub(crate) struct ClientOffer<'a> {
+    /// The server name the client asks for.
+    pub(crate) server_name: &'a Bytes,
+    /// The encoded signature algorithms the client accepts, if it sent them.
+    pub(crate) signature_algorithms: &'a Option<Bytes>,
+    /// The server certificate types the client accepts. Empty if it didn't
+    /// send the extension, and only accepts X.509 certificates.
+    pub(crate) server_certificate_types: &'a [u8],
+    /// The signature schemes the client accepts for delegated credentials.
+    pub(crate) delegated_credential_schemes: &'a [u16],
+    /// The distinguished names of the client's trust anchors.
+    pub(crate) certificate_authorities: &'a [Bytes],
+}
+
+/// Select the entry for the `ciphersuite` among the `candidates`.
+///
+/// In PSK mode this is the candidate with the first of the `psk_identities`
+/// it knows, and the PSK and its index are returned too. The handshake is
+/// aborted with a [`DECRYPT_ERROR`] if the binder of that identity can't be
+/// verified with `verify_binder`. Otherwise it's the first candidate that
+/// signs with the signature scheme of the `ciphersuite`, if the client
+/// `offer`s that scheme, and has one of the certificate types the client
+/// accepts. A candidate with a delegated credential also needs the scheme
+/// among the client's delegated credential schemes.
+/// Among these, the first candidate whose chain ends at one of the client's
+/// trust anchors is preferred.
+fn select_entry<'a>(
+    ciphersuite: &Algorithms,
+    candidates: &[&'a ServerEntry],
+    offer: &ClientOffer,
+    psk_identities: &[Bytes],
+    verify_binder: impl Fn(usize, &PreSharedKey) -> bool,
+) -> Result<(&'a ServerEntry, Option<(usize, PreSharedKey)>), TLSError> {
+    if ciphersuite.psk_mode() {
+        let (index, entry, psk) = psk_identities
+            .iter()
+            .enumerate()
+            .find_map(|(index, identity)| {
+                candidates.iter().find_map(|entry| {
+                    entry
+                        .find_psk(ciphersuite, identity)
+                        .map(|psk| (index, *entry, psk))
+                })
+            })
+            .ok_or(PSK_MODE_MISMATCH)?;
+        return if verify_binder(index, &psk) {
+            Ok((entry, Some((index, psk))))
+        } else {
+            Err(DECRYPT_ERROR)
+        };
+    }
+    let offered = offer
+        .signature_algorithms
+        .as_ref()
+        .ok_or(UNSUPPORTED_ALGORITHM)?;
+    let scheme = ciphersuite.signature();
+    check_mem(scheme.code_point()?.as_raw(), offered.as_raw())
+        .map_err(|_| UNSUPPORTED_ALGORITHM)?;
+    let scheme_code = u16::from_be_bytes(scheme.code_point()?.declassify_array()?);
+    let accepts_delegated_credential = offer.delegated_credential_schemes.contains(&scheme_code);
+    let acceptable: Vec<&'a ServerEntry> = candidates
+        .iter()
+        .copied()
+        .filter(|entry| {
+            // The scheme is asked for on every handshake, so that a remote
+            // signer that is unavailable for a while isn't skipped for good.
+            entry.accepts_certificate_type(offer.server_certificate_types)
+                && (entry.delegated_credential.is_none() || accepts_delegated_credential)
+                && entry.key.scheme() == Ok(scheme)
+        })
+        .collect();
+    acceptable
+        .iter()
+        .find(|entry| {
+            !entry.raw_public_key
+                && chain_matches_authorities(&entry.cert_chain, offer.certificate_authorities)
+        })
+        .or(acceptable.first())
+        .map(|entry| (*entry, None))
+        .ok_or(UNSUPPORTED_ALGORITHM)
 }
 
 /// Look up a server for the given `ciphersuite`.
 ///
-/// The function returns a server with the first algorithm it finds.
+/// The function returns a server with the first algorithm it finds, or an
+/// [`UNRECOGNIZED_NAME`] error if there's no entry for the server name in the
+/// client's `offer`.
+/// If there's no entry for the signature algorithms, server certificate
+/// types, and delegated credential schemes of the `offer`, it returns an
+/// [`UNSUPPORTED_ALGORITHM`] error. Entries that chain to the client's
+/// certificate authorities are preferred.
+/// In PSK mode, it returns a [`PSK_MODE_MISMATCH`] error if there's no entry
+/// with any of the `psk_identities`, and a [`DECRYPT_ERROR`] if the binder of
+/// the selected identity doesn't pass `verify_binder`.
 pub(crate) fn lookup_db(
     ciphersuite: Algorithms,
     db: &ServerDB,
-    sni: &Bytes,
-    tkt: &Option<Bytes>,
+    offer: &ClientOffer,
+    psk_identities: &[Bytes],
+    verify_binder: impl Fn(usize, &PreSharedKey) -> bool,
 ) -> Result<ServerInfo, TLSError> {
-    if eq(sni, &Bytes::new()) || eq(sni, &db.server_name) {
-        match (ciphersuite.psk_mode(), tkt, &db.psk_opt) {
-            (true, Some(ctkt), Some((stkt, psk))) => {
-                check_eq(ctkt, stkt)?;
-                let server = ServerInfo {
-                    cert: db.cert.clone(),
-                    sk: db.sk.clone(),
-                    psk_opt: Some(psk.clone()),
-                };
-                Ok(server)
-            }
-            (false, _, _) => {
-                let server = ServerInfo {
-                    cert: db.cert.clone(),
-                    sk: db.sk.clone(),
-                    psk_opt: None,
-                };
-                Ok(server)
-            }
-            _ => Err(PSK_MODE_MISMATCH),
-        }
+    let sni = offer.server_name.declassify();
+    let candidates = db.lookup(&sni);
+    if candidates.is_empty() {
+        return Err(UNRECOGNIZED_NAME);
+    }
+    let (entry, psk) = select_entry(
+        &ciphersuite,
+        &candidates,
+        offer,
+        psk_identities,
+        verify_binder,
+    )?;
+    let server_name = if sni.is_empty() {
+        None
     } else {
-        Err(parse_failed())
+        Some(Bytes::from(sni))
+    };
+    match (ciphersuite.psk_mode(), psk) {
+        (true, Some((psk_index, psk))) => {
+            let server = ServerInfo {
+                server_name,
+                cert_chain: entry.cert_chain.clone(),
+                key: entry.key.clone(),
+                psk: Some(psk),
+                psk_index,
+                ocsp_response: None,
+                sct_list: None,
+                certificate_compression: None,
+                server_certificate_type: None,
+                ech_retry_configs: None,
+                post_handshake_auth: None,
+                delegated_credential: None,
+                alpn_protocol: None,
+                quic: None,
+            };
+            Ok(server)
+        }
+        (false, _) => {
+            let server = ServerInfo {
+                server_name,
+                cert_chain: entry.cert_chain.clone(),
+                key: entry.key.clone(),
+                psk: None,
+                psk_index: 0,
+                ocsp_response: entry.ocsp_response.clone(),
+                sct_list: entry.sct_list.clone(),
+                certificate_compression: None,
+                server_certificate_type: if offer.server_certificate_types.is_empty() {
+                    None
+                } else {
+                    Some(entry.certificate_type())
+                },
+                ech_retry_configs: None,
+                post_handshake_auth: None,
+                delegated_credential: entry.delegated_credential.clone(),
+                alpn_protocol: None,
+                quic: None,
+            };
+            Ok(server)
+        }
+        _ => Err(PSK_MODE_MISMATCH),
     }
 }
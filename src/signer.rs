@@ -0,0 +1,222 @@
+//! # Server Signing Keys
+//!
+//! The server signs the handshake transcript in the CertificateVerify message.
+//! The [`ServerDB`](crate::server::ServerDB) either holds the raw private key,
+//! which is used with the [`CryptoProvider`] of the handshake, or a [`Signer`].
+//! A signer may keep the key in memory ([`InMemorySigner`]) or forward the
+//! signing request to a remote service such as an HSM or a KMS.
+//!
+//! On Unix, [`UnixSocketSigner`] talks to a signing service over a local socket.
+//! [`serve_unix_signer`] implements that service on top of any other [`Signer`]
+//! and can be used as a stand-in for testing.
+
+use std::{fmt, sync::Arc};
+
+use rand::{CryptoRng, RngCore};
+
+use crate::{
+    tls13cert::{rsa_public_key, verification_key_from_cert, verification_key_from_spki, Spki},
+    tls13crypto::{CryptoProvider, LibcruxProvider, SignatureKey, SignatureScheme},
+    tls13utils::{tlserr, Bytes, TLSError, UNSUPPORTED_ALGORITHM},
+};
+
+#[cfg(all(unix, feature = "api"))]
+mod unix;
+#[cfg(all(unix, feature = "api"))]
+pub use unix::{serve_unix_signer, UnixSocketSigner};
+
+/// A cryptographically secure random number generator that can be used as a
+/// trait object.
+pub trait CryptoRngCore: CryptoRng + RngCore {}
+
+impl<T: CryptoRng + RngCore> CryptoRngCore for T {}
+
+/// A signing key for the CertificateVerify message of the server, or of a
+/// client that authenticates with a certificate.
+pub trait Signer: fmt::Debug + Send + Sync {
+    /// The signature scheme of the key.
+    ///
+    /// Returns a [`TLSError`] if the scheme can't be determined, e.g. because
+    /// the remote signer is unavailable.
+    fn scheme(&self) -> Result<SignatureScheme, TLSError>;
+
+    /// Sign the CertificateVerify `input` with the [`Signer::scheme`].
+    ///
+    /// ECDSA signatures must be returned raw, as the 32 byte big endian `r`
+    /// followed by the 32 byte `s`, not DER encoded. Bertie DER encodes them
+    /// for the handshake itself. RSA-PSS and ML-DSA signatures are returned as
+    /// they are sent.
+    ///
+    /// Returns the signature or a [`TLSError`].
+    fn sign(&self, input: &Bytes, rng: &mut dyn CryptoRngCore) -> Result<Bytes, TLSError>;
+}
+
+/// A raw private key with the certificate, or the raw public key, it belongs
+/// to.
+///
+/// Keys that are passed to Bertie as bytes are kept like this, and signed
+/// with the [`CryptoProvider`] of the handshake.
+#[derive(Clone)]
+pub(crate) struct InMemoryKey {
+    cert: Bytes,
+    raw_public_key: bool,
+    sk: SignatureKey,
+}
+
+impl InMemoryKey {
+    /// Create a new in-memory key for the certificate `cert`.
+    pub(crate) fn new(cert: Bytes, sk: SignatureKey) -> Self {
+        Self {
+            cert,
+            raw_public_key: false,
+            sk,
+        }
+    }
+
+    /// Create a new in-memory key for the DER encoded SubjectPublicKeyInfo
+    /// `spki` of a raw public key.
+    pub(crate) fn for_raw_public_key(spki: Bytes, sk: SignatureKey) -> Self {
+        Self {
+            cert: spki,
+            raw_public_key: true,
+            sk,
+        }
+    }
+
+    /// Read the signature scheme and the location of the key from the
+    /// certificate or the raw public key.
+    fn spki(&self) -> Result<Spki, TLSError> {
+        let spki = if self.raw_public_key {
+            verification_key_from_spki(&self.cert)?
+        } else {
+            verification_key_from_cert(&self.cert)?
+        };
+        Ok(spki)
+    }
+
+    /// The signature scheme of the key.
+    fn scheme(&self) -> Result<SignatureScheme, TLSError> {
+        let (scheme, _) = self.spki()?;
+        Ok(scheme)
+    }
+
+    /// Sign the `input` with the `crypto` provider.
+    fn sign(
+        &self,
+        input: &Bytes,
+        crypto: &impl CryptoProvider,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError> {
+        let (scheme, cert_key) = self.spki()?;
+        match scheme {
+            SignatureScheme::RsaPssRsaSha256 => {
+                let pk = rsa_public_key(&self.cert, cert_key)?;
+                crypto.sign_rsa(&self.sk, &pk.modulus, &pk.exponent, scheme, input, rng)
+            }
+            SignatureScheme::EcdsaSecp256r1Sha256 | SignatureScheme::MlDsa65 => {
+                crypto.sign(&scheme, &self.sk, input, rng)
+            }
+            SignatureScheme::ED25519 => tlserr(UNSUPPORTED_ALGORITHM),
+        }
+    }
+}
+
+impl fmt::Debug for InMemoryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the private key.
+        f.debug_struct("InMemoryKey").finish_non_exhaustive()
+    }
+}
+
+/// The key that signs the CertificateVerify message.
+#[derive(Debug, Clone)]
+pub(crate) enum SigningKey {
+    /// A raw private key, signed with the provider of the handshake.
+    InMemory(InMemoryKey),
+    /// A key that is only reachable through a [`Signer`].
+    External(Arc<dyn Signer>),
+}
+
+impl SigningKey {
+    /// The signature scheme of the key.
+    pub(crate) fn scheme(&self) -> Result<SignatureScheme, TLSError> {
+        match self {
+            SigningKey::InMemory(key) => key.scheme(),
+            SigningKey::External(signer) => signer.scheme(),
+        }
+    }
+
+    /// Sign the CertificateVerify `input`.
+    ///
+    /// In-memory keys are used with the `crypto` provider.
+    pub(crate) fn sign(
+        &self,
+        input: &Bytes,
+        crypto: &impl CryptoProvider,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError> {
+        match self {
+            SigningKey::InMemory(key) => key.sign(input, crypto, rng),
+            SigningKey::External(signer) => signer.sign(input, rng),
+        }
+    }
+}
+
+/// A [`Signer`] holding the raw private key in memory.
+///
+/// The signature scheme, and the RSA public key if needed, are read from the
+/// certificate, or from the SubjectPublicKeyInfo of a raw public key.
+///
+/// The raw key constructors of the [`ServerDB`](crate::server::ServerDB) and
+/// the client credentials don't need this, their keys are signed with the
+/// provider of the handshake. It is useful to serve a key with
+/// [`serve_unix_signer`], or to sign delegated credentials.
+#[derive(Clone)]
+pub struct InMemorySigner<Crypto = LibcruxProvider> {
+    key: InMemoryKey,
+    crypto: Crypto,
+}
+
+impl InMemorySigner {
+    /// Create a new in-memory signer for the certificate `cert` and the raw
+    /// private key `sk`.
+    pub fn new(cert: Bytes, sk: SignatureKey) -> Self {
+        Self::with_provider(LibcruxProvider, cert, sk)
+    }
+
+    /// Create a new in-memory signer for the DER encoded SubjectPublicKeyInfo
+    /// `spki` of a raw public key (RFC 7250) and the raw private key `sk`.
+    pub fn for_raw_public_key(spki: Bytes, sk: SignatureKey) -> Self {
+        Self {
+            key: InMemoryKey::for_raw_public_key(spki, sk),
+            crypto: LibcruxProvider,
+        }
+    }
+}
+
+impl<Crypto: CryptoProvider> InMemorySigner<Crypto> {
+    /// Create a new in-memory signer that signs with the `crypto` provider.
+    pub fn with_provider(crypto: Crypto, cert: Bytes, sk: SignatureKey) -> Self {
+        Self {
+            key: InMemoryKey::new(cert, sk),
+            crypto,
+        }
+    }
+}
+
+impl<Crypto> fmt::Debug for InMemorySigner<Crypto> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the private key.
+        f.debug_struct("InMemorySigner").finish_non_exhaustive()
+    }
+}
+
+impl<Crypto: CryptoProvider + Send + Sync> Signer for InMemorySigner<Crypto> {
+    fn scheme(&self) -> Result<SignatureScheme, TLSError> {
+        self.key.scheme()
+    }
+
+    fn sign(&self, input: &Bytes, mut rng: &mut dyn CryptoRngCore) -> Result<Bytes, TLSError> {
+        self.key.sign(input, &self.crypto, &mut rng)
+    }
+}
@@ -0,0 +1,155 @@
+//! A [`Signer`] that forwards requests to a signing service on a Unix socket.
+//!
+//! Every message, in both directions, is framed as
+//! ```ignore
+//! u8 tag;       // request: operation, response: 0 or a TLSError
+//! u32 length;   // big endian
+//! opaque payload[length];
+//! ```
+//! The `scheme` operation has an empty payload and returns the two byte TLS
+//! code point of the signature scheme.
+//! The `sign` operation carries the CertificateVerify input and returns the
+//! signature.
+//!
+//! A new connection is opened for every request. Reads and writes on it time
+//! out after [`UnixSocketSigner::DEFAULT_TIMEOUT`], unless a different timeout
+//! is set with [`UnixSocketSigner::with_timeout`].
+
+use std::{
+    io::{Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    tls13crypto::SignatureScheme,
+    tls13utils::{Bytes, TLSError, SIGNER_ERROR},
+};
+
+use super::{CryptoRngCore, Signer};
+
+const OP_SCHEME: u8 = 0;
+const OP_SIGN: u8 = 1;
+const STATUS_OK: u8 = 0;
+
+/// Upper bound for messages, to not allocate arbitrary amounts of memory.
+const MAX_MESSAGE_LEN: usize = 1 << 16;
+
+fn write_message(stream: &mut UnixStream, tag: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut message = Vec::with_capacity(5 + payload.len());
+    message.push(tag);
+    message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message)
+}
+
+fn read_message(stream: &mut UnixStream) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header)?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+/// A [`Signer`] backed by a signing service listening on a Unix socket.
+#[derive(Clone, Debug)]
+pub struct UnixSocketSigner {
+    path: PathBuf,
+    timeout: Duration,
+}
+
+impl UnixSocketSigner {
+    /// The read and write timeout of a request if none is set.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Create a signer for the service listening on `path`.
+    ///
+    /// The service is contacted on every request.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set the read and write `timeout` of a request.
+    ///
+    /// A request that times out fails with [`SIGNER_ERROR`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn request(&self, op: u8, payload: &[u8]) -> Result<Vec<u8>, TLSError> {
+        let mut stream = UnixStream::connect(&self.path).map_err(|_| SIGNER_ERROR)?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .map_err(|_| SIGNER_ERROR)?;
+        stream
+            .set_write_timeout(Some(self.timeout))
+            .map_err(|_| SIGNER_ERROR)?;
+        write_message(&mut stream, op, payload).map_err(|_| SIGNER_ERROR)?;
+        let (status, response) = read_message(&mut stream).map_err(|_| SIGNER_ERROR)?;
+        if status == STATUS_OK {
+            Ok(response)
+        } else {
+            Err(status)
+        }
+    }
+}
+
+impl Signer for UnixSocketSigner {
+    fn scheme(&self) -> Result<SignatureScheme, TLSError> {
+        let code_point = Bytes::from(self.request(OP_SCHEME, &[])?);
+        SignatureScheme::from_code_point(code_point.as_raw())
+    }
+
+    fn sign(&self, input: &Bytes, _rng: &mut dyn CryptoRngCore) -> Result<Bytes, TLSError> {
+        Ok(Bytes::from(self.request(OP_SIGN, &input.declassify())?))
+    }
+}
+
+/// Answer a single request from a [`UnixSocketSigner`].
+fn handle_request(
+    signer: &dyn Signer,
+    op: u8,
+    payload: Vec<u8>,
+    rng: &mut impl CryptoRngCore,
+) -> Result<Vec<u8>, TLSError> {
+    match op {
+        OP_SCHEME => Ok(signer.scheme()?.code_point()?.declassify()),
+        OP_SIGN => Ok(signer.sign(&Bytes::from(payload), rng)?.declassify()),
+        _ => Err(SIGNER_ERROR),
+    }
+}
+
+/// Run a signing service for [`UnixSocketSigner`]s on the `listener`, signing
+/// with the `signer`.
+///
+/// This serves one connection at a time and only returns on I/O errors when
+/// accepting connections.
+pub fn serve_unix_signer(
+    listener: &UnixListener,
+    signer: &dyn Signer,
+    rng: &mut impl CryptoRngCore,
+) -> std::io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        // Errors on a single connection only affect that client.
+        while let Ok((op, payload)) = read_message(&mut stream) {
+            let written = match handle_request(signer, op, payload, rng) {
+                Ok(response) => write_message(&mut stream, STATUS_OK, &response),
+                Err(e) => write_message(&mut stream, e, &[]),
+            };
+            if written.is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
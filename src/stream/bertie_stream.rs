@@ -3,13 +3,14 @@ use std::{
     io::{Read, Write},
 };
 
-use crate::{tls13crypto::*, tls13utils::*};
+use crate::{keys::KeyError, tls13crypto::*, tls13utils::*};
 
 #[derive(Debug)]
 pub enum BertieError {
     InvalidState,
     Io(std::io::Error),
     TLS(TLSError),
+    Key(KeyError),
 }
 
 impl From<std::io::Error> for BertieError {
@@ -24,6 +25,12 @@ impl From<TLSError> for BertieError {
     }
 }
 
+impl From<KeyError> for BertieError {
+    fn from(error: KeyError) -> Self {
+        Self::Key(error)
+    }
+}
+
 pub trait TlsStream<T: Write + Read> {
     /// Write `bytes` out to the connection, using the underlying TLS connection.
     fn write_tls(&mut self, bytes: &[u8]) -> Result<(), BertieError>;
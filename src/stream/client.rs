@@ -10,12 +10,13 @@ use std::{
 // use tracing::{event, Level};
 
 use super::bertie_stream::{read_record, BertieError, BertieStream, TlsStream};
-use crate::{tls13crypto::*, tls13utils::*, Client};
+use crate::{client::ClientConfig, tls13crypto::*, tls13utils::*, Client};
 
 pub struct ClientState<Stream: Read + Write> {
     stream: Stream,
     read_buffer: Vec<u8>,
     cstate: Option<Client>,
+    grease: bool,
 }
 
 impl<Stream: Read + Write> ClientState<Stream> {
@@ -25,6 +26,7 @@ impl<Stream: Read + Write> ClientState<Stream> {
             stream,
             read_buffer: vec![],
             cstate: None,
+            grease: false,
         }
     }
 }
@@ -82,6 +84,13 @@ impl<Stream: Read + Write> BertieStream<ClientState<Stream>> {
             host: host.to_string(),
         })
     }
+
+    /// Send GREASE values (RFC 8701) in the ClientHello.
+    ///
+    /// This must be called before [`start`](Self::start).
+    pub fn enable_grease(&mut self) {
+        self.state.grease = true;
+    }
 }
 
 impl BertieStream<ClientState<TcpStream>> {
@@ -124,8 +133,12 @@ impl BertieStream<ClientState<TcpStream>> {
 
         // Client Hello
         let (client_hello, cstate) = {
-            let sni = self.host.as_bytes();
-            Client::connect(self.ciphersuite, &Bytes::from(sni), None, None, rng)?
+            let sni = Bytes::from(self.host.as_bytes());
+            let mut config = ClientConfig::new(self.ciphersuite, &sni);
+            if self.state.grease {
+                config = config.with_grease();
+            }
+            Client::connect_with_config(config, rng)?
         };
         // event!(Level::TRACE, "client hello: {}", client_hello.as_hex());
         // event!(Level::DEBUG, "  {ciphersuite:?}");
@@ -133,36 +146,65 @@ impl BertieStream<ClientState<TcpStream>> {
 
         let mut read_buffer = Vec::new();
 
-        // Server Hello
-        let server_hello = read_record(&mut read_buffer, &mut self.state.stream)?;
+        let mut cstate = cstate;
+        let cstate = loop {
+            // Server Hello
+            let server_hello = read_record(&mut read_buffer, &mut self.state.stream)?;
+
+            // Check for alerts
+            if server_hello[0] == 21 {
+                eprintln!(
+                    "Server does not support proposed algorithms. {:?}",
+                    self.ciphersuite
+                );
+                return Err(UNSUPPORTED_ALGORITHM.into());
+            }
 
-        // Check for alerts
-        if server_hello[0] == 21 {
-            eprintln!(
-                "Server does not support proposed algorithms. {:?}",
-                self.ciphersuite
-            );
-            return Err(UNSUPPORTED_ALGORITHM.into());
-        }
+            // A change cipher spec may follow a HelloRetryRequest.
+            if server_hello[0] == 20 {
+                continue;
+            }
 
-        // Read server hello
-        let cstate = match cstate.read_handshake(&Bytes::from(server_hello)) {
-            Ok((_, cstate)) => cstate,
-            Err(e) => {
-                println!(" >>> ERROR {e}");
-                match e {
-                    UNSUPPORTED_ALGORITHM => {
-                        eprintln!("Server does not support proposed algorithms.")
-                    }
-                    PROTOCOL_VERSION_ALERT => eprintln!("Wrong TLS protocol version TLS({:?})", e),
-                    APPLICATION_DATA_INSTEAD_OF_HANDSHAKE => {
-                        eprintln!("Server sent application data instead of a handshake message.")
+            // Read server hello
+            match cstate.read_handshake(&Bytes::from(server_hello)) {
+                // The server sent a HelloRetryRequest, send the second client
+                // hello and read the server hello again.
+                Ok((Some(client_hello), new_cstate)) => {
+                    self.write_all(&client_hello.declassify())?;
+                    cstate = new_cstate;
+                }
+                Ok((None, cstate)) => break cstate,
+                Err(e) => {
+                    println!(" >>> ERROR {e}");
+                    match e {
+                        UNSUPPORTED_ALGORITHM => {
+                            eprintln!("Server does not support proposed algorithms.")
+                        }
+                        PROTOCOL_VERSION_ALERT => {
+                            eprintln!("Wrong TLS protocol version TLS({:?})", e);
+                            self.write_all(&[21, 03, 03, 00, 02, 2, 70])?;
+                        }
+                        ILLEGAL_PARAMETER => {
+                            eprintln!(
+                                "The server signaled a downgrade or sent an invalid retry request."
+                            );
+                            self.write_all(&[21, 03, 03, 00, 02, 2, 47])?;
+                        }
+                        UNEXPECTED_MESSAGE => {
+                            eprintln!("The server sent a second HelloRetryRequest.");
+                            self.write_all(&[21, 03, 03, 00, 02, 2, 10])?;
+                        }
+                        APPLICATION_DATA_INSTEAD_OF_HANDSHAKE => {
+                            eprintln!(
+                                "Server sent application data instead of a handshake message."
+                            )
+                        }
+                        MISSING_KEY_SHARE => eprintln!("Hello message was missing a key share."),
+                        DECODE_ERROR => eprintln!("Decode error."), // parsing of the server hello failed
+                        _ => eprintln!("Bertie client error {}", e),
                     }
-                    MISSING_KEY_SHARE => eprintln!("Hello message was missing a key share."),
-                    DECODE_ERROR => eprintln!("Decode error."), // parsing of the server hello failed
-                    _ => eprintln!("Bertie client error {}", e),
+                    return Err(e.into());
                 }
-                return Err(e.into());
             }
         };
 
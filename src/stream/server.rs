@@ -7,12 +7,12 @@ use std::{
 use rand::{CryptoRng, RngCore};
 
 use crate::{
+    keys::{check_key_matches_certificate, load_certificates, load_private_key},
     server::ServerDB,
-    tls13cert::{rsa_private_key, verification_key_from_cert},
-    tls13crypto::{Algorithms, SignatureKey, SignatureScheme},
+    tls13crypto::{Algorithms, LibcruxProvider},
     tls13utils::{
         AppData, Bytes, INVALID_COMPRESSION_LIST, MISSING_KEY_SHARE, PARSE_FAILED,
-        PROTOCOL_VERSION_ALERT,
+        PROTOCOL_VERSION_ALERT, UNRECOGNIZED_NAME,
     },
     Server,
 };
@@ -99,6 +99,31 @@ impl<Stream: Read + Write> BertieStream<ServerState<Stream>> {
             host: host.to_string(),
         })
     }
+
+    /// Staple the DER encoded OCSP response in the `ocsp_file` to the server
+    /// certificate.
+    ///
+    /// It is sent to clients that ask for it in a `status_request`.
+    pub fn staple_ocsp_response(&mut self, ocsp_file: &str) -> Result<(), BertieError> {
+        let response = Bytes::from(read_file(ocsp_file)?);
+        for entry in self.state.db.entries.iter_mut() {
+            entry.ocsp_response = Some(response.clone());
+        }
+        Ok(())
+    }
+
+    /// Staple the serialized `SignedCertificateTimestampList` in the
+    /// `sct_file` to the server certificate.
+    ///
+    /// It is sent to clients that ask for it in a `signed_certificate_timestamp`
+    /// extension.
+    pub fn staple_sct_list(&mut self, sct_file: &str) -> Result<(), BertieError> {
+        let sct_list = Bytes::from(read_file(sct_file)?);
+        for entry in self.state.db.entries.iter_mut() {
+            entry.sct_list = Some(sct_list.clone());
+        }
+        Ok(())
+    }
 }
 
 impl BertieStream<ServerState<TcpStream>> {
@@ -180,12 +205,37 @@ impl BertieStream<ServerState<TcpStream>> {
     /// Connect the incoming TLS stream.
     /// This function blocks until it was able to read the TLS client hello.
     pub fn connect(&mut self, rng: &mut (impl RngCore + CryptoRng)) -> Result<(), BertieError> {
-        let client_hello = read_record(&mut self.state.read_buffer, &mut self.state.stream)?;
+        let mut client_hello = read_record(&mut self.state.read_buffer, &mut self.state.stream)?;
+        // Cookies are bound to the address of the client.
+        let client_address = self
+            .state
+            .stream
+            .peer_addr()
+            .ok()
+            .map(|address| Bytes::from(address.to_string().as_bytes()));
+
+        // Ask the client to retry if needed. The server keeps no state until
+        // the second client hello.
+        if let Some(hrr) = Server::hello_retry_request(
+            self.ciphersuite,
+            &self.state.db,
+            &client_hello.as_slice().into(),
+            client_address.clone(),
+        )? {
+            self.write_all(&hrr.declassify())?;
+            client_hello = read_record(&mut self.state.read_buffer, &mut self.state.stream)?;
+            // Clients in middlebox compatibility mode send a change cipher
+            // spec first.
+            if self.check_ccs_message(&client_hello).is_ok() {
+                client_hello = read_record(&mut self.state.read_buffer, &mut self.state.stream)?;
+            }
+        }
 
         match Server::accept(
             self.ciphersuite,
             self.state.db.clone(),
             &client_hello.into(),
+            client_address,
             rng,
         ) {
             Err(x) => {
@@ -196,6 +246,9 @@ impl BertieStream<ServerState<TcpStream>> {
                     PROTOCOL_VERSION_ALERT => {
                         self.write_all(&[21, 03, 03, 00, 02, 2, 70])?;
                     }
+                    UNRECOGNIZED_NAME => {
+                        self.write_all(&[21, 03, 03, 00, 02, 2, 112])?;
+                    }
                     MISSING_KEY_SHARE => {
                         // alerts here are optional
                         eprintln!("Hello message was missing a key share.");
@@ -272,45 +325,19 @@ impl BertieStream<ServerState<TcpStream>> {
 }
 
 /// Set up the server database.
+///
+/// The key and certificate files may be DER or PEM encoded, see [`crate::keys`].
+/// A PEM `cert_file` may hold the certificate chain, starting with the
+/// certificate that belongs to the key.
 pub fn init_db(host: &str, key_file: &str, cert_file: &str) -> Result<ServerDB, BertieError> {
-    let sni = host.as_bytes();
-    let signature_key = read_file(key_file)?;
-    let cert = read_file(cert_file)?.into();
-
-    let spki = verification_key_from_cert(&cert)?;
-    let raw_key = match spki.0 {
-        SignatureScheme::EcdsaSecp256r1Sha256 => {
-            let mut raw_key = vec![];
-            for (i, &byte) in signature_key.iter().enumerate() {
-                if i == 0 && byte != 0x30
-                // We don't handle larger sizes that are differently encoded.
-                {
-                    return Err(BertieError::TLS(255)); // TODO: error code
-                }
+    let key = load_private_key(&read_file(key_file)?)?;
+    let cert_chain = load_certificates(&read_file(cert_file)?)?;
+    check_key_matches_certificate(&key, &cert_chain[0], &LibcruxProvider)?;
 
-                // We only look for the first octet string.
-                if byte == 0x04 {
-                    let len = signature_key[i + 1];
-                    raw_key = signature_key[i + 2..i + 2 + usize::from(len)].to_vec();
-                    break;
-                }
-            }
-            raw_key
-        }
-        SignatureScheme::RsaPssRsaSha256 => {
-            // Read the private exponent (d) from the key.
-            rsa_private_key(&Bytes::from(signature_key))?.declassify()
-        }
-        _ => unreachable!("Unsupported signature scheme {:?}", spki.0),
-    };
-    if raw_key.is_empty() {
-        // We weren't able to read the key.
-        return Err(BertieError::TLS(255)); // TODO: error code
-    }
-    Ok(ServerDB::new(
-        sni.into(),
-        cert,
-        SignatureKey::from(raw_key),
+    Ok(ServerDB::with_chain(
+        host.as_bytes().into(),
+        cert_chain,
+        key.signature_key(),
         None,
     ))
 }
@@ -4,7 +4,8 @@ use crate::tls13handshake::*;
 use crate::tls13utils::*;
 use crate::{
     tls13crypto::{
-        hmac_tag, AeadAlgorithm, Algorithms, HashAlgorithm, KemScheme, Random, SignatureScheme,
+        hmac_tag, AeadAlgorithm, Algorithms, HashAlgorithm, KemScheme, LibcruxProvider,
+        PskKeyExchangeMode, Random, SignatureScheme,
     },
     tls13formats::{handshake_data::HandshakeData, *},
 };
@@ -195,6 +196,7 @@ const TLS_AES_128_GCM_SHA256_X25519_RSA: Algorithms = Algorithms {
     kem: KemScheme::X25519,
     psk_mode: false,
     zero_rtt: false,
+    psk_key_exchange_mode: PskKeyExchangeMode::PskDheKe,
 };
 
 const TLS_AES_128_GCM_SHA256_X25519: Algorithms = Algorithms {
@@ -204,6 +206,7 @@ const TLS_AES_128_GCM_SHA256_X25519: Algorithms = Algorithms {
     kem: KemScheme::X25519,
     psk_mode: false,
     zero_rtt: false,
+    psk_key_exchange_mode: PskKeyExchangeMode::PskDheKe,
 };
 const TLS_CHACHA20_POLY1305_SHA256_X25519: Algorithms = Algorithms {
     hash: HashAlgorithm::SHA256,
@@ -212,6 +215,7 @@ const TLS_CHACHA20_POLY1305_SHA256_X25519: Algorithms = Algorithms {
     kem: KemScheme::X25519,
     psk_mode: false,
     zero_rtt: false,
+    psk_key_exchange_mode: PskKeyExchangeMode::PskDheKe,
 };
 
 const ECDSA_P256_SHA256_CERT: [u8; 522] = [
@@ -264,18 +268,45 @@ fn test_parse_client_hello() {
         Err(x) => {
             println!("Error: {}", x);
         }
-        Ok((cr, sid, sn, gx, tkto, bo, l)) => {
+        Ok(parsed) => {
             println!("Parsed CH!");
-            println!("cr: {}", cr.as_hex());
-            println!("sid: {}", sid.as_hex());
-            println!("sn: {}", sn.as_hex());
-            println!("gx: {}", gx.as_hex());
-            println!("trunc_len: {}", l);
+            println!("cr: {}", parsed.client_randomness.as_hex());
+            println!("sid: {}", parsed.session_id.as_hex());
+            println!("sn: {}", parsed.server_name.as_hex());
+            println!("gx: {}", parsed.gx.as_hex());
+            println!("trunc_len: {}", parsed.trunc_len);
         }
     }
     assert!(b);
 }
 
+#[test]
+fn test_parse_client_hello_info() {
+    let ch = handshake_data::HandshakeData::from(Bytes::from_hex(client_hello));
+    let info = parse_client_hello_info(&ch).unwrap();
+    assert_eq!(info.legacy_session_id.len(), 0);
+    assert_eq!(info.server_name, Some(Bytes::from(b"server")));
+    assert_eq!(info.cipher_suites, vec![0x1301, 0x1303, 0x1302]);
+    assert_eq!(
+        info.supported_groups,
+        vec![0x001d, 0x0017, 0x0018, 0x0019, 0x0100, 0x0101, 0x0102, 0x0103, 0x0104]
+    );
+    assert_eq!(info.key_share_groups, vec![0x001d]);
+    assert_eq!(info.signature_algorithms.len(), 15);
+    assert_eq!(info.signature_algorithms[0], 0x0403);
+    assert!(info.alpn_protocols.is_empty());
+    assert!(info.psk_identities.is_empty());
+    assert_eq!(info.psk_key_exchange_modes, vec![1]);
+    assert!(!info.status_request);
+    assert!(!info.signed_certificate_timestamp);
+    assert!(info.certificate_compression_algorithms.is_empty());
+    assert!(info.server_certificate_types.is_empty());
+    assert_eq!(
+        info.extensions,
+        vec![0x0000, 0xff01, 0x000a, 0x0023, 0x0033, 0x002b, 0x000d, 0x002d, 0x001c]
+    );
+}
+
 #[test]
 fn test_parse_client_hello_record() {
     let mut ch: Bytes = Bytes::from_hex(client_hello_record);
@@ -292,13 +323,13 @@ fn test_parse_client_hello_record() {
                 println!("Error: {}", x);
                 b = false;
             }
-            Ok((cr, sid, sn, gx, tkto, bo, l)) => {
+            Ok(parsed) => {
                 println!("Parsed CH!");
-                println!("cr: {}", cr.as_hex());
-                println!("sid: {}", sid.as_hex());
-                println!("sn: {}", sn.as_hex());
-                println!("gx: {}", gx.as_hex());
-                println!("trunc_len: {}", l);
+                println!("cr: {}", parsed.client_randomness.as_hex());
+                println!("sid: {}", parsed.session_id.as_hex());
+                println!("sn: {}", parsed.server_name.as_hex());
+                println!("gx: {}", parsed.gx.as_hex());
+                println!("trunc_len: {}", parsed.trunc_len);
             }
         },
     }
@@ -310,8 +341,20 @@ fn test_parse_client_hello_roundtrip() {
     let cr = Random::new();
     let gx = Bytes::from_hex(client_x25519_pub);
     let sn = Bytes::zeroes(23);
-    let ch =
-        crate::tls13formats::client_hello(&TLS_AES_128_GCM_SHA256_X25519_RSA, cr, &gx, &sn, &None);
+    let ch = crate::tls13formats::client_hello(
+        &TLS_AES_128_GCM_SHA256_X25519_RSA,
+        cr,
+        &gx,
+        &sn,
+        &[],
+        false,
+        false,
+        &[],
+        &None,
+        &Bytes::new(),
+        &None,
+        None,
+    );
     let mut b = true;
     match ch {
         Err(x) => {
@@ -326,13 +369,13 @@ fn test_parse_client_hello_roundtrip() {
                 Err(x) => {
                     println!("Error: {}", x);
                 }
-                Ok((cr, sid, sn, gx, tkto, bo, l)) => {
+                Ok(parsed) => {
                     println!("Parsed CH!");
-                    println!("cr: {}", cr.as_hex());
-                    println!("sid: {}", sid.as_hex());
-                    println!("sn: {}", sn.as_hex());
-                    println!("gx: {}", gx.as_hex());
-                    println!("trunc_len: {}", l);
+                    println!("cr: {}", parsed.client_randomness.as_hex());
+                    println!("sid: {}", parsed.session_id.as_hex());
+                    println!("sn: {}", parsed.server_name.as_hex());
+                    println!("gx: {}", parsed.gx.as_hex());
+                    println!("trunc_len: {}", parsed.trunc_len);
                 }
             }
         }
@@ -350,7 +393,7 @@ fn test_parse_server_hello() {
         Err(x) => {
             println!("Error: {}", x);
         }
-        Ok((sr, gy)) => {
+        Ok((sr, gy, _)) => {
             println!("Parsed SH!");
             println!("sr: {}", sr.as_hex());
             println!("gy: {}", gy.as_hex());
@@ -372,7 +415,8 @@ fn test_parse_server_hello_roundtrip() {
     let mut sid = Bytes::zeroes(24);
     sid[0] = U8(255);
     let gy = Bytes::from_hex(server_x25519_pub);
-    let sh = crate::tls13formats::server_hello(&TLS_AES_128_GCM_SHA256_X25519_RSA, sr, &sid, &gy);
+    let sh =
+        crate::tls13formats::server_hello(&TLS_AES_128_GCM_SHA256_X25519_RSA, sr, &sid, &gy, 0);
     let mut b = true;
     match sh {
         Err(x) => {
@@ -387,7 +431,7 @@ fn test_parse_server_hello_roundtrip() {
                 Err(x) => {
                     println!("Error: {}", x);
                 }
-                Ok((sr, gy)) => {
+                Ok((sr, gy, _)) => {
                     println!("Parsed SH!");
                     println!("sr: {}", sr.as_hex());
                     println!("gy: {}", gy.as_hex());
@@ -398,16 +442,80 @@ fn test_parse_server_hello_roundtrip() {
     assert!(b);
 }
 
+// A TLS 1.2 ServerHello from a server that supports TLS 1.3, with the
+// downgrade sentinel.
+const tls12_server_hello: &str = "02 00 00 4d 03 03 95 02 d1 4a f1 59 46 c2 ef 8e f5 4a fd 98 3d cf
+ba 82 5c f6 d7 a1 95 98 44 4f 57 4e 47 52 44 01 20 34 b3 64 54 ca
+b2 e7 84 2c 38 9f 7d 88 ec b7 df 27 9e 39 18 cb ac 07 97 0d 4c de
+49 6e 70 f4 c8 c0 2f 00 00 05 ff 01 00 01 00";
+
+#[test]
+fn test_parse_server_hello_downgrade() {
+    const DOWNGRD: [u8; 7] = [0x44, 0x4f, 0x57, 0x4e, 0x47, 0x52, 0x44];
+    let algs = TLS_AES_128_GCM_SHA256_X25519_RSA;
+    let sh = HandshakeData::from(Bytes::from_hex(tls12_server_hello));
+    assert_eq!(
+        parse_server_hello(&algs, &sh).err(),
+        Some(ILLEGAL_PARAMETER)
+    );
+
+    // The sentinels in an otherwise valid TLS 1.3 ServerHello.
+    for last in [0, 1] {
+        let mut sh = Bytes::from_hex(server_hello);
+        for (i, b) in DOWNGRD.iter().chain([last].iter()).enumerate() {
+            sh[30 + i] = U8(*b);
+        }
+        let sh = HandshakeData::from(sh);
+        assert_eq!(
+            parse_server_hello(&algs, &sh).err(),
+            Some(ILLEGAL_PARAMETER)
+        );
+    }
+
+    // Anything else in the random is fine.
+    let mut sh = Bytes::from_hex(server_hello);
+    for (i, b) in DOWNGRD.iter().chain([2].iter()).enumerate() {
+        sh[30 + i] = U8(*b);
+    }
+    assert!(parse_server_hello(&algs, &HandshakeData::from(sh)).is_ok());
+}
+
+#[test]
+fn test_parse_server_hello_version() {
+    let algs = TLS_AES_128_GCM_SHA256_X25519_RSA;
+
+    // The server selects TLS 1.2 in supported_versions.
+    let mut sh = Bytes::from_hex(server_hello);
+    let len = sh.len();
+    sh[len - 1] = U8(3);
+    let sh = HandshakeData::from(sh);
+    assert_eq!(
+        parse_server_hello(&algs, &sh).err(),
+        Some(PROTOCOL_VERSION_ALERT)
+    );
+
+    // The server doesn't send supported_versions.
+    let sh = Bytes::from_hex(server_hello);
+    let mut sh = sh.slice_range(0..sh.len() - 6);
+    sh[3] = U8(0x50);
+    sh[43] = U8(0x28);
+    let sh = HandshakeData::from(sh);
+    assert_eq!(
+        parse_server_hello(&algs, &sh).err(),
+        Some(PROTOCOL_VERSION_ALERT)
+    );
+}
+
 #[test]
 fn test_parse_encrypted_extensions() {
     let ee = HandshakeData::from(Bytes::from_hex(encrypted_extensions));
     let res = parse_encrypted_extensions(&TLS_AES_128_GCM_SHA256_X25519_RSA, &ee);
-    let b = res.is_ok();
+    let b = res == Ok((None, None));
     match res {
         Err(x) => {
             println!("Error: {}", x);
         }
-        Ok(()) => {
+        Ok(_) => {
             println!("Parsed EE!");
         }
     }
@@ -505,6 +613,7 @@ fn test_key_schedule() {
         kem: gn,
         psk_mode,
         zero_rtt,
+        ..
     } = TLS_AES_128_GCM_SHA256_X25519_RSA;
     let transcript = client_hello_bytes.concat(server_hello_bytes);
     let tx_hash = ha.hash(&transcript);
@@ -514,13 +623,20 @@ fn test_key_schedule() {
             println!("Error: {}", x);
         }
         Ok(tx_hash) => {
-            let keys = derive_hk_ms(&ha, &ae, &shared_secret_bytes, &None, &tx_hash);
+            let keys = derive_hk_ms(
+                &ha,
+                &ae,
+                &shared_secret_bytes,
+                &None,
+                &tx_hash,
+                &LibcruxProvider,
+            );
             b = keys.is_ok();
             match keys {
                 Err(x) => {
                     println!("Error: {}", x);
                 }
-                Ok((aead_key_iv1, aead_key_iv2, cfk, sfk, ms)) => {
+                Ok((aead_key_iv1, aead_key_iv2, cfk, sfk, ms, _)) => {
                     println!("Derive Succeeded!");
                     println!(
                         "chk: key {} \n iv {}",
@@ -546,13 +662,13 @@ fn test_key_schedule() {
                             println!("Error: {}", x);
                         }
                         Ok(tx_hash) => {
-                            let keys = derive_app_keys(&ha, &ae, &ms, &tx_hash);
+                            let keys = derive_app_keys(&ha, &ae, &ms, &tx_hash, &LibcruxProvider);
                             b = keys.is_ok();
                             match keys {
                                 Err(x) => {
                                     println!("Error: {}", x);
                                 }
-                                Ok((aead_key_iv1, aead_key_iv2, ms)) => {
+                                Ok((aead_key_iv1, aead_key_iv2, ms, _)) => {
                                     println!("Derive Succeeded!");
                                     println!(
                                         "cak: key {} \n iv {}",
@@ -628,6 +744,7 @@ fn test_finished() {
         kem: gn,
         psk_mode,
         zero_rtt,
+        ..
     } = TLS_AES_128_GCM_SHA256_X25519_RSA;
     let tx1 = ch.concat(sh).concat(ee).concat(sc).concat(cv);
     let tx_hash1 = ha.hash(&tx1);
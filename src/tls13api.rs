@@ -10,7 +10,13 @@
 use rand::{CryptoRng, RngCore};
 
 use crate::{
+    client::ClientConfig,
+    ct::{parse_sct_list, verify_scts, CtPolicy, SignedCertificateTimestamp},
+    delegated_credential::parse_delegated_credential,
+    ech::EchStatus,
+    psk::PreSharedKey,
     server::ServerDB,
+    tls13cert::{check_delegated_credential_validity, verify_ocsp_response},
     tls13crypto::*,
     tls13formats::{handshake_data::HandshakeType, *},
     tls13handshake::*,
@@ -19,9 +25,19 @@ use crate::{
 };
 
 /// The TLS Client state.
-pub enum Client {
+///
+/// The client uses the [`CryptoProvider`] `Crypto` for all cryptographic
+/// operations. It defaults to the [`LibcruxProvider`].
+/// Until the handshake is complete, the client keeps the clock of its
+/// [`ClientConfig`].
+pub enum Client<Crypto = LibcruxProvider> {
     /// The initial client handshake state.
-    Client0(ClientPostClientHello, Option<ClientCipherState0>),
+    Client0(
+        ClientPostClientHello,
+        Option<ClientCipherState0>,
+        fn() -> u64,
+        Crypto,
+    ),
 
     /// The client handshake state after receiving the server hello message.
     ClientH(
@@ -29,23 +45,30 @@ pub enum Client {
         Option<ClientCipherState0>,
         DuplexCipherStateH,
         handshake_data::HandshakeData,
+        fn() -> u64,
+        Crypto,
     ),
 
     /// The client handshake state after finishing the handshake.
-    Client1(ClientPostClientFinished, DuplexCipherState1),
+    Client1(ClientPostClientFinished, DuplexCipherState1, Crypto),
 }
 
 /// Check if the client is using a PSK mode or not.
 ///
 /// Returns `true` if the client is in PSK mode and `false` otherwise.
-pub fn in_psk_mode(c: &Client) -> bool {
+pub fn in_psk_mode<Crypto>(c: &Client<Crypto>) -> bool {
     match c {
-        Client::Client0(cstate, _) => algs_post_client_hello(cstate).psk_mode(),
-        Client::ClientH(cstate, _, _, _) => algs_post_server_hello(cstate).psk_mode(),
-        Client::Client1(cstate, _) => algs_post_client_finished(cstate).psk_mode(),
+        Client::Client0(cstate, _, _, _) => algs_post_client_hello(cstate).psk_mode(),
+        Client::ClientH(cstate, _, _, _, _, _) => algs_post_server_hello(cstate).psk_mode(),
+        Client::Client1(cstate, _, _) => algs_post_client_finished(cstate).psk_mode(),
     }
 }
 
+/// Check if the server rejected ECH.
+fn ech_rejected(status: &EchStatus) -> bool {
+    matches!(status, EchStatus::Rejected { .. })
+}
+
 impl Client {
     /// Start a TLS handshake as client.
     /// Note that Bertie clients only support a single ciphersuite at a time and
@@ -58,6 +81,10 @@ impl Client {
     /// * `psk` for an optional pre-shared-key
     /// * `entropy` for the randomness required in the handshake
     ///
+    /// The `session_ticket` and the `psk` form a resumption PSK, see
+    /// [`ClientConfig::with_psk`]. For other options, see
+    /// [`Client::connect_with_config`].
+    ///
     /// The function returns a [`Result`].
     /// When successful, the function returns a tuple with the first element the
     /// client hello record as bytes, and the [`Client`] state as the second element.
@@ -69,13 +96,56 @@ impl Client {
         psk: Option<Key>,
         rng: &mut (impl CryptoRng + RngCore),
     ) -> Result<(Bytes, Self), TLSError> {
-        let (client_hello, cipherstate0, client_state) =
-            client_init(ciphersuite, server_name, session_ticket, psk, rng)?;
+        let psks = match (session_ticket, psk) {
+            (Some(ticket), Some(key)) => Ok(vec![PreSharedKey::resumption(
+                ticket,
+                key,
+                ciphersuite.hash(),
+            )]),
+            (None, None) => Ok(Vec::new()),
+            _ => Err(PSK_MODE_MISMATCH),
+        }?;
+        Self::connect_with_config(
+            ClientConfig {
+                psks,
+                ..ClientConfig::new(ciphersuite, server_name)
+            },
+            rng,
+        )
+    }
+}
+
+impl<Crypto: CryptoProvider> Client<Crypto> {
+    /// Start a TLS handshake as client with the `config`.
+    ///
+    /// The `rng` provides the randomness required in the handshake.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns a tuple with the first element the
+    /// client hello record as bytes, and the [`Client`] state as the second element.
+    /// If an error occurs, it returns a [`TLSError`].
+    pub fn connect_with_config(
+        config: ClientConfig<Crypto>,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Self), TLSError> {
+        let (client_hello, cipherstate0, _early_secret, client_state) = client_init(
+            config.ciphersuite,
+            &config.server_name,
+            config.psks,
+            config.server_key,
+            config.ech,
+            config.grease,
+            config.credentials,
+            config.certificate_authorities,
+            None,
+            &config.crypto,
+            rng,
+        )?;
         let mut client_hello_record = handshake_record(client_hello)?;
         client_hello_record[2] = U8(0x01);
         Ok((
             client_hello_record,
-            Client::Client0(client_state, cipherstate0),
+            Client::Client0(client_state, cipherstate0, config.clock, config.crypto),
         ))
     }
 
@@ -92,37 +162,81 @@ impl Client {
     /// the second element.
     /// If there's no handshake message, the first element is [`None`].
     /// If an error occurs, it returns a [`TLSError`].
+    ///
+    /// If the server rejected ECH, the server certificate must be for the
+    /// public name of the ECH config, or the function fails with a
+    /// [`BAD_CERTIFICATE`] error. Instead of its Finished message, the client
+    /// then returns an encrypted `ech_required` alert, to be sent to the
+    /// server before closing the connection. The connection can't be used,
+    /// see [`Client::ech_status`].
+    ///
+    /// If the server signed with a delegated credential that isn't valid at
+    /// the time of the clock in the [`ClientConfig`], the function fails with
+    /// a [`BAD_DELEGATED_CREDENTIAL`] error.
+    ///
+    /// If the server sent a HelloRetryRequest, the first element is the second
+    /// client hello record, which must be sent to the server, and the client
+    /// waits for the server hello again.
     pub fn read_handshake(
         self,
         handshake_bytes: &Bytes,
     ) -> Result<(Option<Bytes>, Self), TLSError> {
         match self {
-            Client::Client0(state, cipher_state) => {
+            Client::Client0(state, cipher_state, clock, crypto) => {
                 let sf = get_handshake_record(handshake_bytes)?;
-                let (cipher1, cstate) = client_set_params(&sf, state)?;
-                let buf = handshake_data::HandshakeData::from(Bytes::new());
-                Ok((None, Client::ClientH(cstate, cipher_state, cipher1, buf)))
+                if is_hello_retry_request(&sf) {
+                    let (client_hello, state) = client_retry(&sf, state, &crypto)?;
+                    let client_hello_record = handshake_record(client_hello)?;
+                    // There's no early data after a HelloRetryRequest.
+                    Ok((
+                        Some(client_hello_record),
+                        Client::Client0(state, None, clock, crypto),
+                    ))
+                } else {
+                    let (cipher1, _handshake_secrets, cstate) =
+                        client_set_params(&sf, state, &crypto)?;
+                    let buf = handshake_data::HandshakeData::from(Bytes::new());
+                    Ok((
+                        None,
+                        Client::ClientH(cstate, cipher_state, cipher1, buf, clock, crypto),
+                    ))
+                }
             }
-            Client::ClientH(cstate, cipher0, cipher_hs, buf) => {
-                let (hd, cipher_hs) = decrypt_handshake(handshake_bytes, cipher_hs)?;
+            Client::ClientH(cstate, cipher0, cipher_hs, buf, clock, crypto) => {
+                let (hd, cipher_hs) = decrypt_handshake(handshake_bytes, cipher_hs, &crypto)?;
                 let buf = buf.concat(&hd);
                 if buf.find_handshake_message(HandshakeType::Finished, 0) {
-                    let (cfin, cipher1, cstate) = client_finish(&buf, cstate)?;
-                    let (cf_rec, _cipher_hs) = encrypt_handshake(cfin, 0, cipher_hs)?;
-                    Ok((Some(cf_rec), Client::Client1(cstate, cipher1)))
+                    let (cfin, cipher1, _application_secrets, cstate) =
+                        client_finish(&buf, cstate, clock(), &crypto)?;
+                    if ech_rejected(&ech_status_post_client_finished(&cstate)) {
+                        let (alert_rec, _cipher_hs) =
+                            encrypt_alert(AlertDescription::EchRequired, cipher_hs, &crypto)?;
+                        Ok((Some(alert_rec), Client::Client1(cstate, cipher1, crypto)))
+                    } else {
+                        let (cf_rec, _cipher_hs) = encrypt_handshake(cfin, 0, cipher_hs, &crypto)?;
+                        Ok((Some(cf_rec), Client::Client1(cstate, cipher1, crypto)))
+                    }
                 } else {
-                    Ok((None, Client::ClientH(cstate, cipher0, cipher_hs, buf)))
+                    Ok((
+                        None,
+                        Client::ClientH(cstate, cipher0, cipher_hs, buf, clock, crypto),
+                    ))
                 }
             }
             _ => Err(INCORRECT_STATE),
         }
     }
 
-    /// Read application data and session tickets.
+    /// Read application data, session tickets and certificate requests.
     ///
     /// This function can be used when the TLS handshake is complete, to read
     /// application data and session tickets from the server.
     ///
+    /// A CertificateRequest of the server is kept until it is answered with
+    /// [`Client::certificate_response`]. It fails with an
+    /// [`UNEXPECTED_MESSAGE`] error if the client didn't offer post-handshake
+    /// authentication.
+    ///
     /// It takes the current state and `message_bytes` and returns
     /// the next state or a [`TLSError`].
     ///
@@ -133,15 +247,29 @@ impl Client {
     /// If an error occurs, it returns a [`TLSError`].
     pub fn read(self, message_bytes: &Bytes) -> Result<(Option<AppData>, Self), TLSError> {
         match self {
-            Client::Client1(state, cipher1) => {
-                let (ty, hd, cipher1) = decrypt_data_or_hs(message_bytes, cipher1)?;
+            Client::Client1(state, _, _)
+                if ech_rejected(&ech_status_post_client_finished(&state)) =>
+            {
+                Err(ECH_REQUIRED)
+            }
+            Client::Client1(state, cipher1, crypto) => {
+                let (ty, hd, cipher1) = decrypt_data_or_hs(message_bytes, cipher1, &crypto)?;
                 match ty {
-                    ContentType::ApplicationData => {
-                        Ok((Some(AppData::new(hd)), Client::Client1(state, cipher1)))
+                    ContentType::ApplicationData => Ok((
+                        Some(AppData::new(hd)),
+                        Client::Client1(state, cipher1, crypto),
+                    )),
+                    ContentType::Handshake
+                        if hd.len() >= 4
+                            && hd[0].declassify() == HandshakeType::CertificateRequest as u8 =>
+                    {
+                        let certificate_request = handshake_data::HandshakeData::from(hd);
+                        let state = client_certificate_request(&certificate_request, state)?;
+                        Ok((None, Client::Client1(state, cipher1, crypto)))
                     }
                     ContentType::Handshake => {
                         eprintln!("Received Session Ticket");
-                        Ok((None, Client::Client1(state, cipher1)))
+                        Ok((None, Client::Client1(state, cipher1, crypto)))
                     }
                     _ => Err(PARSE_FAILED),
                 }
@@ -156,30 +284,253 @@ impl Client {
     /// When successful, the function returns a tuple with the first element the
     /// encrypted `application_data` as bytes, and the new [`Client`] state as the second element.
     /// If an error occurs, it returns a [`TLSError`].
-    pub fn write(self, application_data: AppData) -> Result<(Bytes, Client), TLSError> {
+    pub fn write(self, application_data: AppData) -> Result<(Bytes, Self), TLSError> {
         match self {
-            Client::Client1(cstate, cipher1) => {
-                let (by, cipher1) = encrypt_data(application_data, 0, cipher1)?;
-                Ok((by, Client::Client1(cstate, cipher1)))
+            Client::Client1(cstate, _, _)
+                if ech_rejected(&ech_status_post_client_finished(&cstate)) =>
+            {
+                Err(ECH_REQUIRED)
+            }
+            Client::Client1(cstate, cipher1, crypto) => {
+                let (by, cipher1) = encrypt_data(application_data, 0, cipher1, &crypto)?;
+                Ok((by, Client::Client1(cstate, cipher1, crypto)))
             }
             _ => Err(INCORRECT_STATE),
         }
     }
+
+    /// Check if the server asked for a certificate that the client hasn't
+    /// sent yet.
+    pub fn certificate_requested(&self) -> bool {
+        match self {
+            Client::Client1(cstate, _, _) => certificate_requested_post_client_finished(cstate),
+            _ => false,
+        }
+    }
+
+    /// Answer the certificate requests the server sent after the handshake.
+    ///
+    /// The client authenticates with the credentials it was started with, see
+    /// [`ClientConfig::with_client_credentials`]. If it can't sign with any
+    /// of the signature schemes the server asks for, it sends an empty
+    /// certificate instead.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns a tuple with the first element the
+    /// encrypted Certificate, CertificateVerify and Finished messages, to be
+    /// sent to the server, and the new [`Client`] state as the second element.
+    /// If there's no request to answer, the first element is [`None`].
+    /// If an error occurs, it returns a [`TLSError`].
+    pub fn certificate_response(
+        self,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Option<Bytes>, Self), TLSError> {
+        match self {
+            Client::Client1(cstate, cipher1, crypto) => {
+                match client_certificate_response(cstate, &crypto, rng)? {
+                    (Some(messages), cstate) => {
+                        let (by, cipher1) = encrypt_post_handshake(messages, 0, cipher1, &crypto)?;
+                        Ok((Some(by), Client::Client1(cstate, cipher1, crypto)))
+                    }
+                    (None, cstate) => Ok((None, Client::Client1(cstate, cipher1, crypto))),
+                }
+            }
+            _ => Err(INCORRECT_STATE),
+        }
+    }
+
+    /// Get the ECH status of the connection.
+    ///
+    /// When the server rejected ECH, this holds the retry configs it sent.
+    pub fn ech_status(&self) -> EchStatus {
+        match self {
+            Client::Client0(cstate, _, _, _) => ech_status_post_client_hello(cstate),
+            Client::ClientH(cstate, _, _, _, _, _) => ech_status_post_server_hello(cstate),
+            Client::Client1(cstate, _, _) => ech_status_post_client_finished(cstate),
+        }
+    }
+
+    /// Get the certificate chain of the server, starting with the end-entity
+    /// certificate.
+    ///
+    /// The chain is empty before the handshake is complete and in PSK mode.
+    /// With a raw public key, it is just the SubjectPublicKeyInfo.
+    pub fn server_certificates(&self) -> Vec<Bytes> {
+        match self {
+            Client::Client1(cstate, _, _) => certificates_post_client_finished(cstate)
+                .iter()
+                .map(|entry| entry.cert_data.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the DER encoded OCSP response the server stapled to its
+    /// certificate, if any.
+    ///
+    /// The response is available once the handshake is complete. It is not
+    /// checked, see [`Client::verify_ocsp_response`].
+    pub fn ocsp_response(&self) -> Option<Bytes> {
+        match self {
+            Client::Client1(cstate, _, _) => certificates_post_client_finished(cstate)
+                .first()
+                .and_then(|leaf| leaf.ocsp_response().ok().flatten()),
+            _ => None,
+        }
+    }
+
+    /// Verify the stapled OCSP response for the server certificate.
+    ///
+    /// The response must be signed by the `issuer` of the server certificate,
+    /// e.g. the second entry of [`Client::server_certificates`], and be valid
+    /// at the time `now`, in seconds since the Unix epoch.
+    ///
+    /// The function returns `Ok(())` if the certificate is good, a
+    /// [`CERTIFICATE_REVOKED`] error if it is revoked, and a
+    /// [`BAD_CERTIFICATE_STATUS`] or another [`TLSError`] otherwise, e.g. if
+    /// the server didn't staple a response.
+    pub fn verify_ocsp_response(&self, issuer: &Bytes, now: u64) -> Result<(), TLSError> {
+        match self {
+            Client::Client1(cstate, _, crypto) => {
+                let leaf = certificates_post_client_finished(cstate)
+                    .first()
+                    .ok_or(BAD_CERTIFICATE_STATUS)?;
+                let response = leaf.ocsp_response()?.ok_or(BAD_CERTIFICATE_STATUS)?;
+                verify_ocsp_response(&response, &leaf.cert_data, issuer, now, crypto)
+            }
+            _ => Err(INCORRECT_STATE),
+        }
+    }
+
+    /// Get the signed certificate timestamps the server stapled to its
+    /// certificate.
+    ///
+    /// The SCTs are available once the handshake is complete. They are not
+    /// checked, see [`Client::verify_signed_certificate_timestamps`].
+    pub fn signed_certificate_timestamps(&self) -> Vec<SignedCertificateTimestamp> {
+        match self {
+            Client::Client1(cstate, _, _) => certificates_post_client_finished(cstate)
+                .first()
+                .and_then(|leaf| leaf.sct_list().ok().flatten())
+                .and_then(|sct_list| parse_sct_list(&sct_list).ok())
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Check the signed certificate timestamps of the server certificate
+    /// against the `policy`, at the time `now`, in seconds since the Unix
+    /// epoch.
+    ///
+    /// The function returns `Ok(())` if the certificate complies with the
+    /// policy, and a [`CT_POLICY_FAILED`] error otherwise.
+    pub fn verify_signed_certificate_timestamps(
+        &self,
+        policy: &CtPolicy,
+        now: u64,
+    ) -> Result<(), TLSError> {
+        match self {
+            Client::Client1(cstate, _, crypto) => {
+                let leaf = certificates_post_client_finished(cstate)
+                    .first()
+                    .ok_or(CT_POLICY_FAILED)?;
+                let scts = self.signed_certificate_timestamps();
+                verify_scts(policy, &scts, &leaf.cert_data, now, crypto)
+            }
+            _ => Err(INCORRECT_STATE),
+        }
+    }
+
+    /// Get the serialized delegated credential (RFC 9345) the server signed
+    /// with instead of its certificate key, if any.
+    ///
+    /// The credential is available once the handshake is complete. Its
+    /// signature and its expiry at the system time of the handshake have
+    /// been checked, see [`Client::verify_delegated_credential`] to check it
+    /// again later.
+    pub fn delegated_credential(&self) -> Option<Bytes> {
+        match self {
+            Client::Client1(cstate, _, _) => certificates_post_client_finished(cstate)
+                .first()
+                .and_then(|leaf| leaf.delegated_credential().ok().flatten()),
+            _ => None,
+        }
+    }
+
+    /// Check that the delegated credential of the server, if any, is valid at
+    /// the time `now`, in seconds since the Unix epoch.
+    ///
+    /// The handshake already checks this at the time of the clock in the
+    /// [`ClientConfig`]. This is for long-lived connections. A credential may
+    /// be used for at most 7 days, and not after it expired.
+    ///
+    /// The function returns `Ok(())` if the server didn't use a delegated
+    /// credential or it is valid, and a [`BAD_DELEGATED_CREDENTIAL`] or
+    /// another [`TLSError`] otherwise.
+    pub fn verify_delegated_credential(&self, now: u64) -> Result<(), TLSError> {
+        match self {
+            Client::Client1(cstate, _, _) => {
+                // There are no certificates in PSK mode.
+                match certificates_post_client_finished(cstate).first() {
+                    Some(leaf) => match leaf.delegated_credential()? {
+                        Some(dc) => check_delegated_credential_validity(
+                            &parse_delegated_credential(&dc)?,
+                            &leaf.cert_data,
+                            now,
+                        ),
+                        None => Ok(()),
+                    },
+                    None => Ok(()),
+                }
+            }
+            _ => Err(INCORRECT_STATE),
+        }
+    }
+}
+
+/// Get the ClientHello from the first `client_hello` record.
+///
+/// Clients may use any legacy record version in the first record.
+fn get_client_hello_record(
+    client_hello: &Bytes,
+) -> Result<handshake_data::HandshakeData, TLSError> {
+    let mut ch_rec = client_hello.clone();
+    ch_rec[2] = U8(0x03);
+    get_handshake_record(&ch_rec)
+}
+
+/// The configuration a [`Server`] uses for a connection.
+///
+/// It is chosen per connection by the hook passed to [`Server::accept_with_hook`].
+pub struct ServerConfig {
+    /// The ciphersuite to use for this connection.
+    pub ciphersuite: Algorithms,
+    /// The server database containing certificates and keys.
+    pub db: ServerDB,
+    /// The ALPN protocol to select, which must be one of the
+    /// [`ClientHelloInfo::alpn_protocols`] the client offers.
+    ///
+    /// With [`None`], no protocol is negotiated.
+    pub alpn_protocol: Option<Bytes>,
 }
 
 /// The TLS server state.
-pub enum Server {
+///
+/// The server uses the [`CryptoProvider`] `Crypto` for all cryptographic
+/// operations. It defaults to the [`LibcruxProvider`].
+pub enum Server<Crypto = LibcruxProvider> {
     /// The initial server state. The server accepts a new connection in this state.
     ServerH(
         ServerPostServerFinished,
         Option<ServerCipherState0>,
         DuplexCipherStateH,
         DuplexCipherState1,
+        Crypto,
     ),
 
     /// The final server state. The server communicates via the encrypted TLS
     /// channel in this state.
-    Server1(ServerPostClientFinished, DuplexCipherState1),
+    Server1(ServerPostClientFinished, DuplexCipherState1, Crypto),
 }
 impl Server {
     /// Start a new TLS handshake as server.
@@ -190,8 +541,13 @@ impl Server {
     /// * `ciphersuite` to use for this server
     /// * `db` for the server database containing certificates and keys
     /// * `client_hello` for the initial client hello message
+    /// * `client_address` for the address of the client, e.g. its IP address
+    ///   and port, of at most 255 bytes, if known
     /// * `entropy` for the randomness required in the handshake
     ///
+    /// The `client_address` must be the one given to
+    /// [`Server::hello_retry_request`], if the server sent a HelloRetryRequest.
+    ///
     /// The function returns a [`Result`].
     /// When successful, the function returns a three-tuple with the first element the
     /// server hello record as bytes, the second the server finished record as bytes,
@@ -201,22 +557,182 @@ impl Server {
         ciphersuite: Algorithms,
         db: ServerDB,
         client_hello: &Bytes,
+        client_address: Option<Bytes>,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes, Self), TLSError> {
+        Self::accept_with_provider(
+            LibcruxProvider,
+            ciphersuite,
+            db,
+            client_hello,
+            client_address,
+            rng,
+        )
+    }
+
+    /// Start a new TLS handshake as server, with a configuration chosen by the
+    /// `hook`.
+    ///
+    /// The `hook` is called with the parameters the client offers in the
+    /// `client_hello` and returns the [`ServerConfig`] for this connection, or
+    /// a [`TLSError`] to reject it.
+    ///
+    /// See [`Server::accept`] for the other arguments and the return value.
+    pub fn accept_with_hook(
+        client_hello: &Bytes,
+        client_address: Option<Bytes>,
+        hook: impl FnOnce(&ClientHelloInfo) -> Result<ServerConfig, TLSError>,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes, Self), TLSError> {
+        Self::accept_with_provider_and_hook(
+            LibcruxProvider,
+            client_hello,
+            client_address,
+            hook,
+            rng,
+        )
+    }
+
+    /// Check if the server asks the client to retry its `client_hello` with
+    /// a HelloRetryRequest, before accepting it.
+    ///
+    /// This needs a cookie key in the `db`, see [`ServerDB::set_cookie_key`].
+    /// The server then asks clients that didn't send a key share for the
+    /// group of the `ciphersuite`, and with [`ServerDB::set_require_cookie`]
+    /// all clients, to retry.
+    ///
+    /// The cookie in the HelloRetryRequest is bound to the `client_address`,
+    /// e.g. the IP address and port of the client, of at most 255 bytes. The
+    /// server only accepts it from the same address.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns the HelloRetryRequest record to
+    /// send to the client, or [`None`] if the `client_hello` can be passed to
+    /// [`Server::accept`] right away.
+    /// The server keeps no state in between: it passes the second client hello
+    /// of the client to [`Server::accept`] as well, which continues from the
+    /// cookie in it.
+    /// If an error occurs, it returns a [`TLSError`].
+    pub fn hello_retry_request(
+        ciphersuite: Algorithms,
+        db: &ServerDB,
+        client_hello: &Bytes,
+        client_address: Option<Bytes>,
+    ) -> Result<Option<Bytes>, TLSError> {
+        Self::hello_retry_request_with_provider(
+            &LibcruxProvider,
+            ciphersuite,
+            db,
+            client_hello,
+            client_address,
+        )
+    }
+}
+
+impl<Crypto: CryptoProvider> Server<Crypto> {
+    /// Start a new TLS handshake as server, using the `crypto` provider.
+    ///
+    /// See [`Server::accept`] for the other arguments and the return value.
+    pub fn accept_with_provider(
+        crypto: Crypto,
+        ciphersuite: Algorithms,
+        db: ServerDB,
+        client_hello: &Bytes,
+        client_address: Option<Bytes>,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes, Self), TLSError> {
+        let ch = get_client_hello_record(client_hello)?;
+        Self::accept_client_hello(
+            crypto,
+            ServerConfig {
+                ciphersuite,
+                db,
+                alpn_protocol: None,
+            },
+            &ch,
+            client_address,
+            rng,
+        )
+    }
+
+    /// Start a new TLS handshake as server, using the `crypto` provider and a
+    /// configuration chosen by the `hook`.
+    ///
+    /// See [`Server::accept_with_hook`] for the other arguments and the return
+    /// value.
+    pub fn accept_with_provider_and_hook(
+        crypto: Crypto,
+        client_hello: &Bytes,
+        client_address: Option<Bytes>,
+        hook: impl FnOnce(&ClientHelloInfo) -> Result<ServerConfig, TLSError>,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes, Self), TLSError> {
+        let ch = get_client_hello_record(client_hello)?;
+        let config = hook(&parse_client_hello_info(&ch)?)?;
+        Self::accept_client_hello(crypto, config, &ch, client_address, rng)
+    }
+
+    /// Check if the server asks the client to retry its `client_hello`, using
+    /// the `crypto` provider.
+    ///
+    /// See [`Server::hello_retry_request`] for the other arguments and the
+    /// return value.
+    pub fn hello_retry_request_with_provider(
+        crypto: &Crypto,
+        ciphersuite: Algorithms,
+        db: &ServerDB,
+        client_hello: &Bytes,
+        client_address: Option<Bytes>,
+    ) -> Result<Option<Bytes>, TLSError> {
+        let ch = get_client_hello_record(client_hello)?;
+        match server_hello_retry_request(ciphersuite, &ch, db, &client_address, crypto)? {
+            Some(hrr) => Ok(Some(handshake_record(hrr)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn accept_client_hello(
+        crypto: Crypto,
+        config: ServerConfig,
+        ch: &handshake_data::HandshakeData,
+        client_address: Option<Bytes>,
         rng: &mut (impl CryptoRng + RngCore),
     ) -> Result<(Bytes, Bytes, Self), TLSError> {
-        let mut ch_rec = client_hello.clone();
-        ch_rec[2] = U8(0x03);
-        let ch = get_handshake_record(&ch_rec)?;
-        let (server_hello, server_finished, cipher0, cipher_hs, cipher1, sstate) =
-            server_init(ciphersuite, &ch, db, rng)?;
+        let ServerConfig {
+            ciphersuite,
+            db,
+            alpn_protocol,
+        } = config;
+        let (server_hello, server_finished, cipher0, cipher_hs, cipher1, _secrets, sstate) =
+            server_init(
+                ciphersuite,
+                ch,
+                db,
+                &client_address,
+                alpn_protocol,
+                None,
+                &crypto,
+                rng,
+            )?;
         let sh_rec = handshake_record(server_hello)?;
-        let (sf_rec, cipher_hs) = encrypt_handshake(server_finished, 0, cipher_hs)?;
+        let (sf_rec, cipher_hs) = encrypt_handshake(server_finished, 0, cipher_hs, &crypto)?;
         Ok((
             sh_rec,
             sf_rec,
-            Server::ServerH(sstate, cipher0, cipher_hs, cipher1),
+            Server::ServerH(sstate, cipher0, cipher_hs, cipher1, crypto),
         ))
     }
 
+    /// The server name the client asked for and the server accepted.
+    ///
+    /// Returns [`None`] if the client didn't send a server name.
+    pub fn server_name(&self) -> Option<&Bytes> {
+        match self {
+            Server::ServerH(sstate, _, _, _, _) => server_name_post_server_finished(sstate),
+            Server::Server1(sstate, _, _) => server_name_post_client_finished(sstate),
+        }
+    }
+
     /// Read the next handshake Message.
     ///
     /// This function takes the current state and `handshake_bytes` and returns
@@ -227,10 +743,10 @@ impl Server {
     /// If an error occurs, it returns a [`TLSError`].
     pub fn read_handshake(self, handshake_bytes: &Bytes) -> Result<Self, TLSError> {
         match self {
-            Server::ServerH(sstate, _cipher0, cipher_hs, cipher1) => {
-                let (cf, _cipher_hs) = decrypt_handshake(handshake_bytes, cipher_hs)?;
-                let sstate = server_finish(&cf, sstate)?;
-                Ok(Server::Server1(sstate, cipher1))
+            Server::ServerH(sstate, _cipher0, cipher_hs, cipher1, crypto) => {
+                let (cf, _cipher_hs) = decrypt_handshake(handshake_bytes, cipher_hs, &crypto)?;
+                let sstate = server_finish(&cf, sstate, &crypto)?;
+                Ok(Server::Server1(sstate, cipher1, crypto))
             }
             _ => Err(INCORRECT_STATE),
         }
@@ -244,18 +760,72 @@ impl Server {
     /// If an error occurs, it returns a [`TLSError`].
     pub fn write(self, application_data: AppData) -> Result<(Bytes, Self), TLSError> {
         match self {
-            Server::Server1(sstate, cipher1) => {
-                let (by, cipher1) = encrypt_data(application_data, 0, cipher1)?;
-                Ok((by, Server::Server1(sstate, cipher1)))
+            Server::Server1(sstate, cipher1, crypto) => {
+                let (by, cipher1) = encrypt_data(application_data, 0, cipher1, &crypto)?;
+                Ok((by, Server::Server1(sstate, cipher1, crypto)))
+            }
+            _ => Err(INCORRECT_STATE),
+        }
+    }
+
+    /// Ask the client for a certificate after the handshake.
+    ///
+    /// The client must have offered post-handshake authentication, otherwise
+    /// this fails with an [`UNSUPPORTED`] error. Only one request can be
+    /// outstanding: the client answers it in the records passed to
+    /// [`Server::read`], and the certificates it authenticated with are then
+    /// available with [`Server::client_certificates`]. If they don't chain to
+    /// one of the server's trust anchors, [`Server::read`] fails with a
+    /// [`BAD_CERTIFICATE`] error.
+    ///
+    /// The function returns a [`Result`].
+    /// When successful, the function returns a tuple with the first element the
+    /// encrypted CertificateRequest as bytes, and the new [`Server`] state as
+    /// the second element.
+    /// If an error occurs, it returns a [`TLSError`].
+    pub fn request_client_certificate(self) -> Result<(Bytes, Self), TLSError> {
+        match self {
+            Server::Server1(sstate, cipher1, crypto) => {
+                let (certificate_request, sstate) = server_certificate_request(sstate)?;
+                let (by, cipher1) =
+                    encrypt_post_handshake(certificate_request, 0, cipher1, &crypto)?;
+                Ok((by, Server::Server1(sstate, cipher1, crypto)))
             }
             _ => Err(INCORRECT_STATE),
         }
     }
 
-    /// Read application data.
+    /// Check if the server waits for the client to answer a certificate
+    /// request.
+    pub fn certificate_requested(&self) -> bool {
+        match self {
+            Server::Server1(sstate, _, _) => client_auth_pending_post_client_finished(sstate),
+            _ => false,
+        }
+    }
+
+    /// Get the certificate chain the client authenticated with after the
+    /// handshake, starting with the end-entity certificate.
+    ///
+    /// The chain is empty until the client answered a certificate request with
+    /// a certificate. Its signature is checked, and the chain is validated
+    /// against the trust anchors of the server, see
+    /// [`ServerDB::set_client_certificate_authorities`].
+    pub fn client_certificates(&self) -> Vec<Bytes> {
+        match self {
+            Server::Server1(sstate, _, _) => client_certificates_post_client_finished(sstate)
+                .iter()
+                .map(|entry| entry.cert_data.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Read application data and certificates.
     ///
     /// This function can be used when the TLS handshake is complete, to read
-    /// application data from the client.
+    /// application data from the client, and its answer to a certificate
+    /// request.
     ///
     /// It takes the current state and `application_data` and returns
     /// the next state or a [`TLSError`].
@@ -267,9 +837,23 @@ impl Server {
     /// If an error occurs, it returns a [`TLSError`].
     pub fn read(self, application_data: &Bytes) -> Result<(Option<AppData>, Self), TLSError> {
         match self {
-            Server::Server1(sstate, cipher1) => {
-                let (ad, cipher1) = decrypt_data(application_data, cipher1)?;
-                Ok((Some(ad), Server::Server1(sstate, cipher1)))
+            Server::Server1(sstate, cipher1, crypto) => {
+                let (ty, hd, cipher1) = decrypt_data_or_hs(application_data, cipher1, &crypto)?;
+                match ty {
+                    ContentType::ApplicationData => Ok((
+                        Some(AppData::new(hd)),
+                        Server::Server1(sstate, cipher1, crypto),
+                    )),
+                    ContentType::Handshake => {
+                        let sstate = server_certificate_response(
+                            &handshake_data::HandshakeData::from(hd),
+                            sstate,
+                            &crypto,
+                        )?;
+                        Ok((None, Server::Server1(sstate, cipher1, crypto)))
+                    }
+                    _ => Err(PARSE_FAILED),
+                }
             }
             _ => Err(INCORRECT_STATE),
         }
@@ -15,14 +15,28 @@
 //!     }
 //!     BitString // 0x03
 //! }
+//! ```
+//!
+//! It also reads stapled OCSP responses (RFC 6960) to check them against the
+//! certificate and its issuer, and checks delegated credentials (RFC 9345)
+//! against the extensions and the validity of the certificate.
 #[cfg(feature = "hax-pv")]
 use hax_lib_macros::{pv_constructor, pv_handwritten};
 
 #[cfg(not(feature = "secret_integers"))]
 use crate::tls13utils::Declassify;
 use crate::{
-    tls13crypto::{PublicVerificationKey, RsaVerificationKey, SignatureScheme, VerificationKey},
-    tls13utils::{u32_from_be_bytes, Bytes, U8},
+    delegated_credential::{signature_input, DelegatedCredentialData},
+    tls13crypto::{
+        CryptoProvider, HashAlgorithm, PublicVerificationKey, RsaVerificationKey, SignatureScheme,
+        VerificationKey, ML_DSA_65_SIGNING_KEY_LEN, ML_DSA_65_VERIFICATION_KEY_LEN,
+    },
+    tls13formats::parse_ecdsa_signature,
+    tls13utils::{
+        u32_from_be_bytes, Bytes, TLSError, BAD_CERTIFICATE, BAD_CERTIFICATE_STATUS,
+        BAD_DELEGATED_CREDENTIAL, CERTIFICATE_REVOKED, ILLEGAL_PARAMETER, U8,
+        UNSUPPORTED_ALGORITHM,
+    },
 };
 
 /// Certificate key start and length within the certificate DER.
@@ -190,6 +204,18 @@ pub(crate) fn ecdsa_secp256r1_sha256_oid() -> Bytes {
 pub(crate) fn rsa_pkcs1_encryption_oid() -> Bytes {
     [0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01].into()
 }
+/// id-ml-dsa-65 (2.16.840.1.101.3.4.3.18) from draft-ietf-lamps-dilithium-certificates
+pub(crate) fn ml_dsa_65_oid() -> Bytes {
+    [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x03, 0x12].into()
+}
+/// secp384r1 (1.3.132.0.34)
+pub(crate) fn secp384r1_oid() -> Bytes {
+    [0x2B, 0x81, 0x04, 0x00, 0x22].into()
+}
+/// id-Ed25519 (1.3.101.112) from RFC 8410
+pub(crate) fn ed25519_oid() -> Bytes {
+    [0x2B, 0x65, 0x70].into()
+}
 
 fn check_success(val: bool) -> Result<(), Asn1Error> {
     if val {
@@ -217,8 +243,10 @@ fn read_spki(cert: &Bytes, mut offset: usize) -> Result<Spki, Asn1Error> {
     // OID length
     let (mut oid_offset, oid_len) = length(cert, offset + 1)?;
     let (mut ec_pk_oid, mut ecdsa_p256, mut rsa_pk_oid) = (false, false, false);
+    let mut ml_dsa_pk_oid = false;
     let ec_oid = x962_ec_public_key_oid();
     let rsa_oid = rsa_pkcs1_encryption_oid();
+    let ml_dsa_oid = ml_dsa_65_oid();
     // Check OID
     if ec_oid.len() == oid_len {
         ec_pk_oid = true;
@@ -248,7 +276,14 @@ fn read_spki(cert: &Bytes, mut offset: usize) -> Result<Spki, Asn1Error> {
             rsa_pk_oid = rsa_pk_oid && oid_byte_equal;
         }
     }
-    check_success((ec_pk_oid && ecdsa_p256) || rsa_pk_oid)?;
+    if ml_dsa_oid.len() == oid_len {
+        ml_dsa_pk_oid = true;
+        for i in 0..ml_dsa_oid.len() {
+            let oid_byte_equal = cert[oid_offset + i].declassify() == ml_dsa_oid[i].declassify();
+            ml_dsa_pk_oid = ml_dsa_pk_oid && oid_byte_equal;
+        }
+    }
+    check_success((ec_pk_oid && ecdsa_p256) || rsa_pk_oid || ml_dsa_pk_oid)?;
 
     // Skip all the way to the end of the sequence.
     // RSA has a NULL element in there as well. We don't care.
@@ -273,6 +308,11 @@ fn read_spki(cert: &Bytes, mut offset: usize) -> Result<Spki, Asn1Error> {
             SignatureScheme::RsaPssRsaSha256,
             CertificateKey(offset, bit_string_len - 1),
         ))
+    } else if ml_dsa_pk_oid {
+        Ok((
+            SignatureScheme::MlDsa65,
+            CertificateKey(offset, bit_string_len - 1),
+        ))
     } else {
         asn1_error(ASN1_INVALID_CERTIFICATE)
     }
@@ -302,6 +342,14 @@ pub(crate) fn verification_key_from_cert(cert: &Bytes) -> Result<Spki, Asn1Error
     read_spki(cert, offset)
 }
 
+/// Read the public key from a DER encoded SubjectPublicKeyInfo `spki`, e.g.
+/// the key of a Certificate Transparency log.
+///
+/// Returns the offsets within `spki`, like [`verification_key_from_cert`].
+pub(crate) fn verification_key_from_spki(spki: &Bytes) -> Result<Spki, Asn1Error> {
+    read_spki(spki, 0)
+}
+
 /// Read the EC PK from the cert as uncompressed point.
 #[cfg_attr(feature = "hax-pv", pv_constructor)]
 pub(crate) fn ecdsa_public_key(
@@ -348,6 +396,21 @@ pub(crate) fn rsa_public_key(
     })
 }
 
+/// Read the raw ML-DSA-65 public key from the cert.
+#[cfg_attr(feature = "hax-pv", pv_constructor)]
+pub(crate) fn ml_dsa_65_public_key(
+    cert: &Bytes,
+    indices: CertificateKey,
+) -> Result<VerificationKey, Asn1Error> {
+    let CertificateKey(offset, len) = indices;
+
+    if len != ML_DSA_65_VERIFICATION_KEY_LEN {
+        asn1_error(ASN1_INVALID_CERTIFICATE)
+    } else {
+        Ok(cert.slice(offset, len))
+    }
+}
+
 /// Debug print a slice.
 #[cfg(test)]
 #[allow(dead_code)]
@@ -359,34 +422,6 @@ fn debug_print(tag: &str, bytes: &Bytes, offset: usize) {
     );
 }
 
-/// Read the private key (d) from an RSA private key.
-/// We expect to have the [version, privateKeyAlgorithm, privateKey] with
-/// RSAPrivateKey ::= SEQUENCE {
-///     version   Version,
-///     modulus   INTEGER,  -- n
-///     publicExponentINTEGER,  -- e
-///     privateExponent   INTEGER,  -- d
-///     prime1INTEGER,  -- p
-///     prime2INTEGER,  -- q
-///     exponent1 INTEGER,  -- d mod (p-1)
-///     exponent2 INTEGER,  -- d mod (q-1)
-///     coefficient   INTEGER,  -- (inverse of q) mod p
-///     otherPrimeInfos   OtherPrimeInfos OPTIONAL
-/// }
-pub(crate) fn rsa_private_key(key: &Bytes) -> Result<Bytes, Asn1Error> {
-    let mut offset = read_sequence_header(key, 0)?;
-    offset = skip_integer(key, offset)?; // version
-    offset = skip_sequence(key, offset)?;
-    offset = read_octet_header(key, offset)?;
-    offset = read_sequence_header(key, offset)?;
-    offset = skip_integer(key, offset)?; // version
-    offset = skip_integer(key, offset)?; // private key algorithm
-    offset = skip_integer(key, offset)?; // public exponent 65537
-
-    // now we have reached the private exponent d
-    read_integer(key, offset)
-}
-
 /// Get the public key from from a certificate.
 ///
 /// On input of a `certificate` and `spki`, return a [`PublicVerificationKey`]
@@ -406,6 +441,823 @@ pub(crate) fn cert_public_key(
             let pk = rsa_public_key(certificate, spki.1)?;
             Ok(PublicVerificationKey::Rsa(pk))
         }
+        SignatureScheme::MlDsa65 => {
+            let pk = ml_dsa_65_public_key(certificate, spki.1)?;
+            Ok(PublicVerificationKey::MlDsa(pk))
+        }
+    }
+}
+
+/// Read the serial number, the issuer name, and the subject name of the
+/// `cert`.
+///
+/// The names are returned as DER encoded sequences.
+fn read_serial_and_names(cert: &Bytes) -> Result<(Bytes, Bytes, Bytes), Asn1Error> {
+    let mut offset = read_sequence_header(cert, 0)?;
+    offset = read_sequence_header(cert, offset)?;
+    offset = read_version_number(cert, offset)?; // x509 version number
+    let serial_number = read_integer(cert, offset)?;
+    offset = skip_integer(cert, offset)?;
+    offset = skip_sequence(cert, offset)?; // signature algorithm
+    let issuer_start = offset;
+    offset = skip_sequence(cert, offset)?;
+    let issuer_end = offset;
+    offset = skip_sequence(cert, offset)?; // validity
+    let subject_start = offset;
+    let subject_end = skip_sequence(cert, offset)?;
+    check_success(subject_end <= cert.len())?;
+    Ok((
+        serial_number,
+        cert.slice(issuer_start, issuer_end - issuer_start),
+        cert.slice(subject_start, subject_end - subject_start),
+    ))
+}
+
+/// id-pkix-ocsp-basic (1.3.6.1.5.5.7.48.1.1) from RFC 6960
+fn ocsp_basic_oid() -> Bytes {
+    [0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01].into()
+}
+/// id-sha256 (2.16.840.1.101.3.4.2.1)
+fn sha256_oid() -> Bytes {
+    [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01].into()
+}
+/// ecdsa-with-SHA256 (1.2.840.10045.4.3.2) from RFC 5758
+fn ecdsa_with_sha256_oid() -> Bytes {
+    [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02].into()
+}
+
+/// Read the header of the element with the `tag` at `offset`.
+///
+/// Unlike the certificate parser above, this checks that the element lies
+/// within `b`, because OCSP responses are read as they come from the peer.
+///
+/// Returns the offset and the length of the content.
+pub(crate) fn read_element(b: &Bytes, offset: usize, tag: u8) -> Result<(usize, usize), Asn1Error> {
+    check_success(b.len() >= 2 && offset <= b.len() - 2)?;
+    check_tag(b, offset, tag)?;
+    let length_length = length_length(b, offset + 1);
+    // Indefinite lengths are not allowed in DER.
+    check_success(b[offset + 1].declassify() != 0x80)?;
+    check_success(length_length <= 4 && offset + 2 + length_length <= b.len())?;
+    let (start, len) = length(b, offset + 1)?;
+    check_success(len <= b.len() - start)?;
+    Ok((start, len))
+}
+
+/// Check whether the element at `offset` has the `tag`.
+pub(crate) fn has_tag(b: &Bytes, offset: usize, tag: u8) -> bool {
+    offset < b.len() && b[offset].declassify() == tag
+}
+
+/// Skip the element with the `tag` at `offset`.
+///
+/// Returns the offset after the element.
+fn skip_element(b: &Bytes, offset: usize, tag: u8) -> Result<usize, Asn1Error> {
+    let (start, len) = read_element(b, offset, tag)?;
+    Ok(start + len)
+}
+
+/// Read the content of the element with the `tag` at `offset`.
+fn read_element_content(b: &Bytes, offset: usize, tag: u8) -> Result<Bytes, Asn1Error> {
+    let (start, len) = read_element(b, offset, tag)?;
+    Ok(b.slice(start, len))
+}
+
+/// Read the expanded signing key from an ML-DSA-65 private key.
+/// We expect to have the [version, privateKeyAlgorithm, privateKey] with
+/// ML-DSA-PrivateKey ::= CHOICE {
+///     seed [0]  OCTET STRING (SIZE (32)),
+///     expandedKey   OCTET STRING,
+///     both  SEQUENCE {
+///         seed  OCTET STRING (SIZE (32)),
+///         expandedKey   OCTET STRING
+///     }
+/// }
+///
+/// Keys that only hold the seed are not supported.
+#[allow(dead_code)] // Only used by the key loader of the `api`.
+pub(crate) fn ml_dsa_65_private_key(key: &Bytes) -> Result<Bytes, Asn1Error> {
+    let (offset, _) = read_element(key, 0, 0x30)?;
+    let offset = skip_element(key, offset, 0x02)?; // version
+    let offset = skip_element(key, offset, 0x30)?; // private key algorithm
+    let (offset, _) = read_element(key, offset, 0x04)?;
+    let offset = if has_tag(key, offset, 0x30) {
+        // Both seed and expanded key. Skip the seed.
+        let (both, _) = read_element(key, offset, 0x30)?;
+        skip_element(key, both, 0x04)?
+    } else {
+        offset
+    };
+
+    // Now we have reached the expanded key
+    let (offset, len) = read_element(key, offset, 0x04)?;
+    if len != ML_DSA_65_SIGNING_KEY_LEN {
+        asn1_error(ASN1_ERROR)
+    } else {
+        Ok(key.slice(offset, len))
+    }
+}
+
+/// The number of days from 1970-01-01 to the date.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Count years from March, so that the leap day is at the end.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Read the GeneralizedTime `YYYYMMDDHHMMSSZ` at `offset`.
+///
+/// Returns the time in seconds since the Unix epoch.
+fn read_generalized_time(b: &Bytes, offset: usize) -> Result<u64, Asn1Error> {
+    parse_time(&read_element_content(b, offset, 0x18)?)
+}
+
+/// Read the UTCTime `YYMMDDHHMMSSZ` or the GeneralizedTime at `offset`, as
+/// in the validity of a certificate.
+///
+/// Returns the time in seconds since the Unix epoch, and the offset after it.
+fn read_time(b: &Bytes, offset: usize) -> Result<(u64, usize), Asn1Error> {
+    if has_tag(b, offset, 0x17) {
+        let time = read_element_content(b, offset, 0x17)?;
+        check_success(time.len() == 13 && time[0].declassify().is_ascii_digit())?;
+        // Two digit years are from 1950 to 2049 (RFC 5280, Section 4.1.2.5.1).
+        let century: Bytes = if time[0].declassify() >= b'5' {
+            b"19".into()
+        } else {
+            b"20".into()
+        };
+        Ok((
+            parse_time(&century.concat(time))?,
+            skip_element(b, offset, 0x17)?,
+        ))
+    } else {
+        Ok((
+            read_generalized_time(b, offset)?,
+            skip_element(b, offset, 0x18)?,
+        ))
+    }
+}
+
+/// Parse the `time` in the format `YYYYMMDDHHMMSSZ`.
+///
+/// Returns the time in seconds since the Unix epoch.
+fn parse_time(time: &Bytes) -> Result<u64, Asn1Error> {
+    check_success(time.len() == 15 && time[14].declassify() == b'Z')?;
+    let mut digits = [0u64; 14];
+    for (i, digit) in digits.iter_mut().enumerate() {
+        let c = time[i].declassify();
+        check_success(c.is_ascii_digit())?;
+        *digit = (c - b'0') as u64;
+    }
+    let number = |start: usize, len: usize| {
+        digits[start..start + len]
+            .iter()
+            .fold(0, |acc, digit| acc * 10 + digit)
+    };
+    let (year, month, day) = (number(0, 4), number(4, 2), number(6, 2));
+    let (hour, minute, second) = (number(8, 2), number(10, 2), number(12, 2));
+    check_success(
+        year >= 1970
+            && (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second < 60,
+    )?;
+    Ok(days_since_epoch(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// The status of a certificate in an OCSP response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OcspCertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// A `SingleResponse` of an OCSP response.
+///
+/// ```ignore
+/// SingleResponse ::= SEQUENCE {
+///     certID                  CertID,
+///     certStatus              CertStatus,
+///     thisUpdate              GeneralizedTime,
+///     nextUpdate          [0] EXPLICIT GeneralizedTime OPTIONAL,
+///     singleExtensions    [1] EXPLICIT Extensions OPTIONAL }
+///
+/// CertID ::= SEQUENCE {
+///     hashAlgorithm           AlgorithmIdentifier,
+///     issuerNameHash          OCTET STRING,
+///     issuerKeyHash           OCTET STRING,
+///     serialNumber            CertificateSerialNumber }
+/// ```
+pub(crate) struct OcspSingleResponse {
+    pub(crate) issuer_name_hash: Bytes,
+    pub(crate) issuer_key_hash: Bytes,
+    pub(crate) serial_number: Bytes,
+    pub(crate) status: OcspCertStatus,
+    /// Seconds since the Unix epoch.
+    pub(crate) this_update: u64,
+    /// Seconds since the Unix epoch.
+    pub(crate) next_update: Option<u64>,
+}
+
+/// A `BasicOCSPResponse`.
+///
+/// ```ignore
+/// BasicOCSPResponse ::= SEQUENCE {
+///     tbsResponseData      ResponseData,
+///     signatureAlgorithm   AlgorithmIdentifier,
+///     signature            BIT STRING,
+///     certs            [0] EXPLICIT SEQUENCE OF Certificate OPTIONAL }
+/// ```
+pub(crate) struct OcspResponse {
+    /// The DER encoded `ResponseData`, which is signed.
+    pub(crate) tbs_response_data: Bytes,
+    /// The OID of the signature algorithm.
+    pub(crate) signature_algorithm: Bytes,
+    pub(crate) signature: Bytes,
+    pub(crate) responses: Vec<OcspSingleResponse>,
+}
+
+/// Parse the `SingleResponse` at `offset`.
+fn parse_single_response(b: &Bytes, offset: usize) -> Result<OcspSingleResponse, Asn1Error> {
+    let (offset, _) = read_element(b, offset, 0x30)?;
+
+    // The CertID
+    let (cert_id, _) = read_element(b, offset, 0x30)?;
+    let (algorithm, _) = read_element(b, cert_id, 0x30)?;
+    if read_element_content(b, algorithm, 0x06)? != sha256_oid() {
+        return asn1_error(ASN1_UNSUPPORTED_ALGORITHM);
+    }
+    let mut next = skip_element(b, cert_id, 0x30)?;
+    let issuer_name_hash = read_element_content(b, next, 0x04)?;
+    next = skip_element(b, next, 0x04)?;
+    let issuer_key_hash = read_element_content(b, next, 0x04)?;
+    next = skip_element(b, next, 0x04)?;
+    let serial_number = read_element_content(b, next, 0x02)?;
+    let mut offset = skip_element(b, offset, 0x30)?;
+
+    // The CertStatus is one of good [0], revoked [1], or unknown [2].
+    let status = if has_tag(b, offset, 0x80) {
+        offset = skip_element(b, offset, 0x80)?;
+        OcspCertStatus::Good
+    } else if has_tag(b, offset, 0xA1) {
+        offset = skip_element(b, offset, 0xA1)?;
+        OcspCertStatus::Revoked
+    } else {
+        offset = skip_element(b, offset, 0x82)?;
+        OcspCertStatus::Unknown
+    };
+
+    let this_update = read_generalized_time(b, offset)?;
+    offset = skip_element(b, offset, 0x18)?;
+    let next_update = if has_tag(b, offset, 0xA0) {
+        let (time, _) = read_element(b, offset, 0xA0)?;
+        Some(read_generalized_time(b, time)?)
+    } else {
+        None
+    };
+
+    Ok(OcspSingleResponse {
+        issuer_name_hash,
+        issuer_key_hash,
+        serial_number,
+        status,
+        this_update,
+        next_update,
+    })
+}
+
+/// Parse a DER encoded `OCSPResponse`.
+///
+/// ```ignore
+/// OCSPResponse ::= SEQUENCE {
+///     responseStatus         OCSPResponseStatus,
+///     responseBytes      [0] EXPLICIT ResponseBytes OPTIONAL }
+///
+/// ResponseBytes ::= SEQUENCE {
+///     responseType           OBJECT IDENTIFIER,
+///     response               OCTET STRING }
+/// ```
+///
+/// Only successful responses of type `id-pkix-ocsp-basic` are accepted.
+pub(crate) fn parse_ocsp_response(response: &Bytes) -> Result<OcspResponse, Asn1Error> {
+    let (offset, len) = read_element(response, 0, 0x30)?;
+    check_success(offset + len == response.len())?;
+
+    // The status must be successful (0).
+    let status = read_element_content(response, offset, 0x0A)?;
+    check_success(status.len() == 1 && status[0].declassify() == 0)?;
+    let offset = skip_element(response, offset, 0x0A)?;
+
+    let (offset, _) = read_element(response, offset, 0xA0)?;
+    let (offset, _) = read_element(response, offset, 0x30)?;
+    check_success(read_element_content(response, offset, 0x06)? == ocsp_basic_oid())?;
+    let offset = skip_element(response, offset, 0x06)?;
+    let b = read_element_content(response, offset, 0x04)?;
+
+    // The BasicOCSPResponse
+    let (offset, _) = read_element(&b, 0, 0x30)?;
+    let (tbs_start, tbs_len) = read_element(&b, offset, 0x30)?;
+    let tbs_end = tbs_start + tbs_len;
+    let tbs_response_data = b.slice(offset, tbs_end - offset);
+    let (algorithm, _) = read_element(&b, tbs_end, 0x30)?;
+    let signature_algorithm = read_element_content(&b, algorithm, 0x06)?;
+    let signature = read_element_content(&b, skip_element(&b, tbs_end, 0x30)?, 0x03)?;
+    // There are no unused bits in a signature.
+    check_success(signature.len() > 1 && signature[0].declassify() == 0)?;
+    let signature = signature.slice(1, signature.len() - 1);
+
+    // The ResponseData, with an optional version, the responder id by name [1]
+    // or by key [2], and the time it was produced at.
+    let mut offset = tbs_start;
+    if has_tag(&b, offset, 0xA0) {
+        offset = skip_element(&b, offset, 0xA0)?;
+    }
+    offset = if has_tag(&b, offset, 0xA1) {
+        skip_element(&b, offset, 0xA1)?
+    } else {
+        skip_element(&b, offset, 0xA2)?
+    };
+    offset = skip_element(&b, offset, 0x18)?;
+    let (mut offset, len) = read_element(&b, offset, 0x30)?;
+    let end = offset + len;
+    check_success(end <= tbs_end)?;
+    let mut responses = Vec::new();
+    while offset < end {
+        responses.push(parse_single_response(&b, offset)?);
+        offset = skip_element(&b, offset, 0x30)?;
+    }
+    check_success(offset == end)?;
+
+    Ok(OcspResponse {
+        tbs_response_data,
+        signature_algorithm,
+        signature,
+        responses,
+    })
+}
+
+/// Decode the `signature` with the `signature_algorithm` OID that an issuer
+/// with a key for the `scheme` made.
+///
+/// Issuers sign with ECDSA P-256 and SHA-256, or with ML-DSA-65. Other
+/// algorithms fail with an [`UNSUPPORTED_ALGORITHM`] error.
+fn issuer_signature(
+    scheme: &SignatureScheme,
+    signature_algorithm: &Bytes,
+    signature: &Bytes,
+) -> Result<Bytes, TLSError> {
+    match scheme {
+        SignatureScheme::EcdsaSecp256r1Sha256
+            if *signature_algorithm == ecdsa_with_sha256_oid() =>
+        {
+            parse_ecdsa_signature(signature.clone())
+        }
+        SignatureScheme::MlDsa65 if *signature_algorithm == ml_dsa_65_oid() => {
+            Ok(signature.clone())
+        }
+        _ => Err(UNSUPPORTED_ALGORITHM),
+    }
+}
+
+/// Verify the DER encoded OCSP `response` for the certificate `cert`, issued
+/// by `issuer`, at the time `now` in seconds since the Unix epoch.
+///
+/// The response must be signed by the issuer itself with ECDSA P-256 and
+/// SHA-256, or with ML-DSA-65. Delegated OCSP responders are not supported.
+/// The certificate id in the response must use SHA-256.
+///
+/// Returns `Ok(())` if the certificate is good, a [`CERTIFICATE_REVOKED`]
+/// error if it is revoked, and a [`BAD_CERTIFICATE_STATUS`] or another
+/// [`TLSError`] if the response can't be used to tell.
+pub(crate) fn verify_ocsp_response(
+    response: &Bytes,
+    cert: &Bytes,
+    issuer: &Bytes,
+    now: u64,
+    crypto: &impl CryptoProvider,
+) -> Result<(), TLSError> {
+    let response = parse_ocsp_response(response).map_err(|_| BAD_CERTIFICATE_STATUS)?;
+    let (serial_number, cert_issuer, _) = read_serial_and_names(cert)?;
+    let (_, _, issuer_name) = read_serial_and_names(issuer)?;
+    if cert_issuer != issuer_name {
+        return Err(BAD_CERTIFICATE_STATUS);
+    }
+
+    // The issuer signs the response.
+    let spki = verification_key_from_cert(issuer)?;
+    let issuer_key = cert_public_key(issuer, &spki)?;
+    let signature = issuer_signature(&spki.0, &response.signature_algorithm, &response.signature)?;
+    crypto.verify(
+        &spki.0,
+        &issuer_key,
+        &response.tbs_response_data,
+        &signature,
+    )?;
+
+    // Find the response for the certificate.
+    let CertificateKey(key_offset, key_len) = spki.1;
+    let issuer_name_hash = crypto.hash(&HashAlgorithm::SHA256, &issuer_name)?;
+    let issuer_key_hash =
+        crypto.hash(&HashAlgorithm::SHA256, &issuer.slice(key_offset, key_len))?;
+    let single = response
+        .responses
+        .iter()
+        .find(|single| {
+            single.issuer_name_hash == issuer_name_hash
+                && single.issuer_key_hash == issuer_key_hash
+                && single.serial_number == serial_number
+        })
+        .ok_or(BAD_CERTIFICATE_STATUS)?;
+
+    if now < single.this_update || matches!(single.next_update, Some(next) if next < now) {
+        return Err(BAD_CERTIFICATE_STATUS);
+    }
+    match single.status {
+        OcspCertStatus::Good => Ok(()),
+        OcspCertStatus::Revoked => Err(CERTIFICATE_REVOKED),
+        OcspCertStatus::Unknown => Err(BAD_CERTIFICATE_STATUS),
+    }
+}
+
+/// id-ce-keyUsage (2.5.29.15)
+fn key_usage_oid() -> Bytes {
+    [0x55, 0x1D, 0x0F].into()
+}
+/// id-pe-delegationUsage (1.3.6.1.4.1.44363.44) from RFC 9345
+fn delegation_usage_oid() -> Bytes {
+    [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xDA, 0x4B, 0x2C].into()
+}
+
+/// Read the validity and the extensions of the `cert`.
+///
+/// ```ignore
+/// TBSCertificate ::= SEQUENCE {
+///     version         [0] EXPLICIT Version DEFAULT v1,
+///     serialNumber         CertificateSerialNumber,
+///     signature            AlgorithmIdentifier,
+///     issuer               Name,
+///     validity             Validity,
+///     subject              Name,
+///     subjectPublicKeyInfo SubjectPublicKeyInfo,
+///     issuerUniqueID  [1] IMPLICIT UniqueIdentifier OPTIONAL,
+///     subjectUniqueID [2] IMPLICIT UniqueIdentifier OPTIONAL,
+///     extensions      [3] EXPLICIT Extensions OPTIONAL }
+///
+/// Validity ::= SEQUENCE {
+///     notBefore      Time,
+///     notAfter       Time }
+///
+/// Extension ::= SEQUENCE {
+///     extnID      OBJECT IDENTIFIER,
+///     critical    BOOLEAN DEFAULT FALSE,
+///     extnValue   OCTET STRING }
+/// ```
+///
+/// Returns the notBefore and notAfter times in seconds since the Unix epoch,
+/// and the OID and the DER encoded value of each extension.
+fn read_validity_and_extensions(
+    cert: &Bytes,
+) -> Result<(u64, u64, Vec<(Bytes, Bytes)>), Asn1Error> {
+    let (offset, len) = read_element(cert, 0, 0x30)?;
+    check_success(offset + len == cert.len())?;
+    let (mut offset, tbs_len) = read_element(cert, offset, 0x30)?;
+    let tbs_end = offset + tbs_len;
+    if has_tag(cert, offset, 0xA0) {
+        offset = skip_element(cert, offset, 0xA0)?;
+    }
+    offset = skip_element(cert, offset, 0x02)?; // serial number
+    offset = skip_element(cert, offset, 0x30)?; // signature algorithm
+    offset = skip_element(cert, offset, 0x30)?; // issuer
+    let (validity, _) = read_element(cert, offset, 0x30)?;
+    let (not_before, next) = read_time(cert, validity)?;
+    let (not_after, _) = read_time(cert, next)?;
+    offset = skip_element(cert, offset, 0x30)?; // validity
+    offset = skip_element(cert, offset, 0x30)?; // subject
+    offset = skip_element(cert, offset, 0x30)?; // subject public key info
+    if has_tag(cert, offset, 0x81) {
+        offset = skip_element(cert, offset, 0x81)?;
+    }
+    if has_tag(cert, offset, 0x82) {
+        offset = skip_element(cert, offset, 0x82)?;
+    }
+
+    let mut extensions = Vec::new();
+    if offset < tbs_end && has_tag(cert, offset, 0xA3) {
+        let (offset, _) = read_element(cert, offset, 0xA3)?;
+        let (mut offset, len) = read_element(cert, offset, 0x30)?;
+        let end = offset + len;
+        check_success(end <= tbs_end)?;
+        while offset < end {
+            let (mut next, _) = read_element(cert, offset, 0x30)?;
+            let oid = read_element_content(cert, next, 0x06)?;
+            next = skip_element(cert, next, 0x06)?;
+            if has_tag(cert, next, 0x01) {
+                next = skip_element(cert, next, 0x01)?;
+            }
+            extensions.push((oid, read_element_content(cert, next, 0x04)?));
+            offset = skip_element(cert, offset, 0x30)?;
+        }
+        check_success(offset == end)?;
+    }
+    Ok((not_before, not_after, extensions))
+}
+
+/// Read the issuer and the subject name of the `cert`.
+///
+/// Unlike [`read_serial_and_names`], this checks that the names lie within
+/// the certificate, because it is used on the peer's trust anchors.
+///
+/// The names are returned as DER encoded sequences, as they are sent in the
+/// `certificate_authorities` extension.
+pub(crate) fn certificate_names(cert: &Bytes) -> Result<(Bytes, Bytes), Asn1Error> {
+    let (offset, len) = read_element(cert, 0, 0x30)?;
+    check_success(offset + len == cert.len())?;
+    let (mut offset, _) = read_element(cert, offset, 0x30)?;
+    if has_tag(cert, offset, 0xA0) {
+        offset = skip_element(cert, offset, 0xA0)?;
+    }
+    offset = skip_element(cert, offset, 0x02)?; // serial number
+    offset = skip_element(cert, offset, 0x30)?; // signature algorithm
+    let issuer_end = skip_element(cert, offset, 0x30)?;
+    let issuer = cert.slice(offset, issuer_end - offset);
+    let subject_start = skip_element(cert, issuer_end, 0x30)?; // validity
+    let subject_end = skip_element(cert, subject_start, 0x30)?;
+    Ok((
+        issuer,
+        cert.slice(subject_start, subject_end - subject_start),
+    ))
+}
+
+/// Check whether the certificate chain `cert_chain` ends at one of the
+/// certificate authorities with the DER encoded distinguished `names`.
+///
+/// This is the case if one of the `names` issued a certificate of the chain,
+/// or is the subject of one, e.g. of a root included in the chain.
+/// Malformed certificates don't match.
+pub(crate) fn chain_matches_authorities(cert_chain: &[Bytes], names: &[Bytes]) -> bool {
+    cert_chain.iter().any(|cert| match certificate_names(cert) {
+        Ok((issuer, subject)) => names.contains(&issuer) || names.contains(&subject),
+        Err(_) => false,
+    })
+}
+
+/// id-ce-subjectAltName (2.5.29.17)
+fn subject_alt_name_oid() -> Bytes {
+    [0x55, 0x1D, 0x11].into()
+}
+
+/// The DNS names in the subject alternative names of the `cert`.
+///
+/// ```ignore
+/// SubjectAltName ::= GeneralNames
+/// GeneralNames ::= SEQUENCE SIZE (1..MAX) OF GeneralName
+/// GeneralName ::= CHOICE {
+///     ...
+///     dNSName     [2] IA5String,
+///     ... }
+/// ```
+fn dns_names(cert: &Bytes) -> Result<Vec<Bytes>, Asn1Error> {
+    let (_, _, extensions) = read_validity_and_extensions(cert)?;
+    let mut names = Vec::new();
+    if let Some((_, san)) = extensions
+        .iter()
+        .find(|(oid, _)| *oid == subject_alt_name_oid())
+    {
+        let (mut offset, len) = read_element(san, 0, 0x30)?;
+        let end = offset + len;
+        while offset < end {
+            let tag = san[offset].declassify();
+            if tag == 0x82 {
+                names.push(read_element_content(san, offset, tag)?);
+            }
+            offset = skip_element(san, offset, tag)?;
+        }
+        check_success(offset == end)?;
+    }
+    Ok(names)
+}
+
+/// Check whether the DNS `pattern` of a certificate matches the `name`.
+///
+/// Names are compared case-insensitively, and a wildcard `*.` at the start of
+/// the `pattern` matches exactly one label.
+fn dns_name_matches(pattern: &[u8], name: &[u8]) -> bool {
+    if pattern.len() > 2 && pattern[0] == b'*' && pattern[1] == b'.' {
+        match name.iter().position(|c| *c == b'.') {
+            Some(dot) => dot > 0 && name[dot + 1..].eq_ignore_ascii_case(&pattern[2..]),
+            None => false,
+        }
+    } else {
+        pattern.eq_ignore_ascii_case(name)
+    }
+}
+
+/// Check whether the end-entity certificate `cert` is for the DNS `name`,
+/// i.e. one of its subject alternative names matches it (RFC 6125).
+///
+/// The common name isn't used, and malformed certificates don't match.
+pub(crate) fn certificate_matches_name(cert: &Bytes, name: &Bytes) -> bool {
+    match dns_names(cert) {
+        Ok(names) => names
+            .iter()
+            .any(|pattern| dns_name_matches(&pattern.declassify(), &name.declassify())),
+        Err(_) => false,
+    }
+}
+
+/// id-ce-basicConstraints (2.5.29.19)
+fn basic_constraints_oid() -> Bytes {
+    [0x55, 0x1D, 0x13].into()
+}
+
+/// Check whether the `cert` is a CA certificate.
+///
+/// ```ignore
+/// BasicConstraints ::= SEQUENCE {
+///     cA                      BOOLEAN DEFAULT FALSE,
+///     pathLenConstraint       INTEGER (0..MAX) OPTIONAL }
+/// ```
+fn is_ca(cert: &Bytes) -> Result<bool, Asn1Error> {
+    let (_, _, extensions) = read_validity_and_extensions(cert)?;
+    match extensions
+        .iter()
+        .find(|(oid, _)| *oid == basic_constraints_oid())
+    {
+        Some((_, basic_constraints)) => {
+            let (offset, _) = read_element(basic_constraints, 0, 0x30)?;
+            Ok(has_tag(basic_constraints, offset, 0x01)
+                && read_element_content(basic_constraints, offset, 0x01)? == Bytes::from([0xFF]))
+        }
+        None => Ok(false),
+    }
+}
+
+/// Verify that the `issuer` signed the `cert`.
+///
+/// ```ignore
+/// Certificate ::= SEQUENCE {
+///     tbsCertificate       TBSCertificate,
+///     signatureAlgorithm   AlgorithmIdentifier,
+///     signatureValue       BIT STRING }
+/// ```
+///
+/// The issuer name of the `cert` must be the subject name of the `issuer`.
+/// See [`issuer_signature`] for the supported algorithms.
+fn verify_certificate_signature(
+    cert: &Bytes,
+    issuer: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<(), TLSError> {
+    let (issuer_name, _) = certificate_names(cert)?;
+    let (_, subject_name) = certificate_names(issuer)?;
+    check_success(issuer_name == subject_name)?;
+
+    let (offset, _) = read_element(cert, 0, 0x30)?;
+    let (tbs_start, tbs_len) = read_element(cert, offset, 0x30)?;
+    let tbs_end = tbs_start + tbs_len;
+    let tbs_certificate = cert.slice(offset, tbs_end - offset);
+    let (algorithm, _) = read_element(cert, tbs_end, 0x30)?;
+    let signature_algorithm = read_element_content(cert, algorithm, 0x06)?;
+    let signature = read_element_content(cert, skip_element(cert, tbs_end, 0x30)?, 0x03)?;
+    // There are no unused bits in a signature.
+    check_success(signature.len() > 1 && signature[0].declassify() == 0)?;
+    let signature = signature.slice(1, signature.len() - 1);
+
+    let spki = verification_key_from_cert(issuer)?;
+    let issuer_key = cert_public_key(issuer, &spki)?;
+    let signature = issuer_signature(&spki.0, &signature_algorithm, &signature)?;
+    crypto.verify(&spki.0, &issuer_key, &tbs_certificate, &signature)
+}
+
+/// Check that the `cert` is valid at the time `now`, in seconds since the
+/// Unix epoch.
+fn check_validity(cert: &Bytes, now: u64) -> Result<(), TLSError> {
+    let (not_before, not_after, _) = read_validity_and_extensions(cert)?;
+    check_success(not_before <= now && now <= not_after)
+}
+
+/// Validate the certificate chain `cert_chain`, starting with the end-entity
+/// certificate, against the DER encoded `trust_anchors` at the time `now`, in
+/// seconds since the Unix epoch.
+///
+/// The chain must reach a trust anchor: a certificate of the chain is one, or
+/// is signed by one. Until then, each certificate must be valid at `now` and
+/// signed by the next one, which must be a CA certificate.
+/// The trust anchors themselves are trusted as they are.
+///
+/// Returns a [`BAD_CERTIFICATE`] error otherwise.
+pub(crate) fn verify_certificate_chain(
+    cert_chain: &[Bytes],
+    trust_anchors: &[Bytes],
+    now: u64,
+    crypto: &impl CryptoProvider,
+) -> Result<(), TLSError> {
+    for (index, cert) in cert_chain.iter().enumerate() {
+        if trust_anchors.contains(cert) {
+            return Ok(());
+        }
+        check_validity(cert, now).map_err(|_| BAD_CERTIFICATE)?;
+        if trust_anchors
+            .iter()
+            .any(|anchor| verify_certificate_signature(cert, anchor, crypto).is_ok())
+        {
+            return Ok(());
+        }
+        let issuer = cert_chain.get(index + 1).ok_or(BAD_CERTIFICATE)?;
+        check_success(is_ca(issuer)?).map_err(|_| BAD_CERTIFICATE)?;
+        verify_certificate_signature(cert, issuer, crypto).map_err(|_| BAD_CERTIFICATE)?;
+    }
+    Err(BAD_CERTIFICATE)
+}
+
+/// Check that the end-entity certificate `cert` allows delegated credentials.
+///
+/// It must have the DelegationUsage extension, and allow digital signatures
+/// if it has a key usage extension (RFC 9345, Section 4.2).
+///
+/// Returns an [`ILLEGAL_PARAMETER`] error otherwise.
+fn check_delegation_usage(cert: &Bytes) -> Result<(), TLSError> {
+    let (_, _, extensions) = read_validity_and_extensions(cert)?;
+    let delegation_usage = extensions
+        .iter()
+        .any(|(oid, _)| *oid == delegation_usage_oid());
+    let digital_signature = match extensions.iter().find(|(oid, _)| *oid == key_usage_oid()) {
+        // The digitalSignature bit is the first bit of the KeyUsage bit string.
+        Some((_, key_usage)) => {
+            let bits = read_element_content(key_usage, 0, 0x03)?;
+            bits.len() > 1 && bits[1].declassify() & 0x80 != 0
+        }
+        None => true,
+    };
+    if delegation_usage && digital_signature {
+        Ok(())
+    } else {
+        Err(ILLEGAL_PARAMETER)
+    }
+}
+
+/// Verify the delegated credential `dc` for the end-entity certificate
+/// `cert`.
+///
+/// The certificate must allow delegated credentials, and its key must have
+/// signed the credential. The expiry of the credential is checked separately,
+/// see [`check_delegated_credential_validity`].
+///
+/// Returns the delegated key, which the server signs the CertificateVerify
+/// message with.
+pub(crate) fn verify_delegated_credential(
+    dc: &DelegatedCredentialData,
+    cert: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<PublicVerificationKey, TLSError> {
+    check_delegation_usage(cert)?;
+    let spki = verification_key_from_cert(cert)?;
+    if dc.algorithm != spki.0 {
+        return Err(ILLEGAL_PARAMETER);
+    }
+    let cert_key = cert_public_key(cert, &spki)?;
+    let signature = match dc.algorithm {
+        SignatureScheme::EcdsaSecp256r1Sha256 => parse_ecdsa_signature(dc.signature.clone())?,
+        _ => dc.signature.clone(),
+    };
+    let signed = signature_input(cert, &dc.credential, &dc.algorithm)?;
+    crypto.verify(&dc.algorithm, &cert_key, &signed, &signature)?;
+
+    let dc_spki = verification_key_from_spki(&dc.spki)?;
+    if dc_spki.0 != dc.dc_cert_verify_algorithm {
+        return Err(ILLEGAL_PARAMETER);
+    }
+    cert_public_key(&dc.spki, &dc_spki)
+}
+
+/// The longest a delegated credential may remain valid, 7 days.
+const MAX_DELEGATED_CREDENTIAL_VALIDITY: u64 = 7 * 24 * 3600;
+
+/// Check that the delegated credential `dc` for the end-entity certificate
+/// `cert` is valid at the time `now`, in seconds since the Unix epoch.
+///
+/// The credential expires `valid_time` seconds after the notBefore time of
+/// the certificate, which must be at most 7 days after `now`.
+///
+/// Returns a [`BAD_DELEGATED_CREDENTIAL`] error otherwise.
+pub(crate) fn check_delegated_credential_validity(
+    dc: &DelegatedCredentialData,
+    cert: &Bytes,
+    now: u64,
+) -> Result<(), TLSError> {
+    let (not_before, _, _) = read_validity_and_extensions(cert)?;
+    let expiry = not_before + dc.valid_time as u64;
+    if now < not_before || expiry < now || expiry - now > MAX_DELEGATED_CREDENTIAL_VALIDITY {
+        Err(BAD_DELEGATED_CREDENTIAL)
+    } else {
+        Ok(())
     }
 }
 
@@ -442,6 +1294,114 @@ mod unit_test {
         test(&cert);
     }
 
+    #[test]
+    fn ml_dsa_cert() {
+        let cert = fs::read("tests/assets/mldsa65_cert.der").expect("Error reading cert");
+        test(&cert.into());
+
+        let key = fs::read("tests/assets/mldsa65_key.der").expect("Error reading key");
+        let sk = ml_dsa_65_private_key(&key.into()).expect("Error reading ML-DSA key");
+        assert_eq!(sk.len(), ML_DSA_65_SIGNING_KEY_LEN);
+    }
+
+    #[test]
+    fn ocsp_response() {
+        let response = fs::read("tests/assets/chain_ocsp.der").expect("Error reading response");
+        let response = parse_ocsp_response(&response.into()).unwrap();
+        assert_eq!(response.signature_algorithm, ecdsa_with_sha256_oid());
+        assert_eq!(response.responses.len(), 1);
+        let single = &response.responses[0];
+        assert_eq!(single.status, OcspCertStatus::Good);
+        assert_eq!(single.issuer_name_hash.len(), 32);
+        assert_eq!(single.this_update, 1792396388);
+        assert_eq!(single.next_update, Some(2423116388));
+
+        // Truncated responses are rejected.
+        let response = fs::read("tests/assets/chain_ocsp_revoked.der").unwrap();
+        for len in 0..response.len() {
+            assert!(parse_ocsp_response(&response[..len].into()).is_err());
+        }
+        let response = parse_ocsp_response(&response.into()).unwrap();
+        assert_eq!(response.responses[0].status, OcspCertStatus::Revoked);
+    }
+
+    #[test]
+    fn validity_and_extensions() {
+        // UTCTime from 2015-01-01 to 2030-01-01, without DelegationUsage.
+        let cert: Bytes = GOO_GL_DER.into();
+        let (not_before, not_after, _) = read_validity_and_extensions(&cert).unwrap();
+        assert_eq!((not_before, not_after), (1420070400, 1893456000));
+        assert_eq!(check_delegation_usage(&cert), Err(ILLEGAL_PARAMETER));
+
+        let cert: Bytes = fs::read("tests/assets/dc_cert.der").unwrap().into();
+        let (not_before, not_after, extensions) = read_validity_and_extensions(&cert).unwrap();
+        assert_eq!((not_before, not_after), (1792368000, 2423088000));
+        assert!(extensions
+            .iter()
+            .any(|(oid, value)| *oid == delegation_usage_oid()
+                && *value == Bytes::from([0x05, 0x00])));
+        assert_eq!(check_delegation_usage(&cert), Ok(()));
+
+        // Truncated certificates are rejected.
+        for len in 0..cert.len() {
+            assert!(read_validity_and_extensions(&cert.slice(0, len)).is_err());
+        }
+    }
+
+    #[test]
+    fn issuer_and_subject_names() {
+        let cert: Bytes = GOO_GL_DER.into();
+        let (_, issuer, subject) = read_serial_and_names(&cert).unwrap();
+        assert_eq!(certificate_names(&cert), Ok((issuer.clone(), subject)));
+        assert!(chain_matches_authorities(&[cert.clone()], &[issuer]));
+
+        // A self-signed certificate.
+        let dc_cert: Bytes = fs::read("tests/assets/dc_cert.der").unwrap().into();
+        let (issuer, subject) = certificate_names(&dc_cert).unwrap();
+        assert_eq!(issuer, subject);
+        assert!(chain_matches_authorities(
+            &[cert.clone(), dc_cert.clone()],
+            &[subject.clone()]
+        ));
+        assert!(!chain_matches_authorities(&[cert], &[subject.clone()]));
+        assert!(!chain_matches_authorities(&[], &[subject]));
+
+        // Truncated certificates are rejected.
+        for len in 0..dc_cert.len() {
+            assert!(certificate_names(&dc_cert.slice(0, len)).is_err());
+        }
+    }
+
+    #[test]
+    fn dns_names_of_certificates() {
+        let dc_cert: Bytes = fs::read("tests/assets/dc_cert.der").unwrap().into();
+        assert_eq!(dns_names(&dc_cert), Ok(vec![Bytes::from(b"localhost")]));
+        assert!(certificate_matches_name(
+            &dc_cert,
+            &Bytes::from(b"localhost")
+        ));
+        assert!(certificate_matches_name(
+            &dc_cert,
+            &Bytes::from(b"LocalHost")
+        ));
+        assert!(!certificate_matches_name(
+            &dc_cert,
+            &Bytes::from(b"localhost.example")
+        ));
+
+        // The common name isn't used.
+        let cert: Bytes = GOO_GL_DER.into();
+        assert_eq!(dns_names(&cert), Ok(vec![]));
+        assert!(!certificate_matches_name(&cert, &Bytes::from(b"goo.gl")));
+
+        // A wildcard matches exactly one label.
+        assert!(dns_name_matches(b"*.example.com", b"www.example.com"));
+        assert!(!dns_name_matches(b"*.example.com", b"example.com"));
+        assert!(!dns_name_matches(b"*.example.com", b".example.com"));
+        assert!(!dns_name_matches(b"*.example.com", b"a.www.example.com"));
+        assert!(!dns_name_matches(b"www.*.com", b"www.example.com"));
+    }
+
     #[test]
     fn read_cert() {
         let files = fs::read_dir("test_certs").expect("Error listing files.");
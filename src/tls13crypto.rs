@@ -1,3 +1,11 @@
+use ccm::{
+    aead::{
+        generic_array::{typenum::Unsigned, GenericArray},
+        AeadInPlace,
+    },
+    consts::{U12, U16, U8 as Tag8},
+    Ccm, KeyInit,
+};
 #[cfg(feature = "hax-pv")]
 use hax_lib_macros::{pv_constructor, pv_handwritten};
 use libcrux::{
@@ -5,14 +13,20 @@ use libcrux::{
     signature::rsa_pss::{RsaPssKeySize, RsaPssPrivateKey, RsaPssPublicKey},
     *,
 };
+use libcrux_ml_dsa::ml_dsa_65::{
+    self, MLDSA65Signature, MLDSA65SigningKey, MLDSA65VerificationKey,
+};
 use rand::{CryptoRng, RngCore};
 use std::fmt::Display;
 
 use crate::tls13utils::{
-    check_mem, eq, length_u16_encoded, tlserr, Bytes, Error, TLSError, CRYPTO_ERROR,
+    check_mem, eq_slice, length_u16_encoded, tlserr, Bytes, Error, TLSError, CRYPTO_ERROR,
     INVALID_SIGNATURE, U8, UNSUPPORTED_ALGORITHM,
 };
 
+mod provider;
+pub use provider::{CryptoProvider, LibcruxProvider};
+
 pub(crate) type Random = Bytes;
 pub type SignatureKey = Bytes;
 pub(crate) type Psk = Bytes;
@@ -25,6 +39,11 @@ pub(crate) type Digest = Bytes;
 pub(crate) type AeadIV = Bytes;
 pub(crate) type VerificationKey = Bytes;
 
+/// AES-128-CCM with a 16 byte tag and a 12 byte nonce.
+type Aes128Ccm = Ccm<aes::Aes128, U16, U12>;
+/// AES-128-CCM with an 8 byte tag and a 12 byte nonce.
+type Aes128Ccm8 = Ccm<aes::Aes128, Tag8, U12>;
+
 /// An AEAD key and iv package.
 pub(crate) struct AeadKeyIV {
     pub(crate) key: AeadKey,
@@ -39,7 +58,7 @@ impl AeadKeyIV {
 }
 
 /// An AEAD key.
-pub(crate) struct AeadKey {
+pub struct AeadKey {
     bytes: Bytes,
     alg: AeadAlgorithm,
 }
@@ -64,28 +83,40 @@ impl AeadKey {
             AeadAlgorithm::Aes256Gcm => Ok(aead::Key::Aes256(aead::Aes256Key(
                 self.bytes.declassify_array()?,
             ))),
+            // libcrux does not implement AES-CCM.
+            AeadAlgorithm::Aes128Ccm | AeadAlgorithm::Aes128Ccm8 => tlserr(UNSUPPORTED_ALGORITHM),
         }
     }
 
+    /// Get the length of the authentication tag for this key.
+    pub(crate) fn tag_len(&self) -> usize {
+        self.alg.tag_len()
+    }
+
     /// Get the raw bytes of the key.
-    #[cfg(test)]
-    pub(crate) fn bytes(&self) -> &Bytes {
+    pub fn bytes(&self) -> &Bytes {
         &self.bytes
     }
+
+    /// Get the [`AeadAlgorithm`] of the key.
+    pub fn alg(&self) -> AeadAlgorithm {
+        self.alg
+    }
 }
 
 /// An RSA public key.
 #[derive(Debug)]
-pub(crate) struct RsaVerificationKey {
-    pub(crate) modulus: Bytes,
-    pub(crate) exponent: Bytes,
+pub struct RsaVerificationKey {
+    pub modulus: Bytes,
+    pub exponent: Bytes,
 }
 
 /// Bertie public verification keys.
 #[derive(Debug)]
-pub(crate) enum PublicVerificationKey {
+pub enum PublicVerificationKey {
     EcDsa(VerificationKey),  // Uncompressed point 0x04...
     Rsa(RsaVerificationKey), // N, e
+    MlDsa(VerificationKey),  // Raw ML-DSA-65 public key
 }
 
 /// Bertie hash algorithms.
@@ -152,23 +183,6 @@ pub(crate) fn hmac_tag(alg: &HashAlgorithm, mk: &MacKey, input: &Bytes) -> Resul
     .into())
 }
 
-/// Verify a given HMAC `tag`.
-///
-/// Returns `()` if successful or a [`TLSError`].
-#[cfg_attr(feature = "hax-pv", pv_handwritten)]
-pub(crate) fn hmac_verify(
-    alg: &HashAlgorithm,
-    mk: &MacKey,
-    input: &Bytes,
-    tag: &Bytes,
-) -> Result<(), TLSError> {
-    if eq(&hmac_tag(alg, mk, input)?, tag) {
-        Ok(())
-    } else {
-        tlserr(CRYPTO_ERROR)
-    }
-}
-
 /// Get an empty key of the correct size.
 pub(crate) fn zero_key(alg: &HashAlgorithm) -> Bytes {
     Bytes::zeroes(alg.hash_len())
@@ -222,6 +236,8 @@ pub enum AeadAlgorithm {
     Chacha20Poly1305,
     Aes128Gcm,
     Aes256Gcm,
+    Aes128Ccm,
+    Aes128Ccm8,
 }
 
 impl AeadAlgorithm {
@@ -231,6 +247,8 @@ impl AeadAlgorithm {
             AeadAlgorithm::Chacha20Poly1305 => 32,
             AeadAlgorithm::Aes128Gcm => 16,
             AeadAlgorithm::Aes256Gcm => 32,
+            AeadAlgorithm::Aes128Ccm => 16,
+            AeadAlgorithm::Aes128Ccm8 => 16,
         }
     }
 
@@ -240,12 +258,80 @@ impl AeadAlgorithm {
             AeadAlgorithm::Chacha20Poly1305 => 12,
             AeadAlgorithm::Aes128Gcm => 12,
             AeadAlgorithm::Aes256Gcm => 12,
+            AeadAlgorithm::Aes128Ccm => 12,
+            AeadAlgorithm::Aes128Ccm8 => 12,
+        }
+    }
+
+    /// Get the length of the authentication tag for this algorithm.
+    pub(crate) fn tag_len(self) -> usize {
+        match self {
+            AeadAlgorithm::Chacha20Poly1305 => 16,
+            AeadAlgorithm::Aes128Gcm => 16,
+            AeadAlgorithm::Aes256Gcm => 16,
+            AeadAlgorithm::Aes128Ccm => 16,
+            AeadAlgorithm::Aes128Ccm8 => 8,
         }
     }
 }
 
-/// AEAD encrypt
-pub(crate) fn aead_encrypt(
+/// AES-CCM encrypt with the cipher `C`.
+///
+/// The tag is appended to the ciphertext.
+fn ccm_encrypt<C: KeyInit + AeadInPlace>(
+    k: &Bytes,
+    iv: &AeadIV,
+    plain: &Bytes,
+    aad: &Bytes,
+) -> Result<Bytes, TLSError> {
+    let cipher = C::new_from_slice(&k.declassify()).map_err(|_| CRYPTO_ERROR)?;
+    let nonce: [u8; 12] = iv.declassify_array()?;
+    let mut buffer = plain.declassify();
+    match cipher.encrypt_in_place_detached(
+        GenericArray::from_slice(&nonce),
+        &aad.declassify(),
+        &mut buffer,
+    ) {
+        Ok(tag) => {
+            let cipby: Bytes = buffer.into();
+            let tagby: Bytes = tag.as_slice().into();
+            Ok(cipby.concat(tagby))
+        }
+        Err(_) => tlserr(CRYPTO_ERROR),
+    }
+}
+
+/// AES-CCM decrypt with the cipher `C`.
+///
+/// The `tag` has to be split off the ciphertext by the caller.
+fn ccm_decrypt<C: KeyInit + AeadInPlace>(
+    k: &Bytes,
+    iv: &AeadIV,
+    cip: &Bytes,
+    tag: &Bytes,
+    aad: &Bytes,
+) -> Result<Bytes, TLSError> {
+    let cipher = C::new_from_slice(&k.declassify()).map_err(|_| CRYPTO_ERROR)?;
+    let nonce: [u8; 12] = iv.declassify_array()?;
+    let tag = tag.declassify();
+    if tag.len() != <C::TagSize as Unsigned>::USIZE {
+        tlserr(CRYPTO_ERROR)
+    } else {
+        let mut buffer = cip.declassify();
+        match cipher.decrypt_in_place_detached(
+            GenericArray::from_slice(&nonce),
+            &aad.declassify(),
+            &mut buffer,
+            GenericArray::from_slice(&tag),
+        ) {
+            Ok(()) => Ok(buffer.into()),
+            Err(_) => tlserr(CRYPTO_ERROR),
+        }
+    }
+}
+
+/// AEAD encrypt with libcrux.
+fn libcrux_aead_encrypt(
     k: &AeadKey,
     iv: &AeadIV,
     plain: &Bytes,
@@ -267,17 +353,28 @@ pub(crate) fn aead_encrypt(
     }
 }
 
-/// AEAD decrypt.
-pub(crate) fn aead_decrypt(
+/// AEAD encrypt
+pub(crate) fn aead_encrypt(
     k: &AeadKey,
     iv: &AeadIV,
-    cip: &Bytes,
+    plain: &Bytes,
     aad: &Bytes,
 ) -> Result<Bytes, TLSError> {
-    // event!(Level::DEBUG, "AEAD decrypt with {:?}", k.alg);
+    match k.alg {
+        AeadAlgorithm::Aes128Ccm => ccm_encrypt::<Aes128Ccm>(&k.bytes, iv, plain, aad),
+        AeadAlgorithm::Aes128Ccm8 => ccm_encrypt::<Aes128Ccm8>(&k.bytes, iv, plain, aad),
+        _ => libcrux_aead_encrypt(k, iv, plain, aad),
+    }
+}
 
-    let tag = cip.slice(cip.len() - 16, 16);
-    let cip = cip.slice(0, cip.len() - 16);
+/// AEAD decrypt with libcrux.
+fn libcrux_aead_decrypt(
+    k: &AeadKey,
+    iv: &AeadIV,
+    cip: &Bytes,
+    tag: &Bytes,
+    aad: &Bytes,
+) -> Result<Bytes, TLSError> {
     let tag: [u8; 16] = tag.declassify_array()?;
     let plain = aead::decrypt_detached(
         &k.as_libcrux_key()?,
@@ -292,14 +389,47 @@ pub(crate) fn aead_decrypt(
     }
 }
 
+/// AEAD decrypt.
+///
+/// The last [`AeadAlgorithm::tag_len`] bytes of `cip` are the tag.
+pub(crate) fn aead_decrypt(
+    k: &AeadKey,
+    iv: &AeadIV,
+    cip: &Bytes,
+    aad: &Bytes,
+) -> Result<Bytes, TLSError> {
+    // event!(Level::DEBUG, "AEAD decrypt with {:?}", k.alg);
+
+    let tag_len = k.tag_len();
+    if cip.len() < tag_len {
+        tlserr(CRYPTO_ERROR)
+    } else {
+        let tag = cip.slice(cip.len() - tag_len, tag_len);
+        let cip = cip.slice(0, cip.len() - tag_len);
+        match k.alg {
+            AeadAlgorithm::Aes128Ccm => ccm_decrypt::<Aes128Ccm>(&k.bytes, iv, &cip, &tag, aad),
+            AeadAlgorithm::Aes128Ccm8 => ccm_decrypt::<Aes128Ccm8>(&k.bytes, iv, &cip, &tag, aad),
+            _ => libcrux_aead_decrypt(k, iv, &cip, &tag, aad),
+        }
+    }
+}
+
 /// Signature schemes for Bertie.
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum SignatureScheme {
     RsaPssRsaSha256,
     EcdsaSecp256r1Sha256,
     ED25519,
+    MlDsa65,
 }
 
+/// Length of an ML-DSA-65 signing key in bytes.
+pub(crate) const ML_DSA_65_SIGNING_KEY_LEN: usize = 4032;
+/// Length of an ML-DSA-65 verification key in bytes.
+pub(crate) const ML_DSA_65_VERIFICATION_KEY_LEN: usize = 1952;
+/// Length of an ML-DSA-65 signature in bytes.
+pub(crate) const ML_DSA_65_SIGNATURE_LEN: usize = 3309;
+
 impl SignatureScheme {
     /// Get the libcrux signature algorithm from the [`SignatureScheme`].
     fn libcrux_scheme(&self) -> Result<signature::Algorithm, TLSError> {
@@ -311,10 +441,43 @@ impl SignatureScheme {
             SignatureScheme::EcdsaSecp256r1Sha256 => Ok(signature::Algorithm::EcDsaP256(
                 signature::DigestAlgorithm::Sha256,
             )),
+            // ML-DSA is not part of the libcrux signature API.
+            SignatureScheme::MlDsa65 => tlserr(UNSUPPORTED_ALGORITHM),
+        }
+    }
+
+    /// Returns the TLS code point for this scheme when it is supported, or a
+    /// [`TLSError`] otherwise.
+    pub(crate) fn code_point(&self) -> Result<Bytes, TLSError> {
+        match self {
+            SignatureScheme::RsaPssRsaSha256 => Ok([0x08, 0x04].into()),
+            SignatureScheme::EcdsaSecp256r1Sha256 => Ok([0x04, 0x03].into()),
+            SignatureScheme::ED25519 => tlserr(UNSUPPORTED_ALGORITHM),
+            SignatureScheme::MlDsa65 => Ok([0x09, 0x05].into()),
+        }
+    }
+
+    /// Get the [`SignatureScheme`] for the TLS code point in `bytes`.
+    ///
+    /// Only schemes in [`VERIFIABLE_SIGNATURE_SCHEMES`] are recognised.
+    pub(crate) fn from_code_point(bytes: &[U8]) -> Result<Self, TLSError> {
+        let mut out = tlserr(UNSUPPORTED_ALGORITHM);
+        for scheme in VERIFIABLE_SIGNATURE_SCHEMES {
+            if eq_slice(scheme.code_point()?.as_raw(), bytes) {
+                out = Ok(scheme);
+            }
         }
+        out
     }
 }
 
+/// The signature schemes Bertie can verify in a CertificateVerify message.
+pub(crate) const VERIFIABLE_SIGNATURE_SCHEMES: [SignatureScheme; 3] = [
+    SignatureScheme::EcdsaSecp256r1Sha256,
+    SignatureScheme::RsaPssRsaSha256,
+    SignatureScheme::MlDsa65,
+];
+
 /// Sign the `input` with the provided RSA key.
 #[cfg_attr(feature = "hax-pv", pv_constructor)]
 pub(crate) fn sign_rsa(
@@ -356,6 +519,18 @@ pub(crate) fn sign(
     sk: &Bytes,
     input: &Bytes,
     rng: &mut (impl CryptoRng + RngCore),
+) -> Result<Bytes, TLSError> {
+    match algorithm {
+        SignatureScheme::MlDsa65 => sign_ml_dsa_65(sk, input, rng),
+        _ => libcrux_sign(algorithm, sk, input, rng),
+    }
+}
+
+fn libcrux_sign(
+    algorithm: &SignatureScheme,
+    sk: &Bytes,
+    input: &Bytes,
+    rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<Bytes, TLSError> {
     let sig = match algorithm {
         SignatureScheme::EcdsaSecp256r1Sha256 => signature::sign(
@@ -373,6 +548,9 @@ pub(crate) fn sign(
         SignatureScheme::RsaPssRsaSha256 => {
             panic!("wrong function, use sign_rsa")
         }
+        SignatureScheme::MlDsa65 => {
+            panic!("wrong function, use sign_ml_dsa_65")
+        }
     };
     match sig {
         Ok(signature::Signature::Ed25519(sig)) => Ok(sig.as_bytes().into()),
@@ -385,6 +563,24 @@ pub(crate) fn sign(
     }
 }
 
+/// Sign the `input` with the ML-DSA-65 signing key `sk`.
+///
+/// This uses the hedged variant with an empty context string.
+fn sign_ml_dsa_65(
+    sk: &Bytes,
+    input: &Bytes,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<Bytes, TLSError> {
+    let mut randomness = [0u8; 32];
+    rng.fill_bytes(&mut randomness);
+
+    let sk = MLDSA65SigningKey::new(sk.declassify_array()?);
+    match ml_dsa_65::sign(&sk, &input.declassify(), b"", randomness) {
+        Ok(sig) => Ok(sig.as_slice().into()),
+        Err(_) => tlserr(CRYPTO_ERROR),
+    }
+}
+
 /// Verify the `input` bytes against the provided `signature`.
 ///
 /// Return `Ok(())` if the verification succeeds, and a [`TLSError`] otherwise.
@@ -448,6 +644,14 @@ pub(crate) fn verify(
                 }
             }
         }
+        (SignatureScheme::MlDsa65, PublicVerificationKey::MlDsa(pk)) => {
+            let pk = MLDSA65VerificationKey::new(pk.declassify_array()?);
+            let sig = MLDSA65Signature::new(sig.declassify_array()?);
+            match ml_dsa_65::verify(&pk, &input.declassify(), b"", &sig) {
+                Ok(()) => Ok(()),
+                Err(_) => tlserr(INVALID_SIGNATURE),
+            }
+        }
         _ => tlserr(UNSUPPORTED_ALGORITHM),
     }
 }
@@ -483,10 +687,15 @@ pub enum KemScheme {
     X448,
     Secp384r1,
     Secp521r1,
+    X25519MlKem768,
 }
 
 impl KemScheme {
     /// Get the libcrux algorithm for this [`KemScheme`].
+    ///
+    /// The hybrid [`KemScheme::X25519MlKem768`] has no single libcrux algorithm,
+    /// it is assembled from ML-KEM 768 and X25519 in [`kem_keygen`],
+    /// [`kem_encap`], and [`kem_decap`].
     fn libcrux_algorithm(self) -> Result<kem::Algorithm, TLSError> {
         match self {
             KemScheme::X25519 => Ok(kem::Algorithm::X25519),
@@ -496,25 +705,50 @@ impl KemScheme {
     }
 }
 
+/// The length of an ML-KEM 768 encapsulation key.
+const ML_KEM_768_PK_LEN: usize = 1184;
+
+/// The length of an ML-KEM 768 decapsulation key.
+const ML_KEM_768_SK_LEN: usize = 2400;
+
+/// The length of an ML-KEM 768 ciphertext.
+const ML_KEM_768_CT_LEN: usize = 1088;
+
+/// The length of X25519 private and public keys.
+const X25519_LEN: usize = 32;
+
 /// Generate a new KEM key pair.
+///
+/// For [`KemScheme::X25519MlKem768`] this is the `X25519MLKEM768` group of
+/// draft-ietf-tls-ecdhe-mlkem: the key share is the ML-KEM 768 encapsulation
+/// key followed by the X25519 public key.
 #[cfg_attr(feature = "hax-pv", pv_handwritten)]
 pub(crate) fn kem_keygen(
     alg: KemScheme,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<(KemSk, KemPk), TLSError> {
-    let res = kem::key_gen(alg.libcrux_algorithm()?, rng);
-    match res {
-        Ok((sk, pk)) => {
-            // event!(
-            //     Level::TRACE,
-            //     "Generated KEM public key: {}",
-            //     Bytes::from(pk.encode()).as_hex()
-            // );
-            Ok((
-                Bytes::from(sk.encode()),
-                encoding_prefix(alg).concat(Bytes::from(pk.encode())),
-            ))
-        }
+    if alg == KemScheme::X25519MlKem768 {
+        let (ml_kem_sk, ml_kem_pk) = libcrux_kem_keygen(kem::Algorithm::MlKem768, rng)?;
+        let (x25519_sk, x25519_pk) = libcrux_kem_keygen(kem::Algorithm::X25519, rng)?;
+        Ok((ml_kem_sk.concat(x25519_sk), ml_kem_pk.concat(x25519_pk)))
+    } else {
+        let (sk, pk) = libcrux_kem_keygen(alg.libcrux_algorithm()?, rng)?;
+        // event!(
+        //     Level::TRACE,
+        //     "Generated KEM public key: {}",
+        //     pk.as_hex()
+        // );
+        Ok((sk, encoding_prefix(alg).concat(pk)))
+    }
+}
+
+/// Generate a key pair with the libcrux `algorithm`.
+fn libcrux_kem_keygen(
+    algorithm: kem::Algorithm,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(Bytes, Bytes), TLSError> {
+    match kem::key_gen(algorithm, rng) {
+        Ok((sk, pk)) => Ok((Bytes::from(sk.encode()), Bytes::from(pk.encode()))),
         Err(_) => tlserr(CRYPTO_ERROR),
     }
 }
@@ -541,6 +775,10 @@ fn into_raw(alg: KemScheme, point: Bytes) -> Bytes {
 }
 
 /// KEM encapsulation
+///
+/// For [`KemScheme::X25519MlKem768`] the ciphertext is the ML-KEM 768
+/// ciphertext followed by the ephemeral X25519 public key, and the shared
+/// secret is the ML-KEM 768 shared secret followed by the X25519 one.
 #[cfg_attr(feature = "hax-pv", pv_constructor)]
 pub(crate) fn kem_encap(
     alg: KemScheme,
@@ -550,16 +788,42 @@ pub(crate) fn kem_encap(
     // event!(Level::DEBUG, "KEM Encaps with {alg:?}");
     // event!(Level::TRACE, "  pk:  {}", pk.as_hex());
 
-    let pk = into_raw(alg, pk.clone());
-    let pk = PublicKey::decode(alg.libcrux_algorithm()?, &pk.declassify()).unwrap();
-    let res = pk.encapsulate(rng);
-    match res {
-        Ok((shared_secret, ct)) => {
-            let ct = encoding_prefix(alg).concat(Bytes::from(ct.encode()));
-            let shared_secret = to_shared_secret(alg, Bytes::from(shared_secret.encode()));
-            // event!(Level::TRACE, "  output ciphertext: {}", ct.as_hex());
-            Ok((shared_secret, ct))
+    if alg == KemScheme::X25519MlKem768 {
+        let pk = pk.declassify();
+        if pk.len() != ML_KEM_768_PK_LEN + X25519_LEN {
+            tlserr(CRYPTO_ERROR)
+        } else {
+            let (ml_kem_ss, ml_kem_ct) =
+                libcrux_kem_encap(kem::Algorithm::MlKem768, &pk[..ML_KEM_768_PK_LEN], rng)?;
+            let (x25519_ss, x25519_ct) =
+                libcrux_kem_encap(kem::Algorithm::X25519, &pk[ML_KEM_768_PK_LEN..], rng)?;
+            Ok((ml_kem_ss.concat(x25519_ss), ml_kem_ct.concat(x25519_ct)))
         }
+    } else {
+        let pk = into_raw(alg, pk.clone());
+        let (shared_secret, ct) =
+            libcrux_kem_encap(alg.libcrux_algorithm()?, &pk.declassify(), rng)?;
+        let ct = encoding_prefix(alg).concat(ct);
+        let shared_secret = to_shared_secret(alg, shared_secret);
+        // event!(Level::TRACE, "  output ciphertext: {}", ct.as_hex());
+        Ok((shared_secret, ct))
+    }
+}
+
+/// Encapsulate to the raw public key `pk` with the libcrux `algorithm`.
+fn libcrux_kem_encap(
+    algorithm: kem::Algorithm,
+    pk: &[u8],
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(Bytes, Bytes), TLSError> {
+    match PublicKey::decode(algorithm, pk) {
+        Ok(pk) => match pk.encapsulate(rng) {
+            Ok((shared_secret, ct)) => Ok((
+                Bytes::from(shared_secret.encode()),
+                Bytes::from(ct.encode()),
+            )),
+            Err(_) => tlserr(CRYPTO_ERROR),
+        },
         Err(_) => tlserr(CRYPTO_ERROR),
     }
 }
@@ -581,19 +845,61 @@ pub(crate) fn kem_decap(alg: KemScheme, ct: &Bytes, sk: &Bytes) -> Result<Bytes,
     // event!(Level::DEBUG, "KEM Decaps with {alg:?}");
     // event!(Level::TRACE, "  with ciphertext: {}", ct.as_hex());
 
-    let librux_algorithm = alg.libcrux_algorithm()?;
-    let sk = PrivateKey::decode(librux_algorithm, &sk.declassify()).unwrap();
-    let ct = into_raw(alg, ct.clone()).declassify();
-    let ct = Ct::decode(librux_algorithm, &ct).unwrap();
-    let res = ct.decapsulate(&sk);
-    match res {
-        Ok(shared_secret) => {
-            let shared_secret: Bytes = shared_secret.encode().into();
-            // event!(Level::TRACE, "  shared secret: {}", shared_secret.as_hex());
-            let shared_secret = to_shared_secret(alg, shared_secret);
-            Ok(shared_secret)
+    if alg == KemScheme::X25519MlKem768 {
+        let ct = ct.declassify();
+        let sk = sk.declassify();
+        if ct.len() != ML_KEM_768_CT_LEN + X25519_LEN || sk.len() != ML_KEM_768_SK_LEN + X25519_LEN
+        {
+            tlserr(CRYPTO_ERROR)
+        } else {
+            let ml_kem_ss = libcrux_kem_decap(
+                kem::Algorithm::MlKem768,
+                &ct[..ML_KEM_768_CT_LEN],
+                &sk[..ML_KEM_768_SK_LEN],
+            )?;
+            let x25519_ss = libcrux_kem_decap(
+                kem::Algorithm::X25519,
+                &ct[ML_KEM_768_CT_LEN..],
+                &sk[ML_KEM_768_SK_LEN..],
+            )?;
+            Ok(ml_kem_ss.concat(x25519_ss))
+        }
+    } else {
+        let ct = into_raw(alg, ct.clone());
+        let shared_secret =
+            libcrux_kem_decap(alg.libcrux_algorithm()?, &ct.declassify(), &sk.declassify())?;
+        // event!(Level::TRACE, "  shared secret: {}", shared_secret.as_hex());
+        Ok(to_shared_secret(alg, shared_secret))
+    }
+}
+
+/// Decapsulate the raw ciphertext `ct` with the libcrux `algorithm`.
+fn libcrux_kem_decap(algorithm: kem::Algorithm, ct: &[u8], sk: &[u8]) -> Result<Bytes, TLSError> {
+    match (PrivateKey::decode(algorithm, sk), Ct::decode(algorithm, ct)) {
+        (Ok(sk), Ok(ct)) => match ct.decapsulate(&sk) {
+            Ok(shared_secret) => Ok(Bytes::from(shared_secret.encode())),
+            Err(_) => tlserr(CRYPTO_ERROR),
+        },
+        _ => tlserr(CRYPTO_ERROR),
+    }
+}
+
+/// The key exchange modes for PSKs (RFC 8446, Section 4.2.9).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PskKeyExchangeMode {
+    /// PSK-only key establishment, without a key exchange.
+    PskKe,
+    /// PSK with a key exchange, for forward secrecy.
+    PskDheKe,
+}
+
+impl PskKeyExchangeMode {
+    /// The code point of the mode.
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            PskKeyExchangeMode::PskKe => 0,
+            PskKeyExchangeMode::PskDheKe => 1,
         }
-        Err(_) => tlserr(CRYPTO_ERROR),
     }
 }
 
@@ -609,10 +915,14 @@ pub struct Algorithms {
     pub(crate) kem: KemScheme,
     pub(crate) psk_mode: bool,
     pub(crate) zero_rtt: bool,
+    pub(crate) psk_key_exchange_mode: PskKeyExchangeMode,
 }
 
 impl Algorithms {
     /// Create a new [`Algorithms`] object for the TLS 1.3 ciphersuite.
+    ///
+    /// PSKs are used with a key exchange, see
+    /// [`Algorithms::with_psk_key_exchange_mode`].
     pub const fn new(
         hash: HashAlgorithm,
         aead: AeadAlgorithm,
@@ -628,6 +938,18 @@ impl Algorithms {
             kem,
             psk_mode: psk,
             zero_rtt,
+            psk_key_exchange_mode: PskKeyExchangeMode::PskDheKe,
+        }
+    }
+
+    /// Use the PSK key exchange `mode`.
+    ///
+    /// With [`PskKeyExchangeMode::PskKe`], no key shares are exchanged and the
+    /// [`KemScheme`] is unused. This only applies in PSK mode.
+    pub const fn with_psk_key_exchange_mode(self, mode: PskKeyExchangeMode) -> Self {
+        Self {
+            psk_key_exchange_mode: mode,
+            ..self
         }
     }
 
@@ -661,6 +983,17 @@ impl Algorithms {
         self.zero_rtt
     }
 
+    /// Get the [`PskKeyExchangeMode`].
+    pub fn psk_key_exchange_mode(&self) -> PskKeyExchangeMode {
+        self.psk_key_exchange_mode
+    }
+
+    /// Returns `true` when the handshake has a key exchange, i.e. unless
+    /// using a PSK without one.
+    pub(crate) fn key_exchange(&self) -> bool {
+        !self.psk_mode || self.psk_key_exchange_mode == PskKeyExchangeMode::PskDheKe
+    }
+
     /// Returns the TLS ciphersuite for the given algorithm when it is supported, or
     /// a [`TLSError`] otherwise.
     pub(crate) fn ciphersuite(&self) -> Result<Bytes, TLSError> {
@@ -668,6 +1001,8 @@ impl Algorithms {
             (HashAlgorithm::SHA256, AeadAlgorithm::Aes128Gcm) => Ok([0x13, 0x01].into()),
             (HashAlgorithm::SHA384, AeadAlgorithm::Aes256Gcm) => Ok([0x13, 0x02].into()),
             (HashAlgorithm::SHA256, AeadAlgorithm::Chacha20Poly1305) => Ok([0x13, 0x03].into()),
+            (HashAlgorithm::SHA256, AeadAlgorithm::Aes128Ccm) => Ok([0x13, 0x04].into()),
+            (HashAlgorithm::SHA256, AeadAlgorithm::Aes128Ccm8) => Ok([0x13, 0x05].into()),
             _ => tlserr(UNSUPPORTED_ALGORITHM),
         }
     }
@@ -682,17 +1017,28 @@ impl Algorithms {
             KemScheme::X448 => tlserr(UNSUPPORTED_ALGORITHM),
             KemScheme::Secp384r1 => tlserr(UNSUPPORTED_ALGORITHM),
             KemScheme::Secp521r1 => tlserr(UNSUPPORTED_ALGORITHM),
+            KemScheme::X25519MlKem768 => Ok([0x11, 0xEC].into()),
         }
     }
 
     /// Returns the signature id for the given algorithm when it is supported, or a
     ///  [`TLSError`] otherwise.
     pub(crate) fn signature_algorithm(&self) -> Result<Bytes, TLSError> {
-        match self.signature() {
-            SignatureScheme::RsaPssRsaSha256 => Ok([0x08, 0x04].into()),
-            SignatureScheme::EcdsaSecp256r1Sha256 => Ok([0x04, 0x03].into()),
-            SignatureScheme::ED25519 => tlserr(UNSUPPORTED_ALGORITHM),
+        self.signature().code_point()
+    }
+
+    /// Returns the signature ids the client offers in `signature_algorithms`.
+    ///
+    /// This is the configured signature scheme first, followed by every other
+    /// scheme in [`VERIFIABLE_SIGNATURE_SCHEMES`].
+    pub(crate) fn signature_algorithms(&self) -> Result<Bytes, TLSError> {
+        let mut out = self.signature_algorithm()?;
+        for scheme in VERIFIABLE_SIGNATURE_SCHEMES {
+            if scheme != self.signature() {
+                out = out.concat(scheme.code_point()?);
+            }
         }
+        Ok(out)
     }
 
     /// Check the ciphersuite in `bytes` against this ciphersuite.
@@ -743,6 +1089,21 @@ impl TryFrom<&str> for Algorithms {
             "SHA384_Aes256Gcm_RsaPssRsaSha256_X25519" => {
                 Ok(SHA384_Aes256Gcm_RsaPssRsaSha256_X25519)
             }
+            "SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_X25519" => {
+                Ok(SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_X25519)
+            }
+            "SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_P256" => {
+                Ok(SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_P256)
+            }
+            "SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_X25519" => {
+                Ok(SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_X25519)
+            }
+            "SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_P256" => {
+                Ok(SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_P256)
+            }
+            "SHA256_Chacha20Poly1305_MlDsa65_X25519MlKem768" => {
+                Ok(SHA256_Chacha20Poly1305_MlDsa65_X25519MlKem768)
+            }
             _ => Err(Error::UnknownCiphersuite(format!(
                 "Invalid ciphersuite description: {}",
                 s
@@ -916,3 +1277,126 @@ pub const SHA384_Aes256Gcm_EcdsaSecp256r1Sha256_P256: Algorithms = Algorithms::n
     false,
     false,
 );
+
+/// `TLS_AES_128_CCM_SHA256`
+/// with
+/// * x25519 for key exchange
+/// * EcDSA P256 SHA256 for signatures
+pub const SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_X25519: Algorithms = Algorithms::new(
+    HashAlgorithm::SHA256,
+    AeadAlgorithm::Aes128Ccm,
+    SignatureScheme::EcdsaSecp256r1Sha256,
+    KemScheme::X25519,
+    false,
+    false,
+);
+
+/// `TLS_AES_128_CCM_SHA256`
+/// with
+/// * P256 for key exchange
+/// * EcDSA P256 SHA256 for signatures
+pub const SHA256_Aes128Ccm_EcdsaSecp256r1Sha256_P256: Algorithms = Algorithms::new(
+    HashAlgorithm::SHA256,
+    AeadAlgorithm::Aes128Ccm,
+    SignatureScheme::EcdsaSecp256r1Sha256,
+    KemScheme::Secp256r1,
+    false,
+    false,
+);
+
+/// `TLS_AES_128_CCM_8_SHA256`
+/// with
+/// * x25519 for key exchange
+/// * EcDSA P256 SHA256 for signatures
+pub const SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_X25519: Algorithms = Algorithms::new(
+    HashAlgorithm::SHA256,
+    AeadAlgorithm::Aes128Ccm8,
+    SignatureScheme::EcdsaSecp256r1Sha256,
+    KemScheme::X25519,
+    false,
+    false,
+);
+
+/// `TLS_AES_128_CCM_8_SHA256`
+/// with
+/// * P256 for key exchange
+/// * EcDSA P256 SHA256 for signatures
+pub const SHA256_Aes128Ccm8_EcdsaSecp256r1Sha256_P256: Algorithms = Algorithms::new(
+    HashAlgorithm::SHA256,
+    AeadAlgorithm::Aes128Ccm8,
+    SignatureScheme::EcdsaSecp256r1Sha256,
+    KemScheme::Secp256r1,
+    false,
+    false,
+);
+
+/// `TLS_CHACHA20_POLY1305_SHA256`
+/// with
+/// * X25519 + ML-KEM 768 hybrid for key exchange
+/// * ML-DSA 65 for signatures
+pub const SHA256_Chacha20Poly1305_MlDsa65_X25519MlKem768: Algorithms = Algorithms::new(
+    HashAlgorithm::SHA256,
+    AeadAlgorithm::Chacha20Poly1305,
+    SignatureScheme::MlDsa65,
+    KemScheme::X25519MlKem768,
+    false,
+    false,
+);
+
+#[cfg(test)]
+mod unit_test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    /// Read the named value from the X25519MLKEM768 known-answer vector.
+    fn kat_value(name: &str) -> Bytes {
+        let line = include_str!("tls13crypto/x25519mlkem768_kat.txt")
+            .lines()
+            .find(|line| line.split_whitespace().next() == Some(name))
+            .unwrap();
+        let value = Bytes::from_hex(line.split_whitespace().nth(2).unwrap());
+        assert_eq!(
+            value.len(),
+            line.split_whitespace()
+                .nth(1)
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+        );
+        value
+    }
+
+    #[test]
+    fn x25519_ml_kem_768_known_answer() {
+        let sk = kat_value("dk").concat(kat_value("xsk"));
+        let ss = kem_decap(KemScheme::X25519MlKem768, &kat_value("ct"), &sk).unwrap();
+        assert_eq!(ss, kat_value("ss"));
+
+        let (ss, ct) = kem_encap(KemScheme::X25519MlKem768, &kat_value("pk"), &mut OsRng).unwrap();
+        assert_eq!(ct.len(), ML_KEM_768_CT_LEN + X25519_LEN);
+        assert_eq!(kem_decap(KemScheme::X25519MlKem768, &ct, &sk).unwrap(), ss);
+    }
+
+    #[test]
+    fn x25519_ml_kem_768_round_trip() {
+        let (sk, pk) = kem_keygen(KemScheme::X25519MlKem768, &mut OsRng).unwrap();
+        assert_eq!(pk.len(), ML_KEM_768_PK_LEN + X25519_LEN);
+
+        let (ss, ct) = kem_encap(KemScheme::X25519MlKem768, &pk, &mut OsRng).unwrap();
+        assert_eq!(ss.len(), 64);
+        assert_eq!(kem_decap(KemScheme::X25519MlKem768, &ct, &sk).unwrap(), ss);
+
+        // The X25519 half of the key share is a plain X25519 public key.
+        let x25519_pk = pk.slice_range(ML_KEM_768_PK_LEN..pk.len());
+        let (x25519_ss, x25519_ct) = kem_encap(KemScheme::X25519, &x25519_pk, &mut OsRng).unwrap();
+        let x25519_sk = sk.slice_range(ML_KEM_768_SK_LEN..sk.len());
+        assert_eq!(
+            kem_decap(KemScheme::X25519, &x25519_ct, &x25519_sk).unwrap(),
+            x25519_ss
+        );
+
+        let truncated = ct.slice_range(0..ct.len() - 1);
+        assert!(kem_decap(KemScheme::X25519MlKem768, &truncated, &sk).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! # Crypto Provider
+//!
+//! The cryptographic primitives used by the handshake and the record layer.
+//!
+//! Bertie uses [`LibcruxProvider`] by default.
+//! A different backend, e.g. a FIPS validated library or an instrumented mock
+//! for testing, can be plugged in by implementing [`CryptoProvider`].
+
+use rand::{CryptoRng, RngCore};
+
+use crate::tls13utils::{eq, tlserr, Bytes, TLSError, CRYPTO_ERROR};
+
+use super::{
+    AeadIV, AeadKey, Digest, HashAlgorithm, Hmac, KemPk, KemScheme, KemSk, MacKey,
+    PublicVerificationKey, SignatureKey, SignatureScheme,
+};
+
+/// The cryptographic operations required by Bertie.
+pub trait CryptoProvider {
+    /// Hash `data` with the given `alg`.
+    ///
+    /// Returns the digest or a [`TLSError`].
+    fn hash(&self, alg: &HashAlgorithm, data: &Bytes) -> Result<Digest, TLSError>;
+
+    /// Compute the HMAC tag.
+    ///
+    /// Returns the tag or a [`TLSError`].
+    fn hmac_tag(&self, alg: &HashAlgorithm, mk: &MacKey, input: &Bytes) -> Result<Hmac, TLSError>;
+
+    /// Verify a given HMAC `tag`.
+    ///
+    /// Returns `()` if successful or a [`TLSError`].
+    fn hmac_verify(
+        &self,
+        alg: &HashAlgorithm,
+        mk: &MacKey,
+        input: &Bytes,
+        tag: &Bytes,
+    ) -> Result<(), TLSError> {
+        if eq(&self.hmac_tag(alg, mk, input)?, tag) {
+            Ok(())
+        } else {
+            tlserr(CRYPTO_ERROR)
+        }
+    }
+
+    /// HKDF Extract.
+    ///
+    /// Returns the result as [`Bytes`] or a [`TLSError`].
+    fn hkdf_extract(
+        &self,
+        alg: &HashAlgorithm,
+        ikm: &Bytes,
+        salt: &Bytes,
+    ) -> Result<Bytes, TLSError>;
+
+    /// HKDF Expand.
+    ///
+    /// Returns the result as [`Bytes`] or a [`TLSError`].
+    fn hkdf_expand(
+        &self,
+        alg: &HashAlgorithm,
+        prk: &Bytes,
+        info: &Bytes,
+        len: usize,
+    ) -> Result<Bytes, TLSError>;
+
+    /// AEAD encrypt `plain` with the key `k`, nonce `iv` and additional data `aad`.
+    ///
+    /// Returns the ciphertext with the tag appended, or a [`TLSError`].
+    fn aead_encrypt(
+        &self,
+        k: &AeadKey,
+        iv: &AeadIV,
+        plain: &Bytes,
+        aad: &Bytes,
+    ) -> Result<Bytes, TLSError>;
+
+    /// AEAD decrypt the ciphertext with the appended tag in `cip`.
+    ///
+    /// Returns the plaintext, or a [`TLSError`].
+    fn aead_decrypt(
+        &self,
+        k: &AeadKey,
+        iv: &AeadIV,
+        cip: &Bytes,
+        aad: &Bytes,
+    ) -> Result<Bytes, TLSError>;
+
+    /// Sign the bytes in `input` with the signature key `sk` and `algorithm`.
+    ///
+    /// RSA signatures are computed with [`CryptoProvider::sign_rsa`].
+    fn sign(
+        &self,
+        algorithm: &SignatureScheme,
+        sk: &SignatureKey,
+        input: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError>;
+
+    /// Sign the `input` with the provided RSA key.
+    fn sign_rsa(
+        &self,
+        sk: &SignatureKey,
+        pk_modulus: &Bytes,
+        pk_exponent: &Bytes,
+        cert_scheme: SignatureScheme,
+        input: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError>;
+
+    /// Verify the `input` bytes against the provided `sig`.
+    ///
+    /// Return `Ok(())` if the verification succeeds, and a [`TLSError`] otherwise.
+    fn verify(
+        &self,
+        alg: &SignatureScheme,
+        pk: &PublicVerificationKey,
+        input: &Bytes,
+        sig: &Bytes,
+    ) -> Result<(), TLSError>;
+
+    /// Generate a new KEM key pair.
+    fn kem_keygen(
+        &self,
+        alg: KemScheme,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(KemSk, KemPk), TLSError>;
+
+    /// KEM encapsulation.
+    ///
+    /// Returns the shared secret and the ciphertext.
+    fn kem_encap(
+        &self,
+        alg: KemScheme,
+        pk: &KemPk,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes), TLSError>;
+
+    /// KEM decapsulation.
+    ///
+    /// Returns the shared secret.
+    fn kem_decap(&self, alg: KemScheme, ct: &Bytes, sk: &KemSk) -> Result<Bytes, TLSError>;
+}
+
+/// The default [`CryptoProvider`], backed by libcrux.
+///
+/// AES-CCM is implemented with RustCrypto because libcrux doesn't provide it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LibcruxProvider;
+
+impl CryptoProvider for LibcruxProvider {
+    fn hash(&self, alg: &HashAlgorithm, data: &Bytes) -> Result<Digest, TLSError> {
+        alg.hash(data)
+    }
+
+    fn hmac_tag(&self, alg: &HashAlgorithm, mk: &MacKey, input: &Bytes) -> Result<Hmac, TLSError> {
+        super::hmac_tag(alg, mk, input)
+    }
+
+    fn hkdf_extract(
+        &self,
+        alg: &HashAlgorithm,
+        ikm: &Bytes,
+        salt: &Bytes,
+    ) -> Result<Bytes, TLSError> {
+        super::hkdf_extract(alg, ikm, salt)
+    }
+
+    fn hkdf_expand(
+        &self,
+        alg: &HashAlgorithm,
+        prk: &Bytes,
+        info: &Bytes,
+        len: usize,
+    ) -> Result<Bytes, TLSError> {
+        super::hkdf_expand(alg, prk, info, len)
+    }
+
+    fn aead_encrypt(
+        &self,
+        k: &AeadKey,
+        iv: &AeadIV,
+        plain: &Bytes,
+        aad: &Bytes,
+    ) -> Result<Bytes, TLSError> {
+        super::aead_encrypt(k, iv, plain, aad)
+    }
+
+    fn aead_decrypt(
+        &self,
+        k: &AeadKey,
+        iv: &AeadIV,
+        cip: &Bytes,
+        aad: &Bytes,
+    ) -> Result<Bytes, TLSError> {
+        super::aead_decrypt(k, iv, cip, aad)
+    }
+
+    fn sign(
+        &self,
+        algorithm: &SignatureScheme,
+        sk: &SignatureKey,
+        input: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError> {
+        super::sign(algorithm, sk, input, rng)
+    }
+
+    fn sign_rsa(
+        &self,
+        sk: &SignatureKey,
+        pk_modulus: &Bytes,
+        pk_exponent: &Bytes,
+        cert_scheme: SignatureScheme,
+        input: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError> {
+        super::sign_rsa(sk, pk_modulus, pk_exponent, cert_scheme, input, rng)
+    }
+
+    fn verify(
+        &self,
+        alg: &SignatureScheme,
+        pk: &PublicVerificationKey,
+        input: &Bytes,
+        sig: &Bytes,
+    ) -> Result<(), TLSError> {
+        super::verify(alg, pk, input, sig)
+    }
+
+    fn kem_keygen(
+        &self,
+        alg: KemScheme,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(KemSk, KemPk), TLSError> {
+        super::kem_keygen(alg, rng)
+    }
+
+    fn kem_encap(
+        &self,
+        alg: KemScheme,
+        pk: &KemPk,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes), TLSError> {
+        super::kem_encap(alg, pk, rng)
+    }
+
+    fn kem_decap(&self, alg: KemScheme, ct: &Bytes, sk: &KemSk) -> Result<Bytes, TLSError> {
+        super::kem_decap(alg, ct, sk)
+    }
+}
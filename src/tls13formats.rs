@@ -4,8 +4,14 @@
 #[cfg(not(feature = "secret_integers"))]
 use crate::tls13utils::Declassify;
 use crate::{
+    cert_compression::{
+        compress, decompress, supported_algorithms, CertificateCompressionAlgorithm,
+    },
+    ech::ECH_EXTENSION,
+    quic::QuicParameters,
     tls13crypto::{
-        zero_key, Algorithms, Digest, HashAlgorithm, Hmac, KemPk, Random, SignatureScheme,
+        zero_key, Algorithms, CryptoProvider, Digest, HashAlgorithm, Hmac, KemPk, Random,
+        SignatureScheme, ML_DSA_65_SIGNATURE_LEN,
     },
     tls13utils::{
         bytes1, bytes2, bytes_concat, check, check_eq, check_eq_slice, check_eq_with_slice,
@@ -14,12 +20,24 @@ use crate::{
         encode_length_u24, encode_length_u8, eq_slice, length_u16_encoded,
         length_u16_encoded_slice, length_u24_encoded, length_u8_encoded, parse_failed, tlserr,
         u32_as_be_bytes, Bytes, TLSError, APPLICATION_DATA_INSTEAD_OF_HANDSHAKE, DECODE_ERROR,
-        INVALID_COMPRESSION_LIST, INVALID_SIGNATURE, MISSING_KEY_SHARE, PROTOCOL_VERSION_ALERT,
-        PSK_MODE_MISMATCH, U32, U8, UNSUPPORTED_ALGORITHM,
+        ILLEGAL_PARAMETER, INVALID_COMPRESSION_LIST, INVALID_SIGNATURE, MISSING_KEY_SHARE,
+        NEGOTIATION_MISMATCH, PROTOCOL_VERSION_ALERT, PSK_MODE_MISMATCH, U32, U8,
+        UNSUPPORTED_ALGORITHM,
     },
 };
 
+pub(crate) mod client_hello_info;
+pub(crate) mod grease;
 pub(crate) mod handshake_data;
+use client_hello_info::{
+    alpn_protocols, certificate_compression_algorithms, key_share_groups, u16_list, u16_vector,
+    u8_vector,
+};
+pub use client_hello_info::{peek_client_hello, ClientHelloInfo};
+use grease::{
+    grease_cipher_suite, grease_extensions, grease_group, grease_key_share,
+    grease_signature_algorithm, grease_version, Grease,
+};
 #[cfg(not(bench))]
 use handshake_data::{HandshakeData, HandshakeType};
 #[cfg(bench)]
@@ -36,7 +54,7 @@ pub const LABEL_TLS13: [u8; 6] = [116, 108, 115, 049, 051, 032];
 pub const LABEL_DERIVED: [u8; 7] = [100, 101, 114, 105, 118, 101, 100];
 pub const LABEL_FINISHED: [u8; 8] = [102, 105, 110, 105, 115, 104, 101, 100];
 pub const LABEL_RES_BINDER: [u8; 10] = [114, 101, 115, 032, 098, 105, 110, 100, 101, 114];
-// pub const LABEL_EXT_BINDER: [u8; 10] = [101, 120, 116, 032, 098, 105, 110, 100, 101, 114];
+pub const LABEL_EXT_BINDER: [u8; 10] = [101, 120, 116, 032, 098, 105, 110, 100, 101, 114];
 pub const LABEL_EXP_MASTER: [u8; 10] = [101, 120, 112, 032, 109, 097, 115, 116, 101, 114];
 pub const LABEL_RES_MASTER: [u8; 10] = [114, 101, 115, 032, 109, 097, 115, 116, 101, 114];
 pub const LABEL_C_E_TRAFFIC: [u8; 11] = [099, 032, 101, 032, 116, 114, 097, 102, 102, 105, 099];
@@ -50,6 +68,10 @@ pub const LABEL_C_AP_TRAFFIC: [u8; 12] =
     [099, 032, 097, 112, 032, 116, 114, 097, 102, 102, 105, 099];
 pub const LABEL_S_AP_TRAFFIC: [u8; 12] =
     [115, 032, 097, 112, 032, 116, 114, 097, 102, 102, 105, 099];
+pub const LABEL_ECH_ACCEPT_CONFIRMATION: [u8; 23] = [
+    101, 099, 104, 032, 097, 099, 099, 101, 112, 116, 032, 099, 111, 110, 102, 105, 114, 109, 097,
+    116, 105, 111, 110,
+];
 
 pub const PREFIX_SERVER_SIGNATURE: [u8; 98] = [
     0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
@@ -61,6 +83,16 @@ pub const PREFIX_SERVER_SIGNATURE: [u8; 98] = [
     0x79, 0x00,
 ];
 
+pub const PREFIX_CLIENT_SIGNATURE: [u8; 98] = [
+    0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+    0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+    0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+    0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+    0x54, 0x4c, 0x53, 0x20, 0x31, 0x2e, 0x33, 0x2c, 0x20, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74, 0x20,
+    0x43, 0x65, 0x72, 0x74, 0x69, 0x66, 0x69, 0x63, 0x61, 0x74, 0x65, 0x56, 0x65, 0x72, 0x69, 0x66,
+    0x79, 0x00,
+];
+
 /// Build the server name out of the `name` bytes for the client hello.
 fn build_server_name(name: &Bytes) -> Result<Bytes, TLSError> {
     const PREFIX1: &[U8; 2] = &[U8(0), U8(0)];
@@ -83,8 +115,9 @@ fn check_server_name(extension: &[U8]) -> Result<Bytes, TLSError> {
 }
 
 /// Build the supported versions bytes for the client hello.
-fn supported_versions() -> Result<Bytes, TLSError> {
-    Ok(Bytes::from([0, 0x2b]).concat(encode_length_u16(encode_length_u8(&[U8(3), U8(4)])?)?))
+fn supported_versions(grease: Option<Grease>) -> Result<Bytes, TLSError> {
+    let versions = grease_version(grease).concat(bytes2(3, 4));
+    Ok(Bytes::from([0, 0x2b]).concat(encode_length_u16(encode_length_u8(versions.as_raw())?)?))
 }
 
 /// Check the TLS version in the provided `client_hello`.
@@ -98,16 +131,15 @@ fn server_supported_version(_algorithms: &Algorithms) -> Result<Bytes, TLSError>
     Ok(encode_length_u16(bytes2(3, 4))?.prefix(SUPPORTED_VERSION_PREFIX))
 }
 
+/// Check that the server selected TLS 1.3 in `supported_versions`.
 fn check_server_supported_version(_algs: &Algorithms, b: &[U8]) -> Result<(), TLSError> {
-    check_eq_slice(&[U8(3), U8(4)], b)
+    check_eq_slice(&[U8(3), U8(4)], b).map_err(|_| PROTOCOL_VERSION_ALERT)
 }
 
-fn supported_groups(algs: &Algorithms) -> Result<Bytes, TLSError> {
+fn supported_groups(algs: &Algorithms, grease: Option<Grease>) -> Result<Bytes, TLSError> {
     const SUPPORTED_GROUPS_PREFIX: &[U8; 2] = &[U8(0), U8(0x0a)];
-    Ok(
-        encode_length_u16(encode_length_u16(algs.supported_group()?)?)?
-            .prefix(SUPPORTED_GROUPS_PREFIX),
-    )
+    let groups = grease_group(grease).concat(algs.supported_group()?);
+    Ok(encode_length_u16(encode_length_u16(groups)?)?.prefix(SUPPORTED_GROUPS_PREFIX))
 }
 
 fn check_supported_groups(algs: &Algorithms, ch: &[U8]) -> Result<(), TLSError> {
@@ -115,29 +147,210 @@ fn check_supported_groups(algs: &Algorithms, ch: &[U8]) -> Result<(), TLSError>
     check_mem(algs.supported_group()?.as_raw(), &ch[2..ch.len()])
 }
 
-fn signature_algorithms(algs: &Algorithms) -> Result<Bytes, TLSError> {
-    Ok(bytes2(0, 0x0d).concat(encode_length_u16(encode_length_u16(
-        algs.signature_algorithm()?,
+fn signature_algorithms(algs: &Algorithms, grease: Option<Grease>) -> Result<Bytes, TLSError> {
+    let schemes = grease_signature_algorithm(grease).concat(algs.signature_algorithms()?);
+    Ok(bytes2(0, 0x0d).concat(encode_length_u16(encode_length_u16(schemes)?)?))
+}
+
+/// Check the client's `signature_algorithms` extension.
+///
+/// Returns the offered signature scheme code points, in the client's order of
+/// preference, without the length prefix.
+fn check_signature_algorithms(ch: &[U8]) -> Result<Bytes, TLSError> {
+    check_length_encoding_u16_slice(ch)?;
+    if ch.len() < 4 || ch.len() % 2 != 0 {
+        tlserr(parse_failed())
+    } else {
+        Ok(ch[2..ch.len()].into())
+    }
+}
+
+/// Signature schemes the client accepts in certificate signatures.
+///
+/// These are only offered in `signature_algorithms_cert`, so that e.g. a chain
+/// signed with RSA PKCS#1 is acceptable without allowing it for CertificateVerify.
+const CERTIFICATE_SIGNATURE_ALGORITHMS: [u8; 18] = [
+    0x04, 0x03, // ecdsa_secp256r1_sha256
+    0x05, 0x03, // ecdsa_secp384r1_sha384
+    0x06, 0x03, // ecdsa_secp521r1_sha512
+    0x08, 0x04, // rsa_pss_rsae_sha256
+    0x08, 0x05, // rsa_pss_rsae_sha384
+    0x08, 0x06, // rsa_pss_rsae_sha512
+    0x04, 0x01, // rsa_pkcs1_sha256
+    0x05, 0x01, // rsa_pkcs1_sha384
+    0x06, 0x01, // rsa_pkcs1_sha512
+];
+
+fn signature_algorithms_cert() -> Result<Bytes, TLSError> {
+    Ok(bytes2(0, 50).concat(encode_length_u16(encode_length_u16(
+        CERTIFICATE_SIGNATURE_ALGORITHMS.into(),
     )?)?))
 }
 
-fn check_signature_algorithms(algs: &Algorithms, ch: &[U8]) -> Result<(), TLSError> {
+fn check_signature_algorithms_cert(ch: &[U8]) -> Result<(), TLSError> {
     check_length_encoding_u16_slice(ch)?;
-    check_mem(algs.signature_algorithm()?.as_raw(), &ch[2..ch.len()])
+    if ch.len() < 4 || ch.len() % 2 != 0 {
+        tlserr(parse_failed())
+    } else {
+        Ok(())
+    }
+}
+
+/// Build the `delegated_credential` extension of the ClientHello, accepting
+/// delegated credentials for the signature scheme of the `algs`.
+fn delegated_credential_request(algs: &Algorithms) -> Result<Bytes, TLSError> {
+    Ok(bytes2(0, 34).concat(encode_length_u16(encode_length_u16(
+        algs.signature_algorithms()?,
+    )?)?))
+}
+
+/// Build the empty `signed_certificate_timestamp` extension, asking for the
+/// SCTs of the server certificate.
+fn signed_certificate_timestamp_request() -> Result<Bytes, TLSError> {
+    Ok(bytes2(0, 18).concat(encode_length_u16(Bytes::new())?))
+}
+
+/// Build the `compress_certificate` extension, offering the available
+/// certificate compression algorithms.
+///
+/// The extension is omitted if there are none.
+fn compress_certificate() -> Result<Bytes, TLSError> {
+    let algorithms = supported_algorithms();
+    if algorithms.is_empty() {
+        return Ok(Bytes::new());
+    }
+    let mut codes = Bytes::new();
+    for algorithm in algorithms {
+        let code = algorithm.code();
+        codes = codes.concat(bytes2((code >> 8) as u8, code as u8));
+    }
+    Ok(bytes2(0, 27).concat(encode_length_u16(encode_length_u8(codes.as_raw())?)?))
+}
+
+/// The X.509 certificate type.
+pub(crate) const CERTIFICATE_TYPE_X509: u8 = 0;
+
+/// The raw public key certificate type (RFC 7250).
+pub(crate) const CERTIFICATE_TYPE_RAW_PUBLIC_KEY: u8 = 2;
+
+/// Build the `server_certificate_type` extension of the ClientHello, accepting
+/// only raw public keys.
+fn server_certificate_type_raw_public_key() -> Result<Bytes, TLSError> {
+    Ok(
+        bytes2(0, 20).concat(encode_length_u16(encode_length_u8(&[U8(
+            CERTIFICATE_TYPE_RAW_PUBLIC_KEY,
+        )])?)?),
+    )
 }
 
-fn psk_key_exchange_modes() -> Result<Bytes, TLSError> {
+/// Build the `status_request` extension, asking for an OCSP response without
+/// responder ids or request extensions.
+fn status_request() -> Result<Bytes, TLSError> {
+    const STATUS_TYPE_OCSP: &[U8; 1] = &[U8(1)];
+    let request = encode_length_u16(Bytes::new())?.concat(encode_length_u16(Bytes::new())?);
+    Ok(bytes2(0, 5).concat(encode_length_u16(request.prefix(STATUS_TYPE_OCSP))?))
+}
+
+/// Build the `cookie` extension with the `cookie` of a HelloRetryRequest.
+fn cookie(cookie: &Bytes) -> Result<Bytes, TLSError> {
+    Ok(bytes2(0, 44).concat(encode_length_u16(encode_length_u16(cookie.clone())?)?))
+}
+
+/// Build the empty `post_handshake_auth` extension, offering to authenticate
+/// the client after the handshake.
+fn post_handshake_auth() -> Result<Bytes, TLSError> {
+    Ok(bytes2(0, 49).concat(encode_length_u16(Bytes::new())?))
+}
+
+/// Build the `certificate_authorities` extension with the DER encoded
+/// distinguished `names` of the trust anchors.
+///
+/// The extension is omitted if there are none.
+fn certificate_authorities(names: &[Bytes]) -> Result<Bytes, TLSError> {
+    if names.is_empty() {
+        return Ok(Bytes::new());
+    }
+    let mut authorities = Bytes::new();
+    for name in names {
+        authorities = authorities.concat(encode_length_u16(name.clone())?);
+    }
+    Ok(bytes2(0, 47).concat(encode_length_u16(encode_length_u16(authorities)?)?))
+}
+
+/// Parse the content of the `certificate_authorities` extension.
+///
+/// Returns the DER encoded distinguished names, which are not checked.
+pub(crate) fn check_certificate_authorities(b: &[U8]) -> Result<Vec<Bytes>, TLSError> {
+    let len = length_u16_encoded_slice(b)?;
+    check(len > 0 && 2 + len == b.len())?;
+    let mut names = Vec::new();
+    let mut next = 2;
+    while next < b.len() {
+        let name_len = length_u16_encoded_slice(&b[next..b.len()])?;
+        check(name_len > 0)?;
+        names.push(b[next + 2..next + 2 + name_len].into());
+        next = next + 2 + name_len;
+    }
+    Ok(names)
+}
+
+/// Build the `application_layer_protocol_negotiation` extension with the
+/// `protocols`.
+fn application_layer_protocol_negotiation(protocols: &[Bytes]) -> Result<Bytes, TLSError> {
+    let mut names = Bytes::new();
+    for protocol in protocols {
+        names = names.concat(encode_length_u8(protocol.as_raw())?);
+    }
+    Ok(bytes2(0, 16).concat(encode_length_u16(encode_length_u16(names)?)?))
+}
+
+/// Build the extensions QUIC adds to the ClientHello and the
+/// EncryptedExtensions: the `application_layer_protocol_negotiation` and the
+/// `quic_transport_parameters` extension.
+///
+/// Nothing is added without `quic`.
+fn quic_extensions(quic: &Option<QuicParameters>) -> Result<Bytes, TLSError> {
+    match quic {
+        Some(quic) => Ok(
+            application_layer_protocol_negotiation(&quic.alpn_protocols)?
+                .concat(bytes2(0, 57))
+                .concat(encode_length_u16(quic.transport_parameters.clone())?),
+        ),
+        None => Ok(Bytes::new()),
+    }
+}
+
+/// Parse the content of the `cookie` extension.
+fn check_cookie(b: &[U8]) -> Result<Bytes, TLSError> {
+    let len = length_u16_encoded_slice(b)?;
+    check(len > 0 && 2 + len == b.len())?;
+    Ok(Bytes::from(&b[2..b.len()]))
+}
+
+fn psk_key_exchange_modes(algs: &Algorithms) -> Result<Bytes, TLSError> {
     const PSK_MODE_PREFIX: &[U8; 2] = &[U8(0), U8(0x2d)];
-    Ok(encode_length_u16(encode_length_u8(&[U8(1)])?)?.prefix(PSK_MODE_PREFIX))
+    let mode = algs.psk_key_exchange_mode().code();
+    Ok(encode_length_u16(encode_length_u8(&[U8(mode)])?)?.prefix(PSK_MODE_PREFIX))
 }
 
-fn check_psk_key_exchange_modes(client_hello: &[U8]) -> Result<(), TLSError> {
+/// Check the `psk_key_exchange_modes` extension.
+///
+/// In PSK mode, the client must offer the mode of the `algs`.
+fn check_psk_key_exchange_modes(algs: &Algorithms, client_hello: &[U8]) -> Result<(), TLSError> {
     check_length_encoding_u8_slice(client_hello)?;
-    check_eq_with_slice(&[U8(1)], client_hello, 1, 2)
+    let mode = algs.psk_key_exchange_mode().code();
+    let offered = &client_hello[1..client_hello.len()];
+    if algs.psk_mode() && !offered.iter().any(|m| m.declassify() == mode) {
+        Err(PSK_MODE_MISMATCH)
+    } else {
+        Ok(())
+    }
 }
 
-fn key_shares(algs: &Algorithms, gx: KemPk) -> Result<Bytes, TLSError> {
-    let ks = algs.supported_group()?.concat(encode_length_u16(gx)?);
+fn key_shares(algs: &Algorithms, gx: KemPk, grease: Option<Grease>) -> Result<Bytes, TLSError> {
+    let ks = grease_key_share(grease)?
+        .concat(algs.supported_group()?)
+        .concat(encode_length_u16(gx)?);
     const PREFIX: &[U8; 2] = &[U8(0), U8(0x33)];
     Ok(encode_length_u16(encode_length_u16(ks)?)?.prefix(PREFIX))
 }
@@ -171,46 +384,86 @@ fn check_server_key_share(algs: &Algorithms, b: &[U8]) -> Result<Bytes, TLSError
     Ok(Bytes::from(&b[4..b.len()]))
 }
 
-fn pre_shared_key(algs: &Algorithms, session_ticket: &Bytes) -> Result<(Bytes, usize), TLSError> {
-    let identities = encode_length_u16(
-        encode_length_u16(session_ticket.clone())?.concat_array(u32_as_be_bytes(U32(0xffffffff))),
-    )?;
-    let binders = encode_length_u16(encode_length_u8(zero_key(&algs.hash()).as_raw())?)?;
+/// Build the `pre_shared_key` extension with the `identities` and zero
+/// binders.
+///
+/// Returns the extension and the length of the binders.
+fn pre_shared_key(algs: &Algorithms, identities: &[Bytes]) -> Result<(Bytes, usize), TLSError> {
+    let mut encoded_identities = Bytes::new();
+    let mut binders = Bytes::new();
+    for identity in identities {
+        encoded_identities = encoded_identities.concat(
+            encode_length_u16(identity.clone())?.concat_array(u32_as_be_bytes(U32(0xffffffff))),
+        );
+        binders = binders.concat(encode_length_u8(zero_key(&algs.hash()).as_raw())?);
+    }
+    let identities = encode_length_u16(encoded_identities)?;
+    let binders = encode_length_u16(binders)?;
     let binders_len = binders.len();
     let ext = bytes2(0, 41).concat(encode_length_u16(identities.concat(binders))?);
     Ok((ext, binders_len))
 }
 
-fn check_psk_shared_key(algs: &Algorithms, ch: &[U8]) -> Result<(), TLSError> {
-    let len_id = length_u16_encoded(ch)?;
-    let len_tkt = length_u16_encoded(&ch[2..2 + len_id])?;
-    if len_id == len_tkt + 6 {
-        check_length_encoding_u16_slice(&ch[2 + len_id..ch.len()])?;
-        check_length_encoding_u8_slice(&ch[4 + len_id..ch.len()])?;
-        if ch.len() - 5 - len_id != algs.hash().hash_len() {
-            tlserr(parse_failed())
-        } else {
-            Ok(())
-        }
-    } else {
+/// Parse the `pre_shared_key` extension of the client.
+///
+/// Returns the identities and the binders, which come in the same order.
+/// The obfuscated ticket ages are ignored.
+fn check_psk_shared_key(ch: &[U8]) -> Result<(Vec<Bytes>, Vec<Bytes>), TLSError> {
+    let len_ids = length_u16_encoded(ch)?;
+    let mut identities = Vec::new();
+    let mut next = 2;
+    while next < 2 + len_ids {
+        let len_id = length_u16_encoded(&ch[next..2 + len_ids])?;
+        check(len_id > 0 && 2 + len_ids - next >= len_id + 6)?;
+        identities.push(ch[next + 2..next + 2 + len_id].into());
+        next = next + len_id + 6;
+    }
+    check_length_encoding_u16_slice(&ch[next..ch.len()])?;
+    let mut binders = Vec::new();
+    next += 2;
+    while next < ch.len() {
+        let len_binder = length_u8_encoded(&ch[next..ch.len()])?;
+        check(len_binder >= 32)?;
+        binders.push(ch[next + 1..next + 1 + len_binder].into());
+        next = next + len_binder + 1;
+    }
+    if identities.is_empty() || identities.len() != binders.len() {
         tlserr(parse_failed())
+    } else {
+        Ok((identities, binders))
     }
 }
 
-fn server_pre_shared_key(_algs: &Algorithms) -> Result<Bytes, TLSError> {
-    Ok(bytes2(0, 41).concat(encode_length_u16(bytes2(0, 0))?))
+/// The length of the binders in the `pre_shared_key` extension, including
+/// their length prefix.
+///
+/// This is what's cut off the ClientHello for the binder computation.
+fn binders_len(binders: &[Bytes]) -> usize {
+    binders.iter().fold(2, |len, binder| len + 1 + binder.len())
 }
 
-fn check_server_psk_shared_key(_algs: &Algorithms, b: &[U8]) -> Result<(), TLSError> {
-    check_eq_slice(&[U8(0), U8(0)], b)
+/// Build the `pre_shared_key` extension of the server, with the index of the
+/// PSK it selected among the client's offers.
+fn server_pre_shared_key(_algs: &Algorithms, selected_identity: usize) -> Result<Bytes, TLSError> {
+    let selected_identity = bytes2((selected_identity >> 8) as u8, selected_identity as u8);
+    Ok(bytes2(0, 41).concat(encode_length_u16(selected_identity)?))
+}
+
+/// Parse the `pre_shared_key` extension of the server.
+///
+/// Returns the index of the selected PSK.
+fn check_server_psk_shared_key(_algs: &Algorithms, b: &[U8]) -> Result<usize, TLSError> {
+    check(b.len() == 2)?;
+    Ok(((b[0].declassify() as usize) << 8) | b[1].declassify() as usize)
 }
 
 /// TLS Extensions
 pub struct Extensions {
     sni: Option<Bytes>,
     key_share: Option<Bytes>,
-    ticket: Option<Bytes>,
-    binder: Option<Bytes>,
+    psk_identities: Option<Vec<Bytes>>,
+    psk_binders: Option<Vec<Bytes>>,
+    signature_algorithms: Option<Bytes>,
 }
 
 impl Extensions {
@@ -220,8 +473,9 @@ impl Extensions {
         Ok(Extensions {
             sni: merge_opts(self.sni, e2.sni)?,
             key_share: merge_opts(self.key_share, e2.key_share)?,
-            ticket: merge_opts(self.ticket, e2.ticket)?,
-            binder: merge_opts(self.binder, e2.binder)?,
+            psk_identities: merge_opts(self.psk_identities, e2.psk_identities)?,
+            psk_binders: merge_opts(self.psk_binders, e2.psk_binders)?,
+            signature_algorithms: merge_opts(self.signature_algorithms, e2.signature_algorithms)?,
         })
     }
 }
@@ -237,68 +491,108 @@ fn merge_opts<T>(o1: Option<T>, o2: Option<T>) -> Result<Option<T>, TLSError> {
     }
 }
 
-/// Check an extension for validity.
-fn check_extension(algs: &Algorithms, bytes: &[U8]) -> Result<(usize, Extensions), TLSError> {
-    if bytes.len() < 4 {
-        Err(parse_failed())
-    } else {
-        let l0 = bytes[0].declassify() as usize;
-        let l1 = bytes[1].declassify() as usize;
-        let len = length_u16_encoded_slice(&bytes[2..bytes.len()])?;
-        let out = Extensions {
-            sni: None,
-            key_share: None,
-            ticket: None,
-            binder: None,
-        };
-        match (l0 as u8, l1 as u8) {
-            (0, 0) => Ok((
-                4 + len,
-                Extensions {
-                    sni: Some(check_server_name(&bytes[4..4 + len])?),
-                    key_share: None,
-                    ticket: None,
-                    binder: None,
-                },
-            )),
-            (0, 0x2d) => {
-                check_psk_key_exchange_modes(&bytes[4..4 + len])?;
-                Ok((4 + len, out))
-            }
-            (0, 0x2b) => {
-                check_supported_versions(&bytes[4..4 + len])?;
-                Ok((4 + len, out))
+/// Check an extension of the ClientHello and add it to the `info`.
+///
+/// Returns the length of the extension and the parameters the handshake
+/// reads from it, if any.
+/// Without `algs`, only the encoding is checked, not whether the offer fits
+/// the ciphersuite.
+/// Unknown extensions are skipped.
+fn check_extension(
+    algs: Option<&Algorithms>,
+    bytes: &[U8],
+    info: &mut ClientHelloInfo,
+) -> Result<(usize, Extensions), TLSError> {
+    check(bytes.len() >= 4)?;
+    let extension_type = ((bytes[0].declassify() as u16) << 8) | bytes[1].declassify() as u16;
+    let len = length_u16_encoded_slice(&bytes[2..bytes.len()])?;
+    let data = &bytes[4..4 + len];
+    // Every extension may only be sent once.
+    check(!info.extensions.contains(&extension_type))?;
+    info.extensions.push(extension_type);
+    let mut out = Extensions {
+        sni: None,
+        key_share: None,
+        psk_identities: None,
+        psk_binders: None,
+        signature_algorithms: None,
+    };
+    match extension_type {
+        0 => {
+            let sni = check_server_name(data)?;
+            info.server_name = Some(sni.clone());
+            out.sni = Some(sni);
+        }
+        5 => info.status_request = data.first().map(|t| t.declassify()) == Some(1),
+        0x0a => {
+            if let Some(algs) = algs {
+                check_supported_groups(algs, data)?;
             }
-            (0, 0x0a) => {
-                check_supported_groups(algs, &bytes[4..4 + len])?;
-                Ok((4 + len, out))
+            info.supported_groups = u16_vector(data)?;
+        }
+        0x0d => {
+            out.signature_algorithms = Some(check_signature_algorithms(data)?);
+            info.signature_algorithms = u16_vector(data)?;
+        }
+        16 => info.alpn_protocols = alpn_protocols(data)?,
+        18 => {
+            check(data.is_empty())?;
+            info.signed_certificate_timestamp = true;
+        }
+        19 => info.client_certificate_types = u8_vector(data)?,
+        20 => info.server_certificate_types = u8_vector(data)?,
+        27 => info.certificate_compression_algorithms = certificate_compression_algorithms(data)?,
+        34 => info.delegated_credential_schemes = u16_vector(data)?,
+        41 => {
+            // The binders are computed over the ClientHello up to this
+            // extension, which must come last.
+            check(4 + len == bytes.len())?;
+            let (identities, binders) = check_psk_shared_key(data)?;
+            info.psk_identities = identities.clone();
+            out.psk_identities = Some(identities);
+            out.psk_binders = Some(binders);
+        }
+        0x2b => {
+            if algs.is_some() {
+                check_supported_versions(data)?;
             }
-            (0, 0x0d) => {
-                check_signature_algorithms(algs, &bytes[4..4 + len])?;
-                Ok((4 + len, out))
+        }
+        44 => info.cookie = Some(check_cookie(data)?),
+        0x2d => {
+            if let Some(algs) = algs {
+                check_psk_key_exchange_modes(algs, data)?;
             }
-            (0, 0x33) => match check_key_shares(algs, &bytes[4..4 + len]) {
-                Ok(gx) => Ok((
-                    4 + len,
-                    Extensions {
-                        sni: None,
-                        key_share: Some(gx),
-                        ticket: None,
-                        binder: None,
-                    },
-                )),
-                Err(_) => tlserr(MISSING_KEY_SHARE),
-            },
-            (0, 41) => {
-                check_psk_shared_key(algs, &bytes[4..4 + len])?;
-                Ok((4 + len, out))
+            info.psk_key_exchange_modes = u8_vector(data)?;
+        }
+        47 => info.certificate_authorities = check_certificate_authorities(data)?,
+        49 => {
+            check(data.is_empty())?;
+            info.post_handshake_auth = true;
+        }
+        50 => check_signature_algorithms_cert(data)?,
+        0x33 => {
+            if let Some(algs) = algs {
+                out.key_share = Some(check_key_shares(algs, data).map_err(|_| MISSING_KEY_SHARE)?);
             }
-            _ => Ok((4 + len, out)),
+            info.key_share_groups = key_share_groups(data)?;
         }
+        57 => info.quic_transport_parameters = Some(data.into()),
+        ECH_EXTENSION => info.encrypted_client_hello = Some(data.into()),
+        _ => (),
     }
+    Ok((4 + len, out))
 }
 
-fn check_server_extension(algs: &Algorithms, b: &[U8]) -> Result<(usize, Option<Bytes>), TLSError> {
+/// Check an extension of the ServerHello.
+///
+/// Returns the length of the extension, the key share or the index of the
+/// selected PSK, if that's what it is, and whether it is the
+/// `supported_versions` extension.
+#[allow(clippy::type_complexity)]
+fn check_server_extension(
+    algs: &Algorithms,
+    b: &[U8],
+) -> Result<(usize, Option<Bytes>, Option<usize>, bool), TLSError> {
     if b.len() < 4 {
         Err(parse_failed())
     } else {
@@ -306,47 +600,86 @@ fn check_server_extension(algs: &Algorithms, b: &[U8]) -> Result<(usize, Option<
         let l1 = b[1].declassify() as usize;
         let len = length_u16_encoded(&b[2..b.len()])?;
         let mut out = None;
+        let mut selected_identity = None;
+        let mut supported_version = false;
         match (l0 as u8, l1 as u8) {
-            (0, 0x2b) => check_server_supported_version(algs, &b[4..4 + len])?,
+            (0, 0x2b) => {
+                check_server_supported_version(algs, &b[4..4 + len])?;
+                supported_version = true
+            }
             (0, 0x33) => {
                 let gx = check_server_key_share(algs, &b[4..4 + len])?;
                 out = Some(gx)
             }
-            (0, 41) => check_server_psk_shared_key(algs, &b[4..4 + len])?,
+            (0, 41) => selected_identity = Some(check_server_psk_shared_key(algs, &b[4..4 + len])?),
             _ => (),
         }
-        Ok((4 + len, out))
+        Ok((4 + len, out, selected_identity, supported_version))
     }
 }
 
 #[inline(always)]
-fn check_extensions_slice(algs: &Algorithms, b: &[U8]) -> Result<Extensions, TLSError> {
-    let (len, out) = check_extension(algs, b)?;
+fn check_extensions_slice(
+    algs: Option<&Algorithms>,
+    b: &[U8],
+    info: &mut ClientHelloInfo,
+) -> Result<Extensions, TLSError> {
+    let (len, out) = check_extension(algs, b, info)?;
     if len == b.len() {
         Ok(out)
     } else {
-        let out_rest = check_extensions_slice(algs, &b[len..b.len()])?;
+        let out_rest = check_extensions_slice(algs, &b[len..b.len()], info)?;
         out.merge(out_rest)
     }
 }
 
-fn check_extensions(algs: &Algorithms, b: &Bytes) -> Result<Extensions, TLSError> {
-    let (len, out) = check_extension(algs, b.as_raw())?;
+fn check_extensions(
+    algs: Option<&Algorithms>,
+    b: &Bytes,
+    info: &mut ClientHelloInfo,
+) -> Result<Extensions, TLSError> {
+    let (len, out) = check_extension(algs, b.as_raw(), info)?;
     if len == b.len() {
         Ok(out)
     } else {
-        let out_rest = check_extensions_slice(algs, b.raw_slice(len..b.len()))?;
+        let out_rest = check_extensions_slice(algs, b.raw_slice(len..b.len()), info)?;
         out.merge(out_rest)
     }
 }
 
-fn check_server_extensions(algs: &Algorithms, b: &[U8]) -> Result<Option<Bytes>, TLSError> {
-    let (len, out) = check_server_extension(algs, b)?;
+/// Check the extensions of the ServerHello.
+///
+/// Returns the key share and the index of the selected PSK, if any.
+/// The server must select TLS 1.3 in the `supported_versions` extension.
+fn check_server_extensions(
+    algs: &Algorithms,
+    b: &[U8],
+) -> Result<(Option<Bytes>, Option<usize>), TLSError> {
+    let (out, selected_identity, supported_version) = check_server_extensions_slice(algs, b)?;
+    if supported_version {
+        Ok((out, selected_identity))
+    } else {
+        Err(PROTOCOL_VERSION_ALERT)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn check_server_extensions_slice(
+    algs: &Algorithms,
+    b: &[U8],
+) -> Result<(Option<Bytes>, Option<usize>, bool), TLSError> {
+    let (len, out, selected_identity, supported_version) = check_server_extension(algs, b)?;
     if len == b.len() {
-        Ok(out)
+        Ok((out, selected_identity, supported_version))
     } else {
-        let out_rest = check_server_extensions(algs, &b[len..b.len()])?;
-        merge_opts(out, out_rest)
+        let (out_rest, selected_identity_rest, supported_version_rest) =
+            check_server_extensions_slice(algs, &b[len..b.len()])?;
+        check(!(supported_version && supported_version_rest))?;
+        Ok((
+            merge_opts(out, out_rest)?,
+            merge_opts(selected_identity, selected_identity_rest)?,
+            supported_version || supported_version_rest,
+        ))
     }
 }
 
@@ -412,6 +745,7 @@ impl TryFrom<u8> for AlertLevel {
 ///     unknown_psk_identity(115),
 ///     certificate_required(116),
 ///     no_application_protocol(120),
+///     ech_required(121),
 ///     (255)
 /// } AlertDescription;
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -444,6 +778,7 @@ pub enum AlertDescription {
     UnknownPskIdentity = 115,
     CertificateRequired = 116,
     NoApplicationProtocol = 120,
+    EchRequired = 121,
 }
 
 impl AlertDescription {}
@@ -480,6 +815,7 @@ impl TryFrom<u8> for AlertDescription {
             115 => Ok(AlertDescription::UnknownPskIdentity),
             116 => Ok(AlertDescription::CertificateRequired),
             120 => Ok(AlertDescription::NoApplicationProtocol),
+            121 => Ok(AlertDescription::EchRequired),
             _ => tlserr(parse_failed()),
         }
     }
@@ -493,57 +829,140 @@ pub fn bench_client_hello(
     server_name: &Bytes,
     session_ticket: &Option<Bytes>,
 ) -> Result<(HandshakeData, usize), TLSError> {
+    let psk_identities: Vec<Bytes> = session_ticket.iter().cloned().collect();
     client_hello(
         algorithms,
         client_random,
         kem_pk,
         server_name,
-        session_ticket,
+        &psk_identities,
+        false,
+        false,
+        &[],
+        &None,
+        &Bytes::new(),
+        &None,
+        None,
     )
 }
 
 fn get_psk_extensions(
     algorithms: &Algorithms,
-    session_ticket: &Bytes,
+    psk_identities: &[Bytes],
     mut extensions: Bytes,
 ) -> Result<(usize, Bytes), TLSError> {
-    let pskm = psk_key_exchange_modes()?;
-    let (psk, len) = pre_shared_key(algorithms, session_ticket)?;
+    let pskm = psk_key_exchange_modes(algorithms)?;
+    let (psk, len) = pre_shared_key(algorithms, psk_identities)?;
     extensions = extensions.concat(pskm).concat(psk);
     Ok((len, extensions))
 }
 
 /// Build a ClientHello message.
+///
+/// In PSK mode, the client offers PSKs with the `psk_identities`, and the
+/// returned length is the length of the binders at the end of the message.
+/// Otherwise it is 0.
+///
+/// With `raw_public_key`, the client only accepts a raw public key instead of
+/// a server certificate.
+///
+/// With `post_handshake_auth`, the client offers to authenticate with a
+/// certificate after the handshake.
+///
+/// The `certificate_authorities` are the DER encoded distinguished names of
+/// the client's trust anchors. They are omitted if there are none.
+///
+/// With `quic`, the ALPN protocols and the transport parameters are sent, and
+/// the legacy session id is empty, as QUIC has no middlebox compatibility
+/// mode.
+///
+/// The `ech_extension`, if not empty, is the `encrypted_client_hello`
+/// extension.
+///
+/// With `grease`, GREASE values are inserted into the lists of the message,
+/// and GREASE extensions are added.
+#[allow(clippy::too_many_arguments)]
 #[cfg_attr(feature = "hax-pv", pv_constructor)]
 pub(crate) fn client_hello(
     algorithms: &Algorithms,
     client_random: Random,
     kem_pk: &KemPk,
     server_name: &Bytes,
-    session_ticket: &Option<Bytes>,
+    psk_identities: &[Bytes],
+    raw_public_key: bool,
+    post_handshake_auth: bool,
+    certificate_authorities: &[Bytes],
+    quic: &Option<QuicParameters>,
+    ech_extension: &Bytes,
+    hello_retry_cookie: &Option<Bytes>,
+    grease: Option<Grease>,
 ) -> Result<(HandshakeData, usize), TLSError> {
     let version = bytes2(3, 3);
     let compression_methods = bytes2(1, 0);
     // const version: &[U8; 2] = &[U8(3), U8(3)];
     // const compression_methods: &[U8; 2] = &[U8(1), U8(0)];
-    let legacy_session_id = encode_length_u8(&[U8(0); 32])?;
-    let cipher_suites = encode_length_u16(algorithms.ciphersuite()?)?;
+    let legacy_session_id = if quic.is_some() {
+        encode_length_u8(&[])?
+    } else {
+        encode_length_u8(&[U8(0); 32])?
+    };
+    let cipher_suites =
+        encode_length_u16(grease_cipher_suite(grease).concat(algorithms.ciphersuite()?))?;
+    let (first_grease_extension, last_grease_extension) = grease_extensions(grease)?;
     let server_name = build_server_name(server_name)?;
-    let supported_versions = supported_versions()?;
-    let supported_groups = supported_groups(algorithms)?;
-    let signature_algorithms = signature_algorithms(algorithms)?;
-    let key_shares = key_shares(algorithms, kem_pk.clone())?;
+    let supported_versions = supported_versions(grease)?;
+    let supported_groups = supported_groups(algorithms, grease)?;
+    let signature_algorithms = signature_algorithms(algorithms, grease)?;
+    let signature_algorithms_cert = signature_algorithms_cert()?;
+    let status_request = status_request()?;
+    let sct_request = signed_certificate_timestamp_request()?;
+    let delegated_credential = delegated_credential_request(algorithms)?;
+    let compress_certificate = compress_certificate()?;
+    let server_certificate_type = if raw_public_key {
+        server_certificate_type_raw_public_key()?
+    } else {
+        Bytes::new()
+    };
+    let key_shares = if algorithms.key_exchange() {
+        key_shares(algorithms, kem_pk.clone(), grease)?
+    } else {
+        Bytes::new()
+    };
+    let cookie = match hello_retry_cookie {
+        Some(hello_retry_cookie) => cookie(hello_retry_cookie)?,
+        None => Bytes::new(),
+    };
+    let certificate_authorities = self::certificate_authorities(certificate_authorities)?;
+    let post_handshake_auth = if post_handshake_auth {
+        self::post_handshake_auth()?
+    } else {
+        Bytes::new()
+    };
+    let quic = quic_extensions(quic)?;
 
     let extensions = bytes_concat!(
+        first_grease_extension,
         server_name,
         supported_versions,
         supported_groups,
         signature_algorithms,
-        key_shares
+        signature_algorithms_cert,
+        status_request,
+        sct_request,
+        delegated_credential,
+        compress_certificate,
+        server_certificate_type,
+        key_shares,
+        cookie,
+        certificate_authorities,
+        post_handshake_auth,
+        quic,
+        ech_extension.clone(),
+        last_grease_extension
     );
-    let (trunc_len, extensions) = (match (algorithms.psk_mode(), session_ticket) {
-        (true, Some(session_ticket)) => get_psk_extensions(algorithms, session_ticket, extensions),
-        (false, None) => Ok((0, extensions)),
+    let (binders_len, extensions) = (match (algorithms.psk_mode(), psk_identities.is_empty()) {
+        (true, false) => get_psk_extensions(algorithms, psk_identities, extensions),
+        (false, true) => Ok((0, extensions)),
         _ => tlserr(PSK_MODE_MISMATCH),
     })?;
 
@@ -557,29 +976,33 @@ pub(crate) fn client_hello(
         encoded_extensions
     );
     let client_hello = HandshakeData::from_bytes(HandshakeType::ClientHello, &handshake_bytes)?;
-    Ok((client_hello, trunc_len))
+    Ok((client_hello, binders_len))
 }
 
+/// Fill in the `binders` of the `client_hello`, which is `trunc_len` bytes
+/// long without them.
+///
+/// The `client_hello` must have zero binders of the same lengths.
 #[cfg_attr(feature = "hax-pv", pv_handwritten)]
-pub(crate) fn set_client_hello_binder(
-    ciphersuite: &Algorithms,
-    binder: &Option<Hmac>,
+pub(crate) fn set_client_hello_binders(
+    binders: &[Hmac],
     client_hello: HandshakeData,
-    trunc_len: Option<usize>,
+    trunc_len: usize,
 ) -> Result<HandshakeData, TLSError> {
-    let HandshakeData(ch) = client_hello;
-    let chlen = &ch.len();
-    let hlen = ciphersuite.hash().hash_len();
-    match (binder, trunc_len) {
-        (Some(m), Some(trunc_len)) => {
-            if chlen - hlen == trunc_len {
-                Ok(HandshakeData(ch.update_slice(trunc_len, m, 0, hlen)))
-            } else {
-                tlserr(parse_failed())
-            }
-        }
-        (None, None) => Ok(HandshakeData(ch)),
-        (_, _) => tlserr(parse_failed()),
+    let HandshakeData(mut ch) = client_hello;
+    // Skip the two byte length of the list.
+    let mut next = trunc_len + 2;
+    for binder in binders {
+        // Each binder has a one byte length.
+        check(ch.len() > next && ch[next].declassify() as usize == binder.len())?;
+        check(ch.len() - next - 1 >= binder.len())?;
+        ch = ch.update_slice(next + 1, binder, 0, binder.len());
+        next = next + 1 + binder.len();
+    }
+    if next == ch.len() {
+        Ok(HandshakeData(ch))
+    } else {
+        tlserr(parse_failed())
     }
 }
 
@@ -587,43 +1010,44 @@ fn invalid_compression_list() -> Result<(), TLSError> {
     Result::<(), TLSError>::Err(INVALID_COMPRESSION_LIST)
 }
 
+/// A client hello, as read by the server for the given ciphersuite.
+pub struct ParsedClientHello {
+    pub(crate) client_randomness: Random,
+    pub(crate) session_id: Bytes,
+    /// The server name, or empty if the client didn't send one.
+    pub(crate) server_name: Bytes,
+    /// The key share for the group of the ciphersuite, or empty without a key
+    /// exchange.
+    pub(crate) gx: Bytes,
+    /// The PSK identities, in PSK mode only.
+    pub(crate) psk_identities: Option<Vec<Bytes>>,
+    /// The PSK binders, in PSK mode only.
+    pub(crate) psk_binders: Option<Vec<Bytes>>,
+    pub(crate) signature_algorithms: Option<Bytes>,
+    /// The length of the message without the binders, in PSK mode only.
+    pub(crate) trunc_len: usize,
+    /// The other parameters the client offers, read in the same pass.
+    pub(crate) info: ClientHelloInfo,
+}
+
 #[cfg(bench)]
 pub fn bench_parse_client_hello(
     ciphersuite: &Algorithms,
     client_hello: &HandshakeData,
-) -> Result<
-    (
-        Random,
-        Bytes,
-        Bytes,
-        Bytes,
-        Option<Bytes>,
-        Option<Bytes>,
-        usize,
-    ),
-    TLSError,
-> {
+) -> Result<ParsedClientHello, TLSError> {
     parse_client_hello(ciphersuite, client_hello)
 }
 
-/// Parse the provided `client_hello` with the given `ciphersuite`.
-#[allow(clippy::type_complexity)]
-#[cfg_attr(feature = "hax-pv", pv_handwritten)]
-pub(super) fn parse_client_hello(
-    ciphersuite: &Algorithms,
+/// Read the `client_hello` up to and including its extensions.
+///
+/// With a `ciphersuite`, the client's offer is checked against it, see
+/// [`check_extension`].
+/// Returns the client random, the parameters the handshake reads from the
+/// extensions, and the [`ClientHelloInfo`].
+fn read_client_hello(
+    ciphersuite: Option<&Algorithms>,
     client_hello: &HandshakeData,
-) -> Result<
-    (
-        Random,
-        Bytes,
-        Bytes,
-        Bytes,
-        Option<Bytes>,
-        Option<Bytes>,
-        usize,
-    ),
-    TLSError,
-> {
+) -> Result<(Random, Extensions, ClientHelloInfo), TLSError> {
     let HandshakeData(ch) = client_hello.as_handshake_message(HandshakeType::ClientHello)?;
     let ver = bytes2(3, 3);
     let comp = bytes2(1, 0);
@@ -636,7 +1060,15 @@ pub(super) fn parse_client_hello(
     let sidlen = length_u8_encoded(&ch[next..ch.len()])?;
     let sid = ch.slice_range(next + 1..next + 1 + sidlen);
     next = next + 1 + sidlen;
-    let cslen = ciphersuite.check(ch.raw_slice(next..ch.len()))?;
+    let cslen = match ciphersuite {
+        Some(ciphersuite) => ciphersuite.check(ch.raw_slice(next..ch.len()))?,
+        None => 2 + length_u16_encoded(ch.raw_slice(next..ch.len()))?,
+    };
+    let mut info = ClientHelloInfo {
+        legacy_session_id: sid,
+        cipher_suites: u16_list(ch.raw_slice(next + 2..next + cslen))?,
+        ..ClientHelloInfo::default()
+    };
     next += cslen;
     match check_eq_with_slice(comp.as_raw(), ch.as_raw(), next, next + 2) {
         Ok(_) => Ok(()),
@@ -645,84 +1077,93 @@ pub(super) fn parse_client_hello(
     next += 2;
     check_length_encoding_u16(&ch.slice_range(next..ch.len()))?;
     next += 2;
-    let exts = check_extensions(ciphersuite, &ch.slice_range(next..ch.len()))?;
-    //println!("check_extensions");
-    let trunc_len = ch.len() - ciphersuite.hash().hash_len() - 3;
-    match (ciphersuite.psk_mode(), exts) {
-        (
-            _,
-            Extensions {
-                sni: _,
-                key_share: None,
-                ticket: _,
-                binder: _,
-            },
-        ) => Err(MISSING_KEY_SHARE),
-        (
-            true,
-            Extensions {
-                sni: Some(sn),
-                key_share: Some(gx),
-                ticket: Some(tkt),
-                binder: Some(binder),
-            },
-        ) => Ok((crand, sid, sn, gx, Some(tkt), Some(binder), trunc_len)),
-        (
-            true,
-            Extensions {
-                sni: None,
-                key_share: Some(gx),
-                ticket: Some(tkt),
-                binder: Some(binder),
-            },
-        ) => Ok((
-            crand,
-            sid,
-            Bytes::new(),
-            gx,
-            Some(tkt),
-            Some(binder),
-            trunc_len,
-        )),
-        (
-            false,
-            Extensions {
-                sni: Some(sn),
-                key_share: Some(gx),
-                ticket: None,
-                binder: None,
-            },
-        ) => Ok((crand, sid, sn, gx, None, None, 0)),
-        (
-            false,
-            Extensions {
-                sni: None,
-                key_share: Some(gx),
-                ticket: None,
-                binder: None,
-            },
-        ) => Ok((crand, sid, Bytes::new(), gx, None, None, 0)),
-        _ => Err(parse_failed()),
-    }
+    let exts = check_extensions(ciphersuite, &ch.slice_range(next..ch.len()), &mut info)?;
+    Ok((crand, exts, info))
+}
+
+/// Parse the ClientHello handshake message into a [`ClientHelloInfo`].
+///
+/// This is the same pass as in the handshake, without a ciphersuite to check
+/// the offer against.
+/// Returns a [`TLSError`] if the message is malformed.
+pub(crate) fn parse_client_hello_info(
+    client_hello: &HandshakeData,
+) -> Result<ClientHelloInfo, TLSError> {
+    let (_, _, info) = read_client_hello(None, client_hello)?;
+    Ok(info)
+}
+
+/// Parse the provided `client_hello` with the given `ciphersuite`.
+#[cfg_attr(feature = "hax-pv", pv_handwritten)]
+pub(super) fn parse_client_hello(
+    ciphersuite: &Algorithms,
+    client_hello: &HandshakeData,
+) -> Result<ParsedClientHello, TLSError> {
+    let (crand, exts, info) = read_client_hello(Some(ciphersuite), client_hello)?;
+    let Extensions {
+        sni,
+        key_share,
+        psk_identities,
+        psk_binders,
+        signature_algorithms,
+    } = exts;
+    // Without a key exchange, key shares are ignored.
+    let gx = match (ciphersuite.key_exchange(), key_share) {
+        (true, Some(gx)) => Ok(gx),
+        (true, None) => Err(MISSING_KEY_SHARE),
+        (false, _) => Ok(Bytes::new()),
+    }?;
+    let server_name = sni.unwrap_or_default();
+    let (psk_identities, psk_binders, trunc_len) =
+        match (ciphersuite.psk_mode(), psk_identities, psk_binders) {
+            (true, Some(identities), Some(binders)) => {
+                // The binders are computed over the message, including its
+                // header, up to the binders.
+                let trunc_len = client_hello.len() - binders_len(&binders);
+                Ok((Some(identities), Some(binders), trunc_len))
+            }
+            // PSKs are ignored outside of PSK mode.
+            (false, _, _) => Ok((None, None, 0)),
+            (true, _, _) => Err(PSK_MODE_MISMATCH),
+        }?;
+    Ok(ParsedClientHello {
+        client_randomness: crand,
+        session_id: info.legacy_session_id.clone(),
+        server_name,
+        gx,
+        psk_identities,
+        psk_binders,
+        signature_algorithms,
+        trunc_len,
+        info,
+    })
 }
 
 /// Build the server hello message.
+///
+/// In PSK mode, the `selected_identity` is the index of the PSK the server
+/// uses among the client's offers.
 #[cfg_attr(feature = "hax-pv", pv_constructor)]
 pub(crate) fn server_hello(
     algs: &Algorithms,
     sr: Random,
     sid: &Bytes,
     gy: &KemPk,
+    selected_identity: usize,
 ) -> Result<HandshakeData, TLSError> {
     let ver = bytes2(3, 3);
     let sid = encode_length_u8(sid.as_raw())?;
     let cip = algs.ciphersuite()?;
     let comp = bytes1(0);
-    let ks = server_key_shares(algs, gy.clone())?;
+    let ks = if algs.key_exchange() {
+        server_key_shares(algs, gy.clone())?
+    } else {
+        Bytes::new()
+    };
     let sv = server_supported_version(algs)?;
     let mut exts = ks.concat(sv);
     match algs.psk_mode() {
-        true => exts = exts.concat(server_pre_shared_key(algs)?),
+        true => exts = exts.concat(server_pre_shared_key(algs, selected_identity)?),
         false => {}
     }
     let encoded_extensions = encode_length_u16(exts)?;
@@ -746,14 +1187,43 @@ pub fn bench_parse_server_hello(
     algs: &Algorithms,
     server_hello: &HandshakeData,
 ) -> Result<(Random, KemPk), TLSError> {
-    parse_server_hello(algs, server_hello)
+    parse_server_hello(algs, server_hello).map(|(sr, gy, _)| (sr, gy))
 }
 
+/// The `DOWNGRD` start of the last 8 bytes of the random of a TLS 1.3 server
+/// that negotiated an earlier version. It's followed by 1 for TLS 1.2, and by
+/// 0 for TLS 1.1 and below.
+const DOWNGRADE_SENTINEL: [u8; 7] = [0x44, 0x4f, 0x57, 0x4e, 0x47, 0x52, 0x44];
+
+/// Check that the server random `srand` doesn't signal a downgrade.
+///
+/// A TLS 1.3 server that negotiates an earlier version sets the last 8 bytes
+/// of its random to a sentinel (RFC 8446, Section 4.1.3). A client that sees
+/// it in a TLS 1.3 ServerHello is the victim of a downgrade attack, and must
+/// abort with an `illegal_parameter` alert.
+fn check_downgrade_sentinel(srand: &Random) -> Result<(), TLSError> {
+    let tail = srand.slice_range(24..32).declassify();
+    if tail[0..7] == DOWNGRADE_SENTINEL && (tail[7] == 0 || tail[7] == 1) {
+        Err(ILLEGAL_PARAMETER)
+    } else {
+        Ok(())
+    }
+}
+
+/// Parse the ServerHello message.
+///
+/// Returns the server random, the server's key share, which is empty if
+/// there's no key exchange, and in PSK mode the index of the PSK the server
+/// selected.
+///
+/// A ServerHello that selects an earlier version than TLS 1.3 fails with a
+/// [`PROTOCOL_VERSION_ALERT`], and one with a downgrade sentinel with an
+/// [`ILLEGAL_PARAMETER`] error.
 #[cfg_attr(feature = "hax-pv", pv_handwritten)]
 pub(crate) fn parse_server_hello(
     algs: &Algorithms,
     server_hello: &HandshakeData,
-) -> Result<(Random, KemPk), TLSError> {
+) -> Result<(Random, KemPk, Option<usize>), TLSError> {
     let HandshakeData(server_hello) =
         server_hello.as_handshake_message(HandshakeType::ServerHello)?;
     let ver = bytes2(3, 3);
@@ -767,6 +1237,7 @@ pub(crate) fn parse_server_hello(
     next += 2;
     check(server_hello.len() >= next + 32)?;
     let srand = server_hello.slice_range(next..next + 32);
+    check_downgrade_sentinel(&srand)?;
     next += 32;
     let sidlen = length_u8_encoded(&server_hello[next..server_hello.len()])?;
     next = next + 1 + sidlen;
@@ -782,27 +1253,178 @@ pub(crate) fn parse_server_hello(
     next += 1;
     check_length_encoding_u16(&server_hello.slice_range(next..server_hello.len()))?;
     next += 2;
-    let gy = check_server_extensions(algs, &server_hello[next..server_hello.len()])?;
-    if let Some(gy) = gy {
-        Ok((srand, gy))
+    let (gy, selected_identity) =
+        check_server_extensions(algs, &server_hello[next..server_hello.len()])?;
+    // The server must select a PSK if and only if the client offered some.
+    check(algs.psk_mode() == selected_identity.is_some()).map_err(|_| PSK_MODE_MISMATCH)?;
+    match (algs.key_exchange(), gy) {
+        (true, Some(gy)) => Ok((srand, gy, selected_identity)),
+        (true, None) => Err(MISSING_KEY_SHARE),
+        (false, None) => Ok((srand, Bytes::new(), selected_identity)),
+        (false, Some(_)) => Err(UNSUPPORTED_ALGORITHM),
+    }
+}
+
+/// The random of a HelloRetryRequest, the SHA-256 of `HelloRetryRequest`.
+const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// Build the HelloRetryRequest message.
+///
+/// It echoes the legacy session id `sid` of the client, asks for a key share
+/// for the group of the `algs` if `key_share` is set, and holds the `cookie`
+/// the client must send back.
+#[cfg_attr(feature = "hax-pv", pv_constructor)]
+pub(crate) fn hello_retry_request(
+    algs: &Algorithms,
+    sid: &Bytes,
+    key_share: bool,
+    hello_retry_cookie: &Bytes,
+) -> Result<HandshakeData, TLSError> {
+    let ver = bytes2(3, 3);
+    let sr = Bytes::from(HELLO_RETRY_REQUEST_RANDOM);
+    let sid = encode_length_u8(sid.as_raw())?;
+    let cip = algs.ciphersuite()?;
+    let comp = bytes1(0);
+    let ks = if key_share {
+        bytes2(0, 0x33).concat(encode_length_u16(algs.supported_group()?)?)
     } else {
-        Err(MISSING_KEY_SHARE)
+        Bytes::new()
+    };
+    let exts = bytes_concat!(
+        server_supported_version(algs)?,
+        ks,
+        cookie(hello_retry_cookie)?
+    );
+    let encoded_extensions = encode_length_u16(exts)?;
+    HandshakeData::from_bytes(
+        HandshakeType::ServerHello,
+        &bytes_concat!(ver, sr, sid, cip, comp, encoded_extensions),
+    )
+}
+
+/// Check if the ServerHello `server_hello` is a HelloRetryRequest.
+pub(crate) fn is_hello_retry_request(server_hello: &HandshakeData) -> bool {
+    let HandshakeData(sh) = server_hello;
+    sh.len() >= 38
+        && sh[0].declassify() == HandshakeType::ServerHello as u8
+        && sh.slice_range(6..38).declassify() == HELLO_RETRY_REQUEST_RANDOM
+}
+
+/// Parse the HelloRetryRequest message.
+///
+/// Returns the cookie the client must send back.
+///
+/// Bertie clients send a key share for the only group they support, so a
+/// HelloRetryRequest that asks for a key share, or that has no cookie and
+/// wouldn't change the ClientHello, fails with an [`ILLEGAL_PARAMETER`]
+/// error.
+#[cfg_attr(feature = "hax-pv", pv_handwritten)]
+pub(crate) fn parse_hello_retry_request(
+    algs: &Algorithms,
+    hello_retry_request: &HandshakeData,
+) -> Result<Bytes, TLSError> {
+    let HandshakeData(hrr) =
+        hello_retry_request.as_handshake_message(HandshakeType::ServerHello)?;
+    let ver = bytes2(3, 3);
+    let cip = algs.ciphersuite()?;
+    let comp = bytes1(0);
+    let mut next = 0;
+    (match check_eq_with_slice(ver.as_raw(), hrr.as_raw(), next, next + 2) {
+        Ok(_) => Ok(()),
+        Err(_) => protocol_version_alert(),
+    })?;
+    next += 34;
+    check(hrr.len() >= next)?;
+    let sidlen = length_u8_encoded(&hrr[next..hrr.len()])?;
+    next = next + 1 + sidlen;
+    (match check_eq_with_slice(cip.as_raw(), hrr.as_raw(), next, next + 2) {
+        Ok(_) => Ok(()),
+        Err(_) => unsupported_cipher_alert(),
+    })?;
+    next += 2;
+    (match check_eq_with_slice(comp.as_raw(), hrr.as_raw(), next, next + 1) {
+        Ok(_) => Ok(()),
+        Err(_) => invalid_compression_method_alert(),
+    })?;
+    next += 1;
+    check_length_encoding_u16(&hrr.slice_range(next..hrr.len()))?;
+    next += 2;
+    let mut supported_version = false;
+    let mut hello_retry_cookie = None;
+    while next < hrr.len() {
+        check(hrr.len() >= next + 4)?;
+        let len = length_u16_encoded(&hrr[next + 2..hrr.len()])?;
+        let extension = &hrr[next + 4..next + 4 + len];
+        match (hrr[next].declassify(), hrr[next + 1].declassify()) {
+            (0, 0x2b) => {
+                check(!supported_version)?;
+                check_server_supported_version(algs, extension)?;
+                supported_version = true
+            }
+            (0, 0x33) => return Err(ILLEGAL_PARAMETER),
+            (0, 44) => {
+                hello_retry_cookie = merge_opts(hello_retry_cookie, Some(check_cookie(extension)?))?
+            }
+            _ => (),
+        }
+        next = next + 4 + len;
     }
+    check(supported_version).map_err(|_| PROTOCOL_VERSION_ALERT)?;
+    hello_retry_cookie.ok_or(ILLEGAL_PARAMETER)
 }
 
+/// Build the EncryptedExtensions message.
+///
+/// The `server_certificate_type` is confirmed if the client sent a
+/// `server_certificate_type` extension.
+/// The `ech_retry_configs` are sent if the server rejected ECH.
+/// The `alpn_protocol` is sent if the server selected one outside of QUIC.
+/// With `quic`, the selected ALPN protocol, which must be the only one, and
+/// the transport parameters are sent.
 #[cfg_attr(feature = "hax-pv", pv_constructor)]
-pub(crate) fn encrypted_extensions(_algs: &Algorithms) -> Result<HandshakeData, TLSError> {
+pub(crate) fn encrypted_extensions(
+    _algs: &Algorithms,
+    server_certificate_type: &Option<u8>,
+    ech_retry_configs: &Option<Bytes>,
+    alpn_protocol: &Option<Bytes>,
+    quic: &Option<QuicParameters>,
+) -> Result<HandshakeData, TLSError> {
     let handshake_type = bytes1(HandshakeType::EncryptedExtensions as u8);
+    let mut extensions = match server_certificate_type {
+        Some(certificate_type) => {
+            bytes2(0, 20).concat(encode_length_u16(bytes1(*certificate_type))?)
+        }
+        None => Bytes::new(),
+    };
+    if let Some(retry_configs) = ech_retry_configs {
+        extensions = extensions
+            .concat(bytes2((ECH_EXTENSION >> 8) as u8, ECH_EXTENSION as u8))
+            .concat(encode_length_u16(retry_configs.clone())?);
+    }
+    if let Some(protocol) = alpn_protocol {
+        extensions =
+            extensions.concat(application_layer_protocol_negotiation(&[protocol.clone()])?);
+    }
+    extensions = extensions.concat(quic_extensions(quic)?);
     Ok(HandshakeData(handshake_type.concat(encode_length_u24(
-        &encode_length_u16(Bytes::new())?,
+        &encode_length_u16(extensions)?,
     )?)))
 }
 
+/// Parse the EncryptedExtensions message.
+///
+/// Returns the certificate type in the `server_certificate_type` extension
+/// and the ECH retry configs in the `encrypted_client_hello` extension, if
+/// any.
+/// Other extensions are ignored.
 #[cfg_attr(feature = "hax-pv", pv_handwritten)]
 pub(crate) fn parse_encrypted_extensions(
     _algs: &Algorithms,
     encrypted_extensions: &HandshakeData,
-) -> Result<(), TLSError> {
+) -> Result<(Option<u8>, Option<Bytes>), TLSError> {
     let HandshakeData(encrypted_extension_bytes) = encrypted_extensions;
     let expected_handshake_type = bytes1(HandshakeType::EncryptedExtensions as u8);
     check_eq_with_slice(
@@ -813,42 +1435,447 @@ pub(crate) fn parse_encrypted_extensions(
     )?;
     check_length_encoding_u24(
         encrypted_extension_bytes.raw_slice(1..encrypted_extension_bytes.len()),
+    )?;
+    let extensions = encrypted_extension_bytes.raw_slice(4..encrypted_extension_bytes.len());
+    check_length_encoding_u16_slice(extensions)?;
+    let mut server_certificate_type = None;
+    let mut ech_retry_configs = None;
+    let mut next = 2;
+    while next < extensions.len() {
+        check(extensions.len() - next >= 4)?;
+        let len = length_u16_encoded_slice(&extensions[next + 2..extensions.len()])?;
+        let extension_type = ((extensions[next].declassify() as u16) << 8)
+            | extensions[next + 1].declassify() as u16;
+        if extension_type == 20 {
+            check(len == 1 && server_certificate_type.is_none())?;
+            server_certificate_type = Some(extensions[next + 4].declassify());
+        } else if extension_type == ECH_EXTENSION {
+            check(ech_retry_configs.is_none())?;
+            ech_retry_configs = Some(extensions[next + 4..next + 4 + len].into());
+        }
+        next = next + 4 + len;
+    }
+    Ok((server_certificate_type, ech_retry_configs))
+}
+
+/// Parse the QUIC extensions of the EncryptedExtensions message.
+///
+/// Returns the protocol in the `application_layer_protocol_negotiation`
+/// extension, which must hold exactly one, and the content of the
+/// `quic_transport_parameters` extension, if any.
+pub(crate) fn parse_quic_extensions(
+    encrypted_extensions: &HandshakeData,
+) -> Result<(Option<Bytes>, Option<Bytes>), TLSError> {
+    let extensions =
+        encrypted_extensions.as_handshake_message(HandshakeType::EncryptedExtensions)?;
+    let extensions = extensions.to_bytes();
+    check_length_encoding_u16(&extensions)?;
+    let mut protocol = None;
+    let mut transport_parameters = None;
+    let mut next = 2;
+    while next < extensions.len() {
+        check(extensions.len() - next >= 4)?;
+        let len = length_u16_encoded_slice(extensions.raw_slice(next + 2..extensions.len()))?;
+        let extension = extensions.slice_range(next + 4..next + 4 + len);
+        match (
+            extensions[next].declassify(),
+            extensions[next + 1].declassify(),
+        ) {
+            (0, 16) => {
+                // A list with a single protocol name.
+                check(len >= 4 && length_u16_encoded(extension.as_raw())? == len - 2)?;
+                check(extension[2].declassify() as usize == len - 3)?;
+                protocol = merge_opts(protocol, Some(extension.slice_range(3..len)))?;
+            }
+            (0, 57) => {
+                transport_parameters = merge_opts(transport_parameters, Some(extension))?;
+            }
+            _ => (),
+        }
+        next = next + 4 + len;
+    }
+    Ok((protocol, transport_parameters))
+}
+
+/// Build a CertificateRequest message with the `context`, asking for a
+/// signature with one of the schemes in `signature_algorithms` for `algs`.
+///
+/// The `certificate_authorities` are the DER encoded distinguished names of
+/// the trust anchors the client certificate should chain to. They are
+/// omitted if there are none.
+///
+/// ```TLS
+/// struct {
+///     opaque certificate_request_context<0..2^8-1>;
+///     Extension extensions<2..2^16-1>;
+/// } CertificateRequest;
+/// ```
+pub(crate) fn certificate_request(
+    algs: &Algorithms,
+    context: &Bytes,
+    certificate_authorities: &[Bytes],
+) -> Result<HandshakeData, TLSError> {
+    let extensions = signature_algorithms(algs, None)?
+        .concat(self::certificate_authorities(certificate_authorities)?);
+    HandshakeData::from_bytes(
+        HandshakeType::CertificateRequest,
+        &encode_length_u8(context.as_raw())?.concat(encode_length_u16(extensions)?),
     )
 }
+
+/// Parse a CertificateRequest message.
+///
+/// Returns the context and the signature scheme code points of the
+/// `signature_algorithms` extension, which must be present, and the
+/// distinguished names of the `certificate_authorities` extension, if any.
+/// Other extensions are ignored.
+pub(crate) fn parse_certificate_request(
+    certificate_request: &HandshakeData,
+) -> Result<(Bytes, Bytes, Vec<Bytes>), TLSError> {
+    let HandshakeData(cr) =
+        certificate_request.as_handshake_message(HandshakeType::CertificateRequest)?;
+    let context_len = length_u8_encoded(cr.as_raw())?;
+    let context = cr.slice_range(1..1 + context_len);
+    let extensions = cr.raw_slice(1 + context_len..cr.len());
+    check_length_encoding_u16_slice(extensions)?;
+    let mut signature_schemes = None;
+    let mut authorities = None;
+    let mut next = 2;
+    while next < extensions.len() {
+        check(extensions.len() - next >= 4)?;
+        let len = length_u16_encoded_slice(&extensions[next + 2..extensions.len()])?;
+        if extensions[next].declassify() == 0 && extensions[next + 1].declassify() == 0x0d {
+            check(signature_schemes.is_none())?;
+            signature_schemes = Some(check_signature_algorithms(
+                &extensions[next + 4..next + 4 + len],
+            )?);
+        } else if extensions[next].declassify() == 0 && extensions[next + 1].declassify() == 47 {
+            check(authorities.is_none())?;
+            authorities = Some(check_certificate_authorities(
+                &extensions[next + 4..next + 4 + len],
+            )?);
+        }
+        next = next + 4 + len;
+    }
+    match signature_schemes {
+        Some(signature_schemes) => {
+            Ok((context, signature_schemes, authorities.unwrap_or_default()))
+        }
+        None => Err(parse_failed()),
+    }
+}
+/// A `CertificateEntry` of the Certificate message.
+///
+/// ```TLS
+/// struct {
+///     opaque cert_data<1..2^24-1>;
+///     Extension extensions<0..2^16-1>;
+/// } CertificateEntry;
+/// ```
+///
+/// The `extensions` are stored without their length prefix.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CertificateEntry {
+    pub(crate) cert_data: Bytes,
+    pub(crate) extensions: Bytes,
+}
+
+impl CertificateEntry {
+    /// A certificate entry without extensions.
+    pub(crate) fn new(cert_data: Bytes) -> Self {
+        Self {
+            cert_data,
+            extensions: Bytes::new(),
+        }
+    }
+
+    /// Get the data of the extension with the `extension_type` in this entry.
+    ///
+    /// Returns [`None`] if there's no such extension, or a [`TLSError`] if the
+    /// extensions are malformed.
+    fn extension(&self, extension_type: u8) -> Result<Option<&[U8]>, TLSError> {
+        let extensions = self.extensions.as_raw();
+        let mut data = None;
+        let mut next = 0;
+        while next < extensions.len() {
+            check(extensions.len() - next >= 4)?;
+            let len = length_u16_encoded_slice(&extensions[next + 2..extensions.len()])?;
+            if extensions[next].declassify() == 0
+                && extensions[next + 1].declassify() == extension_type
+            {
+                check(data.is_none())?;
+                data = Some(&extensions[next + 4..next + 4 + len]);
+            }
+            next = next + 4 + len;
+        }
+        Ok(data)
+    }
+
+    /// Get the OCSP response stapled to this entry in a `status_request`
+    /// extension.
+    ///
+    /// Returns [`None`] if there's no such extension, or a [`TLSError`] if the
+    /// extensions are malformed.
+    pub(crate) fn ocsp_response(&self) -> Result<Option<Bytes>, TLSError> {
+        match self.extension(5)? {
+            Some(status) => Ok(Some(parse_certificate_status(status)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the `SignedCertificateTimestampList` stapled to this entry in a
+    /// `signed_certificate_timestamp` extension.
+    ///
+    /// Returns [`None`] if there's no such extension, or a [`TLSError`] if the
+    /// extensions are malformed.
+    pub(crate) fn sct_list(&self) -> Result<Option<Bytes>, TLSError> {
+        match self.extension(18)? {
+            Some(sct_list) => {
+                check_length_encoding_u16_slice(sct_list)?;
+                check(sct_list.len() > 2)?;
+                Ok(Some(sct_list.into()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get the serialized `DelegatedCredential` in the `delegated_credential`
+    /// extension of this entry.
+    ///
+    /// Returns [`None`] if there's no such extension, or a [`TLSError`] if the
+    /// extensions are malformed.
+    pub(crate) fn delegated_credential(&self) -> Result<Option<Bytes>, TLSError> {
+        Ok(self.extension(34)?.map(Bytes::from))
+    }
+}
+
+/// Build the `status_request` extension of a certificate entry with the
+/// DER encoded OCSP `response`.
+///
+/// ```TLS
+/// struct {
+///     CertificateStatusType status_type;
+///     select (status_type) {
+///         case ocsp: OCSPResponse;
+///     } response;
+/// } CertificateStatus;
+///
+/// opaque OCSPResponse<1..2^24-1>;
+/// ```
+fn certificate_status(response: &Bytes) -> Result<Bytes, TLSError> {
+    let status = bytes1(1).concat(encode_length_u24(response)?);
+    Ok(bytes2(0, 5).concat(encode_length_u16(status)?))
+}
+
+/// Parse the `CertificateStatus` in a certificate entry extension.
+///
+/// Returns the DER encoded OCSP response.
+fn parse_certificate_status(status: &[U8]) -> Result<Bytes, TLSError> {
+    check(status.len() > 4 && status[0].declassify() == 1)?;
+    let len = length_u24_encoded(&status[1..status.len()])?;
+    check(len > 0 && 4 + len == status.len())?;
+    Ok(status[4..status.len()].into())
+}
+
+/// Build the `signed_certificate_timestamp` extension of a certificate entry
+/// with the serialized `SignedCertificateTimestampList`.
+fn signed_certificate_timestamp(sct_list: &Bytes) -> Result<Bytes, TLSError> {
+    Ok(bytes2(0, 18).concat(encode_length_u16(sct_list.clone())?))
+}
+
+/// Build the `delegated_credential` extension of a certificate entry with the
+/// serialized `DelegatedCredential`.
+fn delegated_credential(delegated_credential: &Bytes) -> Result<Bytes, TLSError> {
+    Ok(bytes2(0, 34).concat(encode_length_u16(delegated_credential.clone())?))
+}
+
+/// Build the certificate entries for the `chain`.
+///
+/// The `ocsp_response`, the `sct_list`, and the serialized
+/// `delegated_credential`, if any, are attached to the end-entity certificate.
+pub(crate) fn certificate_entries(
+    chain: &[Bytes],
+    ocsp_response: &Option<Bytes>,
+    sct_list: &Option<Bytes>,
+    delegated_credential: &Option<Bytes>,
+) -> Result<Vec<CertificateEntry>, TLSError> {
+    let mut entries: Vec<CertificateEntry> = chain
+        .iter()
+        .map(|cert| CertificateEntry::new(cert.clone()))
+        .collect();
+    if let Some(leaf) = entries.first_mut() {
+        if let Some(response) = ocsp_response {
+            leaf.extensions = certificate_status(response)?;
+        }
+        if let Some(sct_list) = sct_list {
+            leaf.extensions = leaf
+                .extensions
+                .clone()
+                .concat(signed_certificate_timestamp(sct_list)?);
+        }
+        if let Some(delegated_credential) = delegated_credential {
+            leaf.extensions = leaf
+                .extensions
+                .clone()
+                .concat(self::delegated_credential(delegated_credential)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Build the Certificate message with the `entries`, starting with the end-entity
+/// certificate.
 #[cfg_attr(feature = "hax-pv", pv_constructor)]
 pub(crate) fn server_certificate(
     _algs: &Algorithms,
-    cert: &Bytes,
+    entries: &[CertificateEntry],
 ) -> Result<HandshakeData, TLSError> {
-    let creq = encode_length_u8(&[])?;
-    let crt = encode_length_u24(cert)?;
-    let ext = encode_length_u16(Bytes::new())?;
-    let crts = encode_length_u24(&crt.concat(ext))?;
+    certificate(&Bytes::new(), entries)
+}
+
+/// Build the Certificate message of the client with the `context` of the
+/// CertificateRequest and the `entries`, which are empty if the client
+/// doesn't authenticate.
+pub(crate) fn client_certificate(
+    context: &Bytes,
+    entries: &[CertificateEntry],
+) -> Result<HandshakeData, TLSError> {
+    certificate(context, entries)
+}
+
+/// Build a Certificate message with the certificate request `context` and the
+/// `entries`.
+fn certificate(context: &Bytes, entries: &[CertificateEntry]) -> Result<HandshakeData, TLSError> {
+    let creq = encode_length_u8(context.as_raw())?;
+    let mut crts = Bytes::new();
+    for entry in entries {
+        let crt = encode_length_u24(&entry.cert_data)?;
+        let ext = encode_length_u16(entry.extensions.clone())?;
+        crts = crts.concat(crt).concat(ext);
+    }
+    let crts = encode_length_u24(&crts)?;
     HandshakeData::from_bytes(HandshakeType::Certificate, &creq.concat(crts))
 }
 
+/// Build the CompressedCertificate message for the `certificate` message with
+/// the `algorithm`.
+///
+/// ```TLS
+/// struct {
+///     CertificateCompressionAlgorithm algorithm;
+///     uint24 uncompressed_length;
+///     opaque compressed_certificate_message<1..2^24-1>;
+/// } CompressedCertificate;
+/// ```
+pub(crate) fn compressed_certificate(
+    algorithm: CertificateCompressionAlgorithm,
+    certificate: &HandshakeData,
+) -> Result<HandshakeData, TLSError> {
+    let HandshakeData(certificate) =
+        certificate.as_handshake_message(HandshakeType::Certificate)?;
+    let code = algorithm.code();
+    let len = certificate.len();
+    let compressed = compress(algorithm, &certificate)?;
+    let message = bytes_concat!(
+        bytes2((code >> 8) as u8, code as u8),
+        bytes1((len >> 16) as u8),
+        bytes2((len >> 8) as u8, len as u8),
+        encode_length_u24(&compressed)?
+    );
+    HandshakeData::from_bytes(HandshakeType::CompressedCertificate, &message)
+}
+
+/// Get the Certificate message from the `certificate` the server sent.
+///
+/// A CompressedCertificate message is decompressed, a Certificate message is
+/// returned as is.
+/// Only the available compression algorithms, which the client offers, are
+/// accepted.
+pub(crate) fn decompress_certificate(
+    certificate: &HandshakeData,
+) -> Result<HandshakeData, TLSError> {
+    let HandshakeData(bytes) = certificate;
+    check(bytes.len() >= 4)?;
+    if bytes[0].declassify() != HandshakeType::CompressedCertificate as u8 {
+        return Ok(HandshakeData::from(bytes.clone()));
+    }
+    let HandshakeData(cc) =
+        certificate.as_handshake_message(HandshakeType::CompressedCertificate)?;
+    check(cc.len() > 8)?;
+    let code = ((cc[0].declassify() as u16) << 8) | cc[1].declassify() as u16;
+    let algorithm =
+        CertificateCompressionAlgorithm::from_code(code).ok_or(UNSUPPORTED_ALGORITHM)?;
+    let uncompressed_len = ((cc[2].declassify() as usize) << 16)
+        | ((cc[3].declassify() as usize) << 8)
+        | cc[4].declassify() as usize;
+    let compressed_len = length_u24_encoded(cc.raw_slice(5..cc.len()))?;
+    check(8 + compressed_len == cc.len())?;
+    let uncompressed = decompress(algorithm, &cc.slice_range(8..cc.len()), uncompressed_len)?;
+    HandshakeData::from_bytes(HandshakeType::Certificate, &uncompressed)
+}
+
 #[cfg(bench)]
 pub fn bench_parse_server_certificate(certificate: &HandshakeData) -> Result<Bytes, TLSError> {
-    parse_server_certificate(certificate)
+    Ok(parse_server_certificate(certificate)?[0].cert_data.clone())
 }
 
+/// Parse the Certificate message.
+///
+/// Returns the non-empty list of certificate entries, starting with the
+/// end-entity certificate, or a [`TLSError`].
 #[cfg_attr(feature = "hax-pv", pv_handwritten)]
-pub(crate) fn parse_server_certificate(certificate: &HandshakeData) -> Result<Bytes, TLSError> {
+pub(crate) fn parse_server_certificate(
+    certificate: &HandshakeData,
+) -> Result<Vec<CertificateEntry>, TLSError> {
+    let (_context, entries) = parse_certificate(certificate)?;
+    if entries.is_empty() {
+        Err(parse_failed())
+    } else {
+        Ok(entries)
+    }
+}
+
+/// Parse the Certificate message of the client.
+///
+/// Returns the certificate request context and the certificate entries,
+/// which are empty if the client didn't authenticate.
+pub(crate) fn parse_client_certificate(
+    certificate: &HandshakeData,
+) -> Result<(Bytes, Vec<CertificateEntry>), TLSError> {
+    parse_certificate(certificate)
+}
+
+/// Parse a Certificate message into the certificate request context and the
+/// certificate entries.
+fn parse_certificate(
+    certificate: &HandshakeData,
+) -> Result<(Bytes, Vec<CertificateEntry>), TLSError> {
     let HandshakeData(sc) = certificate.as_handshake_message(HandshakeType::Certificate)?;
     let mut next = 0;
     let creqlen = length_u8_encoded(&sc[0..sc.len()])?;
+    let context = sc.slice_range(1..1 + creqlen);
     next = next + 1 + creqlen;
     check_length_encoding_u24(sc.raw_slice(next..sc.len()))?;
     next += 3;
-    let crtlen = length_u24_encoded(sc.raw_slice(next..sc.len()))?;
-    next += 3;
-    let crt = sc.slice_range(next..next + crtlen);
-    next += crtlen;
-    let _extlen = length_u16_encoded(&sc[next..sc.len()])?;
-    Ok(crt)
+    let mut entries = Vec::new();
+    while next < sc.len() {
+        let crtlen = length_u24_encoded(sc.raw_slice(next..sc.len()))?;
+        next += 3;
+        let cert_data = sc.slice_range(next..next + crtlen);
+        next += crtlen;
+        let extlen = length_u16_encoded(&sc[next..sc.len()])?;
+        next += 2;
+        let extensions = sc.slice_range(next..next + extlen);
+        next += extlen;
+        entries.push(CertificateEntry {
+            cert_data,
+            extensions,
+        });
+    }
+    Ok((context, entries))
 }
 
-fn ecdsa_signature(sv: &Bytes) -> Result<Bytes, TLSError> {
+pub(crate) fn ecdsa_signature(sv: &Bytes) -> Result<Bytes, TLSError> {
     if sv.len() != 64 {
         Err(parse_failed())
     } else {
@@ -881,7 +1908,7 @@ fn check_r_len(rlen: usize) -> Result<(), TLSError> {
     }
 }
 
-fn parse_ecdsa_signature(sig: Bytes) -> Result<Bytes, TLSError> {
+pub(crate) fn parse_ecdsa_signature(sig: Bytes) -> Result<Bytes, TLSError> {
     if sig.len() < 4 {
         Err(parse_failed())
     } else {
@@ -913,31 +1940,54 @@ pub(crate) fn certificate_verify(algs: &Algorithms, cv: &Bytes) -> Result<Handsh
             }
         }
         SignatureScheme::ED25519 => Err(UNSUPPORTED_ALGORITHM),
+        SignatureScheme::MlDsa65 => Ok(cv.clone()),
     })?;
 
     let sig = algs.signature_algorithm()?.concat(encode_length_u16(sv)?);
     HandshakeData::from_bytes(HandshakeType::CertificateVerify, &sig)
 }
 
+/// Parse a CertificateVerify message.
+///
+/// The signature scheme is the one chosen by the peer. It must be one of the
+/// schemes offered in `signature_algorithms` for `algs`.
+/// Returns the scheme and the raw signature.
 #[cfg_attr(feature = "hax-pv", pv_handwritten)]
 pub(crate) fn parse_certificate_verify(
     algs: &Algorithms,
     certificate_verify: &HandshakeData,
-) -> Result<Bytes, TLSError> {
+) -> Result<(SignatureScheme, Bytes), TLSError> {
     let HandshakeData(cv) =
         certificate_verify.as_handshake_message(HandshakeType::CertificateVerify)?;
-    let sa = algs.signature();
-    check_eq_with_slice(algs.signature_algorithm()?.as_raw(), cv.as_raw(), 0, 2)?;
-    check_length_encoding_u16(&cv.slice_range(2..cv.len()))?;
-    match sa {
-        SignatureScheme::EcdsaSecp256r1Sha256 => parse_ecdsa_signature(cv.slice_range(4..cv.len())),
-        SignatureScheme::RsaPssRsaSha256 => Ok(cv.slice_range(4..cv.len())),
-        SignatureScheme::ED25519 => {
-            if cv.len() - 4 == 64 {
-                Ok(cv.slice_range(4..cv.len()))
-            } else {
-                Err(INVALID_SIGNATURE)
-            }
+    if cv.len() < 4 {
+        tlserr(parse_failed())
+    } else {
+        let sa = SignatureScheme::from_code_point(&cv[0..2])?;
+        if check_mem(&cv[0..2], algs.signature_algorithms()?.as_raw()).is_err() {
+            tlserr(NEGOTIATION_MISMATCH)
+        } else {
+            check_length_encoding_u16(&cv.slice_range(2..cv.len()))?;
+            let sig = (match sa {
+                SignatureScheme::EcdsaSecp256r1Sha256 => {
+                    parse_ecdsa_signature(cv.slice_range(4..cv.len()))
+                }
+                SignatureScheme::RsaPssRsaSha256 => Ok(cv.slice_range(4..cv.len())),
+                SignatureScheme::ED25519 => {
+                    if cv.len() - 4 == 64 {
+                        Ok(cv.slice_range(4..cv.len()))
+                    } else {
+                        Err(INVALID_SIGNATURE)
+                    }
+                }
+                SignatureScheme::MlDsa65 => {
+                    if cv.len() - 4 == ML_DSA_65_SIGNATURE_LEN {
+                        Ok(cv.slice_range(4..cv.len()))
+                    } else {
+                        Err(INVALID_SIGNATURE)
+                    }
+                }
+            })?;
+            Ok((sa, sig))
         }
     }
 }
@@ -1061,6 +2111,7 @@ pub(crate) fn get_handshake_record(p: &Bytes) -> Result<HandshakeData, TLSError>
 
 /// Incremental Transcript Construction
 /// For simplicity, we store the full transcript, but an internal Digest state would suffice.
+#[derive(Clone)]
 pub(crate) struct Transcript {
     hash_algorithm: HashAlgorithm,
     transcript: HandshakeData,
@@ -1074,6 +2125,16 @@ impl Transcript {
         }
     }
 
+    /// Start the transcript after a HelloRetryRequest with the `hash` of the
+    /// first ClientHello, which replaces it (RFC 8446, Section 4.4.1).
+    pub(crate) fn from_client_hello_hash(
+        hash_algorithm: HashAlgorithm,
+        hash: &Digest,
+    ) -> Result<Self, TLSError> {
+        let message_hash = HandshakeData::from_bytes(HandshakeType::MessageHash, hash)?;
+        Ok(Self::new(hash_algorithm).add(&message_hash))
+    }
+
     /// Add the [`HandshakeData`] `msg` to this transcript.
     #[cfg_attr(feature = "hax-pv", pv_constructor)]
     pub(crate) fn add(mut self, msg: &HandshakeData) -> Self {
@@ -1082,8 +2143,8 @@ impl Transcript {
     }
 
     /// Get the hash of this transcript
-    pub(crate) fn transcript_hash(&self) -> Result<Digest, TLSError> {
-        let th = self.hash_algorithm.hash(&self.transcript.0)?;
+    pub(crate) fn transcript_hash(&self, crypto: &impl CryptoProvider) -> Result<Digest, TLSError> {
+        let th = crypto.hash(&self.hash_algorithm, &self.transcript.0)?;
         Ok(th)
     }
 
@@ -1093,10 +2154,12 @@ impl Transcript {
         &self,
         client_hello: &HandshakeData,
         trunc_len: usize,
+        crypto: &impl CryptoProvider,
     ) -> Result<Digest, TLSError> {
         // let Transcript(ha, HandshakeData(tx)) = tx;
         let HandshakeData(ch) = client_hello;
-        self.hash_algorithm.hash(
+        crypto.hash(
+            &self.hash_algorithm,
             &self
                 .transcript
                 .0
@@ -1105,3 +2168,171 @@ impl Transcript {
         )
     }
 }
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+    use crate::tls13crypto::{
+        AeadAlgorithm, KemScheme, SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519,
+    };
+
+    const ALGS: Algorithms = SHA256_Chacha20Poly1305_EcdsaSecp256r1Sha256_X25519;
+
+    #[test]
+    fn unknown_values_are_ignored() {
+        // GREASE and unknown values around the ones we support.
+        let groups = Bytes::from([0, 6, 0x3a, 0x3a, 0x00, 0x1d, 0x12, 0x34]);
+        assert_eq!(check_supported_groups(&ALGS, groups.as_raw()), Ok(()));
+        let versions = Bytes::from([6, 0x7a, 0x7a, 3, 4, 3, 3]);
+        assert_eq!(check_supported_versions(versions.as_raw()), Ok(()));
+        let cipher_suites = Bytes::from([0, 6, 0xda, 0xda, 0x13, 0x03, 0x13, 0x99]);
+        assert_eq!(ALGS.check(cipher_suites.as_raw()), Ok(8));
+        let schemes = Bytes::from([0, 4, 0x8a, 0x8a, 0x04, 0x03]);
+        assert_eq!(
+            check_signature_algorithms(schemes.as_raw()),
+            Ok(Bytes::from([0x8a, 0x8a, 0x04, 0x03]))
+        );
+        let psk_algs = Algorithms::new(
+            HashAlgorithm::SHA256,
+            AeadAlgorithm::Chacha20Poly1305,
+            SignatureScheme::EcdsaSecp256r1Sha256,
+            KemScheme::X25519,
+            true,
+            false,
+        );
+        let modes = Bytes::from([2, 0x0b, 1]);
+        assert_eq!(
+            check_psk_key_exchange_modes(&psk_algs, modes.as_raw()),
+            Ok(())
+        );
+
+        // A GREASE key share with a single byte comes before ours.
+        let gx = Bytes::from([7u8; 32]);
+        let key_shares = bytes_concat!(
+            bytes2(0, 41),
+            bytes2(0x2a, 0x2a),
+            bytes2(0, 1),
+            bytes1(0),
+            bytes2(0, 0x1d),
+            bytes2(0, 32),
+            gx.clone()
+        );
+        assert_eq!(check_key_shares(&ALGS, key_shares.as_raw()), Ok(gx));
+
+        // Unknown extensions are skipped, with or without data.
+        for extension in [
+            Bytes::from([0x4a, 0x4a, 0, 0]),
+            Bytes::from([0xfa, 0xfa, 0, 1, 0]),
+            Bytes::from([0xff, 0x01, 0, 1, 0]),
+        ] {
+            let (len, out) = check_extension(
+                Some(&ALGS),
+                extension.as_raw(),
+                &mut ClientHelloInfo::default(),
+            )
+            .unwrap();
+            assert_eq!(len, extension.len());
+            assert!(out.sni.is_none() && out.key_share.is_none());
+            assert!(out.signature_algorithms.is_none() && out.psk_identities.is_none());
+        }
+
+        // Every extension, even an unknown one, may only be sent once.
+        let mut info = ClientHelloInfo::default();
+        let extensions = Bytes::from([0xfa, 0xfa, 0, 0, 0x4a, 0x4a, 0, 0]);
+        assert!(check_extensions(None, &extensions, &mut info).is_ok());
+        assert_eq!(info.extensions, vec![0xfafa, 0x4a4a]);
+        let extensions = Bytes::from([0xfa, 0xfa, 0, 0, 0xfa, 0xfa, 0, 0]);
+        assert!(check_extensions(None, &extensions, &mut ClientHelloInfo::default()).is_err());
+    }
+
+    #[test]
+    fn grease_client_hello() {
+        let grease = Grease::generate(&mut rand::rngs::OsRng);
+        let gx = Bytes::from([7u8; 32]);
+        let server_name = Bytes::from(b"localhost");
+        let (ch, _) = client_hello(
+            &ALGS,
+            Bytes::zeroes(32),
+            &gx,
+            &server_name,
+            &[],
+            false,
+            false,
+            &[],
+            &None,
+            &Bytes::new(),
+            &None,
+            Some(grease),
+        )
+        .unwrap();
+        let parsed = parse_client_hello(&ALGS, &ch).unwrap();
+        let info = parsed.info;
+        assert_eq!(parsed.server_name, server_name);
+        assert_eq!(parsed.gx, gx);
+        assert_eq!(
+            parsed.signature_algorithms.unwrap().slice_range(2..4),
+            ALGS.signature_algorithm().unwrap()
+        );
+        // The handshake reads the same parameters as the inspection API.
+        assert_eq!(info, parse_client_hello_info(&ch).unwrap());
+
+        // Each list has a GREASE value first.
+        let is_grease = |value: u16| value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff;
+        assert!(is_grease(info.cipher_suites[0]));
+        assert_eq!(info.cipher_suites[1..], [0x1303]);
+        assert!(is_grease(info.supported_groups[0]));
+        assert_eq!(info.supported_groups[1..], [0x001d]);
+        assert!(is_grease(info.key_share_groups[0]));
+        assert_eq!(info.key_share_groups[1..], [0x001d]);
+        assert!(is_grease(info.signature_algorithms[0]));
+        assert_eq!(info.signature_algorithms[1], 0x0403);
+    }
+
+    #[test]
+    fn hello_retry_request_roundtrip() {
+        let sid = Bytes::zeroes(32);
+        let cookie = Bytes::from(b"cookie");
+        let hrr = hello_retry_request(&ALGS, &sid, false, &cookie).unwrap();
+        assert!(is_hello_retry_request(&hrr));
+        assert_eq!(parse_hello_retry_request(&ALGS, &hrr), Ok(cookie.clone()));
+
+        // Bertie clients already sent their only key share.
+        let hrr = hello_retry_request(&ALGS, &sid, true, &cookie).unwrap();
+        assert_eq!(
+            parse_hello_retry_request(&ALGS, &hrr),
+            Err(ILLEGAL_PARAMETER)
+        );
+
+        let sh = server_hello(&ALGS, Bytes::zeroes(32), &sid, &Bytes::zeroes(32), 0).unwrap();
+        assert!(!is_hello_retry_request(&sh));
+    }
+
+    #[test]
+    fn quic_encrypted_extensions() {
+        let quic = QuicParameters::new(vec![Bytes::from(b"h3")], Bytes::from(b"params")).unwrap();
+        let ee = encrypted_extensions(&ALGS, &None, &None, &None, &Some(quic)).unwrap();
+        assert_eq!(
+            parse_quic_extensions(&ee),
+            Ok((Some(Bytes::from(b"h3")), Some(Bytes::from(b"params"))))
+        );
+        // The other extensions don't change.
+        assert_eq!(parse_encrypted_extensions(&ALGS, &ee), Ok((None, None)));
+
+        let ee = encrypted_extensions(&ALGS, &None, &None, &None, &None).unwrap();
+        assert_eq!(parse_quic_extensions(&ee), Ok((None, None)));
+
+        // Outside of QUIC, only the ALPN protocol is sent.
+        let ee =
+            encrypted_extensions(&ALGS, &None, &None, &Some(Bytes::from(b"h2")), &None).unwrap();
+        assert_eq!(
+            parse_quic_extensions(&ee),
+            Ok((Some(Bytes::from(b"h2")), None))
+        );
+
+        // The server selects exactly one protocol.
+        let quic = QuicParameters::new(vec![Bytes::from(b"h3"), Bytes::from(b"h2")], Bytes::new())
+            .unwrap();
+        let ee = encrypted_extensions(&ALGS, &None, &None, &None, &Some(quic)).unwrap();
+        assert!(parse_quic_extensions(&ee).is_err());
+    }
+}
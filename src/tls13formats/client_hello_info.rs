@@ -0,0 +1,228 @@
+//! # ClientHello Inspection
+//!
+//! A typed view of the parameters a client offers in its ClientHello.
+//! The handshake fills it in while it parses the ClientHello. Without a
+//! configured [`Algorithms`](crate::tls13crypto::Algorithms) to check the
+//! offer against, the same parser can be used to pick the configuration for
+//! a connection.
+//!
+//! [`peek_client_hello`] reads the ClientHello from the raw records, e.g. to
+//! route connections by their SNI without terminating TLS.
+
+#[cfg(not(feature = "secret_integers"))]
+use crate::tls13utils::Declassify;
+use crate::tls13utils::{
+    check, length_u16_encoded_slice, length_u8_encoded, Bytes, TLSError, INSUFFICIENT_DATA,
+    PAYLOAD_TOO_LONG, U8,
+};
+
+use super::{
+    check_handshake_record,
+    handshake_data::{HandshakeData, HandshakeType},
+    parse_client_hello_info,
+};
+
+/// The largest record payload, see RFC 8446 Section 5.1.
+const MAX_RECORD_LEN: usize = 1 << 14;
+
+/// The largest ClientHello [`peek_client_hello`] reassembles.
+const MAX_CLIENT_HELLO_LEN: usize = 1 << 16;
+
+/// The parameters offered in a ClientHello.
+///
+/// Code points, e.g. of cipher suites and groups, are kept as numbers, so that
+/// values Bertie doesn't support are visible too.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClientHelloInfo {
+    /// The legacy session id.
+    pub legacy_session_id: Bytes,
+    /// The host name in the `server_name` extension.
+    pub server_name: Option<Bytes>,
+    /// The offered cipher suites, in the client's order of preference.
+    pub cipher_suites: Vec<u16>,
+    /// The groups in the `supported_groups` extension.
+    pub supported_groups: Vec<u16>,
+    /// The groups of the entries in the `key_share` extension.
+    pub key_share_groups: Vec<u16>,
+    /// The schemes in the `signature_algorithms` extension.
+    pub signature_algorithms: Vec<u16>,
+    /// The protocol names in the `application_layer_protocol_negotiation`
+    /// extension.
+    pub alpn_protocols: Vec<Bytes>,
+    /// The identities in the `pre_shared_key` extension.
+    pub psk_identities: Vec<Bytes>,
+    /// The modes in the `psk_key_exchange_modes` extension, where 0 is
+    /// PSK-only and 1 PSK with a key exchange.
+    pub psk_key_exchange_modes: Vec<u8>,
+    /// Whether the client asks for a stapled OCSP response in the
+    /// `status_request` extension.
+    pub status_request: bool,
+    /// Whether the client asks for signed certificate timestamps in the
+    /// `signed_certificate_timestamp` extension.
+    pub signed_certificate_timestamp: bool,
+    /// The schemes in the `delegated_credential` extension, for which the
+    /// client accepts delegated credentials (RFC 9345).
+    pub delegated_credential_schemes: Vec<u16>,
+    /// The algorithms in the `compress_certificate` extension.
+    pub certificate_compression_algorithms: Vec<u16>,
+    /// The certificate types in the `client_certificate_type` extension.
+    ///
+    /// Bertie servers only authenticate clients with X.509 certificates, after
+    /// the handshake, so this doesn't affect the handshake.
+    pub client_certificate_types: Vec<u8>,
+    /// The certificate types in the `server_certificate_type` extension,
+    /// where 0 is X.509 and 2 a raw public key (RFC 7250).
+    pub server_certificate_types: Vec<u8>,
+    /// The DER encoded distinguished names of the client's trust anchors in
+    /// the `certificate_authorities` extension.
+    pub certificate_authorities: Vec<Bytes>,
+    /// Whether the client offers to authenticate after the handshake in the
+    /// `post_handshake_auth` extension.
+    pub post_handshake_auth: bool,
+    /// The cookie of a HelloRetryRequest the client sends back in the
+    /// `cookie` extension, if any.
+    pub cookie: Option<Bytes>,
+    /// The content of the `quic_transport_parameters` extension, if any.
+    pub quic_transport_parameters: Option<Bytes>,
+    /// The content of the `encrypted_client_hello` extension, if any.
+    ///
+    /// In a ClientHelloOuter, the other fields are those of the outer hello,
+    /// e.g. the `server_name` is the public name of the ECH config.
+    pub encrypted_client_hello: Option<Bytes>,
+    /// The types of all extensions, including unknown ones, in the order the
+    /// client sent them.
+    pub extensions: Vec<u16>,
+}
+
+/// Read the big endian [`u16`] at the start of `bytes`.
+fn read_u16(bytes: &[U8]) -> Result<u16, TLSError> {
+    check(bytes.len() >= 2)?;
+    Ok(((bytes[0].declassify() as u16) << 8) | bytes[1].declassify() as u16)
+}
+
+/// Split the vector with a two byte length at the start of `bytes`.
+///
+/// Returns the content and the remaining bytes.
+fn split_u16_vector(bytes: &[U8]) -> Result<(&[U8], &[U8]), TLSError> {
+    let len = length_u16_encoded_slice(bytes)?;
+    Ok((&bytes[2..2 + len], &bytes[2 + len..]))
+}
+
+/// Split the vector with a one byte length at the start of `bytes`.
+///
+/// Returns the content and the remaining bytes.
+fn split_u8_vector(bytes: &[U8]) -> Result<(&[U8], &[U8]), TLSError> {
+    let len = length_u8_encoded(bytes)?;
+    Ok((&bytes[1..1 + len], &bytes[1 + len..]))
+}
+
+/// Parse a list of two byte code points that fills all of `bytes`.
+pub(super) fn u16_list(bytes: &[U8]) -> Result<Vec<u16>, TLSError> {
+    check(bytes.len() % 2 == 0)?;
+    bytes.chunks(2).map(read_u16).collect()
+}
+
+/// Parse a list of two byte code points with a two byte length that fills all
+/// of `bytes`.
+pub(super) fn u16_vector(bytes: &[U8]) -> Result<Vec<u16>, TLSError> {
+    let (list, rest) = split_u16_vector(bytes)?;
+    check(rest.is_empty())?;
+    u16_list(list)
+}
+
+/// Parse the protocol names of the ALPN extension.
+pub(super) fn alpn_protocols(bytes: &[U8]) -> Result<Vec<Bytes>, TLSError> {
+    let (mut list, rest) = split_u16_vector(bytes)?;
+    check(rest.is_empty())?;
+    let mut protocols = Vec::new();
+    while !list.is_empty() {
+        let (name, rest) = split_u8_vector(list)?;
+        protocols.push(name.into());
+        list = rest;
+    }
+    Ok(protocols)
+}
+
+/// Parse a list of one byte code points with a one byte length, e.g. the
+/// certificate types of the `client_certificate_type` and
+/// `server_certificate_type` extensions.
+pub(super) fn u8_vector(bytes: &[U8]) -> Result<Vec<u8>, TLSError> {
+    let (list, rest) = split_u8_vector(bytes)?;
+    check(rest.is_empty() && !list.is_empty())?;
+    Ok(list.iter().map(|t| t.declassify()).collect())
+}
+
+/// Parse the algorithms of the `compress_certificate` extension.
+pub(super) fn certificate_compression_algorithms(bytes: &[U8]) -> Result<Vec<u16>, TLSError> {
+    let (list, rest) = split_u8_vector(bytes)?;
+    check(rest.is_empty() && !list.is_empty())?;
+    u16_list(list)
+}
+
+/// Parse the groups of the key share entries.
+pub(super) fn key_share_groups(bytes: &[U8]) -> Result<Vec<u16>, TLSError> {
+    let (mut list, rest) = split_u16_vector(bytes)?;
+    check(rest.is_empty())?;
+    let mut groups = Vec::new();
+    while !list.is_empty() {
+        groups.push(read_u16(list)?);
+        let (_key_exchange, rest) = split_u16_vector(&list[2..])?;
+        list = rest;
+    }
+    Ok(groups)
+}
+
+/// Read the handshake record at the start of `data`.
+///
+/// Returns the handshake fragment and the length of the record, or an
+/// [`INSUFFICIENT_DATA`] error if the record is incomplete.
+fn peek_handshake_record(data: &[u8]) -> Result<(HandshakeData, usize), TLSError> {
+    if data.len() < 5 {
+        return Err(INSUFFICIENT_DATA);
+    }
+    let len = ((data[3] as usize) << 8) | data[4] as usize;
+    check(len > 0 && len <= MAX_RECORD_LEN)?;
+    if data.len() < 5 + len {
+        return Err(INSUFFICIENT_DATA);
+    }
+    // The legacy record version may be anything from TLS 1.0.
+    let mut record = Bytes::from(&data[0..5 + len]);
+    if data[1] == 3 {
+        record[2] = U8(3);
+    }
+    check_handshake_record(&record)
+}
+
+/// Read the ClientHello from the raw records at the start of `data` without
+/// consuming them.
+///
+/// The ClientHello may be split across several handshake records.
+/// Anything after the ClientHello is ignored.
+/// No [`Algorithms`](crate::tls13crypto::Algorithms) or keys are needed.
+///
+/// Returns the [`ClientHelloInfo`], an [`INSUFFICIENT_DATA`] error if more
+/// data must be read to get the complete ClientHello, or another [`TLSError`]
+/// if the data isn't a valid ClientHello.
+pub fn peek_client_hello(data: &[u8]) -> Result<ClientHelloInfo, TLSError> {
+    let mut message = Bytes::new();
+    let mut rest = data;
+    loop {
+        let (fragment, record_len) = peek_handshake_record(rest)?;
+        message = message.concat(fragment.to_bytes());
+        rest = &rest[record_len..];
+        if message.len() >= 4 {
+            check(message[0].declassify() == HandshakeType::ClientHello as u8)?;
+            let len = ((message[1].declassify() as usize) << 16)
+                | ((message[2].declassify() as usize) << 8)
+                | message[3].declassify() as usize;
+            if len > MAX_CLIENT_HELLO_LEN {
+                return Err(PAYLOAD_TOO_LONG);
+            }
+            if message.len() >= 4 + len {
+                return parse_client_hello_info(&HandshakeData::from(
+                    message.slice_range(0..4 + len),
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! # GREASE
+//!
+//! GREASE (RFC 8701) values are reserved code points a client inserts into
+//! the lists and extensions of its ClientHello. Servers must ignore them like
+//! any other unknown value, and clients sending them make sure they do.
+
+use rand::{CryptoRng, RngCore};
+
+use crate::tls13utils::{bytes2, encode_length_u16, Bytes, TLSError};
+
+/// The GREASE values of a ClientHello.
+///
+/// Each value is one of the reserved values `0x?A?A`, given by its first byte.
+#[derive(Clone, Copy)]
+pub(crate) struct Grease {
+    cipher_suite: u8,
+    group: u8,
+    signature_algorithm: u8,
+    version: u8,
+    extensions: [u8; 2],
+}
+
+/// Pick a random GREASE value.
+fn random_value(rng: &mut (impl CryptoRng + RngCore)) -> u8 {
+    let mut b = [0u8; 1];
+    rng.fill_bytes(&mut b);
+    (b[0] & 0xf0) | 0x0a
+}
+
+impl Grease {
+    /// Pick random GREASE values.
+    pub(crate) fn generate(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let first_extension = random_value(rng);
+        // The two extensions must have different types.
+        let mut second_extension = random_value(rng);
+        if second_extension == first_extension {
+            second_extension ^= 0x10;
+        }
+        Self {
+            cipher_suite: random_value(rng),
+            group: random_value(rng),
+            signature_algorithm: random_value(rng),
+            version: random_value(rng),
+            extensions: [first_extension, second_extension],
+        }
+    }
+}
+
+/// The GREASE value for `value`, or nothing without GREASE.
+fn grease_value(grease: Option<Grease>, value: impl Fn(&Grease) -> u8) -> Bytes {
+    match grease {
+        Some(grease) => {
+            let b = value(&grease);
+            bytes2(b, b)
+        }
+        None => Bytes::new(),
+    }
+}
+
+/// The GREASE cipher suite to offer first.
+pub(super) fn grease_cipher_suite(grease: Option<Grease>) -> Bytes {
+    grease_value(grease, |grease| grease.cipher_suite)
+}
+
+/// The GREASE group to offer first in `supported_groups`.
+pub(super) fn grease_group(grease: Option<Grease>) -> Bytes {
+    grease_value(grease, |grease| grease.group)
+}
+
+/// The GREASE signature algorithm to offer first.
+pub(super) fn grease_signature_algorithm(grease: Option<Grease>) -> Bytes {
+    grease_value(grease, |grease| grease.signature_algorithm)
+}
+
+/// The GREASE version to offer first in `supported_versions`.
+pub(super) fn grease_version(grease: Option<Grease>) -> Bytes {
+    grease_value(grease, |grease| grease.version)
+}
+
+/// The GREASE key share to send first, with a single zero byte as key, for the
+/// GREASE group.
+pub(super) fn grease_key_share(grease: Option<Grease>) -> Result<Bytes, TLSError> {
+    match grease {
+        Some(_) => Ok(grease_group(grease).concat(encode_length_u16(Bytes::from([0]))?)),
+        None => Ok(Bytes::new()),
+    }
+}
+
+/// The GREASE extensions to send first and last: the first is empty, the
+/// second holds a single zero byte.
+pub(super) fn grease_extensions(grease: Option<Grease>) -> Result<(Bytes, Bytes), TLSError> {
+    match grease {
+        Some(grease) => {
+            let [first, second] = grease.extensions;
+            Ok((
+                bytes2(first, first).concat(encode_length_u16(Bytes::new())?),
+                bytes2(second, second).concat(encode_length_u16(Bytes::from([0]))?),
+            ))
+        }
+        None => Ok((Bytes::new(), Bytes::new())),
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    fn is_grease(value: u16) -> bool {
+        value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+    }
+
+    #[test]
+    fn values() {
+        let rng = &mut rand::rngs::OsRng;
+        for _ in 0..64 {
+            let grease = Grease::generate(rng);
+            for value in [
+                grease.cipher_suite,
+                grease.group,
+                grease.signature_algorithm,
+                grease.version,
+                grease.extensions[0],
+                grease.extensions[1],
+            ] {
+                assert!(is_grease(u16::from_be_bytes([value, value])));
+            }
+            assert_ne!(grease.extensions[0], grease.extensions[1]);
+        }
+        assert!(!is_grease(0x1301));
+        assert!(!is_grease(0x0a1a));
+        assert!(is_grease(0xfafa));
+    }
+}
@@ -18,6 +18,7 @@ use crate::tls13utils::{
 ///     certificate_verify(15),
 ///     finished(20),
 ///     key_update(24),
+///     compressed_certificate(25),
 ///     message_hash(254),
 ///     (255)
 /// } HandshakeType;
@@ -35,6 +36,7 @@ pub enum HandshakeType {
     CertificateVerify = 15,
     Finished = 20,
     KeyUpdate = 24,
+    CompressedCertificate = 25,
     MessageHash = 254,
 }
 
@@ -50,12 +52,14 @@ pub fn get_hs_type(t: u8) -> Result<HandshakeType, TLSError> {
         15 => Ok(HandshakeType::CertificateVerify),
         20 => Ok(HandshakeType::Finished),
         24 => Ok(HandshakeType::KeyUpdate),
+        25 => Ok(HandshakeType::CompressedCertificate),
         254 => Ok(HandshakeType::MessageHash),
         _ => tlserr(parse_failed()),
     }
 }
 
 /// Hadshake data of the TLS handshake.
+#[derive(Clone)]
 pub struct HandshakeData(pub(crate) Bytes);
 
 impl HandshakeData {
@@ -1,14 +1,27 @@
 use rand::{CryptoRng, RngCore};
 
 use crate::{
-    server::{lookup_db, ServerDB, ServerInfo},
-    tls13cert::{cert_public_key, rsa_public_key, verification_key_from_cert},
+    cert_compression::CertificateCompressionAlgorithm,
+    client::ClientCredentials,
+    ct::parse_sct_list,
+    delegated_credential::parse_delegated_credential,
+    ech::{
+        ech_inner_extension, ech_outer_placeholder, grease_ech_extension, is_ech_inner,
+        open_client_hello_inner, seal_client_hello_inner, EchConfig, EchOffer, EchStatus,
+    },
+    psk::PreSharedKey,
+    quic::QuicParameters,
+    server::{lookup_db, ClientCertificateTrust, ClientOffer, ServerDB, ServerInfo},
+    tls13cert::{
+        cert_public_key, certificate_matches_name, chain_matches_authorities,
+        check_delegated_credential_validity, verification_key_from_cert,
+        verification_key_from_spki, verify_certificate_chain, verify_delegated_credential,
+    },
     tls13crypto::{
-        hkdf_expand, hkdf_extract, hmac_tag, hmac_verify, kem_decap, kem_encap, kem_keygen, sign,
-        sign_rsa, verify, zero_key, AeadAlgorithm, AeadKey, AeadKeyIV, Algorithms, Digest,
-        HashAlgorithm, KemSk, Key, MacKey, Psk, Random, SignatureScheme,
+        zero_key, AeadAlgorithm, AeadKey, AeadKeyIV, Algorithms, CryptoProvider, Digest,
+        HashAlgorithm, KemPk, KemSk, Key, MacKey, Random,
     },
-    tls13formats::{handshake_data::HandshakeData, *},
+    tls13formats::{grease::Grease, handshake_data::HandshakeData, *},
     tls13record::*,
     tls13utils::*,
 };
@@ -16,8 +29,8 @@ use crate::{
 /* TLS 1.3 Key Schedule: See RFC 8446 Section 7 */
 
 /// Get the hash of an empty byte slice.
-fn hash_empty(algorithm: &HashAlgorithm) -> Result<Digest, TLSError> {
-    algorithm.hash(&Bytes::new())
+fn hash_empty(algorithm: &HashAlgorithm, crypto: &impl CryptoProvider) -> Result<Digest, TLSError> {
+    crypto.hash(algorithm, &Bytes::new())
 }
 
 /// HKDF expand with a `label`.
@@ -27,6 +40,7 @@ fn hkdf_expand_label(
     label: Bytes,
     context: &Bytes,
     len: usize,
+    crypto: &impl CryptoProvider,
 ) -> Result<Key, TLSError> {
     if len >= 65536 {
         Err(PAYLOAD_TOO_LONG)
@@ -36,7 +50,7 @@ fn hkdf_expand_label(
         let info = encode_length_u8(tls13_label.as_raw())?
             .concat(encode_length_u8(context.as_raw())?)
             .prefix(&lenb);
-        hkdf_expand(hash_algorithm, key, &info, len)
+        crypto.hkdf_expand(hash_algorithm, key, &info, len)
     }
 }
 
@@ -45,6 +59,7 @@ pub fn derive_secret(
     key: &Key,
     label: Bytes,
     transcript_hash: &Digest,
+    crypto: &impl CryptoProvider,
 ) -> Result<Key, TLSError> {
     hkdf_expand_label(
         hash_algorithm,
@@ -52,17 +67,27 @@ pub fn derive_secret(
         label,
         transcript_hash,
         hash_algorithm.hash_len(),
+        crypto,
     )
 }
 
-pub fn derive_binder_key(ha: &HashAlgorithm, k: &Key) -> Result<MacKey, TLSError> {
-    let early_secret = hkdf_extract(ha, k, &zero_key(ha))?;
-    derive_secret(
-        ha,
-        &early_secret,
-        bytes(&LABEL_RES_BINDER),
-        &hash_empty(ha)?,
-    )
+/// Derive the binder key for the PSK `k`.
+///
+/// Binders of `external` PSKs use a different label than those of resumption
+/// PSKs.
+pub fn derive_binder_key(
+    ha: &HashAlgorithm,
+    k: &Key,
+    external: bool,
+    crypto: &impl CryptoProvider,
+) -> Result<MacKey, TLSError> {
+    let early_secret = crypto.hkdf_extract(ha, k, &zero_key(ha))?;
+    let label = if external {
+        bytes(&LABEL_EXT_BINDER)
+    } else {
+        bytes(&LABEL_RES_BINDER)
+    };
+    derive_secret(ha, &early_secret, label, &hash_empty(ha, crypto)?, crypto)
 }
 
 /// Derive an AEAD key and iv.
@@ -70,6 +95,7 @@ pub(crate) fn derive_aead_key_iv(
     hash_algorithm: &HashAlgorithm,
     aead_algorithm: &AeadAlgorithm,
     key: &Key,
+    crypto: &impl CryptoProvider,
 ) -> Result<AeadKeyIV, TLSError> {
     let sender_write_key = hkdf_expand_label(
         hash_algorithm,
@@ -77,6 +103,7 @@ pub(crate) fn derive_aead_key_iv(
         bytes(&LABEL_KEY),
         &Bytes::new(),
         aead_algorithm.key_len(),
+        crypto,
     )?;
     let sender_write_iv = hkdf_expand_label(
         hash_algorithm,
@@ -84,6 +111,7 @@ pub(crate) fn derive_aead_key_iv(
         bytes(&LABEL_IV),
         &Bytes::new(),
         aead_algorithm.iv_len(),
+        crypto,
     )?;
     Ok(AeadKeyIV::new(
         AeadKey::new(sender_write_key, *aead_algorithm),
@@ -92,110 +120,185 @@ pub(crate) fn derive_aead_key_iv(
 }
 
 /// Derive 0-RTT AEAD keys.
+///
+/// Returns the keys, the early exporter master secret, and the client early
+/// traffic secret.
 pub(crate) fn derive_0rtt_keys(
     hash_algorithm: &HashAlgorithm,
     aead_algoorithm: &AeadAlgorithm,
     key: &Key,
     tx: &Digest,
-) -> Result<(AeadKeyIV, Key), TLSError> {
-    let early_secret = hkdf_extract(hash_algorithm, key, &zero_key(hash_algorithm))?;
-    let client_early_traffic_secret =
-        derive_secret(hash_algorithm, &early_secret, bytes(&LABEL_C_E_TRAFFIC), tx)?;
+    crypto: &impl CryptoProvider,
+) -> Result<(AeadKeyIV, Key, Key), TLSError> {
+    let early_secret = crypto.hkdf_extract(hash_algorithm, key, &zero_key(hash_algorithm))?;
+    let client_early_traffic_secret = derive_secret(
+        hash_algorithm,
+        &early_secret,
+        bytes(&LABEL_C_E_TRAFFIC),
+        tx,
+        crypto,
+    )?;
     let early_exporter_master_secret = derive_secret(
         hash_algorithm,
         &early_secret,
         bytes(&LABEL_E_EXP_MASTER),
         tx,
+        crypto,
     )?;
     let sender_write_key_iv = derive_aead_key_iv(
         hash_algorithm,
         aead_algoorithm,
         &client_early_traffic_secret,
+        crypto,
     )?;
-    Ok((sender_write_key_iv, early_exporter_master_secret))
+    Ok((
+        sender_write_key_iv,
+        early_exporter_master_secret,
+        client_early_traffic_secret,
+    ))
 }
 
-pub fn derive_finished_key(ha: &HashAlgorithm, k: &Key) -> Result<MacKey, TLSError> {
+pub fn derive_finished_key(
+    ha: &HashAlgorithm,
+    k: &Key,
+    crypto: &impl CryptoProvider,
+) -> Result<MacKey, TLSError> {
     hkdf_expand_label(
         ha,
         k,
         bytes(&LABEL_FINISHED),
         &Bytes::new(),
         ha.hmac_tag_len(),
+        crypto,
     )
 }
 
-/// Derive the handshake keys and master secret.
+/// The client and server traffic secrets of the handshake or the application
+/// data.
+///
+/// The record layer only keeps the keys derived from them, the secrets are
+/// returned by the handshake for QUIC, which derives its own packet
+/// protection keys from them (RFC 9001, Section 5.1).
+pub struct TrafficSecrets {
+    pub(crate) client: Key,
+    pub(crate) server: Key,
+}
+
+/// Derive the handshake keys and master secret, and the handshake traffic
+/// secrets.
+#[allow(clippy::type_complexity)]
 pub(crate) fn derive_hk_ms(
     ha: &HashAlgorithm,
     ae: &AeadAlgorithm,
     shared_secret: &Key,
-    psko: &Option<Psk>,
+    psko: &Option<PreSharedKey>,
     transcript_hash: &Digest,
-) -> Result<(AeadKeyIV, AeadKeyIV, MacKey, MacKey, Key), TLSError> {
+    crypto: &impl CryptoProvider,
+) -> Result<(AeadKeyIV, AeadKeyIV, MacKey, MacKey, Key, TrafficSecrets), TLSError> {
     let psk = if let Some(k) = psko {
-        k.clone()
+        k.key.clone()
     } else {
         zero_key(ha)
     };
-    let early_secret = hkdf_extract(ha, &psk, &zero_key(ha))?;
-    let digest_emp = hash_empty(ha)?;
-    let derived_secret = derive_secret(ha, &early_secret, bytes(&LABEL_DERIVED), &digest_emp)?;
-    let handshake_secret = hkdf_extract(ha, shared_secret, &derived_secret)?;
+    let early_secret = crypto.hkdf_extract(ha, &psk, &zero_key(ha))?;
+    let digest_emp = hash_empty(ha, crypto)?;
+    let derived_secret = derive_secret(
+        ha,
+        &early_secret,
+        bytes(&LABEL_DERIVED),
+        &digest_emp,
+        crypto,
+    )?;
+    let handshake_secret = crypto.hkdf_extract(ha, shared_secret, &derived_secret)?;
     let client_handshake_traffic_secret = derive_secret(
         ha,
         &handshake_secret,
         bytes(&LABEL_C_HS_TRAFFIC),
         transcript_hash,
+        crypto,
     )?;
     let server_handshake_traffic_secret = derive_secret(
         ha,
         &handshake_secret,
         bytes(&LABEL_S_HS_TRAFFIC),
         transcript_hash,
+        crypto,
+    )?;
+    let client_finished_key = derive_finished_key(ha, &client_handshake_traffic_secret, crypto)?;
+    let server_finished_key = derive_finished_key(ha, &server_handshake_traffic_secret, crypto)?;
+    let client_write_key_iv = derive_aead_key_iv(ha, ae, &client_handshake_traffic_secret, crypto)?;
+    let server_write_key_iv = derive_aead_key_iv(ha, ae, &server_handshake_traffic_secret, crypto)?;
+    let master_secret_ = derive_secret(
+        ha,
+        &handshake_secret,
+        bytes(&LABEL_DERIVED),
+        &digest_emp,
+        crypto,
     )?;
-    let client_finished_key = derive_finished_key(ha, &client_handshake_traffic_secret)?;
-    let server_finished_key = derive_finished_key(ha, &server_handshake_traffic_secret)?;
-    let client_write_key_iv = derive_aead_key_iv(ha, ae, &client_handshake_traffic_secret)?;
-    let server_write_key_iv = derive_aead_key_iv(ha, ae, &server_handshake_traffic_secret)?;
-    let master_secret_ = derive_secret(ha, &handshake_secret, bytes(&LABEL_DERIVED), &digest_emp)?;
-    let master_secret = hkdf_extract(ha, &zero_key(ha), &master_secret_)?;
+    let master_secret = crypto.hkdf_extract(ha, &zero_key(ha), &master_secret_)?;
     Ok((
         client_write_key_iv,
         server_write_key_iv,
         client_finished_key,
         server_finished_key,
         master_secret,
+        TrafficSecrets {
+            client: client_handshake_traffic_secret,
+            server: server_handshake_traffic_secret,
+        },
     ))
 }
 
-/// Derive the application keys and master secret.
+/// Derive the application keys and exporter master secret, and the
+/// application traffic secrets.
 pub(crate) fn derive_app_keys(
     ha: &HashAlgorithm,
     ae: &AeadAlgorithm,
     master_secret: &Key,
     tx: &Digest,
-) -> Result<(AeadKeyIV, AeadKeyIV, Key), TLSError> {
+    crypto: &impl CryptoProvider,
+) -> Result<(AeadKeyIV, AeadKeyIV, Key, TrafficSecrets), TLSError> {
     let client_application_traffic_secret_0 =
-        derive_secret(ha, master_secret, bytes(&LABEL_C_AP_TRAFFIC), tx)?;
+        derive_secret(ha, master_secret, bytes(&LABEL_C_AP_TRAFFIC), tx, crypto)?;
     let server_application_traffic_secret_0 =
-        derive_secret(ha, master_secret, bytes(&LABEL_S_AP_TRAFFIC), tx)?;
-    let client_write_key_iv = derive_aead_key_iv(ha, ae, &client_application_traffic_secret_0)?;
-    let server_write_key_iv = derive_aead_key_iv(ha, ae, &server_application_traffic_secret_0)?;
-    let exporter_master_secret = derive_secret(ha, master_secret, bytes(&LABEL_EXP_MASTER), tx)?;
+        derive_secret(ha, master_secret, bytes(&LABEL_S_AP_TRAFFIC), tx, crypto)?;
+    let client_write_key_iv =
+        derive_aead_key_iv(ha, ae, &client_application_traffic_secret_0, crypto)?;
+    let server_write_key_iv =
+        derive_aead_key_iv(ha, ae, &server_application_traffic_secret_0, crypto)?;
+    let exporter_master_secret =
+        derive_secret(ha, master_secret, bytes(&LABEL_EXP_MASTER), tx, crypto)?;
     Ok((
         client_write_key_iv,
         server_write_key_iv,
         exporter_master_secret,
+        TrafficSecrets {
+            client: client_application_traffic_secret_0,
+            server: server_application_traffic_secret_0,
+        },
     ))
 }
 
+/// Derive the key for the Finished messages of post-handshake client
+/// authentication, from the first client application traffic secret.
+fn derive_post_handshake_finished_key(
+    ha: &HashAlgorithm,
+    master_secret: &Key,
+    tx: &Digest,
+    crypto: &impl CryptoProvider,
+) -> Result<MacKey, TLSError> {
+    let client_application_traffic_secret_0 =
+        derive_secret(ha, master_secret, bytes(&LABEL_C_AP_TRAFFIC), tx, crypto)?;
+    derive_finished_key(ha, &client_application_traffic_secret_0, crypto)
+}
+
 pub(crate) fn derive_rms(
     ha: &HashAlgorithm,
     master_secret: &Key,
     tx: &Digest,
+    crypto: &impl CryptoProvider,
 ) -> Result<Key, TLSError> {
-    derive_secret(ha, master_secret, bytes(&LABEL_RES_MASTER), tx)
+    derive_secret(ha, master_secret, bytes(&LABEL_RES_MASTER), tx, crypto)
 }
 
 /* Handshake State Machine */
@@ -205,13 +308,107 @@ PostServerFinished -> PostClientFinished
 There are no optional steps, all states must be traversed, even if the traversals are NOOPS.
 See "put_psk_skip_server_signature" below */
 
-pub struct ClientPostClientHello(Random, Algorithms, KemSk, Option<Psk>, Transcript);
-pub struct ClientPostServerHello(Random, Random, Algorithms, Key, MacKey, MacKey, Transcript);
-pub struct ClientPostCertificateVerify(Random, Random, Algorithms, Key, MacKey, MacKey, Transcript);
-pub struct ClientPostServerFinished(Random, Random, Algorithms, Key, MacKey, Transcript);
+/// What the client sent in the `encrypted_client_hello` extension.
+enum ClientEch {
+    NotOffered,
+    Grease,
+    /// The client encrypted the ClientHelloInner with the `inner_random`, and
+    /// sent the ClientHelloOuter to the `public_name` of the ECH config.
+    Offered {
+        inner_random: Random,
+        inner_client_hello: HandshakeData,
+        public_name: Bytes,
+    },
+}
+
+/// What the client needs to send its ClientHello again after a
+/// HelloRetryRequest.
+struct ClientHelloRetry {
+    server_name: Bytes,
+    kem_pk: KemPk,
+    /// The GREASE ECH extension, which is sent again as is.
+    ech_extension: Bytes,
+    grease: Option<Grease>,
+    certificate_authorities: Vec<Bytes>,
+    quic: Option<QuicParameters>,
+}
+
+/// What the client needs to authenticate after the handshake.
+struct ClientPostHandshakeAuth {
+    credentials: ClientCredentials,
+    finished_key: MacKey,
+    /// The CertificateRequests the client hasn't answered yet.
+    requests: Vec<HandshakeData>,
+}
+
+/// The client states before the server is authenticated hold the raw public
+/// key the client expects, if any.
+///
+/// Until the client got a HelloRetryRequest, it can send its ClientHello
+/// again.
+///
+/// All client states hold the credentials of the client, if it offered
+/// post-handshake authentication.
+pub struct ClientPostClientHello(
+    Random,
+    Algorithms,
+    KemSk,
+    Vec<PreSharedKey>,
+    Transcript,
+    Option<Bytes>,
+    ClientEch,
+    Option<ClientHelloRetry>,
+    Option<ClientCredentials>,
+);
+pub struct ClientPostServerHello(
+    Random,
+    Random,
+    Algorithms,
+    Key,
+    MacKey,
+    MacKey,
+    Transcript,
+    Option<Bytes>,
+    EchStatus,
+    // The public name the server certificate must be for, if ECH was rejected.
+    Option<Bytes>,
+    Option<ClientCredentials>,
+);
+pub struct ClientPostCertificateVerify(
+    Random,
+    Random,
+    Algorithms,
+    Key,
+    MacKey,
+    MacKey,
+    Transcript,
+    Vec<CertificateEntry>,
+    EchStatus,
+    Option<ClientCredentials>,
+);
+pub struct ClientPostServerFinished(
+    Random,
+    Random,
+    Algorithms,
+    Key,
+    MacKey,
+    Transcript,
+    Vec<CertificateEntry>,
+    EchStatus,
+    Option<ClientCredentials>,
+);
 // We do not use most of this state, but we keep the unused parts for verification purposes.
 #[allow(dead_code)]
-pub struct ClientPostClientFinished(Random, Random, Algorithms, Key, Transcript);
+pub struct ClientPostClientFinished(
+    Random,
+    Random,
+    Algorithms,
+    Key,
+    Transcript,
+    Vec<CertificateEntry>,
+    EchStatus,
+    Option<ClientPostHandshakeAuth>,
+);
 
 pub fn algs_post_client_hello(st: &ClientPostClientHello) -> Algorithms {
     st.1
@@ -223,6 +420,38 @@ pub fn algs_post_client_finished(st: &ClientPostClientFinished) -> Algorithms {
     st.2
 }
 
+/// The certificate entries the server sent, starting with the end-entity
+/// certificate.
+///
+/// This is empty in PSK mode.
+pub(crate) fn certificates_post_client_finished(
+    st: &ClientPostClientFinished,
+) -> &[CertificateEntry] {
+    &st.5
+}
+
+/// The ECH status before the server hello.
+pub(crate) fn ech_status_post_client_hello(st: &ClientPostClientHello) -> EchStatus {
+    match st.6 {
+        ClientEch::NotOffered => EchStatus::NotOffered,
+        ClientEch::Grease => EchStatus::Grease,
+        ClientEch::Offered { .. } => EchStatus::Offered,
+    }
+}
+/// The ECH status after the server hello.
+pub(crate) fn ech_status_post_server_hello(st: &ClientPostServerHello) -> EchStatus {
+    st.8.clone()
+}
+/// The ECH status at the end of the handshake.
+pub(crate) fn ech_status_post_client_finished(st: &ClientPostClientFinished) -> EchStatus {
+    st.6.clone()
+}
+
+/// Whether the server asked for a certificate the client hasn't sent yet.
+pub(crate) fn certificate_requested_post_client_finished(st: &ClientPostClientFinished) -> bool {
+    st.7.as_ref().is_some_and(|auth| !auth.requests.is_empty())
+}
+
 /// Server state after processing the client hello.
 pub struct ServerPostClientHello {
     client_randomness: Random,
@@ -231,6 +460,8 @@ pub struct ServerPostClientHello {
     gx: Bytes,
     server: ServerInfo,
     transcript: Transcript,
+    /// The ClientHelloInner, if the server accepted ECH.
+    ech_client_hello: Option<HandshakeData>,
 }
 
 /// Server state after generating the server hello.
@@ -245,27 +476,108 @@ pub struct ServerPostServerHello {
     transcript: Transcript,
 }
 
-pub struct ServerPostCertificateVerify(Random, Random, Algorithms, Key, MacKey, MacKey, Transcript);
-pub struct ServerPostServerFinished(Random, Random, Algorithms, Key, MacKey, Transcript);
+/// The server states after the server hello hold the server name and, if
+/// the client offered post-handshake authentication, the trust anchors for
+/// the client certificate.
+pub struct ServerPostCertificateVerify(
+    Random,
+    Random,
+    Algorithms,
+    Key,
+    MacKey,
+    MacKey,
+    Transcript,
+    Option<Bytes>,
+    Option<ClientCertificateTrust>,
+);
+pub struct ServerPostServerFinished(
+    Random,
+    Random,
+    Algorithms,
+    Key,
+    MacKey,
+    Transcript,
+    Option<Bytes>,
+    Option<ClientCertificateTrust>,
+);
+
+/// The server state of post-handshake client authentication.
+struct ServerPostHandshakeAuth {
+    finished_key: MacKey,
+    /// The number of CertificateRequests sent, which is the context of the
+    /// next one.
+    requests: u64,
+    /// The outstanding CertificateRequest, and the messages the client sent
+    /// for it so far.
+    pending: Option<(HandshakeData, HandshakeData)>,
+    /// The certificate chain the client last authenticated with.
+    client_certificates: Vec<CertificateEntry>,
+    /// What the client's certificate chain is validated against.
+    trust: ClientCertificateTrust,
+}
+
 // We do not use most of this state, but we keep the unsused parts for verification purposes.
 #[allow(dead_code)]
-pub struct ServerPostClientFinished(Random, Random, Algorithms, Key, Transcript);
+pub struct ServerPostClientFinished(
+    Random,
+    Random,
+    Algorithms,
+    Key,
+    Transcript,
+    Option<Bytes>,
+    Option<ServerPostHandshakeAuth>,
+);
+
+/// The server name the client asked for, if any.
+pub fn server_name_post_server_finished(st: &ServerPostServerFinished) -> Option<&Bytes> {
+    st.6.as_ref()
+}
+/// The server name the client asked for, if any.
+pub fn server_name_post_client_finished(st: &ServerPostClientFinished) -> Option<&Bytes> {
+    st.5.as_ref()
+}
+
+/// The certificate entries the client authenticated with after the handshake,
+/// starting with the end-entity certificate.
+///
+/// This is empty if the client didn't authenticate (yet).
+pub(crate) fn client_certificates_post_client_finished(
+    st: &ServerPostClientFinished,
+) -> &[CertificateEntry] {
+    match &st.6 {
+        Some(auth) => &auth.client_certificates,
+        None => &[],
+    }
+}
+
+/// Whether the server waits for the client to answer a CertificateRequest.
+pub(crate) fn client_auth_pending_post_client_finished(st: &ServerPostClientFinished) -> bool {
+    st.6.as_ref().is_some_and(|auth| auth.pending.is_some())
+}
 
 /* Handshake Core Functions: See RFC 8446 Section 4 */
 /* We delegate all details of message formatting and transcript Digestes to the caller */
 
 /* TLS 1.3 Client Side Handshake Functions */
 
+#[allow(clippy::too_many_arguments)]
 fn build_client_hello(
     ciphersuite: Algorithms,
     sn: &Bytes,
-    tkt: Option<Bytes>,
-    psk: Option<Psk>,
+    psks: Vec<PreSharedKey>,
+    server_key: Option<Bytes>,
+    ech: Option<EchOffer>,
+    grease: bool,
+    credentials: Option<ClientCredentials>,
+    certificate_authorities: Vec<Bytes>,
+    quic: Option<QuicParameters>,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<
     (
         HandshakeData,
         Option<ClientCipherState0>,
+        Option<Key>,
         ClientPostClientHello,
     ),
     TLSError,
@@ -273,25 +585,261 @@ fn build_client_hello(
     let tx = Transcript::new(ciphersuite.hash());
     let mut client_random = [0u8; 32];
     rng.fill_bytes(&mut client_random);
-    let (kem_sk, kem_pk) = kem_keygen(ciphersuite.kem(), rng)?;
-    let (client_hello, trunc_len) =
-        client_hello(&ciphersuite, client_random.into(), &kem_pk, sn, &tkt)?;
-    let (nch, cipher0, tx_ch) =
-        compute_psk_binder_zero_rtt(ciphersuite, client_hello, trunc_len, &psk, tx)?;
+    // A PSK can only be used with the hash algorithm it's bound to.
+    check(psks.iter().all(|psk| psk.hash == ciphersuite.hash()))
+        .map_err(|_| UNSUPPORTED_ALGORITHM)?;
+    // ECH is only supported with certificates.
+    check(ech.is_none() || !ciphersuite.psk_mode()).map_err(|_| PSK_MODE_MISMATCH)?;
+    let (kem_sk, kem_pk) = if ciphersuite.key_exchange() {
+        crypto.kem_keygen(ciphersuite.kem(), rng)?
+    } else {
+        (Bytes::new(), Bytes::new())
+    };
+    let grease = if grease {
+        Some(Grease::generate(rng))
+    } else {
+        None
+    };
+    let psk_identities: Vec<Bytes> = psks.iter().map(|psk| psk.identity.clone()).collect();
+    let post_handshake_auth = credentials.is_some();
+    let (client_hello, binders_len) = client_hello(
+        &ciphersuite,
+        client_random.into(),
+        &kem_pk,
+        sn,
+        &psk_identities,
+        server_key.is_some(),
+        post_handshake_auth,
+        &certificate_authorities,
+        &quic,
+        &Bytes::new(),
+        &None,
+        grease,
+    )?;
+    // A ClientHello with ECH is not sent again, except with GREASE ECH.
+    let (client_random, client_hello, ech, retry_ech_extension) = match ech {
+        None => (
+            client_random.into(),
+            client_hello,
+            ClientEch::NotOffered,
+            Some(Bytes::new()),
+        ),
+        Some(EchOffer::Grease) => {
+            let extension = grease_ech_extension(client_hello.len(), rng)?;
+            let (client_hello, _) = client_hello_with_ech(
+                &ciphersuite,
+                client_random.into(),
+                &kem_pk,
+                sn,
+                server_key.is_some(),
+                post_handshake_auth,
+                &certificate_authorities,
+                &quic,
+                &extension,
+                grease,
+            )?;
+            (
+                client_random.into(),
+                client_hello,
+                ClientEch::Grease,
+                Some(extension),
+            )
+        }
+        Some(EchOffer::Config(config)) => {
+            let (client_random, client_hello, ech) = build_client_hello_outer(
+                &ciphersuite,
+                client_random.into(),
+                &kem_pk,
+                sn,
+                server_key.is_some(),
+                post_handshake_auth,
+                &certificate_authorities,
+                &quic,
+                &config,
+                grease,
+                crypto,
+                rng,
+            )?;
+            (client_random, client_hello, ech, None)
+        }
+    };
+    let retry = retry_ech_extension.map(|ech_extension| ClientHelloRetry {
+        server_name: sn.clone(),
+        kem_pk,
+        ech_extension,
+        grease,
+        certificate_authorities,
+        quic,
+    });
+    let (nch, cipher0, early_secret, tx_ch) =
+        compute_psk_binder_zero_rtt(ciphersuite, client_hello, binders_len, &psks, tx, crypto)?;
     Ok((
         nch,
         cipher0,
-        ClientPostClientHello(client_random.into(), ciphersuite, kem_sk, psk, tx_ch),
+        early_secret,
+        ClientPostClientHello(
+            client_random,
+            ciphersuite,
+            kem_sk,
+            psks,
+            tx_ch,
+            server_key,
+            ech,
+            retry,
+            credentials,
+        ),
     ))
 }
 
+/// Build a ClientHello without PSKs, with the `encrypted_client_hello`
+/// extension `ech_extension`.
+#[allow(clippy::too_many_arguments)]
+fn client_hello_with_ech(
+    ciphersuite: &Algorithms,
+    client_random: Random,
+    kem_pk: &Bytes,
+    sn: &Bytes,
+    raw_public_key: bool,
+    post_handshake_auth: bool,
+    certificate_authorities: &[Bytes],
+    quic: &Option<QuicParameters>,
+    ech_extension: &Bytes,
+    grease: Option<Grease>,
+) -> Result<(HandshakeData, usize), TLSError> {
+    client_hello(
+        ciphersuite,
+        client_random,
+        kem_pk,
+        sn,
+        &[],
+        raw_public_key,
+        post_handshake_auth,
+        certificate_authorities,
+        quic,
+        ech_extension,
+        &None,
+        grease,
+    )
+}
+
+/// Build a ClientHelloInner for the server name `sn` with the
+/// `client_random`, and encrypt it into a ClientHelloOuter for the public
+/// name of the ECH `config`.
+///
+/// Both share the key share `kem_pk`. The `certificate_authorities` are only
+/// sent in the ClientHelloInner, because they may identify the client.
+///
+/// Returns the random of the ClientHelloOuter, the ClientHelloOuter and the
+/// ECH state.
+#[allow(clippy::too_many_arguments)]
+fn build_client_hello_outer(
+    ciphersuite: &Algorithms,
+    client_random: Random,
+    kem_pk: &Bytes,
+    sn: &Bytes,
+    raw_public_key: bool,
+    post_handshake_auth: bool,
+    certificate_authorities: &[Bytes],
+    quic: &Option<QuicParameters>,
+    config: &EchConfig,
+    grease: Option<Grease>,
+    crypto: &impl CryptoProvider,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(Random, HandshakeData, ClientEch), TLSError> {
+    let (inner, _) = client_hello_with_ech(
+        ciphersuite,
+        client_random.clone(),
+        kem_pk,
+        sn,
+        raw_public_key,
+        post_handshake_auth,
+        certificate_authorities,
+        quic,
+        &ech_inner_extension()?,
+        grease,
+    )?;
+    let HandshakeData(inner_body) = inner.as_handshake_message(HandshakeType::ClientHello)?;
+    let (extension, encoded_inner, key, nonce) =
+        ech_outer_placeholder(config, &inner_body, sn, crypto, rng)?;
+    let mut outer_random = [0u8; 32];
+    rng.fill_bytes(&mut outer_random);
+    let (outer, _) = client_hello_with_ech(
+        ciphersuite,
+        outer_random.into(),
+        kem_pk,
+        config.public_name(),
+        raw_public_key,
+        post_handshake_auth,
+        &[],
+        quic,
+        &extension,
+        grease,
+    )?;
+    let HandshakeData(outer_body) = outer.as_handshake_message(HandshakeType::ClientHello)?;
+    let outer_body = seal_client_hello_inner(&outer_body, &encoded_inner, &key, &nonce, crypto)?;
+    let outer = HandshakeData::from_bytes(HandshakeType::ClientHello, &outer_body)?;
+    Ok((
+        outer_random.into(),
+        outer,
+        ClientEch::Offered {
+            inner_random: client_random,
+            inner_client_hello: inner,
+            public_name: config.public_name().clone(),
+        },
+    ))
+}
+
+/// The offset of the ECH acceptance confirmation in the ServerHello: the
+/// last 8 bytes of the server random, after the handshake header and the
+/// legacy version.
+const ECH_ACCEPT_CONFIRMATION_OFFSET: usize = 4 + 2 + 24;
+
+/// Compute the ECH acceptance confirmation of the `server_hello` for the
+/// ClientHelloInner `client_hello` with the `client_random`.
+///
+/// The confirmation is computed with the last 8 bytes of the server random
+/// set to zero, and replaces them.
+fn ech_accept_confirmation(
+    ha: &HashAlgorithm,
+    client_hello: &HandshakeData,
+    client_random: &Random,
+    server_hello: &HandshakeData,
+    crypto: &impl CryptoProvider,
+) -> Result<Bytes, TLSError> {
+    let HandshakeData(sh) = server_hello;
+    check(sh.len() >= ECH_ACCEPT_CONFIRMATION_OFFSET + 8)?;
+    let sh = sh.update_slice(ECH_ACCEPT_CONFIRMATION_OFFSET, &Bytes::zeroes(8), 0, 8);
+    let transcript_hash = Transcript::new(*ha)
+        .add(client_hello)
+        .add(&HandshakeData::from(sh))
+        .transcript_hash(crypto)?;
+    let secret = crypto.hkdf_extract(ha, client_random, &zero_key(ha))?;
+    hkdf_expand_label(
+        ha,
+        &secret,
+        bytes(&LABEL_ECH_ACCEPT_CONFIRMATION),
+        &transcript_hash,
+        8,
+        crypto,
+    )
+}
+
 fn compute_psk_binder_zero_rtt(
     algs0: Algorithms,
     ch: HandshakeData,
-    trunc_len: usize,
-    psk: &Option<Psk>,
+    binders_len: usize,
+    psks: &[PreSharedKey],
     tx: Transcript,
-) -> Result<(HandshakeData, Option<ClientCipherState0>, Transcript), TLSError> {
+    crypto: &impl CryptoProvider,
+) -> Result<
+    (
+        HandshakeData,
+        Option<ClientCipherState0>,
+        Option<Key>,
+        Transcript,
+    ),
+    TLSError,
+> {
     let Algorithms {
         hash: ha,
         aead: ae,
@@ -299,60 +847,208 @@ fn compute_psk_binder_zero_rtt(
         kem: _ks,
         psk_mode,
         zero_rtt,
+        psk_key_exchange_mode: _psk_key_exchange_mode,
     } = algs0;
-    match (psk_mode, psk, trunc_len as u8) {
-        (true, Some(k), _) => {
-            let th_trunc = tx.transcript_hash_without_client_hello(&ch, trunc_len)?;
-            let mk = derive_binder_key(&ha, k)?;
-            let binder = hmac_tag(&ha, &mk, &th_trunc)?;
-            let nch = set_client_hello_binder(&algs0, &Some(binder), ch, Some(trunc_len))?;
+    match (psk_mode, psks.first(), binders_len as u8) {
+        (true, Some(first), _) => {
+            // All binders are computed over the ClientHello without binders.
+            let trunc_len = ch.len() - binders_len;
+            let th_trunc = tx.transcript_hash_without_client_hello(&ch, trunc_len, crypto)?;
+            let mut binders = Vec::new();
+            for psk in psks {
+                let mk = derive_binder_key(&ha, &psk.key, psk.external, crypto)?;
+                binders.push(crypto.hmac_tag(&ha, &mk, &th_trunc)?);
+            }
+            let nch = set_client_hello_binders(&binders, ch, trunc_len)?;
             let tx_ch = tx.add(&nch);
             if zero_rtt {
-                let th = tx_ch.transcript_hash()?;
-                let (aek, key) = derive_0rtt_keys(&ha, &ae, k, &th)?;
+                // Early data is sent with the first PSK.
+                let th = tx_ch.transcript_hash(crypto)?;
+                let (aek, key, early_secret) = derive_0rtt_keys(&ha, &ae, &first.key, &th, crypto)?;
                 let cipher0 = Some(client_cipher_state0(ae, aek, 0, key));
-                Ok((nch, cipher0, tx_ch))
+                Ok((nch, cipher0, Some(early_secret), tx_ch))
             } else {
-                Ok((nch, None, tx_ch))
+                Ok((nch, None, None, tx_ch))
             }
         }
         (false, None, 0) => {
             let tx_ch = tx.add(&ch);
-            Ok((ch, None, tx_ch))
+            Ok((ch, None, None, tx_ch))
         }
         _ => Err(PSK_MODE_MISMATCH),
     }
 }
 
+/// Process the HelloRetryRequest `hrr` and build the second ClientHello.
+///
+/// The second ClientHello is the first one with the cookie of the server and
+/// new PSK binders. It has no early data.
+fn put_hello_retry_request(
+    hrr: &HandshakeData,
+    state: ClientPostClientHello,
+    crypto: &impl CryptoProvider,
+) -> Result<(HandshakeData, ClientPostClientHello), TLSError> {
+    let ClientPostClientHello(
+        client_random,
+        ciphersuite,
+        sk,
+        psks,
+        tx,
+        server_key,
+        ech,
+        retry,
+        credentials,
+    ) = state;
+    check(!ciphersuite.zero_rtt).map_err(|_| ZERO_RTT_DISABLED)?;
+    check(!matches!(ech, ClientEch::Offered { .. })).map_err(|_| UNSUPPORTED)?;
+    // There's only one HelloRetryRequest.
+    let retry = retry.ok_or(UNEXPECTED_MESSAGE)?;
+    let cookie = parse_hello_retry_request(&ciphersuite, hrr)?;
+    let psk_identities: Vec<Bytes> = psks.iter().map(|psk| psk.identity.clone()).collect();
+    let (client_hello, binders_len) = client_hello(
+        &ciphersuite,
+        client_random.clone(),
+        &retry.kem_pk,
+        &retry.server_name,
+        &psk_identities,
+        server_key.is_some(),
+        credentials.is_some(),
+        &retry.certificate_authorities,
+        &retry.quic,
+        &retry.ech_extension,
+        &Some(cookie),
+        retry.grease,
+    )?;
+    let tx = Transcript::from_client_hello_hash(ciphersuite.hash, &tx.transcript_hash(crypto)?)?
+        .add(hrr);
+    let (client_hello, _cipher0, _early_secret, tx) =
+        compute_psk_binder_zero_rtt(ciphersuite, client_hello, binders_len, &psks, tx, crypto)?;
+    Ok((
+        client_hello,
+        ClientPostClientHello(
+            client_random,
+            ciphersuite,
+            sk,
+            psks,
+            tx,
+            server_key,
+            ech,
+            None,
+            credentials,
+        ),
+    ))
+}
+
 fn put_server_hello(
     handshake: &HandshakeData,
     state: ClientPostClientHello,
-) -> Result<(DuplexCipherStateH, ClientPostServerHello), TLSError> {
-    let ClientPostClientHello(client_random, ciphersuite, sk, psk, tx) = state;
-
-    let (sr, ct) = parse_server_hello(&ciphersuite, handshake)?;
-    let tx = tx.add(handshake);
-    let shared_secret = kem_decap(ciphersuite.kem, &ct, &sk)?;
-    let th = tx.transcript_hash()?;
-    let (chk, shk, cfk, sfk, ms) = derive_hk_ms(
+    crypto: &impl CryptoProvider,
+) -> Result<(DuplexCipherStateH, TrafficSecrets, ClientPostServerHello), TLSError> {
+    let ClientPostClientHello(
+        client_random,
+        ciphersuite,
+        sk,
+        psks,
+        tx,
+        server_key,
+        ech,
+        _retry,
+        credentials,
+    ) = state;
+
+    let (sr, ct, selected_identity) = parse_server_hello(&ciphersuite, handshake)?;
+    // Continue with the PSK the server selected. With early data, that must
+    // be the first one.
+    let psk = match selected_identity {
+        Some(index) => {
+            check(!ciphersuite.zero_rtt || index == 0).map_err(|_| PSK_MODE_MISMATCH)?;
+            Some(psks.into_iter().nth(index).ok_or(PSK_MODE_MISMATCH)?)
+        }
+        None => None,
+    };
+    // When the server accepted ECH, the handshake continues with the
+    // ClientHelloInner. Otherwise the server authenticates for the public name.
+    let (client_random, tx, ech_status, ech_public_name) = match ech {
+        ClientEch::NotOffered => (
+            client_random,
+            tx.add(handshake),
+            EchStatus::NotOffered,
+            None,
+        ),
+        ClientEch::Grease => (client_random, tx.add(handshake), EchStatus::Grease, None),
+        ClientEch::Offered {
+            inner_random,
+            inner_client_hello,
+            public_name,
+        } => {
+            let confirmation = ech_accept_confirmation(
+                &ciphersuite.hash,
+                &inner_client_hello,
+                &inner_random,
+                handshake,
+                crypto,
+            )?;
+            if confirmation == sr.slice_range(24..32) {
+                let tx = Transcript::new(ciphersuite.hash)
+                    .add(&inner_client_hello)
+                    .add(handshake);
+                (inner_random, tx, EchStatus::Accepted, None)
+            } else {
+                let retry_configs = None;
+                (
+                    client_random,
+                    tx.add(handshake),
+                    EchStatus::Rejected { retry_configs },
+                    Some(public_name),
+                )
+            }
+        }
+    };
+    let shared_secret = if ciphersuite.key_exchange() {
+        crypto.kem_decap(ciphersuite.kem, &ct, &sk)?
+    } else {
+        zero_key(&ciphersuite.hash)
+    };
+    let th = tx.transcript_hash(crypto)?;
+    let (chk, shk, cfk, sfk, ms, secrets) = derive_hk_ms(
         &ciphersuite.hash,
         &ciphersuite.aead,
         &shared_secret,
         &psk,
         &th,
+        crypto,
     )?;
 
     Ok((
         DuplexCipherStateH::new(chk, 0, shk, 0),
-        ClientPostServerHello(client_random, sr, ciphersuite, ms, cfk, sfk, tx),
+        secrets,
+        ClientPostServerHello(
+            client_random,
+            sr,
+            ciphersuite,
+            ms,
+            cfk,
+            sfk,
+            tx,
+            server_key,
+            ech_status,
+            ech_public_name,
+            credentials,
+        ),
     ))
 }
 
+/// Check the server's certificate and its CertificateVerify.
+///
+/// A delegated credential must be valid at the time `now`, in seconds since
+/// the Unix epoch, or this fails with a [`BAD_DELEGATED_CREDENTIAL`] error.
 fn put_server_signature(
     encrypted_extensions: &HandshakeData,
     server_certificate: &HandshakeData,
     server_certificate_verify: &HandshakeData,
     handshake_state: ClientPostServerHello,
+    now: u64,
+    crypto: &impl CryptoProvider,
 ) -> Result<ClientPostCertificateVerify, TLSError> {
     let ClientPostServerHello(
         client_random,
@@ -362,19 +1058,65 @@ fn put_server_signature(
         client_finished_key,
         server_finished_key,
         transcript,
+        server_key,
+        ech_status,
+        ech_public_name,
+        credentials,
     ) = handshake_state;
     if !algorithms.psk_mode() {
-        parse_encrypted_extensions(&algorithms, encrypted_extensions)?;
+        // The server must use a raw public key if and only if the client asked
+        // for one.
+        let (server_certificate_type, retry_configs) =
+            parse_encrypted_extensions(&algorithms, encrypted_extensions)?;
+        // Retry configurations are only meaningful when ECH was rejected.
+        let ech_status = match ech_status {
+            EchStatus::Rejected { .. } => EchStatus::Rejected { retry_configs },
+            ech_status => ech_status,
+        };
+        let expected_type = server_key.as_ref().map(|_| CERTIFICATE_TYPE_RAW_PUBLIC_KEY);
+        check(server_certificate_type == expected_type).map_err(|_| UNSUPPORTED_ALGORITHM)?;
         let transcript = transcript.add(encrypted_extensions);
-        let certificate = parse_server_certificate(server_certificate)?;
+        let certificates = parse_server_certificate(&decompress_certificate(server_certificate)?)?;
+        let certificate = certificates[0].cert_data.clone();
+        // Reject malformed staples right away.
+        certificates[0].ocsp_response()?;
+        if let Some(sct_list) = certificates[0].sct_list()? {
+            parse_sct_list(&sct_list)?;
+        }
         let transcript = transcript.add(server_certificate);
-        let transcript_hash_server_certificate = transcript.transcript_hash()?;
-        let spki = verification_key_from_cert(&certificate)?;
+        let transcript_hash_server_certificate = transcript.transcript_hash(crypto)?;
+        let spki = match &server_key {
+            Some(server_key) => {
+                // A raw public key must be the one the client expects.
+                check(certificates.len() == 1 && certificate == *server_key)
+                    .map_err(|_| BAD_CERTIFICATE)?;
+                verification_key_from_spki(&certificate)?
+            }
+            None => verification_key_from_cert(&certificate)?,
+        };
+        // When ECH was rejected, the certificate must be for the public name
+        // of the ECH config, or the retry configs can't be trusted.
+        if let Some(public_name) = &ech_public_name {
+            check(certificate_matches_name(&certificate, public_name))
+                .map_err(|_| BAD_CERTIFICATE)?;
+        }
         let cert_pk = cert_public_key(&certificate, &spki)?;
-        let cert_signature = parse_certificate_verify(&algorithms, server_certificate_verify)?;
+        let (cert_scheme, cert_signature) =
+            parse_certificate_verify(&algorithms, server_certificate_verify)?;
+        // With a delegated credential, the server signs with the delegated key.
+        let cert_pk = match certificates[0].delegated_credential()? {
+            Some(dc) => {
+                let dc = parse_delegated_credential(&dc)?;
+                check(dc.dc_cert_verify_algorithm == cert_scheme).map_err(|_| ILLEGAL_PARAMETER)?;
+                let dc_key = verify_delegated_credential(&dc, &certificate, crypto)?;
+                check_delegated_credential_validity(&dc, &certificate, now)?;
+                dc_key
+            }
+            None => cert_pk,
+        };
         let sigval = (Bytes::from_slice(&PREFIX_SERVER_SIGNATURE))
             .concat(transcript_hash_server_certificate);
-        verify(&algorithms.signature(), &cert_pk, &sigval, &cert_signature)?;
+        crypto.verify(&cert_scheme, &cert_pk, &sigval, &cert_signature)?;
         let transcript = transcript.add(server_certificate_verify);
         Ok(ClientPostCertificateVerify(
             client_random,
@@ -384,6 +1126,9 @@ fn put_server_signature(
             client_finished_key,
             server_finished_key,
             transcript,
+            certificates,
+            ech_status,
+            credentials,
         ))
     } else {
         Err(PSK_MODE_MISMATCH)
@@ -402,9 +1147,14 @@ fn put_psk_skip_server_signature(
         client_finished_key,
         server_finished_key,
         transcript,
+        _server_key,
+        ech_status,
+        _ech_public_name,
+        credentials,
     ) = handshake_state;
     if algorithms.psk_mode() {
-        parse_encrypted_extensions(&algorithms, encrypted_extensions)?;
+        // There's no certificate of any type, and no ECH, in PSK mode.
+        check(parse_encrypted_extensions(&algorithms, encrypted_extensions)? == (None, None))?;
         let transcript = transcript.add(encrypted_extensions);
         Ok(ClientPostCertificateVerify(
             client_random,
@@ -414,6 +1164,9 @@ fn put_psk_skip_server_signature(
             client_finished_key,
             server_finished_key,
             transcript,
+            Vec::new(),
+            ech_status,
+            credentials,
         ))
     } else {
         Err(PSK_MODE_MISMATCH)
@@ -423,7 +1176,8 @@ fn put_psk_skip_server_signature(
 fn put_server_finished(
     server_finished: &HandshakeData,
     handshake_state: ClientPostCertificateVerify,
-) -> Result<(DuplexCipherState1, ClientPostServerFinished), TLSError> {
+    crypto: &impl CryptoProvider,
+) -> Result<(DuplexCipherState1, TrafficSecrets, ClientPostServerFinished), TLSError> {
     let ClientPostCertificateVerify(
         client_random,
         server_random,
@@ -432,6 +1186,9 @@ fn put_server_finished(
         client_finished_key,
         server_finished_key,
         transcript,
+        certificates,
+        ech_status,
+        credentials,
     ) = handshake_state;
     let Algorithms {
         hash,
@@ -440,21 +1197,24 @@ fn put_server_finished(
         kem,
         psk_mode,
         zero_rtt,
+        psk_key_exchange_mode,
     } = algorithms;
-    let transcript_hash = transcript.transcript_hash()?;
+    let transcript_hash = transcript.transcript_hash(crypto)?;
     let verify_data = parse_finished(server_finished)?;
-    hmac_verify(&hash, &server_finished_key, &transcript_hash, &verify_data)?;
+    crypto.hmac_verify(&hash, &server_finished_key, &transcript_hash, &verify_data)?;
     let transcript = transcript.add(server_finished);
-    let transcript_hash_server_finished = transcript.transcript_hash()?;
-    let (cak, sak, exp) = derive_app_keys(
+    let transcript_hash_server_finished = transcript.transcript_hash(crypto)?;
+    let (cak, sak, exp, secrets) = derive_app_keys(
         &hash,
         &aead,
         &master_secret,
         &transcript_hash_server_finished,
+        crypto,
     )?;
     let cipher1 = duplex_cipher_state1(aead, cak, 0, sak, 0, exp);
     Ok((
         cipher1,
+        secrets,
         ClientPostServerFinished(
             client_random,
             server_random,
@@ -462,12 +1222,16 @@ fn put_server_finished(
             master_secret,
             client_finished_key,
             transcript,
+            certificates,
+            ech_status,
+            credentials,
         ),
     ))
 }
 
 fn get_client_finished(
     handshake_state: ClientPostServerFinished,
+    crypto: &impl CryptoProvider,
 ) -> Result<(HandshakeData, ClientPostClientFinished), TLSError> {
     let ClientPostServerFinished(
         client_random,
@@ -476,14 +1240,31 @@ fn get_client_finished(
         master_secret,
         client_finished_key,
         transcript,
+        certificates,
+        ech_status,
+        credentials,
     ) = handshake_state;
-    let transcript_hash = transcript.transcript_hash()?;
-    let verify_data = hmac_tag(&algorithms.hash(), &client_finished_key, &transcript_hash)?;
+    let transcript_hash = transcript.transcript_hash(crypto)?;
+    let verify_data =
+        crypto.hmac_tag(&algorithms.hash(), &client_finished_key, &transcript_hash)?;
     let client_finished = finished(&verify_data)?;
+    let post_handshake_auth = match credentials {
+        Some(credentials) => Some(ClientPostHandshakeAuth {
+            credentials,
+            finished_key: derive_post_handshake_finished_key(
+                &algorithms.hash(),
+                &master_secret,
+                &transcript_hash,
+                crypto,
+            )?,
+            requests: Vec::new(),
+        }),
+        None => None,
+    };
     let transcript = transcript.add(&client_finished);
-    let transcript_hash = transcript.transcript_hash()?;
+    let transcript_hash = transcript.transcript_hash(crypto)?;
     let resumption_master_secret =
-        derive_rms(&algorithms.hash(), &master_secret, &transcript_hash)?;
+        derive_rms(&algorithms.hash(), &master_secret, &transcript_hash, crypto)?;
     Ok((
         client_finished,
         ClientPostClientFinished(
@@ -492,44 +1273,144 @@ fn get_client_finished(
             algorithms,
             resumption_master_secret,
             transcript,
+            certificates,
+            ech_status,
+            post_handshake_auth,
         ),
     ))
 }
 
+/// Build the Certificate, CertificateVerify and Finished messages that answer
+/// the `certificate_request` of the server after the handshake.
+///
+/// The client signs with its `credentials` if it can sign with one of the
+/// requested signature schemes, and its chain ends at one of the requested
+/// certificate authorities, if there are any. Otherwise it sends an empty
+/// Certificate message, without a CertificateVerify message.
+fn get_certificate_response(
+    algorithms: &Algorithms,
+    transcript: &Transcript,
+    certificate_request: &HandshakeData,
+    credentials: &ClientCredentials,
+    finished_key: &MacKey,
+    crypto: &impl CryptoProvider,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<HandshakeData, TLSError> {
+    let (context, signature_schemes, authorities) = parse_certificate_request(certificate_request)?;
+    let transcript = transcript.clone().add(certificate_request);
+    let scheme = credentials.key.scheme()?;
+    let authenticate = !credentials.cert_chain.is_empty()
+        && check_mem(scheme.code_point()?.as_raw(), signature_schemes.as_raw()).is_ok()
+        && (authorities.is_empty()
+            || chain_matches_authorities(&credentials.cert_chain, &authorities));
+    let (messages, transcript) = if authenticate {
+        let certificate = client_certificate(
+            &context,
+            &certificate_entries(&credentials.cert_chain, &None, &None, &None)?,
+        )?;
+        let transcript = transcript.add(&certificate);
+        let sigval =
+            Bytes::from_slice(&PREFIX_CLIENT_SIGNATURE).concat(transcript.transcript_hash(crypto)?);
+        let signature = credentials.key.sign(&sigval, crypto, rng)?;
+        let certificate_verify = certificate_verify(
+            &Algorithms {
+                signature: scheme,
+                ..*algorithms
+            },
+            &signature,
+        )?;
+        let transcript = transcript.add(&certificate_verify);
+        (certificate.concat(&certificate_verify), transcript)
+    } else {
+        let certificate = client_certificate(&context, &[])?;
+        let transcript = transcript.add(&certificate);
+        (certificate, transcript)
+    };
+    let verify_data = crypto.hmac_tag(
+        &algorithms.hash(),
+        finished_key,
+        &transcript.transcript_hash(crypto)?,
+    )?;
+    Ok(messages.concat(&finished(&verify_data)?))
+}
+
 // Client-Side Handshake API: Usable by Quic and TLS
 // client_init -> (encrypt_zerortt)* ->
 // client_set_params -> (encrypt_handshake | decrypt_handshake)* ->
-// client_finish -> (encrypt_data | decrypt_data)*
+// client_finish -> (encrypt_data | decrypt_data)* ->
+// (client_certificate_request -> client_certificate_response)*
+// Next to each cipher state, the traffic secrets it is derived from are
+// returned, for QUIC. The record layer doesn't keep them.
 
+#[allow(clippy::too_many_arguments)]
 pub fn client_init(
     algs: Algorithms,
     sn: &Bytes,
-    tkt: Option<Bytes>,
-    psk: Option<Psk>,
+    psks: Vec<PreSharedKey>,
+    server_key: Option<Bytes>,
+    ech: Option<EchOffer>,
+    grease: bool,
+    credentials: Option<ClientCredentials>,
+    certificate_authorities: Vec<Bytes>,
+    quic: Option<QuicParameters>,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<
     (
         HandshakeData,
         Option<ClientCipherState0>,
+        Option<Key>,
         ClientPostClientHello,
     ),
     TLSError,
 > {
-    build_client_hello(algs, sn, tkt, psk, rng)
+    build_client_hello(
+        algs,
+        sn,
+        psks,
+        server_key,
+        ech,
+        grease,
+        credentials,
+        certificate_authorities,
+        quic,
+        crypto,
+        rng,
+    )
+}
+
+/// Build the second client hello message after a HelloRetryRequest.
+pub(crate) fn client_retry(
+    payload: &HandshakeData,
+    st: ClientPostClientHello,
+    crypto: &impl CryptoProvider,
+) -> Result<(HandshakeData, ClientPostClientHello), TLSError> {
+    put_hello_retry_request(payload, st, crypto)
 }
 
 /// Update the client state after generating the client hello message.
 pub(crate) fn client_set_params(
     payload: &HandshakeData,
     st: ClientPostClientHello,
-) -> Result<(DuplexCipherStateH, ClientPostServerHello), TLSError> {
-    put_server_hello(payload, st)
+    crypto: &impl CryptoProvider,
+) -> Result<(DuplexCipherStateH, TrafficSecrets, ClientPostServerHello), TLSError> {
+    put_server_hello(payload, st, crypto)
 }
 
 pub fn client_finish(
     payload: &HandshakeData,
     handshake_state: ClientPostServerHello,
-) -> Result<(HandshakeData, DuplexCipherState1, ClientPostClientFinished), TLSError> {
+    now: u64,
+    crypto: &impl CryptoProvider,
+) -> Result<
+    (
+        HandshakeData,
+        DuplexCipherState1,
+        TrafficSecrets,
+        ClientPostClientFinished,
+    ),
+    TLSError,
+> {
     match algs_post_server_hello(&handshake_state).psk_mode() {
         false => {
             let (
@@ -543,43 +1424,181 @@ pub fn client_finish(
                 &server_certificate,
                 &server_certificate_verify,
                 handshake_state,
+                now,
+                crypto,
             )?;
-            let (cipher, client_state_server_finished) =
-                put_server_finished(&server_finished, client_state_certificate_verify)?;
+            let (cipher, secrets, client_state_server_finished) =
+                put_server_finished(&server_finished, client_state_certificate_verify, crypto)?;
             let (client_finished, client_state) =
-                get_client_finished(client_state_server_finished)?;
-            Ok((client_finished, cipher, client_state))
+                get_client_finished(client_state_server_finished, crypto)?;
+            Ok((client_finished, cipher, secrets, client_state))
         }
         true => {
             let (encrypted_extensions, server_finished) = payload.to_two()?;
             let client_state_certificate_verify =
                 put_psk_skip_server_signature(&encrypted_extensions, handshake_state)?;
-            let (cipher, client_state_server_finished) =
-                put_server_finished(&server_finished, client_state_certificate_verify)?;
+            let (cipher, secrets, client_state_server_finished) =
+                put_server_finished(&server_finished, client_state_certificate_verify, crypto)?;
             let (client_finished, client_state) =
-                get_client_finished(client_state_server_finished)?;
-            Ok((client_finished, cipher, client_state))
+                get_client_finished(client_state_server_finished, crypto)?;
+            Ok((client_finished, cipher, secrets, client_state))
         }
     }
 }
 
+/// Process the CertificateRequest `certificate_request` the server sent
+/// after the handshake.
+///
+/// The client must have offered post-handshake authentication. The request is
+/// answered with [`client_certificate_response`].
+pub(crate) fn client_certificate_request(
+    certificate_request: &HandshakeData,
+    st: ClientPostClientFinished,
+) -> Result<ClientPostClientFinished, TLSError> {
+    let ClientPostClientFinished(cr, sr, algs, rms, tx, certificates, ech_status, auth) = st;
+    let mut auth = auth.ok_or(UNEXPECTED_MESSAGE)?;
+    parse_certificate_request(certificate_request)?;
+    auth.requests.push(certificate_request.clone());
+    Ok(ClientPostClientFinished(
+        cr,
+        sr,
+        algs,
+        rms,
+        tx,
+        certificates,
+        ech_status,
+        Some(auth),
+    ))
+}
+
+/// Answer the outstanding CertificateRequests of the server.
+///
+/// Returns the Certificate, CertificateVerify and Finished messages for all of
+/// them, or [`None`] if there are none.
+pub(crate) fn client_certificate_response(
+    st: ClientPostClientFinished,
+    crypto: &impl CryptoProvider,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(Option<HandshakeData>, ClientPostClientFinished), TLSError> {
+    let ClientPostClientFinished(cr, sr, algs, rms, tx, certificates, ech_status, auth) = st;
+    let (messages, auth) = match auth {
+        Some(mut auth) if !auth.requests.is_empty() => {
+            let mut messages = HandshakeData::from(Bytes::new());
+            for request in auth.requests.drain(..) {
+                let response = get_certificate_response(
+                    &algs,
+                    &tx,
+                    &request,
+                    &auth.credentials,
+                    &auth.finished_key,
+                    crypto,
+                    rng,
+                )?;
+                messages = messages.concat(&response);
+            }
+            (Some(messages), Some(auth))
+        }
+        auth => (None, auth),
+    };
+    Ok((
+        messages,
+        ClientPostClientFinished(cr, sr, algs, rms, tx, certificates, ech_status, auth),
+    ))
+}
+
 /* TLS 1.3 Server Side Handshake Functions */
 
 fn put_client_hello(
     ciphersuite: Algorithms,
     ch: &HandshakeData,
     db: ServerDB,
-) -> Result<(Option<ServerCipherState0>, ServerPostClientHello), TLSError> {
-    let (client_randomness, session_id, sni, gx, tkto, bindero, trunc_len) =
-        parse_client_hello(&ciphersuite, ch)?;
-    let tx = Transcript::new(ciphersuite.hash());
-    let th_trunc = tx.transcript_hash_without_client_hello(ch, trunc_len)?;
+    client_address: &Option<Bytes>,
+    alpn_protocol: Option<Bytes>,
+    quic: Option<QuicParameters>,
+    crypto: &impl CryptoProvider,
+) -> Result<
+    (
+        Option<ServerCipherState0>,
+        Option<Key>,
+        ServerPostClientHello,
+    ),
+    TLSError,
+> {
+    let outer_info = parse_client_hello_info(ch)?;
+    // With ECH, the handshake continues with the ClientHelloInner.
+    let (ech_client_hello, ech_retry_configs) =
+        open_client_hello(&ciphersuite, &db, ch, &outer_info, crypto)?;
+    let tx = hello_retry_transcript(&ciphersuite, &db, client_address, &outer_info, crypto)?;
+    let ch = ech_client_hello.as_ref().unwrap_or(ch);
+    let ParsedClientHello {
+        client_randomness,
+        session_id,
+        server_name: sni,
+        gx,
+        psk_identities: identities,
+        psk_binders: binders,
+        signature_algorithms: sigalgs,
+        trunc_len,
+        info,
+    } = parse_client_hello(&ciphersuite, ch)?;
+    // The ClientHelloInner must be marked as such.
+    if ech_client_hello.is_some() {
+        check(
+            info.encrypted_client_hello
+                .as_ref()
+                .is_some_and(is_ech_inner),
+        )
+        .map_err(|_| DECODE_ERROR)?;
+    }
+    let mut identities = identities.unwrap_or_default();
+    let binders = binders.unwrap_or_default();
+    // Early data can only be accepted with the first PSK.
+    if ciphersuite.zero_rtt {
+        identities.truncate(1);
+    }
+    let th_trunc = tx.transcript_hash_without_client_hello(ch, trunc_len, crypto)?;
     let transcript = tx.add(ch);
-    let th = transcript.transcript_hash()?;
-    let server = lookup_db(ciphersuite, &db, &sni, &tkto)?;
-    let cipher0 = process_psk_binder_zero_rtt(ciphersuite, th_trunc, th, &server.psk_opt, bindero)?;
+    let th = transcript.transcript_hash(crypto)?;
+    let offer = ClientOffer {
+        server_name: &sni,
+        signature_algorithms: &sigalgs,
+        server_certificate_types: &info.server_certificate_types,
+        delegated_credential_schemes: &info.delegated_credential_schemes,
+        certificate_authorities: &info.certificate_authorities,
+    };
+    let mut server = lookup_db(ciphersuite, &db, &offer, &identities, |index, psk| {
+        verify_psk_binder(&ciphersuite, &th_trunc, psk, &binders[index], crypto).is_ok()
+    })?;
+    // Only staple what the client asks for.
+    if !info.status_request {
+        server.ocsp_response = None;
+    }
+    if !info.signed_certificate_timestamp {
+        server.sct_list = None;
+    }
+    server.ech_retry_configs = ech_retry_configs;
+    // Only a protocol the client offers can be selected.
+    if let Some(protocol) = &alpn_protocol {
+        check(info.alpn_protocols.contains(protocol)).map_err(|_| NO_APPLICATION_PROTOCOL)?;
+    }
+    server.alpn_protocol = alpn_protocol;
+    server.quic = quic;
+    server.post_handshake_auth = if info.post_handshake_auth {
+        Some(db.client_certificate_trust())
+    } else {
+        None
+    };
+    // Compress with the first algorithm the client offers that is available.
+    if !ciphersuite.psk_mode() {
+        server.certificate_compression = info
+            .certificate_compression_algorithms
+            .iter()
+            .find_map(|code| CertificateCompressionAlgorithm::from_code(*code));
+    }
+    let (cipher0, early_secret) = process_psk_zero_rtt(ciphersuite, th, &server.psk, crypto)?;
     Ok((
         cipher0,
+        early_secret,
         ServerPostClientHello {
             client_randomness,
             ciphersuite,
@@ -587,64 +1606,269 @@ fn put_client_hello(
             gx,
             server,
             transcript,
+            ech_client_hello,
         },
     ))
 }
 
-/// Process the PSK binder for 0-RTT
-fn process_psk_binder_zero_rtt(
+/// Start the transcript of the client hello with the `info`.
+///
+/// After a HelloRetryRequest, the transcript is rebuilt from the cookie the
+/// client sent back, because the server kept no state. A cookie the server
+/// can't check, or that was issued to another `client_address`, is rejected.
+fn hello_retry_transcript(
+    ciphersuite: &Algorithms,
+    db: &ServerDB,
+    client_address: &Option<Bytes>,
+    info: &ClientHelloInfo,
+    crypto: &impl CryptoProvider,
+) -> Result<Transcript, TLSError> {
+    match (&db.cookie_key, &info.cookie) {
+        (Some(cookie_key), Some(cookie)) => {
+            let (key_share, client_hello_hash) = open_hello_retry_cookie(
+                ciphersuite,
+                cookie_key,
+                client_address,
+                (db.clock)(),
+                cookie,
+                crypto,
+            )?;
+            let hrr = hello_retry_request(ciphersuite, &info.legacy_session_id, key_share, cookie)?;
+            Ok(Transcript::from_client_hello_hash(ciphersuite.hash, &client_hello_hash)?.add(&hrr))
+        }
+        (None, Some(_)) => Err(ILLEGAL_PARAMETER),
+        (_, None) => Ok(Transcript::new(ciphersuite.hash)),
+    }
+}
+
+/// How long a client may take to send its ClientHello again after a
+/// HelloRetryRequest, in seconds.
+const HELLO_RETRY_COOKIE_LIFETIME: u64 = 60;
+
+/// The input of the tag of a HelloRetryRequest cookie with the `content`.
+///
+/// It binds the cookie to the `ciphersuite` and the client's `address`, if
+/// the server knows it.
+fn hello_retry_cookie_tag_input(
+    ciphersuite: &Algorithms,
+    address: &Option<Bytes>,
+    content: &Bytes,
+) -> Result<Bytes, TLSError> {
+    let address = match address {
+        Some(address) => address.clone(),
+        None => Bytes::new(),
+    };
+    Ok(ciphersuite
+        .ciphersuite()?
+        .concat(encode_length_u8(address.as_raw())?)
+        .concat(content.clone()))
+}
+
+/// Build the cookie of a HelloRetryRequest for the hash `client_hello_hash`
+/// of the first ClientHello.
+///
+/// The cookie holds whether the server asked for a `key_share`, the time
+/// `now` and the hash, authenticated for the `ciphersuite` and the client's
+/// `address` with the `cookie_key`.
+fn hello_retry_cookie(
+    ciphersuite: &Algorithms,
+    cookie_key: &Bytes,
+    address: &Option<Bytes>,
+    now: u64,
+    key_share: bool,
+    client_hello_hash: &Digest,
+    crypto: &impl CryptoProvider,
+) -> Result<Bytes, TLSError> {
+    let content = bytes1(key_share as u8)
+        .concat(Bytes::from(now.to_be_bytes()))
+        .concat(encode_length_u8(client_hello_hash.as_raw())?);
+    let tag = crypto.hmac_tag(
+        &ciphersuite.hash,
+        cookie_key,
+        &hello_retry_cookie_tag_input(ciphersuite, address, &content)?,
+    )?;
+    Ok(content.concat(tag))
+}
+
+/// Check the `cookie` of a HelloRetryRequest with the `cookie_key`.
+///
+/// Returns whether the server asked for a key share, and the hash of the
+/// first ClientHello. A cookie the server didn't issue for the `ciphersuite`
+/// and the client's `address`, or that wasn't issued within
+/// [`HELLO_RETRY_COOKIE_LIFETIME`] of the time `now`, fails with an
+/// [`ILLEGAL_PARAMETER`] error.
+fn open_hello_retry_cookie(
+    ciphersuite: &Algorithms,
+    cookie_key: &Bytes,
+    address: &Option<Bytes>,
+    now: u64,
+    cookie: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<(bool, Digest), TLSError> {
+    let tag_len = ciphersuite.hash.hmac_tag_len();
+    check(cookie.len() > 10 + tag_len).map_err(|_| ILLEGAL_PARAMETER)?;
+    let content = cookie.slice_range(0..cookie.len() - tag_len);
+    let tag = cookie.slice_range(cookie.len() - tag_len..cookie.len());
+    crypto
+        .hmac_verify(
+            &ciphersuite.hash,
+            cookie_key,
+            &hello_retry_cookie_tag_input(ciphersuite, address, &content)?,
+            &tag,
+        )
+        .map_err(|_| ILLEGAL_PARAMETER)?;
+    let key_share = content[0].declassify();
+    let issued = u64::from_be_bytes(content.slice_range(1..9).declassify_array()?);
+    // Servers that share the cookie key may have slightly different clocks.
+    check(issued.abs_diff(now) <= HELLO_RETRY_COOKIE_LIFETIME).map_err(|_| ILLEGAL_PARAMETER)?;
+    let hash_len = length_u8_encoded(&content[9..content.len()])?;
+    check(key_share <= 1 && 10 + hash_len == content.len()).map_err(|_| ILLEGAL_PARAMETER)?;
+    Ok((key_share == 1, content.slice_range(10..content.len())))
+}
+
+/// Decrypt the ClientHelloInner in the ClientHelloOuter `ch` with the ECH
+/// keys in the `db`.
+///
+/// Returns the ClientHelloInner if the server accepts ECH, and the retry
+/// configs to send if it rejects it. The caller checks that the inner hello
+/// is marked as such when it parses it. ECH is ignored in PSK mode and when the
+/// server has no ECH keys.
+fn open_client_hello(
+    ciphersuite: &Algorithms,
+    db: &ServerDB,
+    ch: &HandshakeData,
+    info: &ClientHelloInfo,
+    crypto: &impl CryptoProvider,
+) -> Result<(Option<HandshakeData>, Option<Bytes>), TLSError> {
+    match &info.encrypted_client_hello {
+        Some(ech) if !ciphersuite.psk_mode() && !db.ech_keys.is_empty() => {
+            let HandshakeData(body) = ch.as_handshake_message(HandshakeType::ClientHello)?;
+            match open_client_hello_inner(
+                &db.ech_keys,
+                &body,
+                ech,
+                &info.legacy_session_id,
+                crypto,
+            )? {
+                Some(inner) => Ok((
+                    Some(HandshakeData::from_bytes(
+                        HandshakeType::ClientHello,
+                        &inner,
+                    )?),
+                    None,
+                )),
+                None => Ok((None, db.ech_config_list()?)),
+            }
+        }
+        _ => Ok((None, None)),
+    }
+}
+
+/// Verify the `binder` of the `psk` over the truncated ClientHello
+/// `th_trunc`.
+fn verify_psk_binder(
+    ciphersuite: &Algorithms,
+    th_trunc: &Digest,
+    psk: &PreSharedKey,
+    binder: &Bytes,
+    crypto: &impl CryptoProvider,
+) -> Result<(), TLSError> {
+    let mk = derive_binder_key(&ciphersuite.hash, &psk.key, psk.external, crypto)?;
+    crypto.hmac_verify(&ciphersuite.hash, &mk, th_trunc, binder)
+}
+
+/// Derive the 0-RTT keys of the selected PSK, and the client early traffic
+/// secret.
+///
+/// The binder of the PSK has been verified when it was selected.
+fn process_psk_zero_rtt(
     ciphersuite: Algorithms,
-    th_trunc: Digest,
     th: Digest,
-    psko: &Option<Psk>,
-    bindero: Option<Bytes>,
-) -> Result<Option<ServerCipherState0>, TLSError> {
-    match (ciphersuite.psk_mode, psko, bindero) {
-        (true, Some(k), Some(binder)) => {
-            let mk = derive_binder_key(&ciphersuite.hash, k)?;
-            hmac_verify(&ciphersuite.hash, &mk, &th_trunc, &binder)?;
+    psko: &Option<PreSharedKey>,
+    crypto: &impl CryptoProvider,
+) -> Result<(Option<ServerCipherState0>, Option<Key>), TLSError> {
+    match (ciphersuite.psk_mode, psko) {
+        (true, Some(psk)) => {
             if ciphersuite.zero_rtt {
-                let (key_iv, early_exporter_ms) =
-                    derive_0rtt_keys(&ciphersuite.hash, &ciphersuite.aead, k, &th)?;
+                let (key_iv, early_exporter_ms, early_secret) =
+                    derive_0rtt_keys(&ciphersuite.hash, &ciphersuite.aead, &psk.key, &th, crypto)?;
                 let cipher0 = Some(server_cipher_state0(key_iv, 0, early_exporter_ms));
-                Ok(cipher0)
+                Ok((cipher0, Some(early_secret)))
             } else {
-                Ok(None)
+                Ok((None, None))
             }
         }
-        (false, None, None) => Ok(None),
+        (false, None) => Ok((None, None)),
         _ => Err(PSK_MODE_MISMATCH),
     }
 }
 
 fn get_server_hello(
     state: ServerPostClientHello,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
-) -> Result<(HandshakeData, DuplexCipherStateH, ServerPostServerHello), TLSError> {
+) -> Result<
+    (
+        HandshakeData,
+        DuplexCipherStateH,
+        TrafficSecrets,
+        ServerPostServerHello,
+    ),
+    TLSError,
+> {
     let mut server_random = [0u8; 32];
     rng.fill_bytes(&mut server_random);
-    let (shared_secret, gy) = kem_encap(state.ciphersuite.kem, &state.gx, rng)?;
+    let (shared_secret, gy) = if state.ciphersuite.key_exchange() {
+        crypto.kem_encap(state.ciphersuite.kem, &state.gx, rng)?
+    } else {
+        (zero_key(&state.ciphersuite.hash), Bytes::new())
+    };
     let sh = server_hello(
         &state.ciphersuite,
         server_random.into(),
         &state.session_id,
         &gy,
+        state.server.psk_index,
     )?;
+    // Signal that ECH was accepted in the server random.
+    let sh = match &state.ech_client_hello {
+        Some(inner) => {
+            let confirmation = ech_accept_confirmation(
+                &state.ciphersuite.hash,
+                inner,
+                &state.client_randomness,
+                &sh,
+                crypto,
+            )?;
+            let HandshakeData(sh) = sh;
+            HandshakeData::from(sh.update_slice(
+                ECH_ACCEPT_CONFIRMATION_OFFSET,
+                &confirmation,
+                0,
+                8,
+            ))
+        }
+        None => sh,
+    };
+    let HandshakeData(sh_bytes) = &sh;
+    let server_random = sh_bytes.slice_range(6..38);
     let transcript = state.transcript.add(&sh);
-    let transcript_hash = transcript.transcript_hash()?;
-    let (chk, shk, cfk, sfk, ms) = derive_hk_ms(
+    let transcript_hash = transcript.transcript_hash(crypto)?;
+    let (chk, shk, cfk, sfk, ms, secrets) = derive_hk_ms(
         &state.ciphersuite.hash,
         &state.ciphersuite.aead,
         &shared_secret,
-        &state.server.psk_opt,
+        &state.server.psk,
         &transcript_hash,
+        crypto,
     )?;
     Ok((
         sh,
         DuplexCipherStateH::new(shk, 0, chk, 0),
+        secrets,
         ServerPostServerHello {
             client_random: state.client_randomness,
-            server_random: server_random.into(),
+            server_random,
             ciphersuite: state.ciphersuite,
             server: state.server,
             master_secret: ms,
@@ -655,22 +1879,9 @@ fn get_server_hello(
     ))
 }
 
-fn get_rsa_signature(
-    cert: &Bytes,
-    sk: &Bytes,
-    sigval: &Bytes,
-    rng: &mut (impl CryptoRng + RngCore),
-) -> Result<Bytes, TLSError> {
-    // To avoid cyclic dependencies between the modules we pull out
-    // the values from the RSA certificate here.
-    // We could really read this from the key as well.
-    let (cert_scheme, cert_slice) = verification_key_from_cert(cert)?;
-    let pk = rsa_public_key(cert, cert_slice)?;
-    sign_rsa(sk, &pk.modulus, &pk.exponent, cert_scheme, sigval, rng)
-}
-
 fn get_server_signature_no_psk(
     state: ServerPostServerHello,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<
     (
@@ -681,24 +1892,32 @@ fn get_server_signature_no_psk(
     ),
     TLSError,
 > {
-    let ee = encrypted_extensions(&state.ciphersuite)?;
+    let ee = encrypted_extensions(
+        &state.ciphersuite,
+        &state.server.server_certificate_type,
+        &state.server.ech_retry_configs,
+        &state.server.alpn_protocol,
+        &state.server.quic,
+    )?;
     let transcript = state.transcript.add(&ee);
-    let sc = server_certificate(&state.ciphersuite, &state.server.cert)?;
+    let sc = server_certificate(
+        &state.ciphersuite,
+        &certificate_entries(
+            &state.server.cert_chain,
+            &state.server.ocsp_response,
+            &state.server.sct_list,
+            &state.server.delegated_credential,
+        )?,
+    )?;
+    let sc = match state.server.certificate_compression {
+        Some(algorithm) => compressed_certificate(algorithm, &sc)?,
+        None => sc,
+    };
     let transcript = transcript.add(&sc);
-    let transcript_hash = transcript.transcript_hash()?;
+    let transcript_hash = transcript.transcript_hash(crypto)?;
     let sigval = Bytes::from_slice(&PREFIX_SERVER_SIGNATURE).concat(transcript_hash);
-    let sig = (match state.ciphersuite.signature() {
-        SignatureScheme::EcdsaSecp256r1Sha256 => sign(
-            &state.ciphersuite.signature(),
-            &state.server.sk,
-            &sigval,
-            rng,
-        ),
-        SignatureScheme::RsaPssRsaSha256 => {
-            get_rsa_signature(&state.server.cert, &state.server.sk, &sigval, rng)
-        }
-        SignatureScheme::ED25519 => Err(UNSUPPORTED_ALGORITHM),
-    })?;
+    // The entry was selected for the signature scheme of the ciphersuite.
+    let sig = state.server.key.sign(&sigval, crypto, rng)?;
     let scv = certificate_verify(&state.ciphersuite, &sig)?;
     let transcript = transcript.add(&scv);
     Ok((
@@ -713,12 +1932,15 @@ fn get_server_signature_no_psk(
             state.cfk,
             state.sfk,
             transcript,
+            state.server.server_name,
+            state.server.post_handshake_auth,
         ),
     ))
 }
 
 fn get_server_signature(
     state: ServerPostServerHello,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<
     (
@@ -730,7 +1952,7 @@ fn get_server_signature(
     TLSError,
 > {
     if !state.ciphersuite.psk_mode() {
-        get_server_signature_no_psk(state, rng)
+        get_server_signature_no_psk(state, crypto, rng)
     } else {
         Err(PSK_MODE_MISMATCH)
     }
@@ -749,11 +1971,21 @@ fn get_skip_server_signature_no_psk(
         sfk,
         transcript: tx,
     } = st;
-    let ee = encrypted_extensions(&algs)?;
+    let ee = encrypted_extensions(&algs, &None, &None, &server.alpn_protocol, &server.quic)?;
     let tx = tx.add(&ee);
     Ok((
         ee,
-        ServerPostCertificateVerify(cr, sr, algs, ms, cfk, sfk, tx),
+        ServerPostCertificateVerify(
+            cr,
+            sr,
+            algs,
+            ms,
+            cfk,
+            sfk,
+            tx,
+            server.server_name,
+            server.post_handshake_auth,
+        ),
     ))
 }
 
@@ -779,8 +2011,17 @@ fn get_skip_server_signature(
 
 fn get_server_finished(
     st: ServerPostCertificateVerify,
-) -> Result<(HandshakeData, DuplexCipherState1, ServerPostServerFinished), TLSError> {
-    let ServerPostCertificateVerify(cr, sr, algs, ms, cfk, sfk, tx) = st;
+    crypto: &impl CryptoProvider,
+) -> Result<
+    (
+        HandshakeData,
+        DuplexCipherState1,
+        TrafficSecrets,
+        ServerPostServerFinished,
+    ),
+    TLSError,
+> {
+    let ServerPostCertificateVerify(cr, sr, algs, ms, cfk, sfk, tx, sni, post_handshake_auth) = st;
     let Algorithms {
         hash: ha,
         aead: ae,
@@ -788,44 +2029,167 @@ fn get_server_finished(
         kem: _gn,
         psk_mode: _psk_mode,
         zero_rtt: _zero_rtt,
+        psk_key_exchange_mode: _psk_key_exchange_mode,
     } = algs;
-    let th_scv = tx.transcript_hash()?;
-    let vd = hmac_tag(&ha, &sfk, &th_scv)?;
+    let th_scv = tx.transcript_hash(crypto)?;
+    let vd = crypto.hmac_tag(&ha, &sfk, &th_scv)?;
     let sfin = finished(&vd)?;
     let tx = tx.add(&sfin);
-    let th_sfin = tx.transcript_hash()?;
-    let (cak, sak, exp) = derive_app_keys(&ha, &ae, &ms, &th_sfin)?;
+    let th_sfin = tx.transcript_hash(crypto)?;
+    let (cak, sak, exp, secrets) = derive_app_keys(&ha, &ae, &ms, &th_sfin, crypto)?;
     let cipher1 = duplex_cipher_state1(ae, sak, 0, cak, 0, exp);
     Ok((
         sfin,
         cipher1,
-        ServerPostServerFinished(cr, sr, algs, ms, cfk, tx),
+        secrets,
+        ServerPostServerFinished(cr, sr, algs, ms, cfk, tx, sni, post_handshake_auth),
     ))
 }
 
 fn put_client_finished(
     cfin: &HandshakeData,
     st: ServerPostServerFinished,
+    crypto: &impl CryptoProvider,
 ) -> Result<ServerPostClientFinished, TLSError> {
-    let ServerPostServerFinished(cr, sr, algs, ms, cfk, tx) = st;
-    let th = tx.transcript_hash()?;
+    let ServerPostServerFinished(cr, sr, algs, ms, cfk, tx, sni, post_handshake_auth) = st;
+    let th = tx.transcript_hash(crypto)?;
     let vd = parse_finished(cfin)?;
-    hmac_verify(&algs.hash(), &cfk, &th, &vd)?;
+    crypto.hmac_verify(&algs.hash(), &cfk, &th, &vd)?;
+    let auth = match post_handshake_auth {
+        Some(trust) => Some(ServerPostHandshakeAuth {
+            finished_key: derive_post_handshake_finished_key(&algs.hash(), &ms, &th, crypto)?,
+            requests: 0,
+            pending: None,
+            client_certificates: Vec::new(),
+            trust,
+        }),
+        None => None,
+    };
     let tx = tx.add(cfin);
-    let th = tx.transcript_hash()?;
-    let rms = derive_rms(&algs.hash(), &ms, &th)?;
-    Ok(ServerPostClientFinished(cr, sr, algs, rms, tx))
+    let th = tx.transcript_hash(crypto)?;
+    let rms = derive_rms(&algs.hash(), &ms, &th, crypto)?;
+    Ok(ServerPostClientFinished(cr, sr, algs, rms, tx, sni, auth))
+}
+
+/// Check the Certificate, CertificateVerify and Finished `messages` of the
+/// client that answer the `certificate_request` after the handshake.
+///
+/// The client's certificate chain must be valid for the `trust` anchors, or
+/// this fails with a [`BAD_CERTIFICATE`] error.
+///
+/// Returns the certificate entries of the client, which are empty if it
+/// didn't authenticate.
+fn put_certificate_response(
+    algs: &Algorithms,
+    transcript: &Transcript,
+    certificate_request: &HandshakeData,
+    messages: &HandshakeData,
+    finished_key: &MacKey,
+    trust: &ClientCertificateTrust,
+    crypto: &impl CryptoProvider,
+) -> Result<Vec<CertificateEntry>, TLSError> {
+    let (certificate, rest) = messages.next_handshake_message()?;
+    let (context, certificates) = parse_client_certificate(&certificate)?;
+    let (expected_context, _, _) = parse_certificate_request(certificate_request)?;
+    check(context == expected_context).map_err(|_| ILLEGAL_PARAMETER)?;
+    let transcript = transcript
+        .clone()
+        .add(certificate_request)
+        .add(&certificate);
+    let (transcript, client_finished) = if certificates.is_empty() {
+        (transcript, rest)
+    } else {
+        let (certificate_verify, rest) = rest.next_handshake_message()?;
+        let certificate = &certificates[0].cert_data;
+        let spki = verification_key_from_cert(certificate)?;
+        let cert_pk = cert_public_key(certificate, &spki)?;
+        let (cert_scheme, cert_signature) = parse_certificate_verify(algs, &certificate_verify)?;
+        let sigval =
+            Bytes::from_slice(&PREFIX_CLIENT_SIGNATURE).concat(transcript.transcript_hash(crypto)?);
+        crypto.verify(&cert_scheme, &cert_pk, &sigval, &cert_signature)?;
+        let cert_chain: Vec<Bytes> = certificates
+            .iter()
+            .map(|entry| entry.cert_data.clone())
+            .collect();
+        verify_certificate_chain(&cert_chain, &trust.trust_anchors, (trust.clock)(), crypto)?;
+        (transcript.add(&certificate_verify), rest)
+    };
+    let verify_data = parse_finished(&client_finished)?;
+    crypto.hmac_verify(
+        &algs.hash(),
+        finished_key,
+        &transcript.transcript_hash(crypto)?,
+        &verify_data,
+    )?;
+    Ok(certificates)
 }
 
 // Server-Side Handshake API: Usable by Quic and TLS
+// [server_hello_retry_request] ->
 // server_init -> (decrypt_zerortt)* | (encrypt_handshake | decrypt_handshake)* ->
-// server_finish -> (encrypt_data | decrypt_data)*
+// server_finish -> (encrypt_data | decrypt_data)* ->
+// (server_certificate_request -> server_certificate_response)*
+
+/// Decide whether the server answers the client hello `ch` with a
+/// HelloRetryRequest, and build it.
+///
+/// This needs a cookie key in the `db`. The server asks clients that didn't
+/// send a key share for its group to retry, or all clients if the `db`
+/// requires a cookie. A client hello with a cookie, ECH or early data isn't
+/// asked to retry. The cookie is bound to the `client_address`, if the server
+/// knows it.
+pub fn server_hello_retry_request(
+    algs: Algorithms,
+    ch: &HandshakeData,
+    db: &ServerDB,
+    client_address: &Option<Bytes>,
+    crypto: &impl CryptoProvider,
+) -> Result<Option<HandshakeData>, TLSError> {
+    let info = parse_client_hello_info(ch)?;
+    let key_share = algs.key_exchange() && {
+        let group = algs.supported_group()?.declassify();
+        !info
+            .key_share_groups
+            .contains(&u16::from_be_bytes([group[0], group[1]]))
+    };
+    match &db.cookie_key {
+        Some(cookie_key)
+            if (key_share || db.require_cookie)
+                && info.cookie.is_none()
+                && info.encrypted_client_hello.is_none()
+                && !algs.zero_rtt =>
+        {
+            let client_hello_hash = Transcript::new(algs.hash).add(ch).transcript_hash(crypto)?;
+            let cookie = hello_retry_cookie(
+                &algs,
+                cookie_key,
+                client_address,
+                (db.clock)(),
+                key_share,
+                &client_hello_hash,
+                crypto,
+            )?;
+            Ok(Some(hello_retry_request(
+                &algs,
+                &info.legacy_session_id,
+                key_share,
+                &cookie,
+            )?))
+        }
+        _ => Ok(None),
+    }
+}
 
 #[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
 pub fn server_init_no_psk(
     algs: Algorithms,
     ch: &HandshakeData,
     db: ServerDB,
+    client_address: &Option<Bytes>,
+    alpn_protocol: Option<Bytes>,
+    quic: Option<QuicParameters>,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<
     (
@@ -834,24 +2198,39 @@ pub fn server_init_no_psk(
         Option<ServerCipherState0>,
         DuplexCipherStateH,
         DuplexCipherState1,
+        (Option<Key>, TrafficSecrets, TrafficSecrets),
         ServerPostServerFinished,
     ),
     TLSError,
 > {
-    let (cipher0, st) = put_client_hello(algs, ch, db)?;
-    let (sh, cipher_hs, st) = get_server_hello(st, rng)?;
+    let (cipher0, early_secret, st) =
+        put_client_hello(algs, ch, db, client_address, alpn_protocol, quic, crypto)?;
+    let (sh, cipher_hs, handshake_secrets, st) = get_server_hello(st, crypto, rng)?;
 
-    let (ee, sc, scv, st) = get_server_signature(st, rng)?;
-    let (sfin, cipher1, st) = get_server_finished(st)?;
+    let (ee, sc, scv, st) = get_server_signature(st, crypto, rng)?;
+    let (sfin, cipher1, application_secrets, st) = get_server_finished(st, crypto)?;
     let flight = ee.concat(&sc).concat(&scv).concat(&sfin);
-    Ok((sh, flight, cipher0, cipher_hs, cipher1, st))
+    Ok((
+        sh,
+        flight,
+        cipher0,
+        cipher_hs,
+        cipher1,
+        (early_secret, handshake_secrets, application_secrets),
+        st,
+    ))
 }
 
 #[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
 pub fn server_init_psk(
     algs: Algorithms,
     ch: &HandshakeData,
     db: ServerDB,
+    client_address: &Option<Bytes>,
+    alpn_protocol: Option<Bytes>,
+    quic: Option<QuicParameters>,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<
     (
@@ -860,24 +2239,39 @@ pub fn server_init_psk(
         Option<ServerCipherState0>,
         DuplexCipherStateH,
         DuplexCipherState1,
+        (Option<Key>, TrafficSecrets, TrafficSecrets),
         ServerPostServerFinished,
     ),
     TLSError,
 > {
-    let (cipher0, st) = put_client_hello(algs, ch, db)?;
-    let (sh, cipher_hs, st) = get_server_hello(st, rng)?;
+    let (cipher0, early_secret, st) =
+        put_client_hello(algs, ch, db, client_address, alpn_protocol, quic, crypto)?;
+    let (sh, cipher_hs, handshake_secrets, st) = get_server_hello(st, crypto, rng)?;
 
     let (ee, st) = get_skip_server_signature(st)?;
-    let (sfin, cipher1, st) = get_server_finished(st)?;
+    let (sfin, cipher1, application_secrets, st) = get_server_finished(st, crypto)?;
     let flight = ee.concat(&sfin);
-    Ok((sh, flight, cipher0, cipher_hs, cipher1, st))
+    Ok((
+        sh,
+        flight,
+        cipher0,
+        cipher_hs,
+        cipher1,
+        (early_secret, handshake_secrets, application_secrets),
+        st,
+    ))
 }
 
 #[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
 pub fn server_init(
     algs: Algorithms,
     ch: &HandshakeData,
     db: ServerDB,
+    client_address: &Option<Bytes>,
+    alpn_protocol: Option<Bytes>,
+    quic: Option<QuicParameters>,
+    crypto: &impl CryptoProvider,
     rng: &mut (impl CryptoRng + RngCore),
 ) -> Result<
     (
@@ -886,19 +2280,102 @@ pub fn server_init(
         Option<ServerCipherState0>,
         DuplexCipherStateH,
         DuplexCipherState1,
+        (Option<Key>, TrafficSecrets, TrafficSecrets),
         ServerPostServerFinished,
     ),
     TLSError,
 > {
     match algs.psk_mode() {
-        false => server_init_no_psk(algs, ch, db, rng),
-        true => server_init_psk(algs, ch, db, rng),
+        false => server_init_no_psk(
+            algs,
+            ch,
+            db,
+            client_address,
+            alpn_protocol,
+            quic,
+            crypto,
+            rng,
+        ),
+        true => server_init_psk(
+            algs,
+            ch,
+            db,
+            client_address,
+            alpn_protocol,
+            quic,
+            crypto,
+            rng,
+        ),
     }
 }
 
 pub fn server_finish(
     cf: &HandshakeData,
     st: ServerPostServerFinished,
+    crypto: &impl CryptoProvider,
+) -> Result<ServerPostClientFinished, TLSError> {
+    put_client_finished(cf, st, crypto)
+}
+
+/// Build a CertificateRequest to authenticate the client after the handshake.
+///
+/// The client must have offered post-handshake authentication, otherwise this
+/// fails with an [`UNSUPPORTED`] error. Only one request can be outstanding at
+/// a time.
+pub(crate) fn server_certificate_request(
+    st: ServerPostClientFinished,
+) -> Result<(HandshakeData, ServerPostClientFinished), TLSError> {
+    let ServerPostClientFinished(cr, sr, algs, rms, tx, sni, auth) = st;
+    let mut auth = auth.ok_or(UNSUPPORTED)?;
+    check(auth.pending.is_none()).map_err(|_| INCORRECT_STATE)?;
+    let context = Bytes::from(auth.requests.to_be_bytes());
+    let certificate_request =
+        certificate_request(&algs, &context, &auth.trust.certificate_authorities)?;
+    auth.requests += 1;
+    auth.pending = Some((
+        certificate_request.clone(),
+        HandshakeData::from(Bytes::new()),
+    ));
+    Ok((
+        certificate_request,
+        ServerPostClientFinished(cr, sr, algs, rms, tx, sni, Some(auth)),
+    ))
+}
+
+/// Process the handshake `messages` the client sent after the handshake.
+///
+/// These answer the outstanding CertificateRequest, and may be split across
+/// several records. Once the client's Finished message is in, the client
+/// certificates are updated.
+pub(crate) fn server_certificate_response(
+    messages: &HandshakeData,
+    st: ServerPostClientFinished,
+    crypto: &impl CryptoProvider,
 ) -> Result<ServerPostClientFinished, TLSError> {
-    put_client_finished(cf, st)
+    let ServerPostClientFinished(cr, sr, algs, rms, tx, sni, auth) = st;
+    let mut auth = auth.ok_or(UNEXPECTED_MESSAGE)?;
+    let (certificate_request, buf) = auth.pending.take().ok_or(UNEXPECTED_MESSAGE)?;
+    let buf = buf.concat(messages);
+    if buf.find_handshake_message(HandshakeType::Finished, 0) {
+        auth.client_certificates = put_certificate_response(
+            &algs,
+            &tx,
+            &certificate_request,
+            &buf,
+            &auth.finished_key,
+            &auth.trust,
+            crypto,
+        )?;
+    } else {
+        auth.pending = Some((certificate_request, buf));
+    }
+    Ok(ServerPostClientFinished(
+        cr,
+        sr,
+        algs,
+        rms,
+        tx,
+        sni,
+        Some(auth),
+    ))
 }
@@ -96,14 +96,15 @@ pub(crate) fn encrypt_record_payload(
     ct: ContentType,
     payload: Bytes,
     pad: usize,
+    crypto: &impl CryptoProvider,
 ) -> Result<Bytes, TLSError> {
     let iv_ctr = derive_iv_ctr(&key_iv.iv, n);
     let inner_plaintext = payload.concat(bytes1(ct as u8)).concat(Bytes::zeroes(pad));
-    let clen = inner_plaintext.len() + 16;
+    let clen = inner_plaintext.len() + key_iv.key.tag_len();
     if clen <= 65536 {
         let clenb = (clen as u16).to_be_bytes();
         let ad = [23, 3, 3, clenb[0], clenb[1]].into();
-        let cip = aead_encrypt(&key_iv.key, &iv_ctr, &inner_plaintext, &ad)?;
+        let cip = crypto.aead_encrypt(&key_iv.key, &iv_ctr, &inner_plaintext, &ad)?;
         let rec = ad.concat(cip);
         Ok(rec)
     } else {
@@ -124,16 +125,17 @@ fn decrypt_record_payload(
     kiv: &AeadKeyIV,
     n: u64,
     ciphertext: &Bytes,
+    crypto: &impl CryptoProvider,
 ) -> Result<(ContentType, Bytes), TLSError> {
     let iv_ctr = derive_iv_ctr(&kiv.iv, n);
     let clen = ciphertext.len() - 5;
-    if clen <= 65536 && clen > 16 {
+    if clen <= 65536 && clen > kiv.key.tag_len() {
         let clen_bytes = (clen as u16).to_be_bytes();
         let ad = [23, 3, 3, clen_bytes[0], clen_bytes[1]].into();
         check_eq(&ad, &ciphertext.slice_range(0..5))?;
 
         let cip = ciphertext.slice_range(5..ciphertext.len());
-        let plain = aead_decrypt(&kiv.key, &iv_ctr, &cip, &ad)?;
+        let plain = crypto.aead_decrypt(&kiv.key, &iv_ctr, &cip, &ad)?;
 
         let payload_len = plain.len() - padlen(&plain, plain.len()) - 1;
         let ct = ContentType::try_from_u8(plain[payload_len].declassify())?;
@@ -153,6 +155,7 @@ fn encrypt_zerortt(
     payload: AppData,
     pad: usize,
     st: ClientCipherState0,
+    crypto: &impl CryptoProvider,
 ) -> Result<(Bytes, ClientCipherState0), TLSError> {
     let ClientCipherState0(ae, kiv, n, exp) = st;
     let rec = encrypt_record_payload(
@@ -161,6 +164,7 @@ fn encrypt_zerortt(
         ContentType::ApplicationData,
         payload.into_raw(),
         pad,
+        crypto,
     )?;
     Ok((rec, ClientCipherState0(ae, kiv, n + 1, exp)))
 }
@@ -171,8 +175,9 @@ fn encrypt_zerortt(
 pub fn decrypt_zerortt(
     ciphertext: &Bytes,
     state: ServerCipherState0,
+    crypto: &impl CryptoProvider,
 ) -> Result<(AppData, ServerCipherState0), TLSError> {
-    let (ct, payload) = decrypt_record_payload(&state.key_iv, state.counter, ciphertext)?;
+    let (ct, payload) = decrypt_record_payload(&state.key_iv, state.counter, ciphertext, crypto)?;
     check(ct == ContentType::ApplicationData)?;
     Ok((
         AppData::new(payload),
@@ -192,6 +197,7 @@ pub(crate) fn encrypt_handshake(
     payload: handshake_data::HandshakeData,
     pad: usize,
     mut state: DuplexCipherStateH,
+    crypto: &impl CryptoProvider,
 ) -> Result<(Bytes, DuplexCipherStateH), TLSError> {
     let payload = payload.to_bytes();
 
@@ -201,6 +207,29 @@ pub(crate) fn encrypt_handshake(
         ContentType::Handshake,
         payload,
         pad,
+        crypto,
+    )?;
+
+    state.sender_counter += 1;
+    Ok((rec, state))
+}
+
+/// Encrypt a fatal alert with the `description` under the handshake keys.
+///
+/// Returns the ciphertext, new [`DuplexCipherStateH`] if successful, or a
+/// [`TLSError`] otherwise.
+pub(crate) fn encrypt_alert(
+    description: AlertDescription,
+    mut state: DuplexCipherStateH,
+    crypto: &impl CryptoProvider,
+) -> Result<(Bytes, DuplexCipherStateH), TLSError> {
+    let rec = encrypt_record_payload(
+        &state.sender_key_iv,
+        state.sender_counter,
+        ContentType::Alert,
+        bytes2(AlertLevel::Fatal as u8, description as u8),
+        0,
+        crypto,
     )?;
 
     state.sender_counter += 1;
@@ -208,15 +237,29 @@ pub(crate) fn encrypt_handshake(
 }
 
 /// Decrypt a handshake message.
+///
+/// An `ech_required` alert fails with an [`ECH_REQUIRED`] error, and any
+/// other alert with a [`GOT_HANDSHAKE_FAILURE_ALERT`] error.
 pub(crate) fn decrypt_handshake(
     ciphertext: &Bytes,
     mut state: DuplexCipherStateH,
+    crypto: &impl CryptoProvider,
 ) -> Result<(handshake_data::HandshakeData, DuplexCipherStateH), TLSError> {
-    let (ct, payload) =
-        decrypt_record_payload(&state.receiver_key_iv, state.receiver_counter, ciphertext)?;
+    let (ct, payload) = decrypt_record_payload(
+        &state.receiver_key_iv,
+        state.receiver_counter,
+        ciphertext,
+        crypto,
+    )?;
     if ct == ContentType::Alert {
+        let ech_required =
+            payload.len() == 2 && payload[1].declassify() == AlertDescription::EchRequired as u8;
         Result::<(handshake_data::HandshakeData, DuplexCipherStateH), TLSError>::Err(
-            GOT_HANDSHAKE_FAILURE_ALERT,
+            if ech_required {
+                ECH_REQUIRED
+            } else {
+                GOT_HANDSHAKE_FAILURE_ALERT
+            },
         )
     } else {
         check(ct == ContentType::Handshake)?;
@@ -229,6 +272,7 @@ pub fn encrypt_data(
     payload: AppData,
     pad: usize,
     st: DuplexCipherState1,
+    crypto: &impl CryptoProvider,
 ) -> Result<(Bytes, DuplexCipherState1), TLSError> {
     let DuplexCipherState1(ae, kiv, n, x, y, exp) = st;
     let rec = encrypt_record_payload(
@@ -237,6 +281,27 @@ pub fn encrypt_data(
         ContentType::ApplicationData,
         payload.into_raw(),
         pad,
+        crypto,
+    )?;
+    Ok((rec, DuplexCipherState1(ae, kiv, n + 1, x, y, exp)))
+}
+
+/// Encrypt the post-handshake `payload` [`HandshakeData`] with the
+/// application keys.
+pub(crate) fn encrypt_post_handshake(
+    payload: handshake_data::HandshakeData,
+    pad: usize,
+    st: DuplexCipherState1,
+    crypto: &impl CryptoProvider,
+) -> Result<(Bytes, DuplexCipherState1), TLSError> {
+    let DuplexCipherState1(ae, kiv, n, x, y, exp) = st;
+    let rec = encrypt_record_payload(
+        &kiv,
+        n,
+        ContentType::Handshake,
+        payload.to_bytes(),
+        pad,
+        crypto,
     )?;
     Ok((rec, DuplexCipherState1(ae, kiv, n + 1, x, y, exp)))
 }
@@ -244,17 +309,19 @@ pub fn encrypt_data(
 pub fn decrypt_data_or_hs(
     ciphertext: &Bytes,
     st: DuplexCipherState1,
+    crypto: &impl CryptoProvider,
 ) -> Result<(ContentType, Bytes, DuplexCipherState1), TLSError> {
     let DuplexCipherState1(ae, x, y, kiv, n, exp) = st;
-    let (ct, payload) = decrypt_record_payload(&kiv, n, ciphertext)?;
+    let (ct, payload) = decrypt_record_payload(&kiv, n, ciphertext, crypto)?;
     Ok((ct, payload, DuplexCipherState1(ae, x, y, kiv, n + 1, exp)))
 }
 pub fn decrypt_data(
     ciphertext: &Bytes,
     st: DuplexCipherState1,
+    crypto: &impl CryptoProvider,
 ) -> Result<(AppData, DuplexCipherState1), TLSError> {
     let DuplexCipherState1(ae, x, y, kiv, n, exp) = st;
-    let (ct, payload) = decrypt_record_payload(&kiv, n, ciphertext)?;
+    let (ct, payload) = decrypt_record_payload(&kiv, n, ciphertext, crypto)?;
     check(ct == ContentType::ApplicationData)?;
     Ok((
         AppData::new(payload),
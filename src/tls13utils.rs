@@ -39,6 +39,19 @@ pub const MISSING_KEY_SHARE: TLSError = 139u8;
 pub const INVALID_SIGNATURE: TLSError = 140u8;
 pub const GOT_HANDSHAKE_FAILURE_ALERT: TLSError = 141u8;
 pub const DECODE_ERROR: TLSError = 142u8;
+pub const SIGNER_ERROR: TLSError = 143u8;
+pub const UNRECOGNIZED_NAME: TLSError = 144u8;
+pub const BAD_CERTIFICATE_STATUS: TLSError = 145u8;
+pub const CERTIFICATE_REVOKED: TLSError = 146u8;
+pub const CT_POLICY_FAILED: TLSError = 147u8;
+pub const BAD_CERTIFICATE: TLSError = 148u8;
+pub const ECH_REQUIRED: TLSError = 149u8;
+pub const ILLEGAL_PARAMETER: TLSError = 150u8;
+pub const UNEXPECTED_MESSAGE: TLSError = 151u8;
+pub const BAD_DELEGATED_CREDENTIAL: TLSError = 152u8;
+pub const NO_APPLICATION_PROTOCOL: TLSError = 153u8;
+pub const MISSING_EXTENSION: TLSError = 154u8;
+pub const DECRYPT_ERROR: TLSError = 155u8;
 
 #[allow(dead_code)]
 pub(crate) fn error_string(c: u8) -> String {
@@ -51,6 +64,13 @@ pub(crate) fn tlserr<T>(err: TLSError) -> Result<T, TLSError> {
     Err(err)
 }
 
+/// The current time of the system clock, in seconds since the Unix epoch.
+pub(crate) fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
 /*
 pub(crate) fn check_eq_size(s1: TLSError, s2: usize) -> Result<()> {
     if s1 == s2 {Ok(())}
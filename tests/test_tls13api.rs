@@ -3,15 +3,38 @@
 
 // These are the sample TLS 1.3 traces taken from RFC 8448
 
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
 use bertie::{
-    server::ServerDB,
+    cert_compression::supported_algorithms,
+    client::{ClientConfig, ClientCredentials},
+    ct::{CtLog, CtPolicy},
+    delegated_credential::DelegatedCredential,
+    ech::{EchKeyPair, EchStatus},
+    keys::{load_certificates, load_private_key},
+    peek_client_hello,
+    psk::PreSharedKey,
+    quic::{EncryptionLevel, QuicClient, QuicOutput, QuicParameters, QuicServer},
+    server::{ServerDB, ServerEntry},
+    signer::InMemorySigner,
     test_utils::TestRng,
     tls13crypto::{
-        AeadAlgorithm, Algorithms, HashAlgorithm, KemScheme, SignatureKey, SignatureScheme,
+        AeadAlgorithm, AeadKey, Algorithms, CryptoProvider, HashAlgorithm, KemScheme,
+        LibcruxProvider, PskKeyExchangeMode, PublicVerificationKey, SignatureKey, SignatureScheme,
     },
-    tls13utils::{eq, random_bytes, AppData, Bytes},
-    Client, Server,
+    tls13utils::{
+        eq, random_bytes, AppData, Bytes, TLSError, BAD_CERTIFICATE, BAD_CERTIFICATE_STATUS,
+        BAD_DELEGATED_CREDENTIAL, CERTIFICATE_REVOKED, CT_POLICY_FAILED, DECRYPT_ERROR,
+        ECH_REQUIRED, ILLEGAL_PARAMETER, INSUFFICIENT_DATA, INVALID_SIGNATURE,
+        NO_APPLICATION_PROTOCOL, PAYLOAD_TOO_LONG, PSK_MODE_MISMATCH, SIGNER_ERROR,
+        UNEXPECTED_MESSAGE, UNRECOGNIZED_NAME, UNSUPPORTED, UNSUPPORTED_ALGORITHM,
+    },
+    Client, ClientHelloInfo, Server, ServerConfig,
 };
+use rand::{CryptoRng, RngCore};
 
 fn load_hex(s: &str) -> Bytes {
     let s_no_ws: String = s.split_whitespace().collect();
@@ -98,6 +121,49 @@ const TLS_CHACHA20_POLY1305_SHA256_X25519: Algorithms = Algorithms::new(
     false,
     false,
 );
+const TLS_CHACHA20_POLY1305_SHA256_X25519_RSA: Algorithms = Algorithms::new(
+    HashAlgorithm::SHA256,
+    AeadAlgorithm::Chacha20Poly1305,
+    SignatureScheme::RsaPssRsaSha256,
+    KemScheme::X25519,
+    false,
+    false,
+);
+
+/// A client configuration of the `ciphersuite` for `localhost`.
+fn config(ciphersuite: Algorithms) -> ClientConfig {
+    ClientConfig::new(ciphersuite, &Bytes::from(b"localhost"))
+}
+
+/// Start a handshake as a client with the `config`.
+fn connect(config: ClientConfig) -> Result<(Bytes, Client), TLSError> {
+    Client::connect_with_config(config, &mut rand::rngs::OsRng)
+}
+
+/// Run a handshake between the client that sent the `client_hello` and a
+/// server with the `db`.
+///
+/// The `client_hello` is what one of the `Client::connect` functions
+/// returned, so that client errors show up here too.
+/// Returns the client and the server after the handshake, or the first error.
+fn handshake(
+    ciphersuite: Algorithms,
+    db: &ServerDB,
+    client_hello: Result<(Bytes, Client), TLSError>,
+) -> Result<(Client, Server), TLSError> {
+    let (client_hello, client) = client_hello?;
+    let (sh, sf, server) = Server::accept(
+        ciphersuite,
+        db.clone(),
+        &client_hello,
+        None,
+        &mut rand::rngs::OsRng,
+    )?;
+    let (_, client) = client.read_handshake(&sh)?;
+    let (cf, client) = client.read_handshake(&sf)?;
+    let server = server.read_handshake(&cf.ok_or(UNEXPECTED_MESSAGE)?)?;
+    Ok((client, server))
+}
 
 #[test]
 fn test_full_round_trip() {
@@ -127,7 +193,7 @@ fn test_full_round_trip() {
         }
         Ok((client_hello, client)) => {
             println!("Client0 Complete {}", server_rng.raw().len());
-            match Server::accept(ciphersuite, db, &client_hello, &mut server_rng) {
+            match Server::accept(ciphersuite, db, &client_hello, None, &mut server_rng) {
                 Err(x) => {
                     println!("ServerInit Error {}", x);
                     b = false;
@@ -187,3 +253,1783 @@ fn test_full_round_trip() {
     }
     assert!(b);
 }
+
+/// A [`CryptoProvider`] that counts the AEAD and signature operations and
+/// forwards everything to the [`LibcruxProvider`].
+#[derive(Clone, Default)]
+struct CountingProvider {
+    aead: Arc<AtomicUsize>,
+    signatures: Arc<AtomicUsize>,
+}
+
+impl CryptoProvider for CountingProvider {
+    fn hash(&self, alg: &HashAlgorithm, data: &Bytes) -> Result<Bytes, TLSError> {
+        LibcruxProvider.hash(alg, data)
+    }
+
+    fn hmac_tag(&self, alg: &HashAlgorithm, mk: &Bytes, input: &Bytes) -> Result<Bytes, TLSError> {
+        LibcruxProvider.hmac_tag(alg, mk, input)
+    }
+
+    fn hkdf_extract(
+        &self,
+        alg: &HashAlgorithm,
+        ikm: &Bytes,
+        salt: &Bytes,
+    ) -> Result<Bytes, TLSError> {
+        LibcruxProvider.hkdf_extract(alg, ikm, salt)
+    }
+
+    fn hkdf_expand(
+        &self,
+        alg: &HashAlgorithm,
+        prk: &Bytes,
+        info: &Bytes,
+        len: usize,
+    ) -> Result<Bytes, TLSError> {
+        LibcruxProvider.hkdf_expand(alg, prk, info, len)
+    }
+
+    fn aead_encrypt(
+        &self,
+        k: &AeadKey,
+        iv: &Bytes,
+        plain: &Bytes,
+        aad: &Bytes,
+    ) -> Result<Bytes, TLSError> {
+        self.aead.fetch_add(1, Ordering::Relaxed);
+        LibcruxProvider.aead_encrypt(k, iv, plain, aad)
+    }
+
+    fn aead_decrypt(
+        &self,
+        k: &AeadKey,
+        iv: &Bytes,
+        cip: &Bytes,
+        aad: &Bytes,
+    ) -> Result<Bytes, TLSError> {
+        self.aead.fetch_add(1, Ordering::Relaxed);
+        LibcruxProvider.aead_decrypt(k, iv, cip, aad)
+    }
+
+    fn sign(
+        &self,
+        algorithm: &SignatureScheme,
+        sk: &SignatureKey,
+        input: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError> {
+        self.signatures.fetch_add(1, Ordering::Relaxed);
+        LibcruxProvider.sign(algorithm, sk, input, rng)
+    }
+
+    fn sign_rsa(
+        &self,
+        sk: &SignatureKey,
+        pk_modulus: &Bytes,
+        pk_exponent: &Bytes,
+        cert_scheme: SignatureScheme,
+        input: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Bytes, TLSError> {
+        self.signatures.fetch_add(1, Ordering::Relaxed);
+        LibcruxProvider.sign_rsa(sk, pk_modulus, pk_exponent, cert_scheme, input, rng)
+    }
+
+    fn verify(
+        &self,
+        alg: &SignatureScheme,
+        pk: &PublicVerificationKey,
+        input: &Bytes,
+        sig: &Bytes,
+    ) -> Result<(), TLSError> {
+        self.signatures.fetch_add(1, Ordering::Relaxed);
+        LibcruxProvider.verify(alg, pk, input, sig)
+    }
+
+    fn kem_keygen(
+        &self,
+        alg: KemScheme,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes), TLSError> {
+        LibcruxProvider.kem_keygen(alg, rng)
+    }
+
+    fn kem_encap(
+        &self,
+        alg: KemScheme,
+        pk: &Bytes,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<(Bytes, Bytes), TLSError> {
+        LibcruxProvider.kem_encap(alg, pk, rng)
+    }
+
+    fn kem_decap(&self, alg: KemScheme, ct: &Bytes, sk: &Bytes) -> Result<Bytes, TLSError> {
+        LibcruxProvider.kem_decap(alg, ct, sk)
+    }
+}
+
+#[test]
+fn test_custom_crypto_provider() {
+    let mut client_rng = TestRng::new(
+        random_bytes(32)
+            .concat(load_hex(client_x25519_priv))
+            .declassify(),
+    );
+    let mut server_rng = TestRng::new(
+        random_bytes(64)
+            .concat(load_hex(server_x25519_priv))
+            .declassify(),
+    );
+    let server_name = load_hex("6c 6f 63 61 6c 68 6f 73 74");
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+
+    let client_crypto = CountingProvider::default();
+    let server_crypto = CountingProvider::default();
+    // The raw key is signed with the provider of the handshake.
+    let db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+
+    let (client_hello, client) = Client::connect_with_config(
+        ClientConfig::new(ciphersuite, &server_name).with_provider(client_crypto.clone()),
+        &mut client_rng,
+    )
+    .unwrap();
+    let (sh, sf, server) = Server::accept_with_provider(
+        server_crypto.clone(),
+        ciphersuite,
+        db,
+        &client_hello,
+        None,
+        &mut server_rng,
+    )
+    .unwrap();
+    let (_, client) = client.read_handshake(&sh).unwrap();
+    let (cf, client) = client.read_handshake(&sf).unwrap();
+    let server = server.read_handshake(&cf.unwrap()).unwrap();
+
+    let data = Bytes::from(b"Hello server, here is the client");
+    let (ap, _client) = client.write(AppData::new(data.clone())).unwrap();
+    let (apo, _server) = server.read(&ap).unwrap();
+    assert!(eq(&data, apo.unwrap().as_raw()));
+
+    // The server signs the transcript and the client verifies the signature.
+    assert_eq!(server_crypto.signatures.load(Ordering::Relaxed), 1);
+    assert_eq!(client_crypto.signatures.load(Ordering::Relaxed), 1);
+    // Both sides encrypt or decrypt the server flight, the client finished
+    // message and the application data.
+    assert!(server_crypto.aead.load(Ordering::Relaxed) >= 3);
+    assert!(client_crypto.aead.load(Ordering::Relaxed) >= 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_signer() {
+    use bertie::signer::{serve_unix_signer, Signer, UnixSocketSigner};
+    use std::os::unix::net::UnixListener;
+
+    let socket = std::env::temp_dir().join(format!("bertie-signer-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let server_name = Bytes::from(b"localhost");
+
+    // The entry is created while the signing service is still down.
+    let signer = UnixSocketSigner::new(&socket).with_timeout(std::time::Duration::from_secs(1));
+    assert_eq!(signer.scheme(), Err(SIGNER_ERROR));
+    let db = ServerDB::with_signer(
+        server_name.clone(),
+        vec![Bytes::from(&ECDSA_P256_SHA256_CERT)],
+        Arc::new(signer.clone()),
+        None,
+    );
+
+    let listener = UnixListener::bind(&socket).unwrap();
+    std::thread::spawn(move || {
+        let signer = InMemorySigner::new(
+            Bytes::from(&ECDSA_P256_SHA256_CERT),
+            SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        );
+        serve_unix_signer(&listener, &signer, &mut rand::rngs::OsRng)
+    });
+    assert_eq!(
+        signer.scheme().unwrap(),
+        SignatureScheme::EcdsaSecp256r1Sha256
+    );
+
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[test]
+fn test_virtual_hosts() {
+    let entry = |name: &[u8]| {
+        ServerEntry::new(
+            Bytes::from(name),
+            Bytes::from(&ECDSA_P256_SHA256_CERT),
+            SignatureKey::from(&ECDSA_P256_SHA256_Key),
+            None,
+        )
+    };
+    let mut db = ServerDB::from_entries(vec![entry(b"example.com"), entry(b"*.example.org")]);
+    let accept = |db: &ServerDB, server_name: &[u8]| {
+        let ciphersuite = TLS_CHACHA20_POLY1305_SHA256_X25519;
+        let (_, server) = handshake(
+            ciphersuite,
+            db,
+            connect(ClientConfig::new(ciphersuite, &Bytes::from(server_name))),
+        )?;
+        Ok::<_, TLSError>(server.server_name().cloned())
+    };
+
+    // Exact names are matched ignoring the case.
+    let accepted = accept(&db, b"Example.COM").unwrap().unwrap();
+    assert_eq!(accepted.declassify(), b"Example.COM");
+
+    // Wildcards match a single label.
+    let accepted = accept(&db, b"www.example.org").unwrap().unwrap();
+    assert_eq!(accepted.declassify(), b"www.example.org");
+    assert_eq!(
+        accept(&db, b"a.b.example.org").unwrap_err(),
+        UNRECOGNIZED_NAME
+    );
+    assert_eq!(accept(&db, b"example.org").unwrap_err(), UNRECOGNIZED_NAME);
+
+    // Unknown names are accepted with a default entry.
+    assert_eq!(accept(&db, b"example.net").unwrap_err(), UNRECOGNIZED_NAME);
+    db.set_default_entry(entry(b"default"));
+    let accepted = accept(&db, b"example.net").unwrap().unwrap();
+    assert_eq!(accepted.declassify(), b"example.net");
+}
+
+#[test]
+fn test_certificate_selection() {
+    let rsa_key = load_private_key(&std::fs::read("tests/assets/rsa_key.der").unwrap()).unwrap();
+    let rsa_cert = load_certificates(&std::fs::read("tests/assets/rsa_cert.der").unwrap())
+        .unwrap()
+        .remove(0);
+    let db = ServerDB::from_entries(vec![
+        ServerEntry::new(
+            Bytes::from(b"localhost"),
+            Bytes::from(&ECDSA_P256_SHA256_CERT),
+            SignatureKey::from(&ECDSA_P256_SHA256_Key),
+            None,
+        ),
+        ServerEntry::new(
+            Bytes::from(b"localhost"),
+            rsa_cert,
+            rsa_key.signature_key(),
+            None,
+        ),
+    ]);
+
+    // The client verifies the CertificateVerify with the certificate it got,
+    // which only succeeds if the server picked the matching entry.
+    for ciphersuite in [
+        TLS_CHACHA20_POLY1305_SHA256_X25519,
+        TLS_CHACHA20_POLY1305_SHA256_X25519_RSA,
+    ] {
+        handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    }
+
+    // There's no ML-DSA certificate.
+    let ciphersuite = Algorithms::new(
+        HashAlgorithm::SHA256,
+        AeadAlgorithm::Chacha20Poly1305,
+        SignatureScheme::MlDsa65,
+        KemScheme::X25519,
+        false,
+        false,
+    );
+    assert_eq!(
+        handshake(ciphersuite, &db, connect(config(ciphersuite))).err(),
+        Some(UNSUPPORTED_ALGORITHM)
+    );
+}
+
+#[test]
+fn test_ocsp_stapling() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    // The responses are valid from 2026-10-19 07:53:08 to 2046-10-14 07:53:08.
+    const NOW: u64 = 1792454400;
+    let key = load_private_key(&std::fs::read("tests/assets/chain_key.pem").unwrap()).unwrap();
+    let chain = load_certificates(&std::fs::read("tests/assets/chain_cert.pem").unwrap()).unwrap();
+    let issuer = chain[1].clone();
+    let entry = ServerEntry::with_chain(
+        Bytes::from(b"localhost"),
+        chain.clone(),
+        key.signature_key(),
+        None,
+    );
+    let staple = |file: &str| {
+        let response = Bytes::from(std::fs::read(file).unwrap());
+        ServerDB::from_entries(vec![entry.clone().with_ocsp_response(response)])
+    };
+
+    // Nothing is stapled by default.
+    let db = ServerDB::from_entries(vec![entry.clone()]);
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    assert_eq!(client.server_certificates(), chain);
+    assert_eq!(client.ocsp_response(), None);
+    assert_eq!(
+        client.verify_ocsp_response(&issuer, NOW),
+        Err(BAD_CERTIFICATE_STATUS)
+    );
+
+    let db = staple("tests/assets/chain_ocsp.der");
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    assert_eq!(
+        client.ocsp_response().unwrap().declassify(),
+        std::fs::read("tests/assets/chain_ocsp.der").unwrap()
+    );
+    assert_eq!(client.verify_ocsp_response(&issuer, NOW), Ok(()));
+
+    // The response is only valid between thisUpdate and nextUpdate.
+    assert_eq!(
+        client.verify_ocsp_response(&issuer, 1792368000),
+        Err(BAD_CERTIFICATE_STATUS)
+    );
+    assert_eq!(
+        client.verify_ocsp_response(&issuer, 2423174400),
+        Err(BAD_CERTIFICATE_STATUS)
+    );
+
+    // The response isn't signed by the server certificate.
+    assert!(client.verify_ocsp_response(&chain[0], NOW).is_err());
+
+    let db = staple("tests/assets/chain_ocsp_revoked.der");
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    assert_eq!(
+        client.verify_ocsp_response(&issuer, NOW),
+        Err(CERTIFICATE_REVOKED)
+    );
+
+    // Change the year the response was produced at.
+    let mut tampered = std::fs::read("tests/assets/chain_ocsp.der").unwrap();
+    let produced_at = tampered.windows(4).position(|w| w == b"2026").unwrap();
+    tampered[produced_at] = b'3';
+    let db = ServerDB::from_entries(vec![entry
+        .clone()
+        .with_ocsp_response(Bytes::from(tampered))]);
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    assert_eq!(
+        client.verify_ocsp_response(&issuer, NOW),
+        Err(INVALID_SIGNATURE)
+    );
+}
+
+#[test]
+fn test_signed_certificate_timestamps() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    // The SCTs were issued at 2026-10-19 08:00:00.
+    const NOW: u64 = 1792454400;
+    let key = load_private_key(&std::fs::read("tests/assets/chain_key.pem").unwrap()).unwrap();
+    let chain = load_certificates(&std::fs::read("tests/assets/chain_cert.pem").unwrap()).unwrap();
+    let log = |file: &str| CtLog::new(Bytes::from(std::fs::read(file).unwrap())).unwrap();
+    let (log1, log2) = (
+        log("tests/assets/ct_log1_key.der"),
+        log("tests/assets/ct_log2_key.der"),
+    );
+    let entry = ServerEntry::with_chain(
+        Bytes::from(b"localhost"),
+        chain.clone(),
+        key.signature_key(),
+        None,
+    );
+
+    // Nothing is stapled by default.
+    let db = ServerDB::from_entries(vec![entry.clone()]);
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    assert!(client.signed_certificate_timestamps().is_empty());
+    assert_eq!(
+        client.verify_signed_certificate_timestamps(&CtPolicy::new(vec![log1.clone()], 1), NOW),
+        Err(CT_POLICY_FAILED)
+    );
+
+    let sct_list = Bytes::from(std::fs::read("tests/assets/chain_scts.bin").unwrap());
+    let db = ServerDB::from_entries(vec![entry.clone().with_sct_list(sct_list)]);
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    let scts = client.signed_certificate_timestamps();
+    assert_eq!(scts.len(), 2);
+    assert_eq!(scts[0].timestamp, 1792396800001);
+    assert_eq!(scts[0].signature_algorithm, 0x0403);
+
+    let policy = CtPolicy::new(vec![log1.clone(), log2.clone()], 2);
+    assert_eq!(
+        client.verify_signed_certificate_timestamps(&policy, NOW),
+        Ok(())
+    );
+
+    // Only one of the logs is known.
+    let policy = CtPolicy::new(vec![log2.clone()], 2);
+    assert_eq!(
+        client.verify_signed_certificate_timestamps(&policy, NOW),
+        Err(CT_POLICY_FAILED)
+    );
+    let policy = CtPolicy::new(vec![log2], 1);
+    assert_eq!(
+        client.verify_signed_certificate_timestamps(&policy, NOW),
+        Ok(())
+    );
+
+    // The SCTs are from the future.
+    assert_eq!(
+        client.verify_signed_certificate_timestamps(&policy, 1792368000),
+        Err(CT_POLICY_FAILED)
+    );
+
+    // The signature of the second log is invalid, the first one still counts.
+    let mut tampered = std::fs::read("tests/assets/chain_scts.bin").unwrap();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    let db = ServerDB::from_entries(vec![entry.with_sct_list(Bytes::from(tampered))]);
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    let policy = CtPolicy::new(vec![log1], 1);
+    assert_eq!(
+        client.verify_signed_certificate_timestamps(&policy, NOW),
+        Ok(())
+    );
+    let policy = CtPolicy::new(vec![log("tests/assets/ct_log2_key.der")], 1);
+    assert_eq!(
+        client.verify_signed_certificate_timestamps(&policy, NOW),
+        Err(CT_POLICY_FAILED)
+    );
+}
+
+#[test]
+fn test_certificate_compression() {
+    let key = load_private_key(&std::fs::read("tests/assets/chain_key.pem").unwrap()).unwrap();
+    let chain = load_certificates(&std::fs::read("tests/assets/chain_cert.pem").unwrap()).unwrap();
+    let db = ServerDB::from_entries(vec![ServerEntry::with_chain(
+        Bytes::from(b"localhost"),
+        chain.clone(),
+        key.signature_key(),
+        None,
+    )]);
+
+    let (client_hello, client) = Client::connect(
+        TLS_CHACHA20_POLY1305_SHA256_X25519,
+        &Bytes::from(b"localhost"),
+        None,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let (sh, sf, server) = Server::accept(
+        TLS_CHACHA20_POLY1305_SHA256_X25519,
+        db,
+        &client_hello,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let (_, client) = client.read_handshake(&sh).unwrap();
+    let (cf, client) = client.read_handshake(&sf).unwrap();
+    server.read_handshake(&cf.unwrap()).unwrap();
+    assert_eq!(client.server_certificates(), chain);
+
+    // The server flight is smaller than the chain if it is compressed.
+    let chain_len: usize = chain.iter().map(|cert| cert.declassify().len()).sum();
+    if supported_algorithms().is_empty() {
+        assert!(sf.declassify().len() > chain_len);
+    } else {
+        assert!(sf.declassify().len() < chain_len);
+    }
+}
+
+#[test]
+fn test_raw_public_key() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    let key = load_private_key(&std::fs::read("tests/assets/chain_key.pem").unwrap()).unwrap();
+    let chain = load_certificates(&std::fs::read("tests/assets/chain_cert.pem").unwrap()).unwrap();
+    let spki = Bytes::from(std::fs::read("tests/assets/chain_spki.der").unwrap());
+    let rpk_entry = ServerEntry::with_raw_public_key(
+        Bytes::from(b"localhost"),
+        spki.clone(),
+        key.signature_key(),
+        None,
+    );
+    let cert_entry = ServerEntry::with_chain(
+        Bytes::from(b"localhost"),
+        chain.clone(),
+        key.signature_key(),
+        None,
+    );
+
+    let connect_with_server_key = |server_key: &Bytes| {
+        config(ciphersuite)
+            .with_server_key(server_key.clone())
+            .and_then(connect)
+    };
+
+    let db = ServerDB::from_entries(vec![rpk_entry.clone()]);
+    let (client, server) = handshake(ciphersuite, &db, connect_with_server_key(&spki)).unwrap();
+    assert_eq!(client.server_certificates(), vec![spki.clone()]);
+    let (record, _client) = client.write(AppData::new(Bytes::from(b"ping"))).unwrap();
+    let (data, _server) = server.read(&record).unwrap();
+    assert_eq!(data.unwrap().into_raw(), Bytes::from(b"ping"));
+
+    // The client only accepts the pinned key.
+    let other_key = Bytes::from(std::fs::read("tests/assets/ct_log1_key.der").unwrap());
+    assert_eq!(
+        handshake(ciphersuite, &db, connect_with_server_key(&other_key)).err(),
+        Some(BAD_CERTIFICATE)
+    );
+
+    // A client that expects a certificate doesn't get a raw public key, and
+    // vice versa.
+    assert_eq!(
+        handshake(ciphersuite, &db, connect(config(ciphersuite))).err(),
+        Some(UNSUPPORTED_ALGORITHM)
+    );
+    let db = ServerDB::from_entries(vec![cert_entry.clone()]);
+    assert_eq!(
+        handshake(ciphersuite, &db, connect_with_server_key(&spki)).err(),
+        Some(UNSUPPORTED_ALGORITHM)
+    );
+
+    // With both, each client gets what it asks for.
+    let db = ServerDB::from_entries(vec![rpk_entry, cert_entry]);
+    let (client, _) = handshake(ciphersuite, &db, connect_with_server_key(&spki)).unwrap();
+    assert_eq!(client.server_certificates(), vec![spki.clone()]);
+    let (client, _) = handshake(ciphersuite, &db, connect(config(ciphersuite))).unwrap();
+    assert_eq!(client.server_certificates(), chain);
+
+    // The expected key must be a supported SubjectPublicKeyInfo.
+    assert!(connect_with_server_key(&chain[0]).is_err());
+}
+
+#[test]
+fn test_external_psk() {
+    const psk_dhe_ke: Algorithms = Algorithms::new(
+        HashAlgorithm::SHA256,
+        AeadAlgorithm::Chacha20Poly1305,
+        SignatureScheme::EcdsaSecp256r1Sha256,
+        KemScheme::X25519,
+        true,
+        false,
+    );
+    const psk_ke: Algorithms = psk_dhe_ke.with_psk_key_exchange_mode(PskKeyExchangeMode::PskKe);
+    let identity = Bytes::from(b"client 1");
+    let key = random_bytes(32);
+    let psk = PreSharedKey::external(identity.clone(), key.clone(), HashAlgorithm::SHA256);
+    let entry = ServerEntry::new(
+        Bytes::from(b"localhost"),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    let db = ServerDB::from_entries(vec![entry.clone().with_psk(psk.clone())]);
+    let connect_with_psk = |ciphersuite, psk| connect(config(ciphersuite).with_psk(psk));
+
+    for ciphersuite in [psk_dhe_ke, psk_ke] {
+        let (client, server) =
+            handshake(ciphersuite, &db, connect_with_psk(ciphersuite, psk.clone())).unwrap();
+        assert!(client.server_certificates().is_empty());
+        let (record, _client) = client.write(AppData::new(Bytes::from(b"ping"))).unwrap();
+        let (data, _server) = server.read(&record).unwrap();
+        assert_eq!(data.unwrap().into_raw(), Bytes::from(b"ping"));
+    }
+
+    // Without a key exchange, there are no key shares.
+    let (client_hello, _client) = connect_with_psk(psk_ke, psk.clone()).unwrap();
+    let info = peek_client_hello(&client_hello.declassify()).unwrap();
+    assert_eq!(info.psk_identities, vec![identity.clone()]);
+    assert_eq!(info.psk_key_exchange_modes, vec![0]);
+    assert!(info.key_share_groups.is_empty());
+
+    // The server only accepts the key exchange mode it's configured with.
+    assert_eq!(
+        Server::accept(
+            psk_dhe_ke,
+            db.clone(),
+            &client_hello,
+            None,
+            &mut rand::rngs::OsRng
+        )
+        .err(),
+        Some(PSK_MODE_MISMATCH)
+    );
+
+    // Unknown identities are rejected.
+    let unknown =
+        PreSharedKey::external(Bytes::from(b"client 2"), key.clone(), HashAlgorithm::SHA256);
+    assert_eq!(
+        handshake(psk_dhe_ke, &db, connect_with_psk(psk_dhe_ke, unknown)).err(),
+        Some(PSK_MODE_MISMATCH)
+    );
+
+    // A PSK is bound to its hash algorithm.
+    let sha384 = PreSharedKey::external(identity.clone(), key.clone(), HashAlgorithm::SHA384);
+    assert_eq!(
+        handshake(
+            psk_dhe_ke,
+            &db,
+            connect_with_psk(psk_dhe_ke, sha384.clone())
+        )
+        .err(),
+        Some(UNSUPPORTED_ALGORITHM)
+    );
+
+    // The binders of external and resumption PSKs differ, so a resumption PSK
+    // with the same identity and key doesn't match.
+    let resumption = PreSharedKey::resumption(identity.clone(), key.clone(), HashAlgorithm::SHA256);
+    assert!(handshake(psk_dhe_ke, &db, connect_with_psk(psk_dhe_ke, resumption)).is_err());
+
+    // Resumption PSKs still work with a session ticket.
+    let db = ServerDB::from_entries(vec![ServerEntry::new(
+        Bytes::from(b"localhost"),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        Some((identity.clone(), key.clone())),
+    )]);
+    let (client_hello, client) = Client::connect(
+        psk_dhe_ke,
+        &Bytes::from(b"localhost"),
+        Some(identity),
+        Some(key),
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let (_client, _server) = handshake(psk_dhe_ke, &db, Ok((client_hello, client))).unwrap();
+}
+
+#[test]
+fn test_multiple_psks() {
+    const ciphersuite: Algorithms = Algorithms::new(
+        HashAlgorithm::SHA256,
+        AeadAlgorithm::Chacha20Poly1305,
+        SignatureScheme::EcdsaSecp256r1Sha256,
+        KemScheme::X25519,
+        true,
+        false,
+    );
+    let psk = PreSharedKey::external(
+        Bytes::from(b"client 1"),
+        random_bytes(32),
+        HashAlgorithm::SHA256,
+    );
+    let db = ServerDB::from_entries(vec![ServerEntry::new(
+        Bytes::from(b"localhost"),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    )
+    .with_psk(psk.clone())]);
+
+    // The server skips identities it doesn't know.
+    let skipped = PreSharedKey::external(
+        Bytes::from(b"client 2"),
+        random_bytes(32),
+        HashAlgorithm::SHA256,
+    );
+    let (client_hello, client) = connect(
+        config(ciphersuite)
+            .with_psk(skipped.clone())
+            .with_psk(psk.clone()),
+    )
+    .unwrap();
+    let info = peek_client_hello(&client_hello.declassify()).unwrap();
+    assert_eq!(
+        info.psk_identities,
+        vec![skipped.identity().clone(), psk.identity().clone()]
+    );
+
+    // The client continues with the second PSK, the one the server picked.
+    let (client, server) = handshake(ciphersuite, &db, Ok((client_hello, client))).unwrap();
+    let (record, _client) = client.write(AppData::new(Bytes::from(b"ping"))).unwrap();
+    let (data, _server) = server.read(&record).unwrap();
+    assert_eq!(data.unwrap().into_raw(), Bytes::from(b"ping"));
+
+    // The handshake is aborted if the binder of the first known identity is
+    // wrong, even if a later one is valid.
+    let wrong_key = PreSharedKey::external(
+        Bytes::from(b"client 1"),
+        random_bytes(32),
+        HashAlgorithm::SHA256,
+    );
+    let (client_hello, _client) =
+        connect(config(ciphersuite).with_psk(wrong_key).with_psk(psk)).unwrap();
+    assert_eq!(
+        Server::accept(
+            ciphersuite,
+            db.clone(),
+            &client_hello,
+            None,
+            &mut rand::rngs::OsRng
+        )
+        .err(),
+        Some(DECRYPT_ERROR)
+    );
+
+    // The handshake fails if the server knows none of the PSKs.
+    let (client_hello, _client) = connect(config(ciphersuite).with_psk(skipped)).unwrap();
+    assert_eq!(
+        Server::accept(ciphersuite, db, &client_hello, None, &mut rand::rngs::OsRng).err(),
+        Some(PSK_MODE_MISMATCH)
+    );
+}
+
+#[test]
+fn test_encrypted_client_hello() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    let entry = |name: &[u8]| {
+        ServerEntry::new(
+            Bytes::from(name),
+            Bytes::from(&ECDSA_P256_SHA256_CERT),
+            SignatureKey::from(&ECDSA_P256_SHA256_Key),
+            None,
+        )
+    };
+    // The certificate for the public name is checked when ECH is rejected.
+    let public_name = Bytes::from(b"public.example");
+    let key = load_private_key(&std::fs::read("tests/assets/p256_key.pem").unwrap()).unwrap();
+    let public_cert =
+        load_certificates(&std::fs::read("tests/assets/ech_public_cert.pem").unwrap()).unwrap();
+    let public_entry =
+        ServerEntry::with_chain(public_name.clone(), public_cert, key.signature_key(), None);
+    let db_without_keys = ServerDB::from_entries(vec![public_entry, entry(b"secret.example")]);
+    let mut db = db_without_keys.clone();
+    db.add_ech_key(EchKeyPair::generate(1, &public_name, &mut rand::rngs::OsRng).unwrap());
+    let ech_config_list = db.ech_config_list().unwrap().unwrap();
+    assert_eq!(db_without_keys.ech_config_list(), Ok(None));
+
+    let secret = || ClientConfig::new(ciphersuite, &Bytes::from(b"secret.example"));
+    let connect_with_ech =
+        |ech_config_list: &Bytes| secret().with_ech(ech_config_list).and_then(connect);
+
+    // Only the public name is visible.
+    let (client_hello, client) = connect_with_ech(&ech_config_list).unwrap();
+    assert_eq!(client.ech_status(), EchStatus::Offered);
+    let info = peek_client_hello(&client_hello.declassify()).unwrap();
+    assert_eq!(info.server_name, Some(Bytes::from(b"public.example")));
+    assert!(info.encrypted_client_hello.is_some());
+
+    // The server accepts ECH and continues with the inner name.
+    let (client, server) = handshake(ciphersuite, &db, Ok((client_hello, client))).unwrap();
+    assert_eq!(client.ech_status(), EchStatus::Accepted);
+    assert_eq!(server.server_name(), Some(&Bytes::from(b"secret.example")));
+    let (record, _client) = client.write(AppData::new(Bytes::from(b"ping"))).unwrap();
+    let (data, _server) = server.read(&record).unwrap();
+    assert_eq!(data.unwrap().into_raw(), Bytes::from(b"ping"));
+
+    // A server with another key rejects ECH and sends its configs, and a
+    // server without ECH keys ignores the extension. The client authenticates
+    // the server for the public name, and aborts the handshake.
+    let mut other_db = db_without_keys.clone();
+    other_db.add_ech_key(EchKeyPair::generate(1, &public_name, &mut rand::rngs::OsRng).unwrap());
+    for (db, retry_configs) in [
+        (&other_db, other_db.ech_config_list().unwrap()),
+        (&db_without_keys, None),
+    ] {
+        assert_eq!(
+            handshake(ciphersuite, db, connect_with_ech(&ech_config_list)).err(),
+            Some(ECH_REQUIRED)
+        );
+        let (client_hello, client) = connect_with_ech(&ech_config_list).unwrap();
+        let (sh, sf, _server) = Server::accept(
+            ciphersuite,
+            db.clone(),
+            &client_hello,
+            None,
+            &mut rand::rngs::OsRng,
+        )
+        .unwrap();
+        let (_, client) = client.read_handshake(&sh).unwrap();
+        let (_, client) = client.read_handshake(&sf).unwrap();
+        assert_eq!(client.ech_status(), EchStatus::Rejected { retry_configs });
+        assert_eq!(
+            client.write(AppData::new(Bytes::from(b"ping"))).err(),
+            Some(ECH_REQUIRED)
+        );
+    }
+
+    // The client doesn't accept retry configs from a server that can't
+    // authenticate for the public name.
+    let mut wrong_db =
+        ServerDB::from_entries(vec![entry(b"public.example"), entry(b"secret.example")]);
+    wrong_db.add_ech_key(EchKeyPair::generate(1, &public_name, &mut rand::rngs::OsRng).unwrap());
+    assert_eq!(
+        handshake(ciphersuite, &wrong_db, connect_with_ech(&ech_config_list)).err(),
+        Some(BAD_CERTIFICATE)
+    );
+
+    // GREASE ECH is a regular handshake, even if the server supports ECH.
+    let (client_hello, client) = connect(secret().with_grease_ech()).unwrap();
+    let info = peek_client_hello(&client_hello.declassify()).unwrap();
+    assert_eq!(info.server_name, Some(Bytes::from(b"secret.example")));
+    assert!(info.encrypted_client_hello.is_some());
+    let (client, server) = handshake(ciphersuite, &db, Ok((client_hello, client))).unwrap();
+    assert_eq!(client.ech_status(), EchStatus::Grease);
+    assert_eq!(server.server_name(), Some(&Bytes::from(b"secret.example")));
+
+    // Malformed config lists are rejected.
+    assert!(connect_with_ech(&Bytes::from(b"ech")).is_err());
+}
+
+#[test]
+fn test_grease() {
+    let server_name = Bytes::from(b"localhost");
+    let db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    for ciphersuite in [
+        TLS_CHACHA20_POLY1305_SHA256_X25519,
+        TLS_AES_128_GCM_SHA256_X25519,
+    ] {
+        let (client_hello, client) = connect(config(ciphersuite).with_grease()).unwrap();
+        let info = peek_client_hello(&client_hello.declassify()).unwrap();
+        assert_eq!(info.cipher_suites.len(), 2);
+        assert_eq!(info.cipher_suites[0] & 0x0f0f, 0x0a0a);
+        assert_eq!(info.key_share_groups.len(), 2);
+
+        // The server ignores the GREASE values.
+        let (client, server) = handshake(ciphersuite, &db, Ok((client_hello, client))).unwrap();
+        let (record, _client) = client.write(AppData::new(Bytes::from(b"ping"))).unwrap();
+        let (data, _server) = server.read(&record).unwrap();
+        assert_eq!(data.unwrap().into_raw(), Bytes::from(b"ping"));
+    }
+}
+
+#[test]
+fn test_hello_retry_request() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    let server_name = Bytes::from(b"localhost");
+    let mut db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    let (client_hello, client) = Client::connect(
+        ciphersuite,
+        &server_name,
+        None,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+
+    // Without a cookie key the server never asks to retry, and with one only
+    // clients without a key share, unless it requires a cookie.
+    assert_eq!(
+        Server::hello_retry_request(ciphersuite, &db, &client_hello, None),
+        Ok(None)
+    );
+    db.set_cookie_key(random_bytes(32));
+    assert_eq!(
+        Server::hello_retry_request(ciphersuite, &db, &client_hello, None),
+        Ok(None)
+    );
+    db.set_require_cookie(true);
+    db.set_clock(|| 1_800_000_000);
+    let address = Bytes::from(b"192.0.2.1:50000");
+    let hrr = Server::hello_retry_request(ciphersuite, &db, &client_hello, Some(address.clone()))
+        .unwrap()
+        .unwrap();
+
+    // The client sends its hello again with the cookie, which the server
+    // continues from.
+    let (client_hello, client) = client.read_handshake(&hrr).unwrap();
+    let client_hello = client_hello.unwrap();
+    let info = peek_client_hello(&client_hello.declassify()).unwrap();
+    assert!(info.cookie.is_some());
+    assert_eq!(
+        Server::hello_retry_request(ciphersuite, &db, &client_hello, None),
+        Ok(None)
+    );
+
+    // Servers with another cookie key, or none, reject the cookie.
+    let mut other_db = db.clone();
+    other_db.set_cookie_key(random_bytes(32));
+    assert_eq!(
+        Server::accept(
+            ciphersuite,
+            other_db,
+            &client_hello,
+            Some(address.clone()),
+            &mut rand::rngs::OsRng
+        )
+        .err(),
+        Some(ILLEGAL_PARAMETER)
+    );
+    let no_cookie_db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    assert_eq!(
+        Server::accept(
+            ciphersuite,
+            no_cookie_db,
+            &client_hello,
+            Some(address.clone()),
+            &mut rand::rngs::OsRng
+        )
+        .err(),
+        Some(ILLEGAL_PARAMETER)
+    );
+
+    // The cookie is bound to the client's address, and expires after a minute.
+    for other_address in [None, Some(Bytes::from(b"198.51.100.7:50000"))] {
+        assert_eq!(
+            Server::accept(
+                ciphersuite,
+                db.clone(),
+                &client_hello,
+                other_address,
+                &mut rand::rngs::OsRng
+            )
+            .err(),
+            Some(ILLEGAL_PARAMETER)
+        );
+    }
+    let mut later_db = db.clone();
+    later_db.set_clock(|| 1_800_000_061);
+    assert_eq!(
+        Server::accept(
+            ciphersuite,
+            later_db,
+            &client_hello,
+            Some(address.clone()),
+            &mut rand::rngs::OsRng
+        )
+        .err(),
+        Some(ILLEGAL_PARAMETER)
+    );
+    let mut in_time_db = db.clone();
+    in_time_db.set_clock(|| 1_800_000_060);
+    let (sh, sf, server) = Server::accept(
+        ciphersuite,
+        in_time_db,
+        &client_hello,
+        Some(address),
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let (_, client) = client.read_handshake(&sh).unwrap();
+    let (cf, client) = client.read_handshake(&sf).unwrap();
+    let server = server.read_handshake(&cf.unwrap()).unwrap();
+    let (record, _client) = client.write(AppData::new(Bytes::from(b"ping"))).unwrap();
+    let (data, _server) = server.read(&record).unwrap();
+    assert_eq!(data.unwrap().into_raw(), Bytes::from(b"ping"));
+
+    // There's only one HelloRetryRequest.
+    let (client_hello, client) = Client::connect(
+        ciphersuite,
+        &server_name,
+        None,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let hrr = Server::hello_retry_request(ciphersuite, &db, &client_hello, None)
+        .unwrap()
+        .unwrap();
+    let (_, client) = client.read_handshake(&hrr).unwrap();
+    assert_eq!(client.read_handshake(&hrr).err(), Some(UNEXPECTED_MESSAGE));
+}
+
+#[test]
+fn test_hello_retry_request_with_psk() {
+    const ciphersuite: Algorithms = Algorithms::new(
+        HashAlgorithm::SHA256,
+        AeadAlgorithm::Chacha20Poly1305,
+        SignatureScheme::EcdsaSecp256r1Sha256,
+        KemScheme::X25519,
+        true,
+        false,
+    );
+    let psk = PreSharedKey::external(
+        Bytes::from(b"client 1"),
+        random_bytes(32),
+        HashAlgorithm::SHA256,
+    );
+    let mut db = ServerDB::from_entries(vec![ServerEntry::new(
+        Bytes::from(b"localhost"),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    )
+    .with_psk(psk.clone())]);
+    db.set_cookie_key(random_bytes(32));
+    db.set_require_cookie(true);
+
+    // The binders of the second client hello cover the HelloRetryRequest.
+    let (client_hello, client) = connect(config(ciphersuite).with_psk(psk)).unwrap();
+    let hrr = Server::hello_retry_request(ciphersuite, &db, &client_hello, None)
+        .unwrap()
+        .unwrap();
+    let (client_hello, client) = client.read_handshake(&hrr).unwrap();
+    let (_client, _server) =
+        handshake(ciphersuite, &db, Ok((client_hello.unwrap(), client))).unwrap();
+}
+
+#[test]
+fn test_downgrade_protection() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    let server_name = Bytes::from(b"localhost");
+    let db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    for sentinel in [b"DOWNGRD\x01", b"DOWNGRD\x00"] {
+        let (client_hello, client) = Client::connect(
+            ciphersuite,
+            &server_name,
+            None,
+            None,
+            &mut rand::rngs::OsRng,
+        )
+        .unwrap();
+        let (sh, _sf, _server) = Server::accept(
+            ciphersuite,
+            db.clone(),
+            &client_hello,
+            None,
+            &mut rand::rngs::OsRng,
+        )
+        .unwrap();
+
+        // A middlebox that downgraded the connection would leave the
+        // sentinel at the end of the server random, after the record
+        // header, the handshake header and the legacy version.
+        let mut sh = sh.declassify();
+        sh[35..43].copy_from_slice(sentinel);
+        assert_eq!(
+            client.read_handshake(&Bytes::from(sh)).err(),
+            Some(ILLEGAL_PARAMETER)
+        );
+    }
+}
+
+#[test]
+fn test_client_hello_hook() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    let server_name = Bytes::from(b"localhost");
+    let db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+
+    let (client_hello, client) = Client::connect(
+        ciphersuite,
+        &server_name,
+        None,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+
+    // Reject the connection.
+    let rejected = Server::accept_with_hook(
+        &client_hello,
+        None,
+        |_| Err(UNRECOGNIZED_NAME),
+        &mut rand::rngs::OsRng,
+    );
+    assert_eq!(rejected.err(), Some(UNRECOGNIZED_NAME));
+
+    // The client doesn't offer any ALPN protocol to select.
+    let rejected = Server::accept_with_hook(
+        &client_hello,
+        None,
+        |info: &ClientHelloInfo| {
+            assert!(info.alpn_protocols.is_empty());
+            Ok(ServerConfig {
+                ciphersuite,
+                db: db.clone(),
+                alpn_protocol: Some(Bytes::from(b"h2")),
+            })
+        },
+        &mut rand::rngs::OsRng,
+    );
+    assert_eq!(rejected.err(), Some(NO_APPLICATION_PROTOCOL));
+
+    let (sh, sf, server) = Server::accept_with_hook(
+        &client_hello,
+        None,
+        |info: &ClientHelloInfo| {
+            assert_eq!(info.server_name, Some(server_name.clone()));
+            assert_eq!(info.cipher_suites, vec![0x1303]);
+            assert_eq!(info.supported_groups, vec![0x001d]);
+            assert_eq!(info.key_share_groups, vec![0x001d]);
+            assert_eq!(info.signature_algorithms[0], 0x0403);
+            assert!(info.psk_identities.is_empty());
+            assert!(info.psk_key_exchange_modes.is_empty());
+            assert!(info.status_request);
+            assert!(info.signed_certificate_timestamp);
+            let compression: Vec<u16> = supported_algorithms()
+                .into_iter()
+                .map(|algorithm| algorithm.code())
+                .collect();
+            assert_eq!(info.certificate_compression_algorithms, compression);
+            assert!(info.server_certificate_types.is_empty());
+            assert!(info.encrypted_client_hello.is_none());
+            Ok(ServerConfig {
+                ciphersuite,
+                db: db.clone(),
+                alpn_protocol: None,
+            })
+        },
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let (_, client) = client.read_handshake(&sh).unwrap();
+    let (cf, _client) = client.read_handshake(&sf).unwrap();
+    server.read_handshake(&cf.unwrap()).unwrap();
+}
+
+#[test]
+fn test_peek_client_hello() {
+    let (client_hello, _client) = Client::connect(
+        TLS_CHACHA20_POLY1305_SHA256_X25519,
+        &Bytes::from(b"example.com"),
+        None,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let record = client_hello.declassify();
+    let info = peek_client_hello(&record).unwrap();
+    assert_eq!(info.server_name, Some(Bytes::from(b"example.com")));
+    assert_eq!(info.cipher_suites, vec![0x1303]);
+
+    // Split the ClientHello into records of at most 16 bytes, followed by
+    // application data that must be ignored.
+    let mut records = Vec::new();
+    for fragment in record[5..].chunks(16) {
+        records.extend_from_slice(&[22, 3, 1, 0, fragment.len() as u8]);
+        records.extend_from_slice(fragment);
+    }
+    let len = records.len();
+    records.extend_from_slice(&[23, 3, 3, 0, 1, 0]);
+    assert_eq!(peek_client_hello(&records).unwrap(), info);
+
+    // Incomplete data asks for more.
+    for end in 0..len {
+        assert_eq!(
+            peek_client_hello(&records[0..end]).unwrap_err(),
+            INSUFFICIENT_DATA
+        );
+    }
+
+    // Corrupted data is rejected or parsed, but doesn't panic.
+    for i in 0..record.len() {
+        for flip in [0x01, 0x80, 0xff] {
+            let mut corrupted = record.clone();
+            corrupted[i] ^= flip;
+            let _ = peek_client_hello(&corrupted);
+        }
+    }
+}
+
+#[test]
+fn test_post_handshake_auth() {
+    const ciphersuite: Algorithms = TLS_AES_128_GCM_SHA256_X25519;
+    let server_name = Bytes::from(b"localhost");
+    let mut db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    let client_cert = Bytes::from(&ECDSA_P256_SHA256_CERT);
+    // The self-signed client certificate is trusted as it is.
+    db.set_client_certificate_authorities(&[client_cert.clone()])
+        .unwrap();
+    let credentials = ClientCredentials::new(
+        vec![client_cert.clone()],
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+    );
+
+    let (mut client, mut server) = handshake(
+        ciphersuite,
+        &db,
+        connect(ClientConfig::new(ciphersuite, &server_name).with_client_credentials(credentials)),
+    )
+    .unwrap();
+    assert!(server.client_certificates().is_empty());
+
+    // The server asks for a certificate twice, one request at a time.
+    for _ in 0..2 {
+        let (request, s) = server.request_client_certificate().unwrap();
+        assert!(s.certificate_requested());
+        let (data, c) = client.read(&request).unwrap();
+        assert!(data.is_none());
+        assert!(c.certificate_requested());
+        let (response, c) = c.certificate_response(&mut rand::rngs::OsRng).unwrap();
+        assert!(!c.certificate_requested());
+        let (data, s) = s.read(&response.unwrap()).unwrap();
+        assert!(data.is_none());
+        assert!(!s.certificate_requested());
+        assert_eq!(s.client_certificates(), vec![client_cert.clone()]);
+        client = c;
+        server = s;
+    }
+
+    // Application data still flows after the authentication.
+    let (record, _client) = client.write(AppData::new(Bytes::from(b"ping"))).unwrap();
+    let (data, _server) = server.read(&record).unwrap();
+    assert_eq!(data.unwrap().into_raw(), Bytes::from(b"ping"));
+}
+
+#[test]
+fn test_post_handshake_auth_not_offered() {
+    const ciphersuite: Algorithms = TLS_AES_128_GCM_SHA256_X25519;
+    let db = ServerDB::new(
+        Bytes::from(b"localhost"),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    let (client_hello, client) = Client::connect(
+        ciphersuite,
+        &Bytes::from(b"localhost"),
+        None,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let (_client, server) = handshake(ciphersuite, &db, Ok((client_hello, client))).unwrap();
+    assert!(matches!(
+        server.request_client_certificate(),
+        Err(UNSUPPORTED)
+    ));
+}
+
+#[test]
+fn test_delegated_credential() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    // The certificate is valid from 2026-10-19 00:00:00.
+    const NOT_BEFORE: u64 = 1792368000;
+    const DAY: u64 = 86400;
+    // The client checks the credential at the time of its clock in the
+    // handshake.
+    const NOW: u64 = NOT_BEFORE + 30 * DAY;
+    let client_config = || config(ciphersuite).with_clock(|| NOW);
+    let cert =
+        load_certificates(&std::fs::read("tests/assets/dc_cert.pem").unwrap()).unwrap()[0].clone();
+    let cert_key =
+        load_private_key(&std::fs::read("tests/assets/dc_cert_key.pem").unwrap()).unwrap();
+    let cert_signer = InMemorySigner::new(cert.clone(), cert_key.signature_key());
+    let dc_key = load_private_key(&std::fs::read("tests/assets/dc_key.pem").unwrap()).unwrap();
+    let dc_spki = Bytes::from(std::fs::read("tests/assets/dc_spki.der").unwrap());
+    let delegate = |cert: &Bytes, signer: &InMemorySigner, expiry: u64| {
+        let dc = DelegatedCredential::delegate(
+            cert,
+            signer,
+            &dc_spki,
+            (expiry - NOT_BEFORE) as u32,
+            &mut rand::rngs::OsRng,
+        )
+        .unwrap();
+        let entry = ServerEntry::with_delegated_credential(
+            Bytes::from(b"localhost"),
+            vec![cert.clone()],
+            DelegatedCredential::new(dc.clone(), dc_key.signature_key()).unwrap(),
+            None,
+        );
+        (dc, ServerDB::from_entries(vec![entry]))
+    };
+
+    // The server signs with the delegated key, valid for two more days.
+    let (dc, db) = delegate(&cert, &cert_signer, NOW + 2 * DAY);
+    let (client, _) = handshake(ciphersuite, &db, connect(client_config())).unwrap();
+    assert_eq!(client.server_certificates(), vec![cert.clone()]);
+    assert_eq!(client.delegated_credential(), Some(dc));
+    assert_eq!(client.verify_delegated_credential(NOW + DAY), Ok(()));
+    assert_eq!(
+        client.verify_delegated_credential(NOW + 3 * DAY),
+        Err(BAD_DELEGATED_CREDENTIAL)
+    );
+    assert_eq!(
+        client.verify_delegated_credential(NOT_BEFORE - 1),
+        Err(BAD_DELEGATED_CREDENTIAL)
+    );
+    assert_eq!(
+        handshake(
+            ciphersuite,
+            &db,
+            connect(client_config().with_clock(|| NOW + 3 * DAY))
+        )
+        .err(),
+        Some(BAD_DELEGATED_CREDENTIAL)
+    );
+
+    // Expired credentials, and credentials valid for more than 7 days, are
+    // rejected in the handshake.
+    for expiry in [NOW - 60, NOW + 30 * DAY] {
+        let (_, db) = delegate(&cert, &cert_signer, expiry);
+        assert_eq!(
+            handshake(ciphersuite, &db, connect(client_config()))
+                .err()
+                .unwrap(),
+            BAD_DELEGATED_CREDENTIAL
+        );
+    }
+
+    // Without a delegated credential, there's nothing to check.
+    let db = ServerDB::from_entries(vec![ServerEntry::new(
+        Bytes::from(b"localhost"),
+        cert.clone(),
+        cert_key.signature_key(),
+        None,
+    )]);
+    let (client, _) = handshake(ciphersuite, &db, connect(client_config())).unwrap();
+    assert_eq!(client.delegated_credential(), None);
+    assert_eq!(client.verify_delegated_credential(NOT_BEFORE), Ok(()));
+
+    // The certificate must allow delegated credentials.
+    let chain_key =
+        load_private_key(&std::fs::read("tests/assets/chain_key.pem").unwrap()).unwrap();
+    let chain = load_certificates(&std::fs::read("tests/assets/chain_cert.pem").unwrap()).unwrap();
+    let chain_signer = InMemorySigner::new(chain[0].clone(), chain_key.signature_key());
+    let (_, db) = delegate(&chain[0], &chain_signer, NOW + 2 * DAY);
+    assert_eq!(
+        handshake(ciphersuite, &db, connect(client_config()))
+            .err()
+            .unwrap(),
+        ILLEGAL_PARAMETER
+    );
+
+    // The credential must be signed with the certificate key.
+    let (_, db) = delegate(&cert, &chain_signer, NOW + 2 * DAY);
+    assert_eq!(
+        handshake(ciphersuite, &db, connect(client_config()))
+            .err()
+            .unwrap(),
+        INVALID_SIGNATURE
+    );
+}
+
+#[test]
+fn test_certificate_authorities() {
+    const ciphersuite: Algorithms = TLS_CHACHA20_POLY1305_SHA256_X25519;
+    let server_name = Bytes::from(b"localhost");
+    // Both entries sign with P-256. The self-signed certificate comes first.
+    let dc_cert =
+        load_certificates(&std::fs::read("tests/assets/dc_cert.pem").unwrap()).unwrap()[0].clone();
+    let dc_cert_key =
+        load_private_key(&std::fs::read("tests/assets/dc_cert_key.pem").unwrap()).unwrap();
+    let chain_key =
+        load_private_key(&std::fs::read("tests/assets/chain_key.pem").unwrap()).unwrap();
+    let chain = load_certificates(&std::fs::read("tests/assets/chain_cert.pem").unwrap()).unwrap();
+    let db = ServerDB::from_entries(vec![
+        ServerEntry::new(
+            server_name.clone(),
+            dc_cert.clone(),
+            dc_cert_key.signature_key(),
+            None,
+        ),
+        ServerEntry::with_chain(
+            server_name.clone(),
+            chain.clone(),
+            chain_key.signature_key(),
+            None,
+        ),
+    ]);
+    let server_certificates = |trust_anchors: &[Bytes]| {
+        let (client_hello, client) = connect(
+            ClientConfig::new(ciphersuite, &server_name)
+                .with_certificate_authorities(trust_anchors)
+                .unwrap(),
+        )
+        .unwrap();
+        let info = peek_client_hello(&client_hello.declassify()).unwrap();
+        assert_eq!(info.certificate_authorities.len(), trust_anchors.len());
+        let (client, _) = handshake(ciphersuite, &db, Ok((client_hello, client))).unwrap();
+        client.server_certificates()
+    };
+
+    // The chain ends at the intermediate CA.
+    assert_eq!(server_certificates(&[chain[1].clone()]), chain);
+    assert_eq!(
+        server_certificates(&[dc_cert.clone()]),
+        vec![dc_cert.clone()]
+    );
+    // Without a matching trust anchor, the first entry is used.
+    assert_eq!(server_certificates(&[]), vec![dc_cert.clone()]);
+    assert_eq!(
+        server_certificates(&[Bytes::from(&ECDSA_P256_SHA256_CERT)]),
+        vec![dc_cert.clone()]
+    );
+
+    // Malformed trust anchors are rejected.
+    assert!(ClientConfig::new(ciphersuite, &server_name)
+        .with_certificate_authorities(&[Bytes::from(&dc_cert.declassify()[..100])])
+        .is_err());
+}
+
+#[test]
+fn test_post_handshake_auth_certificate_authorities() {
+    const ciphersuite: Algorithms = TLS_AES_128_GCM_SHA256_X25519;
+    let server_name = Bytes::from(b"localhost");
+    let client_cert = Bytes::from(&ECDSA_P256_SHA256_CERT);
+    let dc_cert =
+        load_certificates(&std::fs::read("tests/assets/dc_cert.pem").unwrap()).unwrap()[0].clone();
+    let authenticate = |trust_anchors: &[Bytes]| {
+        let mut db = ServerDB::new(
+            server_name.clone(),
+            Bytes::from(&ECDSA_P256_SHA256_CERT),
+            SignatureKey::from(&ECDSA_P256_SHA256_Key),
+            None,
+        );
+        db.set_client_certificate_authorities(trust_anchors)
+            .unwrap();
+        let credentials = ClientCredentials::new(
+            vec![client_cert.clone()],
+            SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        );
+        let (client, server) = handshake(
+            ciphersuite,
+            &db,
+            connect(
+                ClientConfig::new(ciphersuite, &server_name).with_client_credentials(credentials),
+            ),
+        )
+        .unwrap();
+        let (request, server) = server.request_client_certificate().unwrap();
+        let (_, client) = client.read(&request).unwrap();
+        let (response, _client) = client.certificate_response(&mut rand::rngs::OsRng).unwrap();
+        let (_, server) = server.read(&response.unwrap())?;
+        Ok::<_, TLSError>(server.client_certificates())
+    };
+
+    assert_eq!(
+        authenticate(&[client_cert.clone()]),
+        Ok(vec![client_cert.clone()])
+    );
+    // The client certificate doesn't chain to the requested authority.
+    assert_eq!(authenticate(&[dc_cert.clone()]), Ok(vec![]));
+    // Without trust anchors, the server accepts no certificate.
+    assert_eq!(authenticate(&[]), Err(BAD_CERTIFICATE));
+
+    let mut db = ServerDB::new(
+        server_name.clone(),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    assert!(db
+        .set_client_certificate_authorities(&[Bytes::from(&dc_cert.declassify()[..100])])
+        .is_err());
+}
+
+#[test]
+fn test_post_handshake_auth_chain_validation() {
+    const ciphersuite: Algorithms = TLS_AES_128_GCM_SHA256_X25519;
+    // The certificates are valid from 2026-10-19 07:42:24 to 2046-10-14 07:42:24.
+    const NOW: u64 = 1792454400;
+    let server_name = Bytes::from(b"localhost");
+    let key = load_private_key(&std::fs::read("tests/assets/chain_key.pem").unwrap()).unwrap();
+    let chain = load_certificates(&std::fs::read("tests/assets/chain_cert.pem").unwrap()).unwrap();
+    let intermediate = chain[1].clone();
+    let authenticate = |cert_chain: Vec<Bytes>, clock: fn() -> u64| {
+        let mut db = ServerDB::new(
+            server_name.clone(),
+            Bytes::from(&ECDSA_P256_SHA256_CERT),
+            SignatureKey::from(&ECDSA_P256_SHA256_Key),
+            None,
+        );
+        db.set_client_certificate_authorities(&[intermediate.clone()])
+            .unwrap();
+        db.set_clock(clock);
+        let credentials = ClientCredentials::new(cert_chain, key.signature_key());
+        let (client, server) = handshake(
+            ciphersuite,
+            &db,
+            connect(
+                ClientConfig::new(ciphersuite, &server_name).with_client_credentials(credentials),
+            ),
+        )
+        .unwrap();
+        let (request, server) = server.request_client_certificate().unwrap();
+        let (_, client) = client.read(&request).unwrap();
+        let (response, _client) = client.certificate_response(&mut rand::rngs::OsRng).unwrap();
+        let (_, server) = server.read(&response.unwrap())?;
+        Ok::<_, TLSError>(server.client_certificates())
+    };
+
+    // The chain ends at the trust anchor, with or without it.
+    assert_eq!(authenticate(chain.clone(), || NOW), Ok(chain.clone()));
+    assert_eq!(
+        authenticate(vec![chain[0].clone()], || NOW),
+        Ok(vec![chain[0].clone()])
+    );
+
+    // The certificates must be valid at the time of the server.
+    assert_eq!(
+        authenticate(chain.clone(), || 0).err(),
+        Some(BAD_CERTIFICATE)
+    );
+
+    // A certificate with the name, but not the signature, of the trust anchor
+    // is rejected.
+    let mut forged = chain[0].declassify();
+    let last = forged.len() - 1;
+    forged[last] ^= 1;
+    assert_eq!(
+        authenticate(vec![Bytes::from(forged)], || NOW).err(),
+        Some(BAD_CERTIFICATE)
+    );
+}
+
+/// The traffic secrets in the `outputs`, by level.
+fn quic_secrets(outputs: &[QuicOutput]) -> Vec<(EncryptionLevel, Bytes, Option<Bytes>)> {
+    outputs
+        .iter()
+        .flat_map(|output| output.secrets.iter())
+        .map(|secrets| {
+            (
+                secrets.level,
+                secrets.client.clone(),
+                secrets.server.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Pass the handshake messages of the `output` to the `client`, each in two
+/// CRYPTO frames.
+fn quic_client_read(
+    mut client: QuicClient,
+    output: &QuicOutput,
+    outputs: &mut Vec<QuicOutput>,
+) -> Result<QuicClient, TLSError> {
+    for (level, messages) in &output.handshake {
+        let messages = messages.declassify();
+        let (first, second) = messages.split_at(messages.len() / 2);
+        let (first_output, next) = client.read_handshake(*level, &Bytes::from(first))?;
+        assert!(first_output.handshake.is_empty() && first_output.secrets.is_empty());
+        let (second_output, next) = next.read_handshake(*level, &Bytes::from(second))?;
+        outputs.push(second_output);
+        client = next;
+    }
+    Ok(client)
+}
+
+/// Run a QUIC handshake with the client and server `quic` parameters.
+///
+/// Returns both sides and the secrets each of them handed out.
+#[allow(clippy::type_complexity)]
+fn connect_quic(
+    db: &ServerDB,
+    ciphersuite: Algorithms,
+    client_quic: QuicParameters,
+    server_quic: QuicParameters,
+) -> Result<
+    (
+        QuicClient,
+        QuicServer,
+        Vec<(EncryptionLevel, Bytes, Option<Bytes>)>,
+        Vec<(EncryptionLevel, Bytes, Option<Bytes>)>,
+    ),
+    TLSError,
+> {
+    let (client_output, client) =
+        QuicClient::connect(config(ciphersuite), client_quic, &mut rand::rngs::OsRng)?;
+    assert_eq!(client_output.handshake.len(), 1);
+    let (level, client_hello) = &client_output.handshake[0];
+    assert_eq!(*level, EncryptionLevel::Initial);
+    let (server_output, server) = QuicServer::accept(
+        ciphersuite,
+        db.clone(),
+        client_hello,
+        server_quic,
+        &mut rand::rngs::OsRng,
+    )?;
+    let levels: Vec<EncryptionLevel> = server_output
+        .handshake
+        .iter()
+        .map(|(level, _)| *level)
+        .collect();
+    assert_eq!(
+        levels,
+        vec![EncryptionLevel::Initial, EncryptionLevel::Handshake]
+    );
+
+    let mut client_outputs = vec![client_output];
+    let client = quic_client_read(client, &server_output, &mut client_outputs)?;
+    assert!(client.is_handshake_complete());
+    let client_finished = client_outputs.last().unwrap();
+    assert_eq!(client_finished.handshake.len(), 1);
+    let (level, client_finished) = &client_finished.handshake[0];
+    assert_eq!(*level, EncryptionLevel::Handshake);
+    let (output, server) = server.read_handshake(*level, client_finished)?;
+    assert!(output.handshake.is_empty() && output.secrets.is_empty());
+    assert!(server.is_handshake_complete());
+    Ok((
+        client,
+        server,
+        quic_secrets(&client_outputs),
+        quic_secrets(&[server_output]),
+    ))
+}
+
+#[test]
+fn test_quic() {
+    const ciphersuite: Algorithms = TLS_AES_128_GCM_SHA256_X25519;
+    let db = ServerDB::new(
+        Bytes::from(b"localhost"),
+        Bytes::from(&ECDSA_P256_SHA256_CERT),
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+        None,
+    );
+    let client_quic = QuicParameters::new(
+        vec![Bytes::from(b"h3"), Bytes::from(b"hq-interop")],
+        Bytes::from(b"client parameters"),
+    )
+    .unwrap();
+    let server_quic = QuicParameters::new(
+        vec![Bytes::from(b"hq-interop"), Bytes::from(b"h3")],
+        Bytes::from(b"server parameters"),
+    )
+    .unwrap();
+
+    let (client, server, client_secrets, server_secrets) =
+        connect_quic(&db, ciphersuite, client_quic.clone(), server_quic.clone()).unwrap();
+    // Both sides agree on the secrets of each level.
+    assert_eq!(client_secrets, server_secrets);
+    let levels: Vec<EncryptionLevel> = client_secrets.iter().map(|(level, _, _)| *level).collect();
+    assert_eq!(
+        levels,
+        vec![EncryptionLevel::Handshake, EncryptionLevel::OneRtt]
+    );
+    // The server's preference wins.
+    assert_eq!(client.alpn_protocol(), Ok(Bytes::from(b"hq-interop")));
+    assert_eq!(server.alpn_protocol(), Bytes::from(b"hq-interop"));
+    assert_eq!(
+        client.transport_parameters(),
+        Ok(Bytes::from(b"server parameters"))
+    );
+    assert_eq!(
+        server.transport_parameters(),
+        Bytes::from(b"client parameters")
+    );
+    assert_eq!(
+        client.server_certificates(),
+        vec![Bytes::from(&ECDSA_P256_SHA256_CERT)]
+    );
+    assert_eq!(server.server_name(), Some(Bytes::from(b"localhost")));
+
+    // Session tickets are ignored, but QUIC has no KeyUpdate.
+    let (_, client) = client
+        .read_handshake(EncryptionLevel::OneRtt, &Bytes::from(&[4u8, 0, 0, 0]))
+        .unwrap();
+    assert_eq!(
+        client
+            .read_handshake(EncryptionLevel::OneRtt, &Bytes::from(&[24u8, 0, 0, 1, 0]))
+            .err(),
+        Some(UNEXPECTED_MESSAGE)
+    );
+
+    // Without a common protocol the handshake fails.
+    let other_quic =
+        QuicParameters::new(vec![Bytes::from(b"smtp")], Bytes::from(b"parameters")).unwrap();
+    assert_eq!(
+        connect_quic(&db, ciphersuite, client_quic.clone(), other_quic).err(),
+        Some(NO_APPLICATION_PROTOCOL)
+    );
+    assert_eq!(
+        QuicParameters::new(Vec::new(), Bytes::new()).err().unwrap(),
+        NO_APPLICATION_PROTOCOL
+    );
+    assert_eq!(
+        QuicParameters::new(vec![Bytes::new()], Bytes::new())
+            .err()
+            .unwrap(),
+        ILLEGAL_PARAMETER
+    );
+
+    // ECH and post-handshake authentication aren't available over QUIC.
+    let credentials = ClientCredentials::new(
+        vec![Bytes::from(&ECDSA_P256_SHA256_CERT)],
+        SignatureKey::from(&ECDSA_P256_SHA256_Key),
+    );
+    for config in [
+        config(ciphersuite).with_grease_ech(),
+        config(ciphersuite).with_client_credentials(credentials),
+    ] {
+        assert_eq!(
+            QuicClient::connect(config, client_quic.clone(), &mut rand::rngs::OsRng).err(),
+            Some(UNSUPPORTED)
+        );
+    }
+
+    // A TLS client hello has a legacy session id and no transport parameters.
+    let (client_hello, _client) = Client::connect(
+        ciphersuite,
+        &Bytes::from(b"localhost"),
+        None,
+        None,
+        &mut rand::rngs::OsRng,
+    )
+    .unwrap();
+    let client_hello = Bytes::from(&client_hello.declassify()[5..]);
+    assert_eq!(
+        QuicServer::accept(
+            ciphersuite,
+            db.clone(),
+            &client_hello,
+            server_quic.clone(),
+            &mut rand::rngs::OsRng
+        )
+        .err(),
+        Some(ILLEGAL_PARAMETER)
+    );
+
+    // The server rejects EndOfEarlyData and messages at the wrong level.
+    let (client_output, client) =
+        QuicClient::connect(config(ciphersuite), client_quic, &mut rand::rngs::OsRng).unwrap();
+    let accept = || {
+        QuicServer::accept(
+            ciphersuite,
+            db.clone(),
+            &client_output.handshake[0].1,
+            server_quic.clone(),
+            &mut rand::rngs::OsRng,
+        )
+        .unwrap()
+        .1
+    };
+    assert_eq!(
+        accept()
+            .read_handshake(EncryptionLevel::Handshake, &Bytes::from(&[5u8, 0, 0, 0]))
+            .err(),
+        Some(UNEXPECTED_MESSAGE)
+    );
+
+    // Handshake messages, and the CRYPTO data buffered until a flight is
+    // complete, are limited in size.
+    assert_eq!(
+        client
+            .read_handshake(EncryptionLevel::Initial, &Bytes::from(&[2u8, 1, 0, 1]))
+            .err(),
+        Some(PAYLOAD_TOO_LONG)
+    );
+    // A Certificate message of the largest size.
+    let certificate = Bytes::from([vec![11u8, 1, 0, 0], vec![0u8; 1 << 16]].concat());
+    let (output, server) = accept()
+        .read_handshake(EncryptionLevel::Handshake, &certificate)
+        .unwrap();
+    assert!(output.handshake.is_empty() && !server.is_handshake_complete());
+    assert_eq!(
+        server
+            .read_handshake(EncryptionLevel::Handshake, &certificate)
+            .err(),
+        Some(PAYLOAD_TOO_LONG)
+    );
+}